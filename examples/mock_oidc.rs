@@ -0,0 +1,248 @@
+//! A minimal OpenID Connect provider for trying out single sign-on locally
+//!
+//! Run it with `cargo run --example mock_oidc`, then start the app with
+//!
+//! ```bash
+//! OIDC_ISSUER_URL=http://127.0.0.1:9000 OIDC_CLIENT_ID=market OIDC_CLIENT_SECRET=secret \
+//! OIDC_REDIRECT_URL=http://localhost:3000/auth/oidc/callback cargo run
+//! ```
+//!
+//! The login page lets you pick any subject and profile; nothing is verified.
+//! Set `MOCK_OIDC_PORT` to listen elsewhere.
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use market::domain::pkce_challenge;
+use rand::RngCore;
+use rsa::pkcs1v15::SigningKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const CLIENT_ID: &str = "market";
+const CLIENT_SECRET: &str = "secret";
+const KEY_ID: &str = "mock-key-1";
+
+#[derive(Clone)]
+struct Provider {
+    issuer: String,
+    key: Arc<RsaPrivateKey>,
+    codes: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+/// An authorization code waiting to be redeemed
+struct Grant {
+    redirect_uri: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    sub: String,
+    preferred_username: String,
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct AuthorizeQuery {
+    client_id: String,
+    redirect_uri: String,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApproveQuery {
+    redirect_uri: String,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    sub: String,
+    preferred_username: String,
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    code_verifier: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let port = std::env::var("MOCK_OIDC_PORT").unwrap_or_else(|_| "9000".to_string());
+    let issuer = format!("http://127.0.0.1:{}", port);
+
+    println!("Generating signing key...");
+    let key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048)?;
+
+    let provider = Provider {
+        issuer: issuer.clone(),
+        key: Arc::new(key),
+        codes: Arc::new(Mutex::new(HashMap::new())),
+    };
+
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/authorize", get(authorize))
+        .route("/authorize/approve", get(approve))
+        .route("/token", post(token))
+        .route("/jwks", get(jwks))
+        .with_state(provider);
+
+    println!("Mock OpenID provider at {} (client id '{}', secret '{}')", issuer, CLIENT_ID, CLIENT_SECRET);
+    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn discovery(State(provider): State<Provider>) -> Json<serde_json::Value> {
+    Json(json!({
+        "issuer": provider.issuer,
+        "authorization_endpoint": format!("{}/authorize", provider.issuer),
+        "token_endpoint": format!("{}/token", provider.issuer),
+        "jwks_uri": format!("{}/jwks", provider.issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+/// Login form standing in for the provider's real sign-in page
+async fn authorize(Query(query): Query<AuthorizeQuery>) -> Response {
+    if query.client_id != CLIENT_ID {
+        return (StatusCode::BAD_REQUEST, "Unknown client_id").into_response();
+    }
+
+    let hidden = |name: &str, value: &str| {
+        format!(r#"<input type="hidden" name="{}" value="{}">"#, name, html_escape(value))
+    };
+    Html(format!(
+        r#"<!doctype html>
+<h1>mock identity provider</h1>
+<form method="get" action="/authorize/approve">
+    {}{}{}{}
+    <p><label>subject <input name="sub" value="mock-user-1"></label></p>
+    <p><label>preferred_username <input name="preferred_username" value="mockuser"></label></p>
+    <p><label>email <input name="email" value="mockuser@example.com"></label></p>
+    <button type="submit">sign in</button>
+</form>"#,
+        hidden("redirect_uri", &query.redirect_uri),
+        hidden("state", &query.state),
+        hidden("nonce", query.nonce.as_deref().unwrap_or_default()),
+        hidden("code_challenge", query.code_challenge.as_deref().unwrap_or_default()),
+    ))
+    .into_response()
+}
+
+async fn approve(State(provider): State<Provider>, Query(query): Query<ApproveQuery>) -> Redirect {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+
+    let redirect = format!(
+        "{}?code={}&state={}",
+        query.redirect_uri,
+        code,
+        url_escape(&query.state)
+    );
+    provider.codes.lock().unwrap().insert(
+        code,
+        Grant {
+            redirect_uri: query.redirect_uri,
+            nonce: query.nonce.filter(|n| !n.is_empty()),
+            code_challenge: query.code_challenge.filter(|c| !c.is_empty()),
+            sub: query.sub,
+            preferred_username: query.preferred_username,
+            email: query.email,
+        },
+    );
+    Redirect::to(&redirect)
+}
+
+async fn token(State(provider): State<Provider>, headers: HeaderMap, Form(form): Form<TokenForm>) -> Response {
+    let expected_auth = format!("Basic {}", STANDARD.encode(format!("{}:{}", CLIENT_ID, CLIENT_SECRET)));
+    if headers.get("authorization").and_then(|v| v.to_str().ok()) != Some(expected_auth.as_str()) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "invalid_client" }))).into_response();
+    }
+    if form.grant_type != "authorization_code" {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "unsupported_grant_type" }))).into_response();
+    }
+
+    let Some(grant) = provider.codes.lock().unwrap().remove(&form.code) else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response();
+    };
+    let pkce_ok = match (&grant.code_challenge, &form.code_verifier) {
+        (Some(challenge), Some(verifier)) => &pkce_challenge(verifier) == challenge,
+        (None, _) => true,
+        (Some(_), None) => false,
+    };
+    if grant.redirect_uri != form.redirect_uri || !pkce_ok {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response();
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": provider.issuer,
+        "sub": grant.sub,
+        "aud": CLIENT_ID,
+        "iat": now,
+        "exp": now + 300,
+        "nonce": grant.nonce,
+        "preferred_username": grant.preferred_username,
+        "email": grant.email,
+    });
+    let header = json!({ "alg": "RS256", "typ": "JWT", "kid": KEY_ID });
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = SigningKey::<Sha256>::new((*provider.key).clone()).sign(signing_input.as_bytes());
+    let id_token = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+    Json(json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+    .into_response()
+}
+
+async fn jwks(State(provider): State<Provider>) -> Json<serde_json::Value> {
+    Json(json!({
+        "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": KEY_ID,
+            "n": URL_SAFE_NO_PAD.encode(provider.key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(provider.key.e().to_bytes_be()),
+        }]
+    }))
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn url_escape(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,20 @@
+-- Add traded volume to markets so listings can be sorted by activity
+-- Volume is the total number of shares bought or sold (YES + NO)
+ALTER TABLE markets ADD COLUMN volume REAL NOT NULL DEFAULT 0.0;
+
+-- Backfill from price snapshots: each snapshot records outstanding shares after a trade,
+-- so the change from the previous snapshot is the size of that trade
+UPDATE markets
+SET volume = COALESCE((
+    SELECT SUM(delta)
+    FROM (
+        SELECT
+            ABS(q_yes - LAG(q_yes, 1, 0.0) OVER (ORDER BY created_at, id))
+                + ABS(q_no - LAG(q_no, 1, 0.0) OVER (ORDER BY created_at, id)) AS delta
+        FROM price_snapshots
+        WHERE price_snapshots.market_id = markets.id
+    )
+), 0.0);
+
+CREATE INDEX idx_markets_created_at ON markets(created_at);
+CREATE INDEX idx_markets_volume ON markets(volume);
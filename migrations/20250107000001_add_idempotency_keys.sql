@@ -0,0 +1,16 @@
+-- Idempotency keys for trade submissions
+-- A key is scoped to the user who sent it; replays within the retention window return the stored outcome
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    user_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    -- Identifies the request the key was first used with, e.g. 'buy:1:yes:10'
+    fingerprint TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'completed')),
+    -- Redirect location returned by the completed request
+    response TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, key),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_idempotency_keys_created_at ON idempotency_keys(created_at);
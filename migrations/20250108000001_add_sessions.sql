@@ -0,0 +1,10 @@
+-- Persistent login sessions (see SqliteSessionStore)
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    -- JSON-encoded session data
+    data TEXT NOT NULL,
+    -- Unix timestamp (seconds) after which the session is no longer valid
+    expiry_date INTEGER NOT NULL
+);
+
+CREATE INDEX idx_sessions_expiry_date ON sessions(expiry_date);
@@ -0,0 +1,4 @@
+-- Privilege level for role-based access control
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user' CHECK(role IN ('user', 'moderator', 'admin'));
+
+CREATE INDEX idx_users_role ON users(role);
@@ -0,0 +1,17 @@
+-- Bumped whenever a user's password changes; sessions created with an older value are invalid
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
+
+-- Single-use password reset tokens; only a SHA-256 hash of the token is stored
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    token_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    -- Admin who issued the token
+    created_by INTEGER,
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_password_reset_tokens_user ON password_reset_tokens(user_id);
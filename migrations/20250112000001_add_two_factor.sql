@@ -0,0 +1,20 @@
+-- TOTP two-factor authentication
+-- A row exists only once the user has confirmed enrollment with a valid code
+CREATE TABLE IF NOT EXISTS totp_credentials (
+    user_id INTEGER PRIMARY KEY,
+    -- Base32-encoded shared secret
+    secret TEXT NOT NULL,
+    -- Time step of the last accepted code; codes at or before it are rejected as replays
+    last_used_step INTEGER,
+    enabled_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Single-use recovery codes, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at TEXT,
+    PRIMARY KEY (user_id, code_hash),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
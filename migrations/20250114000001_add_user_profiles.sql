@@ -0,0 +1,8 @@
+-- Public profile details, editable from settings
+ALTER TABLE users ADD COLUMN display_name TEXT;
+ALTER TABLE users ADD COLUMN bio TEXT;
+ALTER TABLE users ADD COLUMN hide_positions BOOLEAN NOT NULL DEFAULT 0;
+
+-- Profit or loss locked in by selling shares back to the market maker
+-- Settlement at resolution is derived from the remaining shares instead
+ALTER TABLE positions ADD COLUMN realized_pnl REAL NOT NULL DEFAULT 0.0;
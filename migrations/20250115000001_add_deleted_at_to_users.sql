@@ -0,0 +1,3 @@
+-- Set when a user deletes their account; the row stays, anonymized, so that
+-- markets, positions and price history keep referring to a valid user
+ALTER TABLE users ADD COLUMN deleted_at TEXT;
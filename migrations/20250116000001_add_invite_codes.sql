@@ -0,0 +1,19 @@
+-- Invite codes gating signup when registration is invite-only
+CREATE TABLE IF NOT EXISTS invite_codes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    code TEXT NOT NULL UNIQUE,
+    created_by INTEGER NOT NULL,
+    -- Balance given to each account created with the code
+    starting_balance REAL NOT NULL,
+    max_uses INTEGER NOT NULL CHECK(max_uses > 0),
+    uses INTEGER NOT NULL DEFAULT 0,
+    expires_at TEXT,
+    revoked_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+CREATE INDEX idx_invite_codes_created_by ON invite_codes(created_by);
+
+-- The invite a user signed up with, if any
+ALTER TABLE users ADD COLUMN invite_id INTEGER REFERENCES invite_codes(id);
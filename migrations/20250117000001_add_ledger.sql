@@ -0,0 +1,39 @@
+-- Double-entry ledger: every balance movement is a journal whose entries sum to
+-- zero. Accounts are 'user:<id>', 'market:<id>' (market maker), 'fees' and
+-- 'faucet'; users.balance is kept as a cache of the user account's total.
+CREATE TABLE IF NOT EXISTS ledger_journals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK(kind IN ('grant', 'buy', 'sell', 'payout', 'fee', 'forfeit')),
+    market_id INTEGER,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (market_id) REFERENCES markets(id)
+);
+
+CREATE TABLE IF NOT EXISTS ledger_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    journal_id INTEGER NOT NULL,
+    account TEXT NOT NULL,
+    amount REAL NOT NULL,
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals(id)
+);
+
+CREATE INDEX idx_ledger_entries_account ON ledger_entries(account);
+CREATE INDEX idx_ledger_entries_journal ON ledger_entries(journal_id);
+CREATE INDEX idx_ledger_journals_market ON ledger_journals(market_id);
+
+-- Existing balances predate the ledger, so they are opened as a single grant
+-- from the faucet; market maker accounts start empty.
+INSERT INTO ledger_journals (kind, created_at)
+SELECT 'grant', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+WHERE EXISTS (SELECT 1 FROM users WHERE balance != 0);
+
+INSERT INTO ledger_entries (journal_id, account, amount)
+SELECT (SELECT MAX(id) FROM ledger_journals), 'user:' || id, balance
+FROM users
+WHERE balance != 0;
+
+INSERT INTO ledger_entries (journal_id, account, amount)
+SELECT (SELECT MAX(id) FROM ledger_journals), 'faucet', -SUM(balance)
+FROM users
+WHERE balance != 0
+HAVING COUNT(*) > 0;
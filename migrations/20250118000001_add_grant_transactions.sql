@@ -0,0 +1,28 @@
+-- Allowance grants are recorded in the trade history as 'grant' transactions,
+-- which belong to no market. SQLite cannot alter a CHECK constraint or make a
+-- column nullable in place, so the table is rebuilt.
+CREATE TABLE transactions_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    market_id INTEGER,
+    transaction_type TEXT NOT NULL CHECK(transaction_type IN ('buy', 'sell', 'payout', 'grant')),
+    side TEXT CHECK(side IN ('yes', 'no')),
+    shares REAL NOT NULL,
+    price REAL NOT NULL,
+    amount REAL NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (market_id) REFERENCES markets(id)
+);
+
+INSERT INTO transactions_new (id, user_id, market_id, transaction_type, side, shares, price, amount, created_at)
+SELECT id, user_id, market_id, transaction_type, side, shares, price, amount, created_at
+FROM transactions;
+
+DROP TABLE transactions;
+ALTER TABLE transactions_new RENAME TO transactions;
+
+CREATE INDEX idx_transactions_user ON transactions(user_id);
+CREATE INDEX idx_transactions_market ON transactions(market_id);
+-- Finds a user's latest grant when rate-limiting allowance claims
+CREATE INDEX idx_transactions_user_type ON transactions(user_id, transaction_type);
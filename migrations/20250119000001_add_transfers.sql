@@ -0,0 +1,51 @@
+-- User-to-user transfers. Each is posted to the ledger as a 'transfer' journal,
+-- which the kind CHECK on ledger_journals does not allow yet. SQLite cannot
+-- alter a CHECK constraint, so both ledger tables are rebuilt; the entries are
+-- rebuilt too so that no row references the old journals table when it is dropped.
+CREATE TABLE ledger_journals_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK(kind IN ('grant', 'buy', 'sell', 'payout', 'fee', 'forfeit', 'transfer')),
+    market_id INTEGER,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (market_id) REFERENCES markets(id)
+);
+
+INSERT INTO ledger_journals_new (id, kind, market_id, created_at)
+SELECT id, kind, market_id, created_at FROM ledger_journals;
+
+CREATE TABLE ledger_entries_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    journal_id INTEGER NOT NULL,
+    account TEXT NOT NULL,
+    amount REAL NOT NULL,
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals_new(id)
+);
+
+INSERT INTO ledger_entries_new (id, journal_id, account, amount)
+SELECT id, journal_id, account, amount FROM ledger_entries;
+
+DROP TABLE ledger_entries;
+DROP TABLE ledger_journals;
+-- Renaming also updates the foreign key of ledger_entries_new
+ALTER TABLE ledger_journals_new RENAME TO ledger_journals;
+ALTER TABLE ledger_entries_new RENAME TO ledger_entries;
+
+CREATE INDEX idx_ledger_entries_account ON ledger_entries(account);
+CREATE INDEX idx_ledger_entries_journal ON ledger_entries(journal_id);
+CREATE INDEX idx_ledger_journals_market ON ledger_journals(market_id);
+
+CREATE TABLE IF NOT EXISTS transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sender_id INTEGER NOT NULL,
+    recipient_id INTEGER NOT NULL,
+    amount REAL NOT NULL CHECK(amount > 0),
+    memo TEXT,
+    journal_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (sender_id) REFERENCES users(id),
+    FOREIGN KEY (recipient_id) REFERENCES users(id),
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals(id)
+);
+
+CREATE INDEX idx_transfers_sender ON transfers(sender_id, created_at);
+CREATE INDEX idx_transfers_recipient ON transfers(recipient_id);
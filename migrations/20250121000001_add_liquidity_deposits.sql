@@ -0,0 +1,33 @@
+-- Any user can deepen an open market by adding to its subsidy. The deposit
+-- scales b and the cost function state together so prices do not move; the
+-- part of q_yes/q_no that the scaling added is held by no position and kept
+-- in virtual_yes/virtual_no, and cost_base is C(q) at which the market maker
+-- has collected nothing (C(0, 0) = b * ln 2 until the first deposit).
+ALTER TABLE markets ADD COLUMN virtual_yes REAL NOT NULL DEFAULT 0.0;
+ALTER TABLE markets ADD COLUMN virtual_no REAL NOT NULL DEFAULT 0.0;
+ALTER TABLE markets ADD COLUMN cost_base REAL NOT NULL DEFAULT 0.0;
+
+UPDATE markets SET cost_base = liquidity_param * 0.6931471805599453;
+
+CREATE TABLE IF NOT EXISTS liquidity_deposits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    market_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    amount REAL NOT NULL CHECK(amount > 0),
+    liquidity_param REAL NOT NULL,
+    journal_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (market_id) REFERENCES markets(id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals(id)
+);
+
+CREATE INDEX idx_liquidity_deposits_market ON liquidity_deposits(market_id, id);
+CREATE INDEX idx_liquidity_deposits_user ON liquidity_deposits(user_id);
+
+-- The creator's up-front subsidy is each market's first deposit
+INSERT INTO liquidity_deposits (market_id, user_id, amount, liquidity_param, journal_id, created_at)
+SELECT m.id, m.creator_id, m.subsidy, m.liquidity_param, j.id, j.created_at
+FROM markets m
+JOIN ledger_journals j ON j.market_id = m.id AND j.kind = 'subsidy'
+WHERE m.subsidy > 0;
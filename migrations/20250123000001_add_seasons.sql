@@ -0,0 +1,43 @@
+-- Seasons are competitions with their own balances: joining grants the
+-- starting balance into a 'season:S:user:N' ledger account, which pays for
+-- trades in the season's markets. Standings are archived when a season ends.
+CREATE TABLE seasons (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    starting_balance REAL NOT NULL CHECK(starting_balance > 0),
+    starts_at TEXT NOT NULL,
+    ends_at TEXT NOT NULL,
+    archived_at TEXT,
+    created_by INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+CREATE TABLE season_participants (
+    season_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    joined_at TEXT NOT NULL,
+    PRIMARY KEY (season_id, user_id),
+    FOREIGN KEY (season_id) REFERENCES seasons(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_season_participants_user ON season_participants(user_id);
+
+-- Final standings, copied from the live leaderboard when the season ends
+CREATE TABLE season_standings (
+    season_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    rank INTEGER NOT NULL,
+    balance REAL NOT NULL,
+    positions_value REAL NOT NULL,
+    total_value REAL NOT NULL,
+    PRIMARY KEY (season_id, user_id),
+    FOREIGN KEY (season_id) REFERENCES seasons(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+ALTER TABLE markets ADD COLUMN season_id INTEGER REFERENCES seasons(id);
+
+CREATE INDEX idx_markets_season ON markets(season_id);
@@ -0,0 +1,14 @@
+-- When a market was resolved, so that settlements fall into leaderboard windows
+ALTER TABLE markets ADD COLUMN resolved_at TEXT;
+
+-- Markets resolved before this column existed: the time of their last payout,
+-- or failing that the time they closed
+UPDATE markets
+SET resolved_at = COALESCE(
+    (SELECT MAX(t.created_at) FROM transactions t WHERE t.market_id = markets.id AND t.transaction_type = 'payout'),
+    closed_at
+)
+WHERE resolved = 1;
+
+-- Leaderboards aggregate trades of one type over a time window
+CREATE INDEX idx_transactions_type_created ON transactions(transaction_type, created_at);
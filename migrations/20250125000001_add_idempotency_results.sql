@@ -0,0 +1,3 @@
+-- The trade a completed request executed, as JSON, so replays can return it
+-- Rows completed before this migration have no result and replay the redirect alone
+ALTER TABLE idempotency_keys ADD COLUMN result TEXT;
@@ -0,0 +1,2 @@
+-- Optional address for password reset links, used when mail delivery is configured
+ALTER TABLE users ADD COLUMN email TEXT;
@@ -0,0 +1,76 @@
+-- Journal kinds are validated by JournalKind when journals are written and
+-- read, so adding a kind no longer needs a migration. SQLite cannot drop a
+-- CHECK constraint, so the journals table is rebuilt without it, along with
+-- every table that references it so that no row references the old table
+-- when it is dropped.
+CREATE TABLE ledger_journals_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    market_id INTEGER,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (market_id) REFERENCES markets(id)
+);
+
+INSERT INTO ledger_journals_new (id, kind, market_id, created_at)
+SELECT id, kind, market_id, created_at FROM ledger_journals;
+
+CREATE TABLE ledger_entries_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    journal_id INTEGER NOT NULL,
+    account TEXT NOT NULL,
+    amount REAL NOT NULL,
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals_new(id)
+);
+
+INSERT INTO ledger_entries_new (id, journal_id, account, amount)
+SELECT id, journal_id, account, amount FROM ledger_entries;
+
+CREATE TABLE transfers_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sender_id INTEGER NOT NULL,
+    recipient_id INTEGER NOT NULL,
+    amount REAL NOT NULL CHECK(amount > 0),
+    memo TEXT,
+    journal_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (sender_id) REFERENCES users(id),
+    FOREIGN KEY (recipient_id) REFERENCES users(id),
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals_new(id)
+);
+
+INSERT INTO transfers_new (id, sender_id, recipient_id, amount, memo, journal_id, created_at)
+SELECT id, sender_id, recipient_id, amount, memo, journal_id, created_at FROM transfers;
+
+CREATE TABLE liquidity_deposits_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    market_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    amount REAL NOT NULL CHECK(amount > 0),
+    liquidity_param REAL NOT NULL,
+    journal_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (market_id) REFERENCES markets(id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (journal_id) REFERENCES ledger_journals_new(id)
+);
+
+INSERT INTO liquidity_deposits_new (id, market_id, user_id, amount, liquidity_param, journal_id, created_at)
+SELECT id, market_id, user_id, amount, liquidity_param, journal_id, created_at FROM liquidity_deposits;
+
+DROP TABLE liquidity_deposits;
+DROP TABLE transfers;
+DROP TABLE ledger_entries;
+DROP TABLE ledger_journals;
+-- Renaming also updates the foreign keys of the other new tables
+ALTER TABLE ledger_journals_new RENAME TO ledger_journals;
+ALTER TABLE ledger_entries_new RENAME TO ledger_entries;
+ALTER TABLE transfers_new RENAME TO transfers;
+ALTER TABLE liquidity_deposits_new RENAME TO liquidity_deposits;
+
+CREATE INDEX idx_ledger_entries_account ON ledger_entries(account);
+CREATE INDEX idx_ledger_entries_journal ON ledger_entries(journal_id);
+CREATE INDEX idx_ledger_journals_market ON ledger_journals(market_id);
+CREATE INDEX idx_transfers_sender ON transfers(sender_id, created_at);
+CREATE INDEX idx_transfers_recipient ON transfers(recipient_id);
+CREATE INDEX idx_liquidity_deposits_market ON liquidity_deposits(market_id, id);
+CREATE INDEX idx_liquidity_deposits_user ON liquidity_deposits(user_id);
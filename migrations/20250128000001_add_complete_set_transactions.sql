@@ -0,0 +1,31 @@
+-- Minting and redeeming complete sets are recorded in the trade history as
+-- 'mint' and 'redeem' transactions, one per side. Like journal kinds,
+-- transaction types are now validated by TransactionType rather than a CHECK,
+-- which SQLite cannot alter in place, so the table is rebuilt without it.
+CREATE TABLE transactions_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    market_id INTEGER,
+    transaction_type TEXT NOT NULL,
+    side TEXT CHECK(side IN ('yes', 'no')),
+    shares REAL NOT NULL,
+    price REAL NOT NULL,
+    amount REAL NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (market_id) REFERENCES markets(id)
+);
+
+INSERT INTO transactions_new (id, user_id, market_id, transaction_type, side, shares, price, amount, created_at)
+SELECT id, user_id, market_id, transaction_type, side, shares, price, amount, created_at
+FROM transactions;
+
+DROP TABLE transactions;
+ALTER TABLE transactions_new RENAME TO transactions;
+
+CREATE INDEX idx_transactions_user ON transactions(user_id);
+CREATE INDEX idx_transactions_market ON transactions(market_id);
+-- Finds a user's latest grant when rate-limiting allowance claims
+CREATE INDEX idx_transactions_user_type ON transactions(user_id, transaction_type);
+-- Leaderboards aggregate trades of one type over a time window
+CREATE INDEX idx_transactions_type_created ON transactions(transaction_type, created_at);
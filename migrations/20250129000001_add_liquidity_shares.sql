@@ -0,0 +1,7 @@
+-- Providers hold LP shares issued at deposit time against what the market
+-- maker held then, so a late deposit does not share in what the market maker
+-- had already earned. Existing deposits keep the split by amount they were
+-- made under: one share per dollar.
+ALTER TABLE liquidity_deposits ADD COLUMN shares REAL NOT NULL DEFAULT 0.0;
+
+UPDATE liquidity_deposits SET shares = amount;
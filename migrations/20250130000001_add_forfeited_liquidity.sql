@@ -0,0 +1,7 @@
+-- A deleted account forfeits its liquidity deposits: what they would have got
+-- back at resolution goes to the faucet instead. Accounts deleted before this
+-- forfeit theirs too.
+ALTER TABLE liquidity_deposits ADD COLUMN forfeited INTEGER NOT NULL DEFAULT 0;
+
+UPDATE liquidity_deposits SET forfeited = 1
+WHERE user_id IN (SELECT id FROM users WHERE deleted_at IS NOT NULL);
@@ -0,0 +1,93 @@
+//! Administrative subcommands, run instead of the web server when arguments are given
+use crate::Database;
+use crate::domain::{validate_password, Role};
+use crate::repository::{ReconciliationRepository, RepositoryError, UserRepository};
+
+const USAGE: &str = "\
+usage: market [COMMAND]
+
+Without a command, runs the web server.
+
+commands:
+  create-admin <username> [password]
+      Promote an existing user to admin, or create a new admin account.
+      The password may also be given in the ADMIN_PASSWORD environment variable.
+  check [--repair]
+      Recompute outstanding shares, market maker takings and balances and
+      report where they disagree with what is stored. With --repair, reset
+      outstanding shares to the positions held and cached balances to the ledger.";
+
+/// Run the subcommand named by `args` (program name excluded)
+pub async fn run(db: &Database, args: &[String]) -> anyhow::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("create-admin") => {
+            let username = args.get(1).ok_or_else(|| anyhow::anyhow!("missing username\n\n{}", USAGE))?;
+            let password = args.get(2).cloned().or_else(|| std::env::var("ADMIN_PASSWORD").ok());
+            create_admin(db, username, password.as_deref()).await
+        }
+        Some("check") => match args.get(1).map(String::as_str) {
+            None => check(db, false).await,
+            Some("--repair") => check(db, true).await,
+            Some(other) => anyhow::bail!("unknown option '{}'\n\n{}", other, USAGE),
+        },
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => anyhow::bail!("unknown command '{}'\n\n{}", other, USAGE),
+        None => Ok(()),
+    }
+}
+
+async fn create_admin(db: &Database, username: &str, password: Option<&str>) -> anyhow::Result<()> {
+    let user_repo = UserRepository::new(db.pool().clone());
+
+    let user = match user_repo.find_by_username(username).await {
+        Ok(user) => user,
+        Err(RepositoryError::NotFound) => {
+            let password = password
+                .ok_or_else(|| anyhow::anyhow!("user '{}' does not exist; a password is required to create it", username))?;
+            if username.len() < 3 {
+                anyhow::bail!("Username must be at least 3 characters");
+            }
+            validate_password(password).map_err(|e| anyhow::anyhow!(e))?;
+            let password_hash = bcrypt::hash(password, bcrypt::DEFAULT_COST)?;
+            let user = user_repo.create(username, &password_hash).await?;
+            println!("Created user '{}'", username);
+            user
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    user_repo.update_role(user.id, Role::Admin).await?;
+    println!("'{}' is now an admin", username);
+    Ok(())
+}
+
+async fn check(db: &Database, repair: bool) -> anyhow::Result<()> {
+    let reconcile_repo = ReconciliationRepository::new(db.pool().clone());
+    let report = reconcile_repo.check().await?;
+
+    println!(
+        "Checked {} markets, {} positions and {} users",
+        report.markets_checked, report.positions_checked, report.users_checked
+    );
+    for discrepancy in &report.discrepancies {
+        println!("  {}", discrepancy);
+    }
+    if report.is_clean() {
+        println!("No discrepancies found");
+        return Ok(());
+    }
+
+    if repair {
+        let repaired = reconcile_repo.repair(&report).await?;
+        println!("Repaired {} of {} discrepancies", repaired, report.discrepancies.len());
+        if repaired == report.discrepancies.len() {
+            return Ok(());
+        }
+        anyhow::bail!("{} discrepancies need to be fixed by hand", report.discrepancies.len() - repaired);
+    }
+
+    anyhow::bail!("found {} discrepancies", report.discrepancies.len())
+}
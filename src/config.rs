@@ -0,0 +1,256 @@
+//! Runtime configuration read from environment variables
+use crate::domain::{
+    normalize_email, AllowancePeriod, AllowancePolicy, LoginThrottlePolicy, RegistrationMode, Role, TransferLimits,
+    TwoFactorPolicy, DEFAULT_STARTING_BALANCE,
+};
+use std::time::Duration;
+
+/// Where login sessions are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStoreKind {
+    /// In-process only; every restart logs all users out
+    Memory,
+    /// The `sessions` table of the application database
+    Sqlite,
+}
+
+impl std::fmt::Display for SessionStoreKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionStoreKind::Memory => write!(f, "memory"),
+            SessionStoreKind::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+impl std::str::FromStr for SessionStoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "memory" => Ok(SessionStoreKind::Memory),
+            "sqlite" => Ok(SessionStoreKind::Sqlite),
+            _ => Err(format!("Invalid session store: {} (expected 'memory' or 'sqlite')", s)),
+        }
+    }
+}
+
+/// OpenID Connect provider used for single sign-on
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; provider metadata is discovered under `/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    /// Omitted for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+    /// This app's callback, e.g. `http://localhost:3000/auth/oidc/callback`
+    pub redirect_url: String,
+    pub scopes: String,
+    /// Create an account on first login; otherwise identities must be linked from settings
+    pub auto_provision: bool,
+    pub starting_balance: f64,
+}
+
+impl OidcConfig {
+    /// Configured when `OIDC_ISSUER_URL` is set
+    fn from_env(default_starting_balance: f64) -> anyhow::Result<Option<Self>> {
+        let Ok(issuer_url) = std::env::var("OIDC_ISSUER_URL") else {
+            return Ok(None);
+        };
+        let client_id = std::env::var("OIDC_CLIENT_ID")
+            .map_err(|_| anyhow::anyhow!("OIDC_CLIENT_ID is required when OIDC_ISSUER_URL is set"))?;
+        let redirect_url = std::env::var("OIDC_REDIRECT_URL")
+            .map_err(|_| anyhow::anyhow!("OIDC_REDIRECT_URL is required when OIDC_ISSUER_URL is set"))?;
+        Ok(Some(Self {
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok().filter(|s| !s.is_empty()),
+            redirect_url,
+            scopes: env_or("OIDC_SCOPES", "openid profile email"),
+            auto_provision: parse_env("OIDC_AUTO_PROVISION", true)?,
+            starting_balance: parse_env("OIDC_STARTING_BALANCE", default_starting_balance)?,
+        }))
+    }
+}
+
+/// Outgoing mail, handed to a sendmail-compatible program
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    /// Sender address of every message
+    pub from: String,
+    /// Program that reads a message on stdin and delivers it, e.g. `/usr/sbin/sendmail` or msmtp
+    pub sendmail_path: String,
+}
+
+impl MailConfig {
+    /// Configured when `MAIL_FROM` is set
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(from) = std::env::var("MAIL_FROM").ok().filter(|s| !s.is_empty()) else {
+            return Ok(None);
+        };
+        let from = normalize_email(&from)
+            .ok()
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("Invalid MAIL_FROM: {}", from))?;
+        Ok(Some(Self {
+            from,
+            sendmail_path: env_or("SENDMAIL_PATH", "/usr/sbin/sendmail"),
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub database_url: String,
+    pub host: String,
+    pub port: u16,
+    /// Address users reach the app at, without a trailing slash; links sent outside the app start with it
+    pub public_url: String,
+    /// Outgoing mail, if any; enables password resets by email
+    pub mail: Option<MailConfig>,
+    pub session_store: SessionStoreKind,
+    /// How often expired sessions are purged from a persistent store
+    pub session_cleanup_interval: Duration,
+    /// Failed-login throttling applied to each username
+    pub login_throttle_per_user: LoginThrottlePolicy,
+    /// Failed-login throttling applied to each client IP
+    pub login_throttle_per_ip: LoginThrottlePolicy,
+    /// Take the client IP from `X-Forwarded-For`; only safe behind a reverse proxy that sets it
+    pub trust_forwarded_for: bool,
+    /// Who must enroll in two-factor authentication
+    pub two_factor: TwoFactorPolicy,
+    /// Single sign-on provider, if any
+    pub oidc: Option<OidcConfig>,
+    /// Allow signup and login with a username and password
+    pub password_login: bool,
+    /// Who may sign up with a username and password
+    pub registration: RegistrationMode,
+    /// Balance of accounts created without an invite that sets one
+    pub starting_balance: f64,
+    /// Let every user create invite codes, not only admins
+    pub user_invites: bool,
+    /// Recurring grants and top-ups for users who run low
+    pub allowance: AllowancePolicy,
+    /// How much users may send each other
+    pub transfer_limits: TransferLimits,
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let starting_balance = parse_env("STARTING_BALANCE", DEFAULT_STARTING_BALANCE)?;
+        if !starting_balance.is_finite() || starting_balance < 0.0 {
+            anyhow::bail!("Invalid STARTING_BALANCE: cannot be negative");
+        }
+        let oidc = OidcConfig::from_env(starting_balance)?;
+        let password_login = parse_env("PASSWORD_LOGIN", true)?;
+        if !password_login && oidc.is_none() {
+            anyhow::bail!("PASSWORD_LOGIN=false requires single sign-on (OIDC_ISSUER_URL) to be configured");
+        }
+
+        let port = parse_env("PORT", 3000)?;
+        let public_url = env_or("PUBLIC_URL", &format!("http://localhost:{}", port));
+        if !public_url.starts_with("http://") && !public_url.starts_with("https://") {
+            anyhow::bail!("Invalid PUBLIC_URL: must start with http:// or https://");
+        }
+
+        let session_cleanup_secs: u64 = parse_env("SESSION_CLEANUP_INTERVAL_SECS", 300)?;
+        if session_cleanup_secs == 0 {
+            anyhow::bail!("Invalid SESSION_CLEANUP_INTERVAL_SECS: must be at least 1");
+        }
+
+        Ok(Self {
+            database_url: env_or("DATABASE_URL", "sqlite:market.db"),
+            host: env_or("HOST", "127.0.0.1"),
+            port,
+            public_url: public_url.trim_end_matches('/').to_string(),
+            mail: MailConfig::from_env()?,
+            session_store: parse_env("SESSION_STORE", SessionStoreKind::Sqlite)?,
+            session_cleanup_interval: Duration::from_secs(session_cleanup_secs),
+            login_throttle_per_user: login_throttle("LOGIN_MAX_FAILURES", 5)?,
+            login_throttle_per_ip: login_throttle("LOGIN_MAX_FAILURES_PER_IP", 20)?,
+            trust_forwarded_for: parse_env("TRUST_FORWARDED_FOR", false)?,
+            two_factor: TwoFactorPolicy {
+                required_role: required_role(&env_or("REQUIRE_2FA_ROLE", "none"))?,
+                required_for_oracles: parse_env("REQUIRE_2FA_FOR_ORACLES", false)?,
+            },
+            oidc,
+            password_login,
+            registration: parse_env("REGISTRATION_MODE", RegistrationMode::Open)?,
+            starting_balance,
+            user_invites: parse_env("ALLOW_USER_INVITES", true)?,
+            allowance: allowance()?,
+            transfer_limits: TransferLimits {
+                max_amount: parse_env("TRANSFER_MAX_AMOUNT", 1000.0)?,
+                daily_limit: parse_env("TRANSFER_DAILY_LIMIT", 5000.0)?,
+            },
+        })
+    }
+
+    pub fn bind_addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// A throttle policy whose failure threshold is read from `max_failures_var`;
+/// lockout length and delays are shared between scopes
+fn login_throttle(max_failures_var: &str, default_max_failures: u32) -> anyhow::Result<LoginThrottlePolicy> {
+    let max_failures = parse_env(max_failures_var, default_max_failures)?;
+    if max_failures == 0 {
+        anyhow::bail!("Invalid {}: must be at least 1", max_failures_var);
+    }
+    Ok(LoginThrottlePolicy {
+        max_failures,
+        lockout: chrono::Duration::seconds(parse_env("LOGIN_LOCKOUT_SECS", 900)?),
+        base_delay: chrono::Duration::milliseconds(parse_env("LOGIN_BASE_DELAY_MS", 1000)?),
+        max_delay: chrono::Duration::milliseconds(parse_env("LOGIN_MAX_DELAY_MS", 30_000)?),
+    })
+}
+
+/// Allowance settings; `ALLOWANCE_PERIOD` is `none`, `daily` or `weekly`
+fn allowance() -> anyhow::Result<AllowancePolicy> {
+    let period = env_or("ALLOWANCE_PERIOD", "none");
+    let period = if period.eq_ignore_ascii_case("none") {
+        None
+    } else {
+        Some(period.parse::<AllowancePeriod>().map_err(|e| anyhow::anyhow!("Invalid ALLOWANCE_PERIOD: {}", e))?)
+    };
+    let amount: f64 = parse_env("ALLOWANCE_AMOUNT", 100.0)?;
+    let minimum_balance: f64 = parse_env("ALLOWANCE_MIN_BALANCE", 0.0)?;
+    if !amount.is_finite() || amount < 0.0 || !minimum_balance.is_finite() || minimum_balance < 0.0 {
+        anyhow::bail!("Invalid ALLOWANCE_AMOUNT or ALLOWANCE_MIN_BALANCE: cannot be negative");
+    }
+    Ok(AllowancePolicy {
+        period,
+        amount,
+        minimum_balance,
+        automatic: parse_env("ALLOWANCE_AUTOMATIC", false)?,
+    })
+}
+
+/// `none`, or the minimum role that must use two-factor authentication
+fn required_role(value: &str) -> anyhow::Result<Option<Role>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Invalid REQUIRE_2FA_ROLE: {}", e))
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn parse_env<T>(name: &str, default: T) -> anyhow::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
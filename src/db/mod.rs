@@ -1,3 +1,7 @@
+mod session_store;
+
+pub use session_store::SqliteSessionStore;
+
 use sqlx::sqlite::SqlitePool;
 
 #[derive(Clone)]
@@ -11,6 +15,22 @@ impl Database {
         Ok(Self { pool })
     }
 
+    /// A migrated database held in memory, for tests
+    #[cfg(test)]
+    pub async fn in_memory() -> Self {
+        // Every connection to `sqlite::memory:` opens a database of its own, so keep exactly one
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .expect("in-memory database");
+        let db = Self { pool };
+        db.run_migrations().await.expect("migrations");
+        db
+    }
+
     pub async fn run_migrations(&self) -> Result<(), sqlx::Error> {
         sqlx::migrate!("./migrations").run(&self.pool).await?;
         Ok(())
@@ -0,0 +1,118 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How often the allowance can be claimed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllowancePeriod {
+    Daily,
+    Weekly,
+}
+
+impl AllowancePeriod {
+    pub fn duration(&self) -> Duration {
+        match self {
+            AllowancePeriod::Daily => Duration::days(1),
+            AllowancePeriod::Weekly => Duration::weeks(1),
+        }
+    }
+}
+
+impl std::fmt::Display for AllowancePeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllowancePeriod::Daily => write!(f, "daily"),
+            AllowancePeriod::Weekly => write!(f, "weekly"),
+        }
+    }
+}
+
+impl std::str::FromStr for AllowancePeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "daily" => Ok(AllowancePeriod::Daily),
+            "weekly" => Ok(AllowancePeriod::Weekly),
+            _ => Err(format!("Invalid allowance period: {} (expected 'daily' or 'weekly')", s)),
+        }
+    }
+}
+
+/// Recurring play money granted so that users who go broke can keep trading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AllowancePolicy {
+    /// `None` disables the allowance
+    pub period: Option<AllowancePeriod>,
+    /// Granted on each claim
+    pub amount: f64,
+    /// A claim tops the balance up to at least this much, even when that exceeds `amount`
+    pub minimum_balance: f64,
+    /// Grant the allowance to every user from a background job instead of waiting for claims
+    pub automatic: bool,
+}
+
+impl AllowancePolicy {
+    /// Amount a claim grants to a user holding `balance`
+    pub fn grant_for(&self, balance: f64) -> f64 {
+        self.amount.max(self.minimum_balance - balance).max(0.0)
+    }
+
+    /// Earliest time the allowance can be claimed again after a claim at `last_claim`
+    pub fn next_claim_at(&self, last_claim: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.period.map(|period| last_claim + period.duration())
+    }
+
+    /// Whether a user who last claimed at `last_claim` may claim at `now`
+    pub fn can_claim(&self, last_claim: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        match (self.period, last_claim) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(period), Some(last)) => now >= last + period.duration(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> AllowancePolicy {
+        AllowancePolicy { period: Some(AllowancePeriod::Daily), amount: 50.0, minimum_balance: 200.0, automatic: false }
+    }
+
+    #[test]
+    fn test_grant_tops_up_to_minimum() {
+        let policy = policy();
+        assert_eq!(policy.grant_for(1000.0), 50.0);
+        assert_eq!(policy.grant_for(180.0), 50.0);
+        assert_eq!(policy.grant_for(0.0), 200.0);
+
+        let top_up_only = AllowancePolicy { amount: 0.0, ..policy };
+        assert_eq!(top_up_only.grant_for(500.0), 0.0);
+        assert_eq!(top_up_only.grant_for(25.0), 175.0);
+    }
+
+    #[test]
+    fn test_claims_rate_limited_per_period() {
+        let policy = policy();
+        let now = Utc::now();
+        assert!(policy.can_claim(None, now));
+        assert!(!policy.can_claim(Some(now - Duration::hours(23)), now));
+        assert!(policy.can_claim(Some(now - Duration::hours(24)), now));
+        assert_eq!(policy.next_claim_at(now), Some(now + Duration::days(1)));
+
+        let disabled = AllowancePolicy { period: None, ..policy };
+        assert!(!disabled.can_claim(None, now));
+        assert_eq!(disabled.next_claim_at(now), None);
+    }
+
+    #[test]
+    fn test_period_roundtrip() {
+        for period in [AllowancePeriod::Daily, AllowancePeriod::Weekly] {
+            assert_eq!(period.to_string().parse::<AllowancePeriod>(), Ok(period));
+        }
+        assert_eq!("Weekly".parse::<AllowancePeriod>(), Ok(AllowancePeriod::Weekly));
+        assert!("monthly".parse::<AllowancePeriod>().is_err());
+    }
+}
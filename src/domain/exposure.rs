@@ -0,0 +1,145 @@
+//! How much the automated market maker has won or lost on each market
+//!
+//! Everything follows from the LMSR cost function: the market maker has taken
+//! in C(q) less its cost base (C(0, 0) until liquidity is added) from traders,
+//! plus $1 per complete set minted and not redeemed, and owes $1 for every
+//! share of the winning outcome held in a position.
+use serde::Serialize;
+use crate::domain::{Market, MarketId, MarketSide, UserId};
+
+/// The market maker's takings and liabilities on one market
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MarketMakerExposure {
+    pub market_id: MarketId,
+    pub question: String,
+    pub creator_id: UserId,
+    pub liquidity_param: f64,
+    pub subsidy: f64,
+    pub resolved: bool,
+    pub outcome: Option<bool>,
+    /// Net cost traders have paid in, see `Market::collected`, plus complete sets outstanding
+    pub collected: f64,
+    /// Owed to holders if YES wins, one dollar per outstanding YES share
+    pub liability_yes: f64,
+    /// Owed to holders if NO wins
+    pub liability_no: f64,
+    /// Profit (or loss, when negative) if YES wins
+    pub pnl_if_yes: f64,
+    /// Profit (or loss, when negative) if NO wins
+    pub pnl_if_no: f64,
+    /// Most the market maker can ever lose on this market, see `Market::max_loss`
+    pub max_loss: f64,
+    /// Profit (or loss) once the market has resolved
+    pub realized_pnl: Option<f64>,
+}
+
+impl MarketMakerExposure {
+    pub fn for_market(market: &Market) -> Self {
+        let collected = market.collected() + market.complete_sets;
+        let liability_yes = market.outstanding_shares(MarketSide::Yes);
+        let liability_no = market.outstanding_shares(MarketSide::No);
+        let pnl_if_yes = collected - liability_yes;
+        let pnl_if_no = collected - liability_no;
+        let realized_pnl = match (market.resolved, market.outcome) {
+            (true, Some(true)) => Some(pnl_if_yes),
+            (true, Some(false)) => Some(pnl_if_no),
+            _ => None,
+        };
+
+        Self {
+            market_id: market.id,
+            question: market.question.clone(),
+            creator_id: market.creator_id,
+            liquidity_param: market.liquidity_param,
+            subsidy: market.subsidy,
+            resolved: market.resolved,
+            outcome: market.outcome,
+            collected,
+            liability_yes,
+            liability_no,
+            pnl_if_yes,
+            pnl_if_no,
+            max_loss: market.max_loss(),
+            realized_pnl,
+        }
+    }
+
+    /// Loss if the worse outcome for the market maker wins, zero if it profits either way
+    pub fn worst_case_loss(&self) -> f64 {
+        (-self.pnl_if_yes.min(self.pnl_if_no)).max(0.0)
+    }
+}
+
+/// Sums over a set of markets
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExposureTotals {
+    pub markets: usize,
+    pub collected: f64,
+    /// Worst-case loss summed over the markets still open
+    pub open_worst_case_loss: f64,
+    /// Bound on that loss summed over the markets still open
+    pub open_max_loss: f64,
+    /// Realized profit (or loss) summed over the resolved markets
+    pub realized_pnl: f64,
+}
+
+impl ExposureTotals {
+    pub fn of(exposures: &[MarketMakerExposure]) -> Self {
+        exposures.iter().fold(Self::default(), |mut totals, exposure| {
+            totals.markets += 1;
+            totals.collected += exposure.collected;
+            match exposure.realized_pnl {
+                Some(pnl) => totals.realized_pnl += pnl,
+                None if !exposure.resolved => {
+                    totals.open_worst_case_loss += exposure.worst_case_loss();
+                    totals.open_max_loss += exposure.max_loss;
+                }
+                None => {}
+            }
+            totals
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::LmsrPricing;
+    use chrono::Utc;
+
+    fn market(q_yes: f64, q_no: f64) -> Market {
+        let mut market = Market::new_lmsr(1, "Q?".to_string(), None, 1, None, Utc::now(), 100.0, Utc::now());
+        market.q_yes = q_yes;
+        market.q_no = q_no;
+        market
+    }
+
+    #[test]
+    fn test_exposure_of_open_market() {
+        let cost = LmsrPricing::calculate_buy_cost(0.0, 0.0, 50.0, MarketSide::Yes, 100.0).unwrap();
+        let exposure = MarketMakerExposure::for_market(&market(50.0, 0.0));
+
+        assert!((exposure.collected - cost).abs() < 1e-9);
+        assert!((exposure.pnl_if_yes - (cost - 50.0)).abs() < 1e-9);
+        assert!((exposure.pnl_if_no - cost).abs() < 1e-9);
+        assert!((exposure.worst_case_loss() - (50.0 - cost)).abs() < 1e-9);
+        assert!(exposure.worst_case_loss() <= exposure.max_loss);
+        assert_eq!(exposure.realized_pnl, None);
+    }
+
+    #[test]
+    fn test_exposure_of_resolved_market() {
+        let mut resolved = market(50.0, 20.0);
+        resolved.resolved = true;
+        resolved.outcome = Some(false);
+        let exposure = MarketMakerExposure::for_market(&resolved);
+        assert_eq!(exposure.realized_pnl, Some(exposure.pnl_if_no));
+
+        let open = MarketMakerExposure::for_market(&market(50.0, 0.0));
+        let totals = ExposureTotals::of(&[exposure.clone(), open.clone()]);
+        assert_eq!(totals.markets, 2);
+        assert!((totals.realized_pnl - exposure.pnl_if_no).abs() < 1e-9);
+        assert!((totals.open_worst_case_loss - open.worst_case_loss()).abs() < 1e-9);
+        assert!((totals.open_max_loss - open.max_loss).abs() < 1e-9);
+    }
+}
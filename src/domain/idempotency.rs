@@ -0,0 +1,111 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use crate::domain::{TradeQuote, UserId};
+
+/// Header carrying the client-chosen key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Response header carrying the executed trade as JSON, on the original response and on replays
+pub const TRADE_RESULT_HEADER: &str = "Trade-Result";
+
+/// Longest key accepted from a client
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// How long a key is remembered; after this a reused key starts a new request
+pub fn idempotency_retention() -> Duration {
+    Duration::hours(24)
+}
+
+/// Fresh random key, embedded in HTML forms so a double submit is recognised
+pub fn generate_idempotency_key() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Reject keys that are empty, too long or contain anything but visible ASCII
+pub fn validate_idempotency_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(format!(
+            "Idempotency key must be between 1 and {} characters",
+            MAX_IDEMPOTENCY_KEY_LEN
+        ));
+    }
+    if !key.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err("Idempotency key may only contain visible ASCII characters".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdempotencyStatus {
+    /// The first request with this key is still executing
+    Pending,
+    Completed,
+}
+
+impl std::fmt::Display for IdempotencyStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdempotencyStatus::Pending => write!(f, "pending"),
+            IdempotencyStatus::Completed => write!(f, "completed"),
+        }
+    }
+}
+
+impl std::str::FromStr for IdempotencyStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(IdempotencyStatus::Pending),
+            "completed" => Ok(IdempotencyStatus::Completed),
+            _ => Err(format!("Invalid idempotency status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    pub user_id: UserId,
+    pub key: String,
+    pub fingerprint: String,
+    pub status: IdempotencyStatus,
+    pub response: Option<String>,
+    /// The trade the completed request executed
+    pub result: Option<TradeQuote>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl IdempotencyRecord {
+    /// Whether a replay describes the same request as the one that claimed the key
+    pub fn matches(&self, fingerprint: &str) -> bool {
+        self.fingerprint == fingerprint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_idempotency_key() {
+        assert!(validate_idempotency_key("3f2b9c1e-7a4d-4e8b-9f00-123456789abc").is_ok());
+        assert!(validate_idempotency_key("").is_err());
+        assert!(validate_idempotency_key("has space").is_err());
+        assert!(validate_idempotency_key(&"k".repeat(MAX_IDEMPOTENCY_KEY_LEN)).is_ok());
+        assert!(validate_idempotency_key(&"k".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1)).is_err());
+        assert!(validate_idempotency_key(&generate_idempotency_key()).is_ok());
+        assert_ne!(generate_idempotency_key(), generate_idempotency_key());
+    }
+
+    #[test]
+    fn test_status_roundtrip() {
+        for status in [IdempotencyStatus::Pending, IdempotencyStatus::Completed] {
+            assert_eq!(status.to_string().parse::<IdempotencyStatus>().unwrap(), status);
+        }
+        assert!("done".parse::<IdempotencyStatus>().is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Invite codes and the registration modes that require them
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::domain::UserId;
+
+pub type InviteId = i64;
+
+/// Characters used in generated codes, without easily confused ones (0/O, 1/I/L)
+const INVITE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+
+/// Length of a generated code, excluding the separating dash
+const INVITE_CODE_LEN: usize = 10;
+
+/// Most signups a regular user's invite can admit
+pub const MAX_USER_INVITE_USES: i64 = 5;
+
+/// Longest a regular user's invite stays valid
+pub const MAX_USER_INVITE_DAYS: i64 = 30;
+
+/// Who may create an account through the signup form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegistrationMode {
+    /// Anyone; an invite code is optional and may carry a different starting balance
+    #[default]
+    Open,
+    /// Only holders of a valid invite code
+    InviteOnly,
+    /// Nobody
+    Closed,
+}
+
+impl std::fmt::Display for RegistrationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistrationMode::Open => write!(f, "open"),
+            RegistrationMode::InviteOnly => write!(f, "invite-only"),
+            RegistrationMode::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+impl std::str::FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "open" => Ok(RegistrationMode::Open),
+            "invite-only" | "invite_only" | "invite" => Ok(RegistrationMode::InviteOnly),
+            "closed" => Ok(RegistrationMode::Closed),
+            _ => Err(format!("Invalid registration mode: {} (expected 'open', 'invite-only' or 'closed')", s)),
+        }
+    }
+}
+
+/// A code that admits up to `max_uses` signups until it expires or is revoked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteCode {
+    pub id: InviteId,
+    pub code: String,
+    pub created_by: UserId,
+    /// Balance of accounts created with this code
+    pub starting_balance: f64,
+    pub max_uses: i64,
+    pub uses: i64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl InviteCode {
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.uses < self.max_uses && self.expires_at.is_none_or(|at| at > now)
+    }
+
+    /// Why the code can no longer be used, or "active"
+    pub fn status(&self, now: DateTime<Utc>) -> &'static str {
+        if self.revoked_at.is_some() {
+            "revoked"
+        } else if self.uses >= self.max_uses {
+            "used up"
+        } else if self.expires_at.is_some_and(|at| at <= now) {
+            "expired"
+        } else {
+            "active"
+        }
+    }
+}
+
+/// Terms requested for a new invite, before limits are applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InviteTerms {
+    pub max_uses: i64,
+    /// `None` never expires
+    pub valid_days: Option<i64>,
+    /// `None` uses the configured default
+    pub starting_balance: Option<f64>,
+}
+
+impl InviteTerms {
+    /// Check the terms, holding regular users to the limits for their invites
+    ///
+    /// Only admins may set a starting balance or issue invites without an expiry.
+    pub fn validate(&self, is_admin: bool) -> Result<(), String> {
+        if self.max_uses < 1 {
+            return Err("An invite must allow at least one signup".to_string());
+        }
+        if let Some(days) = self.valid_days {
+            if days < 1 {
+                return Err("An invite must be valid for at least one day".to_string());
+            }
+        }
+        if let Some(balance) = self.starting_balance {
+            if !balance.is_finite() || balance < 0.0 {
+                return Err("Starting balance cannot be negative".to_string());
+            }
+        }
+        if is_admin {
+            return Ok(());
+        }
+
+        if self.starting_balance.is_some() {
+            return Err("Only admins can set the starting balance of an invite".to_string());
+        }
+        if self.max_uses > MAX_USER_INVITE_USES {
+            return Err(format!("Invites can admit at most {} signups", MAX_USER_INVITE_USES));
+        }
+        match self.valid_days {
+            Some(days) if days <= MAX_USER_INVITE_DAYS => Ok(()),
+            _ => Err(format!("Invites can be valid for at most {} days", MAX_USER_INVITE_DAYS)),
+        }
+    }
+
+    pub fn expires_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.valid_days.map(|days| now + Duration::days(days))
+    }
+}
+
+/// A new random invite code, e.g. `K7QF3-MZ9TA`
+pub fn generate_invite_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..INVITE_CODE_LEN)
+        .map(|_| INVITE_ALPHABET[rng.gen_range(0..INVITE_ALPHABET.len())] as char)
+        .collect();
+    format!("{}-{}", &chars[..INVITE_CODE_LEN / 2], &chars[INVITE_CODE_LEN / 2..])
+}
+
+/// Canonical form of a code as typed, ignoring case and surrounding spaces
+pub fn normalize_invite_code(input: &str) -> String {
+    input.trim().to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(now: DateTime<Utc>) -> InviteCode {
+        InviteCode {
+            id: 1,
+            code: generate_invite_code(),
+            created_by: 1,
+            starting_balance: 500.0,
+            max_uses: 2,
+            uses: 0,
+            expires_at: Some(now + Duration::days(1)),
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    #[test]
+    fn test_invite_usable() {
+        let now = Utc::now();
+        let mut code = invite(now);
+        assert!(code.is_usable(now));
+        assert_eq!(code.status(now), "active");
+        assert!(!code.is_usable(now + Duration::days(2)));
+        assert_eq!(code.status(now + Duration::days(2)), "expired");
+
+        code.uses = 2;
+        assert!(!code.is_usable(now));
+        assert_eq!(code.status(now), "used up");
+
+        code.uses = 0;
+        code.revoked_at = Some(now);
+        assert!(!code.is_usable(now));
+        assert_eq!(code.status(now), "revoked");
+    }
+
+    #[test]
+    fn test_invite_terms() {
+        let user_terms = InviteTerms { max_uses: 1, valid_days: Some(7), starting_balance: None };
+        assert!(user_terms.validate(false).is_ok());
+        assert!(InviteTerms { max_uses: MAX_USER_INVITE_USES + 1, ..user_terms }.validate(false).is_err());
+        assert!(InviteTerms { valid_days: None, ..user_terms }.validate(false).is_err());
+        assert!(InviteTerms { starting_balance: Some(5000.0), ..user_terms }.validate(false).is_err());
+
+        let admin_terms = InviteTerms { max_uses: 100, valid_days: None, starting_balance: Some(5000.0) };
+        assert!(admin_terms.validate(true).is_ok());
+        assert!(InviteTerms { starting_balance: Some(-1.0), ..admin_terms }.validate(true).is_err());
+        assert!(InviteTerms { max_uses: 0, ..admin_terms }.validate(true).is_err());
+    }
+
+    #[test]
+    fn test_invite_code_format() {
+        let code = generate_invite_code();
+        assert_eq!(code.len(), INVITE_CODE_LEN + 1);
+        assert_eq!(normalize_invite_code(&format!("  {}\n", code.to_lowercase())), code);
+        assert_eq!("invite-only".parse::<RegistrationMode>(), Ok(RegistrationMode::InviteOnly));
+        assert_eq!(RegistrationMode::InviteOnly.to_string(), "invite-only");
+        assert!("maybe".parse::<RegistrationMode>().is_err());
+    }
+}
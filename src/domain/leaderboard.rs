@@ -0,0 +1,216 @@
+//! Rankings of traders by profit, portfolio value, volume and calibration
+//!
+//! Only main-balance trading counts: season markets have their own standings.
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::UserId;
+
+/// Most entries a leaderboard shows
+pub const MAX_LEADERBOARD_SIZE: i64 = 100;
+
+/// Fewest trades in resolved markets for a calibration score to be ranked
+pub const MIN_CALIBRATION_TRADES: i64 = 5;
+
+/// What a leaderboard ranks traders by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardMetric {
+    /// Gains from sales, merged sets and settlement of shares held at resolution
+    #[default]
+    Profit,
+    /// Cash plus open positions marked to market; always as of now
+    Portfolio,
+    /// Total traded, bought plus sold, including complete sets
+    Volume,
+    /// Share-weighted Brier score of the prices paid in resolved markets
+    Calibration,
+}
+
+impl LeaderboardMetric {
+    pub const ALL: [LeaderboardMetric; 4] = [
+        LeaderboardMetric::Profit,
+        LeaderboardMetric::Portfolio,
+        LeaderboardMetric::Volume,
+        LeaderboardMetric::Calibration,
+    ];
+
+    /// Brier scores are errors, so the best calibrated trader has the lowest
+    pub fn higher_is_better(&self) -> bool {
+        *self != LeaderboardMetric::Calibration
+    }
+
+    /// Whether the metric is restricted to a time window
+    pub fn is_windowed(&self) -> bool {
+        *self != LeaderboardMetric::Portfolio
+    }
+}
+
+impl std::fmt::Display for LeaderboardMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LeaderboardMetric::Profit => write!(f, "profit"),
+            LeaderboardMetric::Portfolio => write!(f, "portfolio"),
+            LeaderboardMetric::Volume => write!(f, "volume"),
+            LeaderboardMetric::Calibration => write!(f, "calibration"),
+        }
+    }
+}
+
+impl std::str::FromStr for LeaderboardMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "profit" => Ok(LeaderboardMetric::Profit),
+            "portfolio" => Ok(LeaderboardMetric::Portfolio),
+            "volume" => Ok(LeaderboardMetric::Volume),
+            "calibration" => Ok(LeaderboardMetric::Calibration),
+            _ => Err(format!("Invalid leaderboard metric: {}", s)),
+        }
+    }
+}
+
+/// Period of trading a leaderboard covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardWindow {
+    Week,
+    Month,
+    #[default]
+    All,
+}
+
+impl LeaderboardWindow {
+    pub const ALL: [LeaderboardWindow; 3] = [LeaderboardWindow::Week, LeaderboardWindow::Month, LeaderboardWindow::All];
+
+    /// Start of the window ending at `now`, or `None` for all time
+    pub fn since(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            LeaderboardWindow::Week => Some(now - Duration::days(7)),
+            LeaderboardWindow::Month => Some(now - Duration::days(30)),
+            LeaderboardWindow::All => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LeaderboardWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LeaderboardWindow::Week => write!(f, "week"),
+            LeaderboardWindow::Month => write!(f, "month"),
+            LeaderboardWindow::All => write!(f, "all"),
+        }
+    }
+}
+
+impl std::str::FromStr for LeaderboardWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "week" => Ok(LeaderboardWindow::Week),
+            "month" => Ok(LeaderboardWindow::Month),
+            "all" => Ok(LeaderboardWindow::All),
+            _ => Err(format!("Invalid leaderboard window: {}", s)),
+        }
+    }
+}
+
+/// One trader's place on a leaderboard
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    /// 1 for the leader; traders with the same score share a rank
+    pub rank: i64,
+    pub user_id: UserId,
+    pub username: String,
+    pub score: f64,
+    /// Trades the score is based on; open positions for portfolio value
+    pub trades: i64,
+}
+
+impl LeaderboardEntry {
+    pub fn new(user_id: UserId, username: String, score: f64, trades: i64) -> Self {
+        Self {
+            rank: 0,
+            user_id,
+            username,
+            score,
+            trades,
+        }
+    }
+}
+
+/// Order entries best first for the metric, keep the top `limit` and number their ranks
+pub fn rank_entries(
+    metric: LeaderboardMetric,
+    mut entries: Vec<LeaderboardEntry>,
+    limit: usize,
+) -> Vec<LeaderboardEntry> {
+    entries.sort_by(|a, b| {
+        let by_score = if metric.higher_is_better() {
+            b.score.total_cmp(&a.score)
+        } else {
+            a.score.total_cmp(&b.score)
+        };
+        by_score.then(a.user_id.cmp(&b.user_id))
+    });
+    entries.truncate(limit);
+
+    let mut previous: Option<(f64, i64)> = None;
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.rank = match previous {
+            Some((score, rank)) if score == entry.score => rank,
+            _ => i as i64 + 1,
+        };
+        previous = Some((entry.score, entry.rank));
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        for metric in LeaderboardMetric::ALL {
+            assert_eq!(metric.to_string().parse::<LeaderboardMetric>(), Ok(metric));
+        }
+        for window in LeaderboardWindow::ALL {
+            assert_eq!(window.to_string().parse::<LeaderboardWindow>(), Ok(window));
+        }
+        assert!("accuracy".parse::<LeaderboardMetric>().is_err());
+        assert!("year".parse::<LeaderboardWindow>().is_err());
+    }
+
+    #[test]
+    fn test_window_since() {
+        let now = Utc::now();
+        assert_eq!(LeaderboardWindow::Week.since(now), Some(now - Duration::days(7)));
+        assert_eq!(LeaderboardWindow::Month.since(now), Some(now - Duration::days(30)));
+        assert_eq!(LeaderboardWindow::All.since(now), None);
+    }
+
+    #[test]
+    fn test_rank_entries() {
+        let entries = || {
+            vec![
+                LeaderboardEntry::new(1, "a".to_string(), 0.20, 5),
+                LeaderboardEntry::new(2, "b".to_string(), 0.10, 8),
+                LeaderboardEntry::new(3, "c".to_string(), 0.20, 6),
+                LeaderboardEntry::new(4, "d".to_string(), 0.05, 9),
+            ]
+        };
+
+        let ranks = |metric, limit| {
+            rank_entries(metric, entries(), limit)
+                .iter()
+                .map(|e| (e.user_id, e.rank))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranks(LeaderboardMetric::Profit, 10), vec![(1, 1), (3, 1), (2, 3), (4, 4)]);
+        // Lower Brier scores rank first
+        assert_eq!(ranks(LeaderboardMetric::Calibration, 10), vec![(4, 1), (2, 2), (1, 3), (3, 3)]);
+        assert_eq!(ranks(LeaderboardMetric::Volume, 2), vec![(1, 1), (3, 1)]);
+    }
+}
@@ -0,0 +1,258 @@
+//! Double-entry ledger behind every balance movement
+//!
+//! Money only moves through journals whose entries sum to zero. A positive
+//! amount credits an account and a negative one debits it, so a user's balance
+//! is the sum of the entries on their account and the faucet runs negative by
+//! the amount of money ever issued.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::{MarketId, SeasonId, UserId};
+
+pub type JournalId = i64;
+
+/// Largest drift tolerated between two amounts that should be equal
+pub const LEDGER_EPSILON: f64 = 1e-6;
+
+/// An account money can be held in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum LedgerAccount {
+    /// Spendable money of one user, cached as `User::balance`
+    User(UserId),
+    /// The automated market maker of one market, which takes the other side of every trade
+    MarketMaker(MarketId),
+    /// Fees collected by the exchange
+    Fees,
+    /// Source of newly issued money, and sink of forfeited money
+    Faucet,
+    /// A user's balance in one season, kept apart from their main balance
+    Season(SeasonId, UserId),
+}
+
+impl LedgerAccount {
+    /// The account a user trades from in a market, depending on the season it belongs to
+    pub fn trader(user_id: UserId, season_id: Option<SeasonId>) -> Self {
+        match season_id {
+            Some(season_id) => LedgerAccount::Season(season_id, user_id),
+            None => LedgerAccount::User(user_id),
+        }
+    }
+}
+
+impl std::fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerAccount::User(id) => write!(f, "user:{}", id),
+            LedgerAccount::MarketMaker(id) => write!(f, "market:{}", id),
+            LedgerAccount::Fees => write!(f, "fees"),
+            LedgerAccount::Faucet => write!(f, "faucet"),
+            LedgerAccount::Season(season_id, user_id) => write!(f, "season:{}:user:{}", season_id, user_id),
+        }
+    }
+}
+
+impl std::str::FromStr for LedgerAccount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid ledger account: {}", s);
+        match s.split_once(':') {
+            Some(("user", id)) => id.parse().map(LedgerAccount::User).map_err(|_| invalid()),
+            Some(("market", id)) => id.parse().map(LedgerAccount::MarketMaker).map_err(|_| invalid()),
+            Some(("season", rest)) => match rest.split_once(":user:") {
+                Some((season_id, user_id)) => Ok(LedgerAccount::Season(
+                    season_id.parse().map_err(|_| invalid())?,
+                    user_id.parse().map_err(|_| invalid())?,
+                )),
+                None => Err(invalid()),
+            },
+            None if s == "fees" => Ok(LedgerAccount::Fees),
+            None if s == "faucet" => Ok(LedgerAccount::Faucet),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Why money moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalKind {
+    /// Money issued to a user, e.g. the starting balance
+    Grant,
+    Buy,
+    Sell,
+    /// Winning shares redeemed at resolution
+    Payout,
+    Fee,
+    /// Balance given up when an account is deleted
+    Forfeit,
+    /// Balance sent from one user to another
+    Transfer,
+    /// A creator or liquidity provider funding the worst-case loss of a market's market maker
+    Subsidy,
+    /// What the market maker holds after paying the winners, returned to the providers at resolution
+    Refund,
+    /// A complete set (one YES and one NO share) bought from the market maker for $1
+    Mint,
+    /// A matched YES and NO pair sold back to the market maker for $1
+    Redeem,
+}
+
+impl std::fmt::Display for JournalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalKind::Grant => write!(f, "grant"),
+            JournalKind::Buy => write!(f, "buy"),
+            JournalKind::Sell => write!(f, "sell"),
+            JournalKind::Payout => write!(f, "payout"),
+            JournalKind::Fee => write!(f, "fee"),
+            JournalKind::Forfeit => write!(f, "forfeit"),
+            JournalKind::Transfer => write!(f, "transfer"),
+            JournalKind::Subsidy => write!(f, "subsidy"),
+            JournalKind::Refund => write!(f, "refund"),
+            JournalKind::Mint => write!(f, "mint"),
+            JournalKind::Redeem => write!(f, "redeem"),
+        }
+    }
+}
+
+impl std::str::FromStr for JournalKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grant" => Ok(JournalKind::Grant),
+            "buy" => Ok(JournalKind::Buy),
+            "sell" => Ok(JournalKind::Sell),
+            "payout" => Ok(JournalKind::Payout),
+            "fee" => Ok(JournalKind::Fee),
+            "forfeit" => Ok(JournalKind::Forfeit),
+            "transfer" => Ok(JournalKind::Transfer),
+            "subsidy" => Ok(JournalKind::Subsidy),
+            "refund" => Ok(JournalKind::Refund),
+            "mint" => Ok(JournalKind::Mint),
+            "redeem" => Ok(JournalKind::Redeem),
+            _ => Err(format!("Invalid journal kind: {}", s)),
+        }
+    }
+}
+
+/// One side of a journal: `amount` credited to (or, when negative, debited from) `account`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub account: LedgerAccount,
+    pub amount: f64,
+}
+
+/// A set of entries posted together, which must balance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub kind: JournalKind,
+    pub market_id: Option<MarketId>,
+    pub entries: Vec<LedgerEntry>,
+}
+
+impl Journal {
+    /// Move `amount` from one account to another
+    pub fn transfer(kind: JournalKind, from: LedgerAccount, to: LedgerAccount, amount: f64) -> Self {
+        let market_id = [from, to].into_iter().find_map(|account| match account {
+            LedgerAccount::MarketMaker(id) => Some(id),
+            _ => None,
+        });
+        Journal {
+            kind,
+            market_id,
+            entries: vec![
+                LedgerEntry { account: from, amount: -amount },
+                LedgerEntry { account: to, amount },
+            ],
+        }
+    }
+
+    /// Issue new money to a user
+    pub fn grant(user_id: UserId, amount: f64) -> Self {
+        Self::transfer(JournalKind::Grant, LedgerAccount::Faucet, LedgerAccount::User(user_id), amount)
+    }
+
+    /// Check that the journal has finite amounts summing to zero
+    pub fn validate(&self) -> Result<(), String> {
+        if self.entries.len() < 2 {
+            return Err("A journal needs at least two entries".to_string());
+        }
+        if self.entries.iter().any(|entry| !entry.amount.is_finite()) {
+            return Err("Journal amounts must be finite".to_string());
+        }
+        let total: f64 = self.entries.iter().map(|entry| entry.amount).sum();
+        if total.abs() > LEDGER_EPSILON {
+            return Err(format!("Journal does not balance (off by {})", total));
+        }
+        Ok(())
+    }
+}
+
+/// A user whose cached balance disagrees with their ledger account
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BalanceMismatch {
+    pub user_id: UserId,
+    /// `User::balance` as stored
+    pub cached: f64,
+    /// Sum of the entries on the user's account
+    pub ledger: f64,
+}
+
+/// A posted entry, as read back for a user's statement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostedEntry {
+    pub journal_id: JournalId,
+    pub kind: JournalKind,
+    pub market_id: Option<MarketId>,
+    pub amount: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_balances() {
+        let journal = Journal::transfer(JournalKind::Buy, LedgerAccount::User(1), LedgerAccount::MarketMaker(7), 12.5);
+        assert!(journal.validate().is_ok());
+        assert_eq!(journal.market_id, Some(7));
+        assert_eq!(journal.entries[0], LedgerEntry { account: LedgerAccount::User(1), amount: -12.5 });
+
+        let grant = Journal::grant(1, 1000.0);
+        assert!(grant.validate().is_ok());
+        assert_eq!(grant.market_id, None);
+
+        let mut unbalanced = grant.clone();
+        unbalanced.entries[1].amount = 999.0;
+        assert!(unbalanced.validate().is_err());
+
+        let mut single = grant;
+        single.entries.pop();
+        assert!(single.validate().is_err());
+
+        assert!(Journal::grant(1, f64::NAN).validate().is_err());
+    }
+
+    #[test]
+    fn test_account_roundtrip() {
+        for account in [
+            LedgerAccount::User(3),
+            LedgerAccount::MarketMaker(42),
+            LedgerAccount::Fees,
+            LedgerAccount::Faucet,
+            LedgerAccount::Season(2, 3),
+        ] {
+            assert_eq!(account.to_string().parse::<LedgerAccount>(), Ok(account));
+        }
+        assert!("user:abc".parse::<LedgerAccount>().is_err());
+        assert!("bank".parse::<LedgerAccount>().is_err());
+        assert!("season:2".parse::<LedgerAccount>().is_err());
+        assert_eq!(LedgerAccount::trader(3, Some(2)), LedgerAccount::Season(2, 3));
+        assert_eq!(LedgerAccount::trader(3, None), LedgerAccount::User(3));
+        assert_eq!("forfeit".parse::<JournalKind>(), Ok(JournalKind::Forfeit));
+        assert_eq!("transfer".parse::<JournalKind>(), Ok(JournalKind::Transfer));
+    }
+}
@@ -0,0 +1,148 @@
+//! Subsidy deposited into a market's market maker by its creator and by liquidity providers
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::{MarketId, UserId};
+
+pub type LiquidityDepositId = i64;
+
+/// One deposit into a market's subsidy; the creator's up-front subsidy is the first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityDeposit {
+    pub id: LiquidityDepositId,
+    pub market_id: MarketId,
+    pub user_id: UserId,
+    pub username: String,
+    pub amount: f64,
+    /// LP shares issued for the deposit, see `lp_shares_for_deposit`
+    pub shares: f64,
+    /// `b` after the deposit
+    pub liquidity_param: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// LP shares issued for depositing `amount` into a market maker holding `balance` against `outstanding` shares
+///
+/// Shares are bought at what the market maker holds per share, so what it has
+/// already earned stays with the earlier providers. The first deposit, or one
+/// into a market maker holding nothing, gets one share per dollar.
+pub fn lp_shares_for_deposit(amount: f64, outstanding: f64, balance: f64) -> f64 {
+    if outstanding <= 0.0 || balance <= 0.0 {
+        return amount;
+    }
+    amount * outstanding / balance
+}
+
+/// A user's total deposits into one market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiquidityProvider {
+    pub user_id: UserId,
+    pub username: String,
+    pub amount: f64,
+    pub shares: f64,
+    /// Fraction of the market maker's remaining balance the provider receives at resolution
+    pub share: f64,
+}
+
+impl LiquidityProvider {
+    /// Group deposits by user, in order of each user's first deposit
+    pub fn from_deposits(deposits: &[LiquidityDeposit]) -> Vec<Self> {
+        let mut providers: Vec<Self> = Vec::new();
+        for deposit in deposits {
+            match providers.iter_mut().find(|p| p.user_id == deposit.user_id) {
+                Some(provider) => {
+                    provider.amount += deposit.amount;
+                    provider.shares += deposit.shares;
+                }
+                None => providers.push(Self {
+                    user_id: deposit.user_id,
+                    username: deposit.username.clone(),
+                    amount: deposit.amount,
+                    shares: deposit.shares,
+                    share: 0.0,
+                }),
+            }
+        }
+
+        let total: f64 = providers.iter().map(|p| p.shares).sum();
+        if total > 0.0 {
+            for provider in &mut providers {
+                provider.share = provider.shares / total;
+            }
+        }
+        providers
+    }
+}
+
+/// Split `balance` between providers in proportion to their LP shares
+///
+/// The last provider receives the remainder, so the amounts add up to
+/// `balance` exactly. Nothing is distributed from a balance that is not positive.
+pub fn distribute_pro_rata(balance: f64, providers: &[LiquidityProvider]) -> Vec<(UserId, f64)> {
+    let total: f64 = providers.iter().map(|p| p.shares).sum();
+    if balance <= 0.0 || total <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut remaining = balance;
+    let mut amounts: Vec<(UserId, f64)> = providers
+        .iter()
+        .map(|p| {
+            let amount = balance * p.shares / total;
+            remaining -= amount;
+            (p.user_id, amount)
+        })
+        .collect();
+    if let Some(last) = amounts.last_mut() {
+        last.1 += remaining;
+    }
+    amounts.retain(|(_, amount)| *amount > 0.0);
+    amounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deposit(user_id: UserId, amount: f64) -> LiquidityDeposit {
+        LiquidityDeposit {
+            id: 0,
+            market_id: 1,
+            user_id,
+            username: format!("user{}", user_id),
+            amount,
+            shares: amount,
+            liquidity_param: 100.0,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_providers_from_deposits() {
+        let providers = LiquidityProvider::from_deposits(&[deposit(1, 60.0), deposit(2, 30.0), deposit(1, 10.0)]);
+        assert_eq!(providers.len(), 2);
+        assert_eq!(providers[0].user_id, 1);
+        assert_eq!(providers[0].amount, 70.0);
+        assert!((providers[0].share - 0.7).abs() < 1e-9);
+        assert!((providers[1].share - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_distribute_pro_rata() {
+        let providers = LiquidityProvider::from_deposits(&[deposit(1, 1.0), deposit(2, 1.0), deposit(3, 1.0)]);
+        let amounts = distribute_pro_rata(100.0, &providers);
+        assert_eq!(amounts.len(), 3);
+        assert_eq!(amounts.iter().map(|(_, amount)| amount).sum::<f64>(), 100.0);
+
+        assert!(distribute_pro_rata(0.0, &providers).is_empty());
+        assert!(distribute_pro_rata(-5.0, &providers).is_empty());
+        assert!(distribute_pro_rata(100.0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_lp_shares_for_deposit() {
+        assert_eq!(lp_shares_for_deposit(50.0, 0.0, 0.0), 50.0);
+        // The market maker has earned 50% on the 100 shares outstanding
+        assert!((lp_shares_for_deposit(75.0, 100.0, 150.0) - 50.0).abs() < 1e-9);
+        assert_eq!(lp_shares_for_deposit(50.0, 100.0, 0.0), 50.0);
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What failed logins are counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoginScope {
+    Username,
+    Ip,
+}
+
+impl std::fmt::Display for LoginScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginScope::Username => write!(f, "username"),
+            LoginScope::Ip => write!(f, "ip"),
+        }
+    }
+}
+
+/// Thresholds for slowing down and locking out repeated failed logins
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoginThrottlePolicy {
+    /// Failures after which the key is locked out
+    pub max_failures: u32,
+    /// How long a lockout lasts; failures older than this are forgotten
+    pub lockout: Duration,
+    /// Wait imposed after the first failure, doubled for each further one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl LoginThrottlePolicy {
+    /// Wait required after `failures` consecutive failures before the next attempt
+    pub fn delay_after(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::zero();
+        }
+        let factor = 2i32.saturating_pow(failures.saturating_sub(1).min(20));
+        (self.base_delay * factor).min(self.max_delay)
+    }
+}
+
+/// Failed login history for one username or client IP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoginAttempts {
+    pub failures: u32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl LoginAttempts {
+    /// Time before which further attempts are refused, if any
+    pub fn blocked_until(&self, policy: &LoginThrottlePolicy, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if let Some(locked_until) = self.locked_until {
+            if locked_until > now {
+                return Some(locked_until);
+            }
+        }
+        let next_allowed = self.last_failure_at + policy.delay_after(self.failures);
+        (next_allowed > now && !self.is_stale(policy, now)).then_some(next_allowed)
+    }
+
+    /// Whether the key is currently locked out (as opposed to merely delayed)
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.locked_until.is_some_and(|until| until > now)
+    }
+
+    /// Failures older than the lockout window no longer count
+    fn is_stale(&self, policy: &LoginThrottlePolicy, now: DateTime<Utc>) -> bool {
+        !self.is_locked(now) && now - self.last_failure_at >= policy.lockout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LoginThrottlePolicy {
+        LoginThrottlePolicy {
+            max_failures: 3,
+            lockout: Duration::minutes(15),
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::seconds(5),
+        }
+    }
+
+    #[test]
+    fn test_delay_after() {
+        let policy = policy();
+        assert_eq!(policy.delay_after(0), Duration::zero());
+        assert_eq!(policy.delay_after(1), Duration::seconds(1));
+        assert_eq!(policy.delay_after(2), Duration::seconds(2));
+        assert_eq!(policy.delay_after(3), Duration::seconds(4));
+        assert_eq!(policy.delay_after(4), Duration::seconds(5));
+        assert_eq!(policy.delay_after(u32::MAX), Duration::seconds(5));
+    }
+
+    #[test]
+    fn test_blocked_until_delay() {
+        let policy = policy();
+        let now = Utc::now();
+        let attempts = LoginAttempts {
+            failures: 2,
+            last_failure_at: now,
+            locked_until: None,
+        };
+        assert_eq!(attempts.blocked_until(&policy, now), Some(now + Duration::seconds(2)));
+        assert_eq!(attempts.blocked_until(&policy, now + Duration::seconds(2)), None);
+        assert!(!attempts.is_locked(now));
+    }
+
+    #[test]
+    fn test_blocked_until_lockout() {
+        let policy = policy();
+        let now = Utc::now();
+        let attempts = LoginAttempts {
+            failures: 3,
+            last_failure_at: now,
+            locked_until: Some(now + policy.lockout),
+        };
+        assert!(attempts.is_locked(now));
+        assert_eq!(attempts.blocked_until(&policy, now), Some(now + policy.lockout));
+        assert_eq!(attempts.blocked_until(&policy, now + policy.lockout), None);
+    }
+}
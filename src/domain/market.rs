@@ -1,9 +1,18 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::domain::UserId;
+use crate::domain::{LmsrPricing, SeasonId, UserId};
 
 pub type MarketId = i64;
 
+/// Liquidity parameter `b` suggested on the new-market form
+pub const DEFAULT_LIQUIDITY_PARAM: f64 = 100.0;
+
+/// Smallest `b` a creator may choose; shallower markets swing wildly on tiny trades
+pub const MIN_LIQUIDITY_PARAM: f64 = 10.0;
+
+/// Largest `b` a creator may choose
+pub const MAX_LIQUIDITY_PARAM: f64 = 100_000.0;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MarketSide {
@@ -18,6 +27,11 @@ impl MarketSide {
             MarketSide::No => MarketSide::Yes,
         }
     }
+
+    /// The winning side of a market resolved to `outcome`
+    pub fn from_outcome(outcome: bool) -> Self {
+        if outcome { MarketSide::Yes } else { MarketSide::No }
+    }
 }
 
 impl std::fmt::Display for MarketSide {
@@ -42,12 +56,36 @@ impl std::str::FromStr for MarketSide {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MarketStatus {
     Active,
     Closed,
     Resolved,
 }
 
+impl std::fmt::Display for MarketStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarketStatus::Active => write!(f, "active"),
+            MarketStatus::Closed => write!(f, "closed"),
+            MarketStatus::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+impl std::str::FromStr for MarketStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "active" => Ok(MarketStatus::Active),
+            "closed" => Ok(MarketStatus::Closed),
+            "resolved" => Ok(MarketStatus::Resolved),
+            _ => Err(format!("Invalid market status: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Market {
     pub id: MarketId,
@@ -66,10 +104,28 @@ pub struct Market {
     pub q_yes: f64,
     pub q_no: f64,
     pub liquidity_param: f64,
+    /// Paid up front by the creator and liquidity providers to cover the market maker's worst-case loss
+    pub subsidy: f64,
+    /// Shares in `q_yes`/`q_no` that no position holds, added when liquidity
+    /// deposits rescaled the cost function
+    pub virtual_yes: f64,
+    pub virtual_no: f64,
+    /// C(q) at which the market maker has collected nothing: C(0, 0) at
+    /// creation, adjusted by every liquidity deposit
+    pub cost_base: f64,
+    /// Complete sets (one YES and one NO share for $1) minted less those
+    /// redeemed; they add to positions and to the market maker's cash without
+    /// moving `q_yes`/`q_no`
+    pub complete_sets: f64,
+    /// The competition this market belongs to, whose participants trade it with their season balance
+    pub season_id: Option<SeasonId>,
+    /// Total shares traded on either side
+    pub volume: f64,
     pub created_at: DateTime<Utc>,
 }
 
 impl Market {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: MarketId,
         question: String,
@@ -96,10 +152,18 @@ impl Market {
             q_yes: 0.0,
             q_no: 0.0,
             liquidity_param: 100.0,
+            subsidy: 0.0,
+            virtual_yes: 0.0,
+            virtual_no: 0.0,
+            cost_base: LmsrPricing::max_loss(100.0),
+            complete_sets: 0.0,
+            season_id: None,
+            volume: 0.0,
             created_at,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new_lmsr(
         id: MarketId,
         question: String,
@@ -125,6 +189,13 @@ impl Market {
             q_yes: 0.0,
             q_no: 0.0,
             liquidity_param,
+            subsidy: 0.0,
+            virtual_yes: 0.0,
+            virtual_no: 0.0,
+            cost_base: LmsrPricing::max_loss(liquidity_param),
+            complete_sets: 0.0,
+            season_id: None,
+            volume: 0.0,
             created_at,
         }
     }
@@ -184,6 +255,94 @@ impl Market {
     pub fn total_outstanding_shares(&self) -> f64 {
         self.q_yes + self.q_no
     }
+
+    /// Shares held by positions on one side, i.e. what the market maker owes if that side wins
+    pub fn outstanding_shares(&self, side: MarketSide) -> f64 {
+        match side {
+            MarketSide::Yes => self.q_yes - self.virtual_yes + self.complete_sets,
+            MarketSide::No => self.q_no - self.virtual_no + self.complete_sets,
+        }
+    }
+
+    /// What one share on `side` is worth on top of what has already been paid out
+    ///
+    /// Its current price while the market is open; nothing once it resolves,
+    /// since winning shares have been paid into their holders' balances.
+    pub fn unsettled_value(&self, side: MarketSide) -> f64 {
+        if self.resolved {
+            0.0
+        } else {
+            LmsrPricing::instantaneous_price(self.q_yes, self.q_no, side, self.liquidity_param)
+        }
+    }
+
+    /// Cost function state to quote trades against
+    ///
+    /// Shares minted as complete sets can be sold to the market maker like any
+    /// other, so both sides are shifted up by the sets outstanding. LMSR prices
+    /// only depend on `q_yes - q_no`, so the shift changes no price or cost.
+    pub fn pricing_state(&self) -> (f64, f64) {
+        let shift = self.complete_sets.max(0.0);
+        (self.q_yes + shift, self.q_no + shift)
+    }
+
+    /// What a complete set's $1 is split into at the current price, as (YES, NO) per share
+    pub fn complete_set_prices(&self) -> (f64, f64) {
+        let yes = LmsrPricing::implied_probability(self.q_yes, self.q_no, self.liquidity_param);
+        (yes, 1.0 - yes)
+    }
+
+    /// Net amount traders have paid the market maker
+    pub fn collected(&self) -> f64 {
+        LmsrPricing::cost_function(self.q_yes, self.q_no, self.liquidity_param) - self.cost_base
+    }
+
+    /// Most the market maker can lose on this market, however it trades from here
+    ///
+    /// Buying without limit on one side drives C(q) - q towards zero on that
+    /// side, leaving a loss of `cost_base` less that side's virtual shares.
+    /// Without deposits this is b * ln 2.
+    pub fn max_loss(&self) -> f64 {
+        self.cost_base - self.virtual_yes.min(self.virtual_no)
+    }
+
+    /// Deepen the market with `amount` of extra subsidy without moving its price
+    ///
+    /// `b` and the cost function state are scaled by the same factor, which
+    /// keeps every price and the amount collected so far unchanged. The factor
+    /// is chosen so that `max_loss` grows by at most `amount`, so a deposit
+    /// buys less depth the further the price is from 50%.
+    pub fn add_liquidity(&mut self, amount: f64) -> Result<(), String> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err("Amount must be positive".to_string());
+        }
+        let b = self.liquidity_param;
+        let cost = LmsrPricing::cost_function(self.q_yes, self.q_no, b);
+        let factor = 1.0 + amount / (cost - self.q_yes.min(self.q_no));
+        if b * factor > MAX_LIQUIDITY_PARAM {
+            return Err(format!("Liquidity cannot exceed {}", MAX_LIQUIDITY_PARAM));
+        }
+
+        self.virtual_yes += (factor - 1.0) * self.q_yes;
+        self.virtual_no += (factor - 1.0) * self.q_no;
+        self.q_yes *= factor;
+        self.q_no *= factor;
+        self.liquidity_param = b * factor;
+        self.cost_base += (factor - 1.0) * cost;
+        self.subsidy += amount;
+        Ok(())
+    }
+}
+
+/// Check a creator-chosen liquidity parameter
+pub fn validate_liquidity_param(b: f64) -> Result<(), String> {
+    if !b.is_finite() || !(MIN_LIQUIDITY_PARAM..=MAX_LIQUIDITY_PARAM).contains(&b) {
+        return Err(format!(
+            "Liquidity must be between {} and {}",
+            MIN_LIQUIDITY_PARAM, MAX_LIQUIDITY_PARAM
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -196,13 +355,13 @@ mod tests {
         let future = Utc::now() + Duration::days(1);
         let past = Utc::now() - Duration::days(1);
 
-        let active = Market::new(1, "Q?".to_string(), None, 1, future, 100.0, 100.0, Utc::now());
+        let active = Market::new(1, "Q?".to_string(), None, 1, None, future, 100.0, 100.0, Utc::now());
         assert_eq!(active.status(), MarketStatus::Active);
 
-        let closed = Market::new(1, "Q?".to_string(), None, 1, past, 100.0, 100.0, Utc::now());
+        let closed = Market::new(1, "Q?".to_string(), None, 1, None, past, 100.0, 100.0, Utc::now());
         assert_eq!(closed.status(), MarketStatus::Closed);
 
-        let mut resolved = Market::new(1, "Q?".to_string(), None, 1, past, 100.0, 100.0, Utc::now());
+        let mut resolved = Market::new(1, "Q?".to_string(), None, 1, None, past, 100.0, 100.0, Utc::now());
         resolved.resolve(true).unwrap();
         assert_eq!(resolved.status(), MarketStatus::Resolved);
     }
@@ -212,17 +371,17 @@ mod tests {
         let future = Utc::now() + Duration::days(1);
         let past = Utc::now() - Duration::days(1);
 
-        let active = Market::new(1, "Q?".to_string(), None, 1, future, 100.0, 100.0, Utc::now());
+        let active = Market::new(1, "Q?".to_string(), None, 1, None, future, 100.0, 100.0, Utc::now());
         assert!(active.can_trade());
 
-        let closed = Market::new(1, "Q?".to_string(), None, 1, past, 100.0, 100.0, Utc::now());
+        let closed = Market::new(1, "Q?".to_string(), None, 1, None, past, 100.0, 100.0, Utc::now());
         assert!(!closed.can_trade());
     }
 
     #[test]
     fn test_resolve() {
         let past = Utc::now() - Duration::days(1);
-        let mut market = Market::new(1, "Q?".to_string(), None, 1, past, 100.0, 100.0, Utc::now());
+        let mut market = Market::new(1, "Q?".to_string(), None, 1, None, past, 100.0, 100.0, Utc::now());
 
         assert!(market.resolve(true).is_ok());
         assert_eq!(market.outcome, Some(true));
@@ -231,4 +390,64 @@ mod tests {
         // Cannot resolve again
         assert!(market.resolve(false).is_err());
     }
+
+    #[test]
+    fn test_add_liquidity_keeps_price() {
+        let mut market = Market::new_lmsr(1, "Q?".to_string(), None, 1, None, Utc::now(), 100.0, Utc::now());
+        market.subsidy = LmsrPricing::max_loss(100.0);
+        assert!((market.max_loss() - market.subsidy).abs() < 1e-9);
+
+        // An empty market at 50% gets b + amount / ln 2
+        let mut empty = market.clone();
+        empty.add_liquidity(LmsrPricing::max_loss(50.0)).unwrap();
+        assert!((empty.liquidity_param - 150.0).abs() < 1e-9);
+
+        market.q_yes = 80.0;
+        market.q_no = 10.0;
+        let price = LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param);
+        let collected = market.collected();
+        let max_loss = market.max_loss();
+
+        market.add_liquidity(50.0).unwrap();
+        assert!(market.liquidity_param > 100.0);
+        let after = LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param);
+        assert!((after - price).abs() < 1e-9);
+        assert!((market.collected() - collected).abs() < 1e-9);
+        assert!((market.outstanding_shares(MarketSide::Yes) - 80.0).abs() < 1e-9);
+        assert!((market.outstanding_shares(MarketSide::No) - 10.0).abs() < 1e-9);
+        assert!(market.max_loss() <= max_loss + 50.0 + 1e-9);
+        assert!(market.max_loss() <= market.subsidy + 1e-9);
+
+        assert!(market.add_liquidity(0.0).is_err());
+        assert!(market.add_liquidity(f64::NAN).is_err());
+        assert!(market.add_liquidity(1e9).is_err());
+    }
+
+    #[test]
+    fn test_complete_sets() {
+        let mut market = Market::new_lmsr(1, "Q?".to_string(), None, 1, None, Utc::now(), 100.0, Utc::now());
+        market.q_yes = 5.0;
+        let (yes, no) = market.complete_set_prices();
+        assert!((yes + no - 1.0).abs() < 1e-9);
+        assert!(yes > 0.5);
+
+        // Minted shares are held on both sides and can be sold to the market maker at the same price
+        market.complete_sets = 20.0;
+        assert!((market.outstanding_shares(MarketSide::Yes) - 25.0).abs() < 1e-9);
+        assert!((market.outstanding_shares(MarketSide::No) - 20.0).abs() < 1e-9);
+        assert!(LmsrPricing::calculate_sell_proceeds(market.q_yes, market.q_no, 10.0, MarketSide::No, 100.0).is_err());
+        let (q_yes, q_no) = market.pricing_state();
+        assert!((LmsrPricing::implied_probability(q_yes, q_no, 100.0) - yes).abs() < 1e-9);
+        let proceeds = LmsrPricing::calculate_sell_proceeds(q_yes, q_no, 10.0, MarketSide::No, 100.0).unwrap();
+        assert!(proceeds > 0.0 && proceeds < 10.0 * no);
+    }
+
+    #[test]
+    fn test_validate_liquidity_param() {
+        assert!(validate_liquidity_param(DEFAULT_LIQUIDITY_PARAM).is_ok());
+        assert!(validate_liquidity_param(MIN_LIQUIDITY_PARAM).is_ok());
+        assert!(validate_liquidity_param(MIN_LIQUIDITY_PARAM - 1.0).is_err());
+        assert!(validate_liquidity_param(MAX_LIQUIDITY_PARAM + 1.0).is_err());
+        assert!(validate_liquidity_param(f64::NAN).is_err());
+    }
 }
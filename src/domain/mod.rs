@@ -3,9 +3,73 @@ mod market;
 mod position;
 mod pricing;
 mod price_snapshot;
+mod trade;
+mod pagination;
+mod webhook;
+mod idempotency;
+mod password_reset;
+mod login_throttle;
+mod totp;
+mod oidc;
+mod profile;
+mod transaction;
+mod invite;
+mod ledger;
+mod reconcile;
+mod allowance;
+mod transfer;
+mod exposure;
+mod liquidity;
+mod season;
+mod leaderboard;
 
-pub use user::{User, UserId};
-pub use market::{Market, MarketId, MarketSide, MarketStatus};
+pub use user::{
+    normalize_email, normalize_profile_text, validate_password, Role, User, UserId, DEFAULT_STARTING_BALANCE,
+    MAX_BIO_LEN, MAX_DISPLAY_NAME_LEN, MAX_EMAIL_LEN, MIN_PASSWORD_LEN,
+};
+pub use market::{
+    validate_liquidity_param, Market, MarketId, MarketSide, MarketStatus, DEFAULT_LIQUIDITY_PARAM, MAX_LIQUIDITY_PARAM,
+    MIN_LIQUIDITY_PARAM,
+};
 pub use position::{Position, PositionId};
 pub use pricing::{AmmPricing, LmsrPricing};
-pub use price_snapshot::PriceSnapshot;
+pub use price_snapshot::{Candle, CandleInterval, PriceSnapshot};
+pub use trade::{TradeAction, TradeQuote, TradeSize};
+pub use pagination::{Cursor, CursorKey, ListQuery, MarketSort, Page, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+pub use webhook::{
+    is_public_address, sign_payload, DeliveryStatus, WebhookDelivery, WebhookEvent, WebhookId, WebhookSubscription,
+    MAX_DELIVERY_ATTEMPTS,
+};
+pub use idempotency::{
+    generate_idempotency_key, idempotency_retention, validate_idempotency_key, IdempotencyRecord, IdempotencyStatus,
+    IDEMPOTENCY_KEY_HEADER, MAX_IDEMPOTENCY_KEY_LEN, TRADE_RESULT_HEADER,
+};
+pub use password_reset::{password_reset_email_cooldown, password_reset_ttl, PasswordResetToken};
+pub use login_throttle::{LoginAttempts, LoginScope, LoginThrottlePolicy};
+pub use totp::{
+    generate_recovery_codes, hash_recovery_code, is_recovery_code, time_step, TotpCredential, TotpSecret,
+    TwoFactorPolicy, RECOVERY_CODE_COUNT, TOTP_DIGITS, TOTP_PERIOD_SECS,
+};
+pub use oidc::{pkce_challenge, Audience, IdTokenClaims, OidcLoginRequest, UserIdentity, NO_PASSWORD_HASH};
+pub use profile::TrackRecord;
+pub use transaction::{Transaction, TransactionId, TransactionType};
+pub use invite::{
+    generate_invite_code, normalize_invite_code, InviteCode, InviteId, InviteTerms, RegistrationMode,
+    MAX_USER_INVITE_DAYS, MAX_USER_INVITE_USES,
+};
+pub use ledger::{
+    BalanceMismatch, Journal, JournalId, JournalKind, LedgerAccount, LedgerEntry, PostedEntry, LEDGER_EPSILON,
+};
+pub use reconcile::{differs, expected_market_maker_balance, Discrepancy, ReconciliationReport};
+pub use allowance::{AllowancePeriod, AllowancePolicy};
+pub use transfer::{normalize_memo, Transfer, TransferId, TransferLimits, MAX_TRANSFER_MEMO_LEN};
+pub use exposure::{ExposureTotals, MarketMakerExposure};
+pub use liquidity::{
+    distribute_pro_rata, lp_shares_for_deposit, LiquidityDeposit, LiquidityDepositId, LiquidityProvider,
+};
+pub use season::{
+    rank_standings, validate_season, Season, SeasonId, SeasonStanding, SeasonStatus, MAX_SEASON_NAME_LEN,
+};
+pub use leaderboard::{
+    rank_entries, LeaderboardEntry, LeaderboardMetric, LeaderboardWindow, MAX_LEADERBOARD_SIZE, MIN_CALIBRATION_TRADES,
+};
@@ -0,0 +1,222 @@
+//! OpenID Connect single sign-on: ID token claims and the identities they map to
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::domain::UserId;
+
+/// Allowance for clock differences between us and the provider
+const CLOCK_SKEW_SECS: i64 = 60;
+
+/// Username length limits for provisioned accounts
+const MIN_USERNAME_LEN: usize = 3;
+const MAX_USERNAME_LEN: usize = 32;
+
+/// Password hash stored for accounts created through single sign-on
+///
+/// It is not a valid bcrypt hash, so password login never succeeds for them.
+pub const NO_PASSWORD_HASH: &str = "!";
+
+/// An external account linked to a local user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub user_id: UserId,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The `aud` claim, which may be a single audience or a list
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    pub fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// Claims of an ID token that login relies on
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    pub exp: i64,
+    pub iat: i64,
+    pub nonce: Option<String>,
+    pub preferred_username: Option<String>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// Check the claims were issued by `issuer`, for `client_id`, in answer to our request
+    pub fn validate(&self, issuer: &str, client_id: &str, nonce: &str, now: DateTime<Utc>) -> Result<(), String> {
+        if self.iss.trim_end_matches('/') != issuer.trim_end_matches('/') {
+            return Err(format!("ID token issued by unexpected issuer {}", self.iss));
+        }
+        if !self.aud.contains(client_id) {
+            return Err("ID token was issued for a different client".to_string());
+        }
+        let now = now.timestamp();
+        if self.exp + CLOCK_SKEW_SECS < now {
+            return Err("ID token has expired".to_string());
+        }
+        if self.iat - CLOCK_SKEW_SECS > now {
+            return Err("ID token was issued in the future".to_string());
+        }
+        if self.nonce.as_deref() != Some(nonce) {
+            return Err("ID token nonce does not match the login request".to_string());
+        }
+        if self.sub.is_empty() {
+            return Err("ID token has no subject".to_string());
+        }
+        Ok(())
+    }
+
+    /// Username for an account provisioned from these claims, before de-duplication
+    ///
+    /// Prefers `preferred_username`, then the local part of `email`, then `name`,
+    /// keeping only characters allowed in local usernames.
+    pub fn suggested_username(&self) -> String {
+        let candidates = [
+            self.preferred_username.as_deref(),
+            self.email.as_deref().and_then(|email| email.split('@').next()),
+            self.name.as_deref(),
+        ];
+        candidates
+            .into_iter()
+            .flatten()
+            .map(sanitize_username)
+            .find(|username| username.len() >= MIN_USERNAME_LEN)
+            .unwrap_or_else(|| "user".to_string())
+    }
+}
+
+/// Per-login secrets tying the provider's callback to the request that started it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcLoginRequest {
+    /// Echoed back by the provider; guards the callback against CSRF
+    pub state: String,
+    /// Embedded in the ID token; guards against token replay
+    pub nonce: String,
+    /// PKCE code verifier (RFC 7636)
+    pub code_verifier: String,
+    /// Set when a logged-in user is linking their existing account
+    pub link_user_id: Option<UserId>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl OidcLoginRequest {
+    pub fn new(link_user_id: Option<UserId>) -> Self {
+        OidcLoginRequest {
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+            link_user_id,
+            started_at: Utc::now(),
+        }
+    }
+
+    /// S256 PKCE challenge sent with the authorization request
+    pub fn code_challenge(&self) -> String {
+        pkce_challenge(&self.code_verifier)
+    }
+}
+
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn sanitize_username(raw: &str) -> String {
+    raw.chars()
+        .filter_map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' | '-' | '.' => Some(c),
+            'A'..='Z' => Some(c.to_ascii_lowercase()),
+            ' ' => Some('_'),
+            _ => None,
+        })
+        .take(MAX_USERNAME_LEN)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(now: DateTime<Utc>) -> IdTokenClaims {
+        IdTokenClaims {
+            iss: "https://idp.example.com".to_string(),
+            sub: "248289761001".to_string(),
+            aud: Audience::Many(vec!["market".to_string(), "other".to_string()]),
+            exp: now.timestamp() + 300,
+            iat: now.timestamp(),
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+            preferred_username: None,
+            email: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_claims() {
+        let now = Utc::now();
+        let valid = claims(now);
+        assert!(valid.validate("https://idp.example.com/", "market", "n-0S6_WzA2Mj", now).is_ok());
+        assert!(valid.validate("https://evil.example.com", "market", "n-0S6_WzA2Mj", now).is_err());
+        assert!(valid.validate("https://idp.example.com", "someone-else", "n-0S6_WzA2Mj", now).is_err());
+        assert!(valid.validate("https://idp.example.com", "market", "other-nonce", now).is_err());
+
+        let later = now + chrono::Duration::seconds(300 + CLOCK_SKEW_SECS + 1);
+        assert!(valid.validate("https://idp.example.com", "market", "n-0S6_WzA2Mj", later).is_err());
+
+        let single = IdTokenClaims { aud: Audience::One("market".to_string()), ..claims(now) };
+        assert!(single.validate("https://idp.example.com", "market", "n-0S6_WzA2Mj", now).is_ok());
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636 appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        let request = OidcLoginRequest::new(None);
+        assert_ne!(request.state, request.nonce);
+        assert_eq!(request.code_challenge(), pkce_challenge(&request.code_verifier));
+    }
+
+    #[test]
+    fn test_suggested_username() {
+        let now = Utc::now();
+        let mut c = claims(now);
+        assert_eq!(c.suggested_username(), "user");
+
+        c.name = Some("Jane Doe".to_string());
+        assert_eq!(c.suggested_username(), "jane_doe");
+
+        c.email = Some("J.Doe+markets@example.com".to_string());
+        assert_eq!(c.suggested_username(), "j.doemarkets");
+
+        c.preferred_username = Some("jd".to_string());
+        assert_eq!(c.suggested_username(), "j.doemarkets");
+
+        c.preferred_username = Some("JaneD".to_string());
+        assert_eq!(c.suggested_username(), "janed");
+    }
+}
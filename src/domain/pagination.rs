@@ -0,0 +1,195 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use crate::domain::MarketStatus;
+
+/// Default number of items per page
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Upper bound on the number of items a single page may request
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Orderings available for market and position listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketSort {
+    #[default]
+    Newest,
+    EndingSoon,
+    MostTraded,
+    /// Markets whose YES probability is closest to 50%
+    Closest,
+}
+
+impl MarketSort {
+    pub const ALL: [MarketSort; 4] = [
+        MarketSort::Newest,
+        MarketSort::EndingSoon,
+        MarketSort::MostTraded,
+        MarketSort::Closest,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MarketSort::Newest => "newest",
+            MarketSort::EndingSoon => "ending soon",
+            MarketSort::MostTraded => "most traded",
+            MarketSort::Closest => "closest to 50%",
+        }
+    }
+}
+
+impl std::fmt::Display for MarketSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarketSort::Newest => write!(f, "newest"),
+            MarketSort::EndingSoon => write!(f, "ending_soon"),
+            MarketSort::MostTraded => write!(f, "most_traded"),
+            MarketSort::Closest => write!(f, "closest"),
+        }
+    }
+}
+
+impl std::str::FromStr for MarketSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "newest" => Ok(MarketSort::Newest),
+            "ending_soon" => Ok(MarketSort::EndingSoon),
+            "most_traded" => Ok(MarketSort::MostTraded),
+            "closest" => Ok(MarketSort::Closest),
+            _ => Err(format!("Invalid sort: {}", s)),
+        }
+    }
+}
+
+/// Value of the sort column for the last item of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CursorKey {
+    Text(String),
+    Number(f64),
+}
+
+/// Opaque keyset cursor pointing just after the last item of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub sort: MarketSort,
+    pub key: CursorKey,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(s: &str) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| "Invalid cursor".to_string())?;
+        serde_json::from_slice(&bytes).map_err(|_| "Invalid cursor".to_string())
+    }
+}
+
+/// Filter, ordering and position within a listing
+#[derive(Debug, Clone)]
+pub struct ListQuery {
+    pub status: Option<MarketStatus>,
+    pub sort: MarketSort,
+    pub cursor: Option<Cursor>,
+    pub limit: i64,
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        Self {
+            status: None,
+            sort: MarketSort::default(),
+            cursor: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl ListQuery {
+    /// Build a query from raw request parameters, validating each of them
+    pub fn parse(
+        status: Option<&str>,
+        sort: Option<&str>,
+        cursor: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Self, String> {
+        let status = match status {
+            None | Some("") | Some("all") => None,
+            Some(s) => Some(s.parse()?),
+        };
+        let sort = match sort {
+            None | Some("") => MarketSort::default(),
+            Some(s) => s.parse()?,
+        };
+        let cursor = match cursor {
+            None | Some("") => None,
+            Some(s) => Some(Cursor::decode(s)?),
+        };
+        if let Some(ref c) = cursor {
+            if c.sort != sort {
+                return Err("Cursor does not match sort order".to_string());
+            }
+        }
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+        Ok(Self { status, sort, cursor, limit })
+    }
+}
+
+/// One page of a listing plus the cursor for the following page, if any
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Cursor>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = Cursor {
+            sort: MarketSort::Closest,
+            key: CursorKey::Number(0.123456789012345),
+            id: 42,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+
+        let cursor = Cursor {
+            sort: MarketSort::Newest,
+            key: CursorKey::Text("2025-01-04T12:00:00Z".to_string()),
+            id: 7,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+
+        assert!(Cursor::decode("not a cursor").is_err());
+    }
+
+    #[test]
+    fn test_list_query_parse() {
+        let query = ListQuery::parse(Some("resolved"), Some("most_traded"), None, Some(1000)).unwrap();
+        assert_eq!(query.status, Some(MarketStatus::Resolved));
+        assert_eq!(query.sort, MarketSort::MostTraded);
+        assert_eq!(query.limit, MAX_PAGE_SIZE);
+
+        let query = ListQuery::parse(Some("all"), None, None, None).unwrap();
+        assert_eq!(query.status, None);
+        assert_eq!(query.sort, MarketSort::Newest);
+        assert_eq!(query.limit, DEFAULT_PAGE_SIZE);
+
+        assert!(ListQuery::parse(Some("pending"), None, None, None).is_err());
+
+        // A cursor from one ordering cannot be reused with another
+        let cursor = Cursor { sort: MarketSort::Newest, key: CursorKey::Number(1.0), id: 1 };
+        assert!(ListQuery::parse(None, Some("closest"), Some(&cursor.encode()), None).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::domain::UserId;
+
+/// How long an issued reset token stays usable
+pub fn password_reset_ttl() -> Duration {
+    Duration::hours(24)
+}
+
+/// Shortest gap between reset links a user requests by email
+pub fn password_reset_email_cooldown() -> Duration {
+    Duration::minutes(5)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordResetToken {
+    pub user_id: UserId,
+    /// Admin who issued the token; `None` when the user requested it by email
+    pub created_by: Option<UserId>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PasswordResetToken {
+    /// Generate a new secret token, returning it together with the hash to store
+    ///
+    /// Only the hash is persisted, so a leaked database cannot be used to reset passwords.
+    pub fn generate() -> (String, String) {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+        let hash = Self::hash(&token);
+        (token, hash)
+    }
+
+    pub fn hash(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Link to the reset page for `token`, under the app's public URL
+    pub fn url(public_url: &str, token: &str) -> String {
+        format!("{}/reset-password?token={}", public_url, token)
+    }
+
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.used_at.is_none() && now < self.expires_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_hash() {
+        let (token, hash) = PasswordResetToken::generate();
+        assert_eq!(token.len(), 64);
+        assert_eq!(PasswordResetToken::hash(&token), hash);
+        assert_ne!(token, hash);
+        assert_ne!(PasswordResetToken::generate().0, token);
+        assert_eq!(
+            PasswordResetToken::url("https://market.example.com", &token),
+            format!("https://market.example.com/reset-password?token={}", token)
+        );
+    }
+
+    #[test]
+    fn test_is_usable() {
+        let now = Utc::now();
+        let mut reset = PasswordResetToken {
+            user_id: 1,
+            created_by: Some(2),
+            expires_at: now + password_reset_ttl(),
+            used_at: None,
+            created_at: now,
+        };
+        assert!(reset.is_usable(now));
+        assert!(!reset.is_usable(now + password_reset_ttl()));
+        reset.used_at = Some(now);
+        assert!(!reset.is_usable(now));
+    }
+}
@@ -12,11 +12,14 @@ pub struct Position {
     pub side: MarketSide,
     pub shares: f64,
     pub avg_price: f64,
+    /// Profit or loss locked in by selling shares, against their average price
+    pub realized_pnl: f64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
 impl Position {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: PositionId,
         user_id: UserId,
@@ -34,6 +37,7 @@ impl Position {
             side,
             shares,
             avg_price,
+            realized_pnl: 0.0,
             created_at,
             updated_at,
         }
@@ -65,6 +69,24 @@ impl Position {
         Ok(())
     }
 
+    /// Sell shares for `proceeds`, realizing the difference from their average price
+    pub fn sell_shares(&mut self, shares: f64, proceeds: f64) -> Result<(), String> {
+        self.remove_shares(shares)?;
+        self.realized_pnl += proceeds - shares * self.avg_price;
+        Ok(())
+    }
+
+    /// Whether this position is on the side that won a market resolved to `outcome`
+    pub fn wins(&self, outcome: bool) -> bool {
+        self.side == MarketSide::from_outcome(outcome)
+    }
+
+    /// Profit or loss of the shares still held when the market resolved to `outcome`
+    pub fn settlement_pnl(&self, outcome: bool) -> f64 {
+        let payout = if self.wins(outcome) { self.payout_if_wins() } else { 0.0 };
+        payout - self.shares * self.avg_price
+    }
+
     /// Calculate the current value of this position at a given price
     pub fn value_at_price(&self, current_price: f64) -> f64 {
         self.shares * current_price
@@ -111,6 +133,24 @@ mod tests {
         assert!(position.remove_shares(10.0).is_err());
     }
 
+    #[test]
+    fn test_sell_and_settle() {
+        let mut position = Position::new(
+            1, 1, 1, MarketSide::Yes,
+            10.0, 0.5, Utc::now(), Utc::now()
+        );
+
+        // Sell 4 shares for 2.8 (0.7 each)
+        assert!(position.sell_shares(4.0, 2.8).is_ok());
+        assert!((position.realized_pnl - 0.8).abs() < 1e-9);
+        assert!(position.sell_shares(10.0, 5.0).is_err());
+        assert!((position.realized_pnl - 0.8).abs() < 1e-9);
+
+        // 6 shares left at 0.5: win pays 6, loss forfeits 3
+        assert!((position.settlement_pnl(true) - 3.0).abs() < 1e-9);
+        assert!((position.settlement_pnl(false) - (-3.0)).abs() < 1e-9);
+    }
+
     #[test]
     fn test_profit_loss() {
         let position = Position::new(
@@ -33,3 +33,130 @@ impl PriceSnapshot {
         }
     }
 }
+
+/// OHLC candle of YES probability aggregated over one time bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub market_id: i64,
+    pub bucket_start: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Shares traded (YES + NO) within the bucket
+    pub volume: f64,
+    pub trades: i64,
+}
+
+/// Bucket width used when resampling price history into candles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    OneDay,
+    #[serde(rename = "1w")]
+    OneWeek,
+}
+
+impl CandleInterval {
+    const ALL: [CandleInterval; 7] = [
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::FifteenMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::FourHours,
+        CandleInterval::OneDay,
+        CandleInterval::OneWeek,
+    ];
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::FifteenMinutes => 15 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::FourHours => 4 * 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+            CandleInterval::OneWeek => 7 * 24 * 60 * 60,
+        }
+    }
+
+    /// Pick the smallest interval that keeps a range under `max_candles` buckets
+    pub fn auto_for_range(from: DateTime<Utc>, to: DateTime<Utc>, max_candles: i64) -> Self {
+        let span = (to - from).num_seconds().max(1);
+        Self::ALL
+            .into_iter()
+            .find(|interval| span / interval.seconds() < max_candles)
+            .unwrap_or(CandleInterval::OneWeek)
+    }
+}
+
+impl std::fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::FifteenMinutes => "15m",
+            CandleInterval::OneHour => "1h",
+            CandleInterval::FourHours => "4h",
+            CandleInterval::OneDay => "1d",
+            CandleInterval::OneWeek => "1w",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::str::FromStr for CandleInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|interval| interval.to_string() == s)
+            .ok_or_else(|| format!("Invalid candle interval: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_interval_roundtrip() {
+        for interval in CandleInterval::ALL {
+            assert_eq!(interval.to_string().parse::<CandleInterval>().unwrap(), interval);
+        }
+        assert!("2h".parse::<CandleInterval>().is_err());
+    }
+
+    #[test]
+    fn test_auto_interval() {
+        let now = Utc::now();
+        assert_eq!(
+            CandleInterval::auto_for_range(now - Duration::hours(1), now, 100),
+            CandleInterval::OneMinute
+        );
+        assert_eq!(
+            CandleInterval::auto_for_range(now - Duration::days(1), now, 100),
+            CandleInterval::FifteenMinutes
+        );
+        assert_eq!(
+            CandleInterval::auto_for_range(now - Duration::days(30), now, 100),
+            CandleInterval::OneDay
+        );
+        assert_eq!(
+            CandleInterval::auto_for_range(now - Duration::days(5000), now, 100),
+            CandleInterval::OneWeek
+        );
+    }
+}
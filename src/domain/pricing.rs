@@ -19,7 +19,9 @@ pub struct LmsrPricing;
 impl LmsrPricing {
     /// Calculate the LMSR cost function
     /// C(q) = b * ln(e^(q_yes/b) + e^(q_no/b))
-    fn cost_function(q_yes: f64, q_no: f64, b: f64) -> f64 {
+    ///
+    /// The market maker has taken in C(q) - C(0, 0) over all trades that led to `q`.
+    pub fn cost_function(q_yes: f64, q_no: f64, b: f64) -> f64 {
         let exp_yes = (q_yes / b).exp();
         let exp_no = (q_no / b).exp();
         b * (exp_yes + exp_no).ln()
@@ -125,6 +127,88 @@ impl LmsrPricing {
         Ok(proceeds)
     }
 
+    /// Calculate how many shares a given amount buys
+    ///
+    /// Inverse of `calculate_buy_cost`: solves C(q + shares) - C(q) = cost for shares.
+    /// Buying YES: shares = b * ln(e^((C + cost)/b) - e^(q_no/b)) - q_yes
+    pub fn shares_for_cost(
+        q_yes: f64,
+        q_no: f64,
+        cost: f64,
+        side: MarketSide,
+        b: f64,
+    ) -> Result<f64, String> {
+        if cost <= 0.0 {
+            return Err("Amount must be positive".to_string());
+        }
+        if b <= 0.0 {
+            return Err("Liquidity parameter must be positive".to_string());
+        }
+
+        let (q_side, q_other) = match side {
+            MarketSide::Yes => (q_yes, q_no),
+            MarketSide::No => (q_no, q_yes),
+        };
+
+        // Factor out e^((C + cost)/b) to keep the exponent negative
+        let target = Self::cost_function(q_yes, q_no, b) + cost;
+        let shares = target + b * (-((q_other - target) / b).exp()).ln_1p() - q_side;
+
+        if !shares.is_finite() || shares <= 0.0 {
+            return Err("Invalid calculation resulted in non-positive shares".to_string());
+        }
+
+        Ok(shares)
+    }
+
+    /// Calculate how many shares must be sold to receive a given amount
+    ///
+    /// Inverse of `calculate_sell_proceeds`: solves C(q) - C(q - shares) = proceeds for shares.
+    pub fn shares_for_proceeds(
+        q_yes: f64,
+        q_no: f64,
+        proceeds: f64,
+        side: MarketSide,
+        b: f64,
+    ) -> Result<f64, String> {
+        if proceeds <= 0.0 {
+            return Err("Amount must be positive".to_string());
+        }
+        if b <= 0.0 {
+            return Err("Liquidity parameter must be positive".to_string());
+        }
+
+        let (q_side, q_other) = match side {
+            MarketSide::Yes => (q_yes, q_no),
+            MarketSide::No => (q_no, q_yes),
+        };
+
+        // Selling can never return more than C(q) - q_other
+        let target = Self::cost_function(q_yes, q_no, b) - proceeds;
+        if target <= q_other {
+            return Err("Not enough shares to sell".to_string());
+        }
+
+        let shares = q_side - (target + b * (-((q_other - target) / b).exp()).ln_1p());
+
+        if !shares.is_finite() || shares <= 0.0 {
+            return Err("Invalid calculation resulted in non-positive shares".to_string());
+        }
+        if shares > q_side {
+            return Err("Not enough shares to sell".to_string());
+        }
+
+        Ok(shares)
+    }
+
+    /// Most the market maker can lose on a market that starts at q = (0, 0)
+    ///
+    /// The worst case is every winning share paying out against C(q) - C(0),
+    /// which is bounded by C(0, 0) = b * ln 2.
+    pub fn max_loss(b: f64) -> f64 {
+        b * std::f64::consts::LN_2
+    }
+
     /// Calculate the current implied probability of YES
     ///
     /// Probability = e^(q_yes/b) / (e^(q_yes/b) + e^(q_no/b))
@@ -358,6 +442,38 @@ mod tests {
         assert!(LmsrPricing::calculate_buy_cost(0.0, 0.0, 10.0, MarketSide::Yes, -10.0).is_err());
     }
 
+    #[test]
+    fn test_lmsr_shares_for_cost_roundtrip() {
+        let b = 100.0;
+        let shares = LmsrPricing::shares_for_cost(20.0, 5.0, 50.0, MarketSide::No, b).unwrap();
+        let cost = LmsrPricing::calculate_buy_cost(20.0, 5.0, shares, MarketSide::No, b).unwrap();
+        assert!((cost - 50.0).abs() < 1e-9);
+
+        assert!(LmsrPricing::shares_for_cost(0.0, 0.0, 0.0, MarketSide::Yes, b).is_err());
+    }
+
+    #[test]
+    fn test_lmsr_shares_for_proceeds_roundtrip() {
+        let b = 100.0;
+        let shares = LmsrPricing::shares_for_proceeds(40.0, 10.0, 15.0, MarketSide::Yes, b).unwrap();
+        let proceeds = LmsrPricing::calculate_sell_proceeds(40.0, 10.0, shares, MarketSide::Yes, b).unwrap();
+        assert!((proceeds - 15.0).abs() < 1e-9);
+
+        // Cannot raise more than selling every outstanding share returns
+        let max = LmsrPricing::calculate_sell_proceeds(40.0, 10.0, 40.0, MarketSide::Yes, b).unwrap();
+        assert!(LmsrPricing::shares_for_proceeds(40.0, 10.0, max + 1.0, MarketSide::Yes, b).is_err());
+    }
+
+    #[test]
+    fn test_lmsr_loss_bounded_by_max_loss() {
+        let b = 100.0;
+        // Everyone buys YES and YES wins: the market maker pays q_yes and collected C(q) - C(0)
+        for q_yes in [10.0, 100.0, 1000.0, 10000.0] {
+            let collected = LmsrPricing::calculate_buy_cost(0.0, 0.0, q_yes, MarketSide::Yes, b).unwrap();
+            assert!(q_yes - collected <= LmsrPricing::max_loss(b) + 1e-9);
+        }
+    }
+
     // Old CPMM Tests (kept for backward compatibility)
     #[test]
     fn test_initial_probability() {
@@ -0,0 +1,110 @@
+//! Forecasting track record shown on public user profiles
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use crate::domain::{MarketId, MarketSide, Position};
+
+/// Profit and accuracy of a user's trading
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrackRecord {
+    /// Gains from selling shares plus settlement of shares held at resolution
+    pub realized_profit: f64,
+    /// Resolved markets in which the user held shares at resolution
+    pub resolved_markets: i64,
+    /// Of those, the markets where most of the user's shares were on the winning side
+    pub correct_calls: i64,
+}
+
+impl TrackRecord {
+    /// Build the record from every position of a user, open or sold
+    ///
+    /// `outcomes` holds the outcome of each resolved market; positions in other
+    /// markets only contribute what their sales realized.
+    pub fn from_positions(positions: &[Position], outcomes: &HashMap<MarketId, bool>) -> Self {
+        let mut record = TrackRecord::default();
+        // Shares held at resolution per market: (yes, no)
+        let mut holdings: BTreeMap<MarketId, (f64, f64)> = BTreeMap::new();
+
+        for position in positions {
+            record.realized_profit += position.realized_pnl;
+
+            let Some(&outcome) = outcomes.get(&position.market_id) else {
+                continue;
+            };
+            record.realized_profit += position.settlement_pnl(outcome);
+
+            if position.shares > 0.0 {
+                let held = holdings.entry(position.market_id).or_default();
+                match position.side {
+                    MarketSide::Yes => held.0 += position.shares,
+                    MarketSide::No => held.1 += position.shares,
+                }
+            }
+        }
+
+        for (market_id, (yes, no)) in holdings {
+            // A perfectly hedged holding makes no call either way
+            if yes == no {
+                continue;
+            }
+            let called = if yes > no { MarketSide::Yes } else { MarketSide::No };
+            record.resolved_markets += 1;
+            if called == MarketSide::from_outcome(outcomes[&market_id]) {
+                record.correct_calls += 1;
+            }
+        }
+
+        record
+    }
+
+    /// Share of resolved markets called correctly, if any have resolved
+    pub fn accuracy(&self) -> Option<f64> {
+        (self.resolved_markets > 0).then(|| self.correct_calls as f64 / self.resolved_markets as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn position(market_id: MarketId, side: MarketSide, shares: f64, avg_price: f64, realized_pnl: f64) -> Position {
+        let mut position = Position::new(1, 1, market_id, side, shares, avg_price, Utc::now(), Utc::now());
+        position.realized_pnl = realized_pnl;
+        position
+    }
+
+    #[test]
+    fn test_track_record() {
+        let positions = vec![
+            // Market 1 resolved YES: mostly YES, hedged with some NO
+            position(1, MarketSide::Yes, 10.0, 0.4, 0.0),
+            position(1, MarketSide::No, 2.0, 0.5, 0.0),
+            // Market 2 resolved YES: called NO
+            position(2, MarketSide::No, 5.0, 0.2, 0.0),
+            // Market 3 resolved NO, but sold out before resolution
+            position(3, MarketSide::Yes, 0.0, 0.5, 1.5),
+            // Market 4 still open
+            position(4, MarketSide::Yes, 3.0, 0.5, -0.5),
+        ];
+        let outcomes = HashMap::from([(1, true), (2, true), (3, false)]);
+
+        let record = TrackRecord::from_positions(&positions, &outcomes);
+        // 1: +6 and -1; 2: -1; 3: +1.5 sold; 4: -0.5 sold
+        assert!((record.realized_profit - 5.0).abs() < 1e-9);
+        assert_eq!(record.resolved_markets, 2);
+        assert_eq!(record.correct_calls, 1);
+        assert_eq!(record.accuracy(), Some(0.5));
+    }
+
+    #[test]
+    fn test_no_resolved_markets() {
+        let record = TrackRecord::from_positions(&[position(1, MarketSide::Yes, 1.0, 0.5, 0.0)], &HashMap::new());
+        assert_eq!(record, TrackRecord::default());
+        assert_eq!(record.accuracy(), None);
+
+        let hedged = [position(1, MarketSide::Yes, 4.0, 0.5, 0.0), position(1, MarketSide::No, 4.0, 0.5, 0.0)];
+        let record = TrackRecord::from_positions(&hedged, &HashMap::from([(1, false)]));
+        assert_eq!(record.resolved_markets, 0);
+        assert!((record.realized_profit - 0.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,194 @@
+//! Consistency checks over markets, positions and balances
+//!
+//! Every figure the exchange caches can be recomputed from another record:
+//! outstanding shares from positions, the market maker's takings from the LMSR
+//! cost function, and balances from the grants, trades and payouts a user has
+//! made. A `Discrepancy` is any place where the two disagree.
+use serde::Serialize;
+use crate::domain::{Market, MarketId, MarketSide, PositionId, UserId, LEDGER_EPSILON};
+
+/// One place where the books do not add up
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Discrepancy {
+    /// A market's outstanding shares differ from the shares held in its positions
+    OutstandingShares {
+        market_id: MarketId,
+        side: MarketSide,
+        stored: f64,
+        positions: f64,
+    },
+    /// A position holds a negative number of shares
+    NegativeShares {
+        position_id: PositionId,
+        user_id: UserId,
+        market_id: MarketId,
+        shares: f64,
+    },
+    /// The market maker's ledger account differs from what the cost function says it has taken in
+    MarketMakerPnl {
+        market_id: MarketId,
+        ledger: f64,
+        expected: f64,
+    },
+    /// A cached `User::balance` differs from the user's ledger account
+    CachedBalance {
+        user_id: UserId,
+        cached: f64,
+        ledger: f64,
+    },
+    /// A balance differs from the user's grants plus the trades and payouts in their history
+    TradeHistory {
+        user_id: UserId,
+        balance: f64,
+        expected: f64,
+    },
+}
+
+impl Discrepancy {
+    /// Whether `repair` can fix this discrepancy by rewriting the cached figure
+    ///
+    /// Outstanding shares are rebuilt from positions and cached balances from
+    /// the ledger. The rest need a person to decide which record is wrong.
+    pub fn is_repairable(&self) -> bool {
+        matches!(self, Discrepancy::OutstandingShares { .. } | Discrepancy::CachedBalance { .. })
+    }
+}
+
+impl std::fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Discrepancy::OutstandingShares { market_id, side, stored, positions } => write!(
+                f,
+                "market {} has {:.6} {} shares outstanding but its positions hold {:.6}",
+                market_id, stored, side, positions
+            ),
+            Discrepancy::NegativeShares { position_id, user_id, market_id, shares } => write!(
+                f,
+                "position {} of user {} in market {} holds {:.6} shares",
+                position_id, user_id, market_id, shares
+            ),
+            Discrepancy::MarketMakerPnl { market_id, ledger, expected } => write!(
+                f,
+                "market maker of market {} holds {:.6} but the cost function says {:.6}",
+                market_id, ledger, expected
+            ),
+            Discrepancy::CachedBalance { user_id, cached, ledger } => write!(
+                f,
+                "balance of user {} is {:.6} but the ledger says {:.6}",
+                user_id, cached, ledger
+            ),
+            Discrepancy::TradeHistory { user_id, balance, expected } => write!(
+                f,
+                "balance of user {} is {:.6} but grants, trades and payouts add up to {:.6}",
+                user_id, balance, expected
+            ),
+        }
+    }
+}
+
+/// Result of one run of the checker
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReconciliationReport {
+    pub markets_checked: usize,
+    pub positions_checked: usize,
+    pub users_checked: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ReconciliationReport {
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+
+    pub fn repairable(&self) -> impl Iterator<Item = &Discrepancy> {
+        self.discrepancies.iter().filter(|d| d.is_repairable())
+    }
+}
+
+/// What the market maker of a market should hold
+///
+/// It starts with the subsidy paid in by the creator and liquidity providers,
+/// collects C(q) less the cost base from traders and $1 for every complete set
+/// still outstanding, and once the market resolves
+/// pays $1 for every winning share held in a position. Whatever is left then
+/// goes to the providers; a market nobody subsidised keeps it.
+pub fn expected_market_maker_balance(market: &Market) -> f64 {
+    let paid_out = match (market.resolved, market.outcome) {
+        (true, Some(true)) => market.outstanding_shares(MarketSide::Yes),
+        (true, Some(false)) => market.outstanding_shares(MarketSide::No),
+        _ => 0.0,
+    };
+    let balance = market.subsidy + market.collected() + market.complete_sets - paid_out;
+    if market.resolved && market.subsidy > 0.0 {
+        balance.min(0.0)
+    } else {
+        balance
+    }
+}
+
+/// Whether two amounts differ by more than rounding
+pub fn differs(a: f64, b: f64) -> bool {
+    let diff = (a - b).abs();
+    diff.is_nan() || diff > LEDGER_EPSILON
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::LmsrPricing;
+    use chrono::Utc;
+
+    #[test]
+    fn test_expected_market_maker_balance() {
+        let b = 100.0;
+        let mut market = Market::new_lmsr(1, "Q?".to_string(), None, 1, None, Utc::now(), b, Utc::now());
+        assert!(expected_market_maker_balance(&market).abs() < 1e-9);
+
+        // Buying from an empty market pays exactly the buy cost to the market maker
+        let cost = LmsrPricing::calculate_buy_cost(0.0, 0.0, 30.0, MarketSide::Yes, b).unwrap();
+        market.q_yes = 30.0;
+        assert!((expected_market_maker_balance(&market) - cost).abs() < 1e-9);
+
+        // Resolving YES pays out every YES share; resolving NO pays nothing
+        let mut yes = market.clone();
+        yes.resolved = true;
+        yes.outcome = Some(true);
+        assert!((expected_market_maker_balance(&yes) - (cost - 30.0)).abs() < 1e-9);
+        let mut no = yes.clone();
+        no.outcome = Some(false);
+        assert!((expected_market_maker_balance(&no) - cost).abs() < 1e-9);
+
+        // Each complete set outstanding adds $1 and pays out $1 whichever side wins
+        let mut sets = no.clone();
+        sets.complete_sets = 10.0;
+        assert!((expected_market_maker_balance(&sets) - cost).abs() < 1e-9);
+        sets.outcome = Some(true);
+        assert!((expected_market_maker_balance(&sets) - (cost - 30.0)).abs() < 1e-9);
+
+        // A subsidised market holds the subsidy until resolution, then hands everything left to its providers
+        market.subsidy = LmsrPricing::max_loss(b);
+        assert!((expected_market_maker_balance(&market) - (market.subsidy + cost)).abs() < 1e-9);
+        market.add_liquidity(20.0).unwrap();
+        assert!((expected_market_maker_balance(&market) - (market.subsidy + cost)).abs() < 1e-9);
+        market.resolved = true;
+        market.outcome = Some(true);
+        assert!(expected_market_maker_balance(&market).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_differs() {
+        assert!(!differs(1.0, 1.0 + LEDGER_EPSILON / 2.0));
+        assert!(differs(1.0, 1.001));
+        assert!(differs(f64::NAN, 0.0));
+    }
+
+    #[test]
+    fn test_repairable() {
+        let shares = Discrepancy::OutstandingShares { market_id: 1, side: MarketSide::Yes, stored: 5.0, positions: 4.0 };
+        let negative = Discrepancy::NegativeShares { position_id: 1, user_id: 2, market_id: 1, shares: -1.0 };
+        let report = ReconciliationReport { discrepancies: vec![shares.clone(), negative], ..Default::default() };
+        assert!(!report.is_clean());
+        assert_eq!(report.repairable().collect::<Vec<_>>(), vec![&shares]);
+    }
+}
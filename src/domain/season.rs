@@ -0,0 +1,203 @@
+//! Forecasting competitions with their own balances, markets and leaderboard
+//!
+//! Joining a season grants its starting balance into a separate ledger account
+//! (`LedgerAccount::Season`). Trades, payouts and complete sets in the season's
+//! markets go through that account and leave the participant's main balance
+//! alone. Once the season ends its standings are ranked and archived.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::UserId;
+
+pub type SeasonId = i64;
+
+/// Longest season name, in characters
+pub const MAX_SEASON_NAME_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeasonStatus {
+    /// Open to join but not yet to trade
+    Upcoming,
+    Active,
+    Ended,
+}
+
+impl std::fmt::Display for SeasonStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeasonStatus::Upcoming => write!(f, "upcoming"),
+            SeasonStatus::Active => write!(f, "active"),
+            SeasonStatus::Ended => write!(f, "ended"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Season {
+    pub id: SeasonId,
+    pub name: String,
+    pub description: Option<String>,
+    /// Granted to every participant when they join
+    pub starting_balance: f64,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    /// When the final standings were archived
+    pub archived_at: Option<DateTime<Utc>>,
+    pub created_by: UserId,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Season {
+    pub fn status(&self) -> SeasonStatus {
+        let now = Utc::now();
+        if self.archived_at.is_some() || now >= self.ends_at {
+            SeasonStatus::Ended
+        } else if now < self.starts_at {
+            SeasonStatus::Upcoming
+        } else {
+            SeasonStatus::Active
+        }
+    }
+
+    /// Whether the season's markets are open to trading
+    pub fn is_active(&self) -> bool {
+        self.status() == SeasonStatus::Active
+    }
+
+    pub fn can_join(&self) -> bool {
+        self.status() != SeasonStatus::Ended
+    }
+
+    /// Whether the season is over but its standings have not been archived yet
+    pub fn needs_archiving(&self) -> bool {
+        self.archived_at.is_none() && self.status() == SeasonStatus::Ended
+    }
+}
+
+/// Check the terms of a new season
+pub fn validate_season(
+    name: &str,
+    starting_balance: f64,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Name is required".to_string());
+    }
+    if name.chars().count() > MAX_SEASON_NAME_LEN {
+        return Err(format!("Name must be at most {} characters", MAX_SEASON_NAME_LEN));
+    }
+    if !starting_balance.is_finite() || starting_balance <= 0.0 {
+        return Err("Starting balance must be positive".to_string());
+    }
+    if ends_at <= starts_at {
+        return Err("Season must end after it starts".to_string());
+    }
+    if ends_at <= Utc::now() {
+        return Err("Season must end in the future".to_string());
+    }
+    Ok(())
+}
+
+/// One participant's place in a season
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeasonStanding {
+    pub user_id: UserId,
+    pub username: String,
+    /// 1 for the leader; participants with the same total share a rank
+    pub rank: i64,
+    /// Season balance
+    pub balance: f64,
+    /// Positions in the season's markets, marked to market
+    pub positions_value: f64,
+    pub total_value: f64,
+}
+
+impl SeasonStanding {
+    pub fn new(user_id: UserId, username: String, balance: f64, positions_value: f64) -> Self {
+        Self {
+            user_id,
+            username,
+            rank: 0,
+            balance,
+            positions_value,
+            total_value: balance + positions_value,
+        }
+    }
+}
+
+/// Order standings by total value, highest first, and number their ranks
+pub fn rank_standings(mut standings: Vec<SeasonStanding>) -> Vec<SeasonStanding> {
+    standings.sort_by(|a, b| b.total_value.total_cmp(&a.total_value).then(a.user_id.cmp(&b.user_id)));
+
+    let mut previous: Option<(f64, i64)> = None;
+    for (i, standing) in standings.iter_mut().enumerate() {
+        standing.rank = match previous {
+            Some((total, rank)) if total == standing.total_value => rank,
+            _ => i as i64 + 1,
+        };
+        previous = Some((standing.total_value, standing.rank));
+    }
+    standings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn season(starts_in: i64, ends_in: i64) -> Season {
+        Season {
+            id: 1,
+            name: "Q1".to_string(),
+            description: None,
+            starting_balance: 1000.0,
+            starts_at: Utc::now() + Duration::days(starts_in),
+            ends_at: Utc::now() + Duration::days(ends_in),
+            archived_at: None,
+            created_by: 1,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_season_status() {
+        assert_eq!(season(1, 10).status(), SeasonStatus::Upcoming);
+        assert!(season(1, 10).can_join());
+        assert!(!season(1, 10).is_active());
+
+        assert!(season(-1, 10).is_active());
+
+        let ended = season(-10, -1);
+        assert_eq!(ended.status(), SeasonStatus::Ended);
+        assert!(!ended.can_join());
+        assert!(ended.needs_archiving());
+
+        let mut archived = season(-1, 10);
+        archived.archived_at = Some(Utc::now());
+        assert_eq!(archived.status(), SeasonStatus::Ended);
+        assert!(!archived.needs_archiving());
+    }
+
+    #[test]
+    fn test_validate_season() {
+        let now = Utc::now();
+        assert!(validate_season("Q1", 1000.0, now, now + Duration::days(90)).is_ok());
+        assert!(validate_season(" ", 1000.0, now, now + Duration::days(90)).is_err());
+        assert!(validate_season("Q1", 0.0, now, now + Duration::days(90)).is_err());
+        assert!(validate_season("Q1", 1000.0, now, now).is_err());
+        assert!(validate_season("Q1", 1000.0, now - Duration::days(90), now - Duration::days(1)).is_err());
+    }
+
+    #[test]
+    fn test_rank_standings() {
+        let standings = rank_standings(vec![
+            SeasonStanding::new(1, "a".to_string(), 900.0, 0.0),
+            SeasonStanding::new(2, "b".to_string(), 1000.0, 200.0),
+            SeasonStanding::new(3, "c".to_string(), 800.0, 100.0),
+            SeasonStanding::new(4, "d".to_string(), 500.0, 0.0),
+        ]);
+        let ranks: Vec<(UserId, i64)> = standings.iter().map(|s| (s.user_id, s.rank)).collect();
+        assert_eq!(ranks, vec![(2, 1), (1, 2), (3, 2), (4, 4)]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Time-based one-time passwords (RFC 6238) for two-factor authentication
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use crate::domain::{Role, UserId};
+
+/// Digits in a generated code
+pub const TOTP_DIGITS: u32 = 6;
+
+/// Seconds each code is valid for
+pub const TOTP_PERIOD_SECS: i64 = 30;
+
+/// Neighbouring time steps accepted on either side to tolerate clock drift
+const TOTP_SKEW_STEPS: i64 = 1;
+
+/// Recovery codes issued at enrollment
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Secret length in bytes (160 bits, as recommended for HMAC-SHA1)
+const SECRET_LEN: usize = 20;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A shared TOTP secret
+#[derive(Clone, PartialEq, Eq)]
+pub struct TotpSecret(Vec<u8>);
+
+impl std::fmt::Debug for TotpSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TotpSecret(..)")
+    }
+}
+
+impl TotpSecret {
+    pub fn generate() -> Self {
+        let mut bytes = vec![0u8; SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        TotpSecret(bytes)
+    }
+
+    /// Parse an unpadded base32 secret, as shown to users and stored in the database
+    pub fn from_base32(encoded: &str) -> Option<Self> {
+        base32_decode(encoded).filter(|bytes| !bytes.is_empty()).map(TotpSecret)
+    }
+
+    pub fn to_base32(&self) -> String {
+        base32_encode(&self.0)
+    }
+
+    /// The code for a given time step
+    pub fn code_at(&self, step: i64) -> String {
+        hotp(&self.0, step as u64, TOTP_DIGITS)
+    }
+
+    /// Check `code` against the steps around `now`, returning the step it matched
+    ///
+    /// Callers must reject steps at or before the last one accepted for the user,
+    /// so that a code cannot be replayed.
+    pub fn verify(&self, code: &str, now: DateTime<Utc>) -> Option<i64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let current = time_step(now);
+        (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+            .find(|&step| constant_time_eq(self.code_at(step).as_bytes(), code.as_bytes()))
+    }
+
+    /// `otpauth://` URI understood by authenticator apps, usually shown as a QR code
+    pub fn provisioning_uri(&self, issuer: &str, account: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            uri_escape(issuer),
+            uri_escape(account),
+            self.to_base32(),
+            uri_escape(issuer),
+            TOTP_DIGITS,
+            TOTP_PERIOD_SECS
+        )
+    }
+}
+
+/// A user's confirmed TOTP enrollment
+///
+/// Deliberately not serializable, so the secret can't leak into an API response.
+#[derive(Debug, Clone)]
+pub struct TotpCredential {
+    pub user_id: UserId,
+    pub secret: TotpSecret,
+    /// Most recent time step accepted, to reject replayed codes
+    pub last_used_step: Option<i64>,
+    pub enabled_at: DateTime<Utc>,
+}
+
+/// Who must enroll in two-factor authentication before using privileged features
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwoFactorPolicy {
+    /// Users with at least this role; `None` requires it of no role
+    pub required_role: Option<Role>,
+    /// Designated oracles, before they close or resolve a market
+    pub required_for_oracles: bool,
+}
+
+impl TwoFactorPolicy {
+    pub fn requires_for_role(&self, role: Role) -> bool {
+        self.required_role.is_some_and(|required| role.has(required))
+    }
+}
+
+/// The time step containing `now`
+pub fn time_step(now: DateTime<Utc>) -> i64 {
+    now.timestamp().div_euclid(TOTP_PERIOD_SECS)
+}
+
+/// Generate single-use recovery codes, returning them with the hashes to store
+pub fn generate_recovery_codes() -> Vec<(String, String)> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let hex = hex::encode(bytes);
+            let code = format!("{}-{}", &hex[..5], &hex[5..]);
+            let hash = hash_recovery_code(&code);
+            (code, hash)
+        })
+        .collect()
+}
+
+/// Hash a recovery code as entered, ignoring case, spaces and dashes
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// Whether `input` looks like a recovery code rather than a TOTP code
+pub fn is_recovery_code(input: &str) -> bool {
+    input.chars().filter(|c| c.is_ascii_alphanumeric()).count() == 10
+}
+
+/// HMAC-based one-time password (RFC 4226)
+fn hotp(key: &[u8], counter: u64, digits: u32) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]])
+        & 0x7fff_ffff;
+    format!("{:0width$}", binary % 10u32.pow(digits), width = digits as usize)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    output
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|&a| a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+fn uri_escape(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // RFC 6238 appendix B, SHA-1 variant
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let cases = [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+        ];
+        for (timestamp, expected) in cases {
+            let step = time_step(Utc.timestamp_opt(timestamp, 0).unwrap());
+            assert_eq!(hotp(RFC_SECRET, step as u64, 8), expected);
+        }
+    }
+
+    #[test]
+    fn test_base32_roundtrip() {
+        assert_eq!(base32_encode(RFC_SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode("gezdgnbvgy3tqojq gezdgnbvgy3tqojq").unwrap(), RFC_SECRET);
+        assert_eq!(base32_decode("MZXW6==="), Some(b"foo".to_vec()));
+        assert_eq!(base32_decode("not base32!"), None);
+
+        let secret = TotpSecret::generate();
+        assert_eq!(TotpSecret::from_base32(&secret.to_base32()), Some(secret));
+    }
+
+    #[test]
+    fn test_verify_with_skew() {
+        let secret = TotpSecret(RFC_SECRET.to_vec());
+        let now = Utc.timestamp_opt(1111111111, 0).unwrap();
+        let step = time_step(now);
+
+        assert_eq!(secret.verify(&secret.code_at(step), now), Some(step));
+        assert_eq!(secret.verify(&secret.code_at(step - 1), now), Some(step - 1));
+        assert_eq!(secret.verify(&secret.code_at(step + 1), now), Some(step + 1));
+        assert_eq!(secret.verify(&secret.code_at(step - 2), now), None);
+        assert_eq!(secret.verify("12345", now), None);
+        assert_eq!(secret.verify("abcdef", now), None);
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        let (code, hash) = &codes[0];
+        assert!(is_recovery_code(code));
+        assert_eq!(&hash_recovery_code(&code.to_uppercase().replace('-', " ")), hash);
+        assert!(!is_recovery_code("123456"));
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = TwoFactorPolicy { required_role: Some(Role::Moderator), required_for_oracles: false };
+        assert!(policy.requires_for_role(Role::Admin));
+        assert!(policy.requires_for_role(Role::Moderator));
+        assert!(!policy.requires_for_role(Role::User));
+        let optional = TwoFactorPolicy { required_role: None, required_for_oracles: false };
+        assert!(!optional.requires_for_role(Role::Admin));
+    }
+}
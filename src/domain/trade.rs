@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use crate::domain::{LmsrPricing, MarketSide};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeAction {
+    Buy,
+    Sell,
+}
+
+impl std::fmt::Display for TradeAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradeAction::Buy => write!(f, "buy"),
+            TradeAction::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+impl std::str::FromStr for TradeAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "buy" => Ok(TradeAction::Buy),
+            "sell" => Ok(TradeAction::Sell),
+            _ => Err(format!("Invalid trade action: {}", s)),
+        }
+    }
+}
+
+/// How the size of a trade is specified
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TradeSize {
+    /// A fixed number of shares
+    Shares(f64),
+    /// A currency amount to spend (buy) or receive (sell)
+    Amount(f64),
+}
+
+/// Full preview of a trade against the LMSR market maker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeQuote {
+    pub action: TradeAction,
+    pub side: MarketSide,
+    pub shares: f64,
+    /// Cost paid for a buy, proceeds received for a sell
+    pub amount: f64,
+    pub avg_price: f64,
+    /// Instantaneous price of `side` before and after the trade
+    pub price_before: f64,
+    pub price_after: f64,
+    /// Implied YES probability before and after the trade
+    pub probability_before: f64,
+    pub probability_after: f64,
+    /// Relative change of the traded side's price, in percent
+    pub price_impact_pct: f64,
+    pub q_yes_after: f64,
+    pub q_no_after: f64,
+}
+
+impl TradeQuote {
+    pub fn new(
+        q_yes: f64,
+        q_no: f64,
+        b: f64,
+        action: TradeAction,
+        side: MarketSide,
+        size: TradeSize,
+    ) -> Result<Self, String> {
+        let (shares, amount) = match (action, size) {
+            (TradeAction::Buy, TradeSize::Shares(shares)) => {
+                (shares, LmsrPricing::calculate_buy_cost(q_yes, q_no, shares, side, b)?)
+            }
+            (TradeAction::Buy, TradeSize::Amount(cost)) => {
+                (LmsrPricing::shares_for_cost(q_yes, q_no, cost, side, b)?, cost)
+            }
+            (TradeAction::Sell, TradeSize::Shares(shares)) => {
+                (shares, LmsrPricing::calculate_sell_proceeds(q_yes, q_no, shares, side, b)?)
+            }
+            (TradeAction::Sell, TradeSize::Amount(proceeds)) => {
+                (LmsrPricing::shares_for_proceeds(q_yes, q_no, proceeds, side, b)?, proceeds)
+            }
+        };
+
+        let delta = match action {
+            TradeAction::Buy => shares,
+            TradeAction::Sell => -shares,
+        };
+        let (q_yes_after, q_no_after) = match side {
+            MarketSide::Yes => (q_yes + delta, q_no),
+            MarketSide::No => (q_yes, q_no + delta),
+        };
+
+        let price_before = LmsrPricing::instantaneous_price(q_yes, q_no, side, b);
+        let price_after = LmsrPricing::instantaneous_price(q_yes_after, q_no_after, side, b);
+
+        Ok(Self {
+            action,
+            side,
+            shares,
+            amount,
+            avg_price: amount / shares,
+            price_before,
+            price_after,
+            probability_before: LmsrPricing::implied_probability(q_yes, q_no, b),
+            probability_after: LmsrPricing::implied_probability(q_yes_after, q_no_after, b),
+            price_impact_pct: (price_after - price_before) / price_before * 100.0,
+            q_yes_after,
+            q_no_after,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buy_quote_moves_price_up() {
+        let quote = TradeQuote::new(0.0, 0.0, 100.0, TradeAction::Buy, MarketSide::Yes, TradeSize::Shares(10.0)).unwrap();
+        assert_eq!(quote.q_yes_after, 10.0);
+        assert!((quote.price_before - 0.5).abs() < 1e-9);
+        assert!(quote.price_after > quote.price_before);
+        assert!(quote.price_impact_pct > 0.0);
+        assert!(quote.avg_price > quote.price_before && quote.avg_price < quote.price_after);
+    }
+
+    #[test]
+    fn test_sell_quote_by_amount() {
+        let quote = TradeQuote::new(0.0, 50.0, 100.0, TradeAction::Sell, MarketSide::No, TradeSize::Amount(10.0)).unwrap();
+        assert!((quote.amount - 10.0).abs() < 1e-9);
+        assert!((quote.q_no_after - (50.0 - quote.shares)).abs() < 1e-9);
+        assert!(quote.price_impact_pct < 0.0);
+        // Selling NO raises the YES probability
+        assert!(quote.probability_after > quote.probability_before);
+    }
+
+    #[test]
+    fn test_sell_more_than_outstanding() {
+        assert!(TradeQuote::new(5.0, 0.0, 100.0, TradeAction::Sell, MarketSide::Yes, TradeSize::Shares(10.0)).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::{MarketId, MarketSide, UserId};
+
+pub type TransactionId = i64;
+
+/// Kind of money movement recorded in a user's history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    Buy,
+    Sell,
+    /// Winning shares redeemed at resolution
+    Payout,
+    /// Allowance or top-up issued to the user, not tied to a market
+    Grant,
+    /// Complete sets bought from the market maker for $1 each, recorded once per
+    /// side at the share of the $1 that side was bought for
+    Mint,
+    /// Matched YES and NO pairs sold back to the market maker for $1 each, recorded like mints
+    Redeem,
+}
+
+impl std::fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionType::Buy => write!(f, "buy"),
+            TransactionType::Sell => write!(f, "sell"),
+            TransactionType::Payout => write!(f, "payout"),
+            TransactionType::Grant => write!(f, "grant"),
+            TransactionType::Mint => write!(f, "mint"),
+            TransactionType::Redeem => write!(f, "redeem"),
+        }
+    }
+}
+
+impl std::str::FromStr for TransactionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "buy" => Ok(TransactionType::Buy),
+            "sell" => Ok(TransactionType::Sell),
+            "payout" => Ok(TransactionType::Payout),
+            "grant" => Ok(TransactionType::Grant),
+            "mint" => Ok(TransactionType::Mint),
+            "redeem" => Ok(TransactionType::Redeem),
+            _ => Err(format!("Invalid transaction type: {}", s)),
+        }
+    }
+}
+
+/// A recorded trade, complete set, payout or grant, kept as the user's trade history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: TransactionId,
+    pub user_id: UserId,
+    /// `None` for grants
+    pub market_id: Option<MarketId>,
+    pub transaction_type: TransactionType,
+    pub side: Option<MarketSide>,
+    pub shares: f64,
+    /// Average price per share
+    pub price: f64,
+    /// Paid for a buy or mint, received for a sell, redemption, payout or grant
+    pub amount: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_type_roundtrip() {
+        for transaction_type in [
+            TransactionType::Buy,
+            TransactionType::Sell,
+            TransactionType::Payout,
+            TransactionType::Grant,
+            TransactionType::Mint,
+            TransactionType::Redeem,
+        ] {
+            assert_eq!(transaction_type.to_string().parse::<TransactionType>(), Ok(transaction_type));
+        }
+        assert!("refund".parse::<TransactionType>().is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Balance sent from one user to another, e.g. to settle side bets or tip
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::UserId;
+
+pub type TransferId = i64;
+
+/// Longest memo that can accompany a transfer, in characters
+pub const MAX_TRANSFER_MEMO_LEN: usize = 200;
+
+/// A completed transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub id: TransferId,
+    pub sender_id: UserId,
+    pub sender: String,
+    pub recipient_id: UserId,
+    pub recipient: String,
+    pub amount: f64,
+    pub memo: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How much a user may send
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferLimits {
+    /// Largest single transfer
+    pub max_amount: f64,
+    /// Most a user may send in total over any 24 hours
+    pub daily_limit: f64,
+}
+
+impl TransferLimits {
+    /// Check a transfer of `amount` by a user who has sent `sent_today` in the last 24 hours
+    pub fn check(&self, amount: f64, sent_today: f64) -> Result<(), String> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err("Amount must be positive".to_string());
+        }
+        if amount > self.max_amount {
+            return Err(format!("Transfers are limited to ${:.2} each", self.max_amount));
+        }
+        if sent_today + amount > self.daily_limit {
+            return Err(format!(
+                "Transfers are limited to ${:.2} a day; ${:.2} left today",
+                self.daily_limit,
+                (self.daily_limit - sent_today).max(0.0)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Trim a memo, treating an empty one as absent
+pub fn normalize_memo(memo: Option<&str>) -> Result<Option<String>, String> {
+    let Some(memo) = memo.map(str::trim).filter(|m| !m.is_empty()) else {
+        return Ok(None);
+    };
+    if memo.chars().count() > MAX_TRANSFER_MEMO_LEN {
+        return Err(format!("Memo must be at most {} characters", MAX_TRANSFER_MEMO_LEN));
+    }
+    Ok(Some(memo.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let limits = TransferLimits { max_amount: 100.0, daily_limit: 250.0 };
+        assert!(limits.check(100.0, 0.0).is_ok());
+        assert!(limits.check(100.01, 0.0).is_err());
+        assert!(limits.check(50.0, 200.0).is_ok());
+        assert!(limits.check(50.01, 200.0).is_err());
+        assert!(limits.check(0.0, 0.0).is_err());
+        assert!(limits.check(-5.0, 0.0).is_err());
+        assert!(limits.check(f64::NAN, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_normalize_memo() {
+        assert_eq!(normalize_memo(None), Ok(None));
+        assert_eq!(normalize_memo(Some("   ")), Ok(None));
+        assert_eq!(normalize_memo(Some(" lunch bet ")), Ok(Some("lunch bet".to_string())));
+        assert!(normalize_memo(Some(&"x".repeat(MAX_TRANSFER_MEMO_LEN + 1))).is_err());
+    }
+}
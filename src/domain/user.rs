@@ -3,13 +3,127 @@ use serde::{Deserialize, Serialize};
 
 pub type UserId = i64;
 
+/// Balance given to accounts created through signup
+pub const DEFAULT_STARTING_BALANCE: f64 = 1000.0;
+
+/// Shortest password accepted at signup or on change
+pub const MIN_PASSWORD_LEN: usize = 6;
+
+/// Longest display name shown on a public profile
+pub const MAX_DISPLAY_NAME_LEN: usize = 50;
+
+/// Longest bio shown on a public profile
+pub const MAX_BIO_LEN: usize = 500;
+
+/// Longest email address accepted (RFC 5321)
+pub const MAX_EMAIL_LEN: usize = 254;
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.len() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+/// Trim optional profile text, treating blank input as unset
+pub fn normalize_profile_text(input: &str, max_len: usize, field: &str) -> Result<Option<String>, String> {
+    let trimmed = input.trim();
+    if trimmed.chars().count() > max_len {
+        return Err(format!("{} must be at most {} characters", field, max_len));
+    }
+    Ok((!trimmed.is_empty()).then(|| trimmed.to_string()))
+}
+
+/// Trim an optional email address, treating blank input as unset
+///
+/// Only the shape is checked: one `@` with text on both sides and nothing that
+/// could break out of a mail header.
+pub fn normalize_email(input: &str) -> Result<Option<String>, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if trimmed.len() > MAX_EMAIL_LEN {
+        return Err(format!("Email must be at most {} characters", MAX_EMAIL_LEN));
+    }
+    let valid = match trimmed.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.is_empty()
+                && !domain.contains('@')
+                && trimmed.chars().all(|c| c.is_ascii_graphic() && !matches!(c, '<' | '>' | ',' | ';'))
+        }
+        None => false,
+    };
+    if !valid {
+        return Err("Invalid email address".to_string());
+    }
+    Ok(Some(trimmed.to_string()))
+}
+
+/// Privilege level of a user, ordered from least to most privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    /// Can close any market to trading
+    Moderator,
+    /// Can additionally manage users and their roles
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::User, Role::Moderator, Role::Admin];
+
+    /// Whether this role grants at least the privileges of `required`
+    pub fn has(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::User => write!(f, "user"),
+            Role::Moderator => write!(f, "moderator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Invalid role: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: UserId,
     pub username: String,
     pub password_hash: String,
     pub balance: f64,
+    pub role: Role,
+    /// Incremented on password change or reset to log out existing sessions
+    pub session_version: i64,
+    /// Name shown on the public profile instead of the username
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// Keep open positions off the public profile
+    pub hide_positions: bool,
+    /// Where password reset links are sent; never shown publicly
+    pub email: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// Set once the account has been deleted and anonymized
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl User {
@@ -19,10 +133,31 @@ impl User {
             username,
             password_hash,
             balance,
+            role: Role::User,
+            session_version: 0,
+            display_name: None,
+            bio: None,
+            hide_positions: false,
+            email: None,
             created_at,
+            deleted_at: None,
         }
     }
 
+    /// Display name if set, otherwise the username
+    pub fn public_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.username)
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Username given to an account when it is deleted
+    pub fn anonymized_username(id: UserId) -> String {
+        format!("deleted-{}", id)
+    }
+
     pub fn can_afford(&self, amount: f64) -> bool {
         self.balance >= amount
     }
@@ -61,10 +196,45 @@ mod tests {
         assert_eq!(user.balance, 50.0); // Should not change on error
     }
 
+    #[test]
+    fn test_role_ordering() {
+        assert!(Role::Admin.has(Role::Moderator));
+        assert!(Role::Moderator.has(Role::Moderator));
+        assert!(!Role::Moderator.has(Role::Admin));
+        assert!(!Role::User.has(Role::Moderator));
+        for role in Role::ALL {
+            assert_eq!(role.to_string().parse::<Role>().unwrap(), role);
+        }
+        assert!("root".parse::<Role>().is_err());
+    }
+
     #[test]
     fn test_add_balance() {
         let mut user = User::new(1, "test".to_string(), "hash".to_string(), 100.0, Utc::now());
         user.add_balance(50.0);
         assert_eq!(user.balance, 150.0);
     }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email("  ada@example.com "), Ok(Some("ada@example.com".to_string())));
+        assert_eq!(normalize_email("  "), Ok(None));
+        let invalid = ["ada", "@example.com", "ada@", "a@b@c", "ada lovelace@example.com", "ada@example.com\r\nBcc: x@y"];
+        for invalid in invalid {
+            assert!(normalize_email(invalid).is_err(), "{}", invalid);
+        }
+        assert!(normalize_email(&format!("{}@example.com", "a".repeat(MAX_EMAIL_LEN))).is_err());
+    }
+
+    #[test]
+    fn test_profile_text() {
+        assert_eq!(normalize_profile_text("  Ada  ", MAX_DISPLAY_NAME_LEN, "Display name"), Ok(Some("Ada".to_string())));
+        assert_eq!(normalize_profile_text("   ", MAX_DISPLAY_NAME_LEN, "Display name"), Ok(None));
+        assert!(normalize_profile_text(&"x".repeat(MAX_BIO_LEN + 1), MAX_BIO_LEN, "Bio").is_err());
+
+        let mut user = User::new(1, "ada".to_string(), "hash".to_string(), 100.0, Utc::now());
+        assert_eq!(user.public_name(), "ada");
+        user.display_name = Some("Ada Lovelace".to_string());
+        assert_eq!(user.public_name(), "Ada Lovelace");
+    }
 }
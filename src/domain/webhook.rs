@@ -0,0 +1,249 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::domain::{MarketId, UserId};
+
+pub type WebhookId = i64;
+
+/// Deliveries are abandoned after this many failed attempts
+pub const MAX_DELIVERY_ATTEMPTS: i64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "market.created")]
+    MarketCreated,
+    #[serde(rename = "market.traded")]
+    MarketTraded,
+    #[serde(rename = "market.closed")]
+    MarketClosed,
+    #[serde(rename = "market.resolved")]
+    MarketResolved,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::MarketCreated,
+        WebhookEvent::MarketTraded,
+        WebhookEvent::MarketClosed,
+        WebhookEvent::MarketResolved,
+    ];
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookEvent::MarketCreated => write!(f, "market.created"),
+            WebhookEvent::MarketTraded => write!(f, "market.traded"),
+            WebhookEvent::MarketClosed => write!(f, "market.closed"),
+            WebhookEvent::MarketResolved => write!(f, "market.resolved"),
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.to_string() == s)
+            .ok_or_else(|| format!("Invalid webhook event: {}", s))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSubscription {
+    pub id: WebhookId,
+    pub user_id: UserId,
+    /// `None` subscribes to every market
+    pub market_id: Option<MarketId>,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookSubscription {
+    pub fn matches(&self, event: WebhookEvent, market_id: MarketId) -> bool {
+        self.active
+            && self.events.contains(&event)
+            && self.market_id.is_none_or(|id| id == market_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryStatus::Pending => write!(f, "pending"),
+            DeliveryStatus::Delivered => write!(f, "delivered"),
+            DeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(format!("Invalid delivery status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription_id: WebhookId,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    /// Delay before the next attempt after `attempts` failures, or `None` once exhausted
+    ///
+    /// Exponential backoff starting at 30 seconds and capped at one hour.
+    pub fn retry_delay(attempts: i64) -> Option<Duration> {
+        if attempts >= MAX_DELIVERY_ATTEMPTS {
+            return None;
+        }
+        let exponent = (attempts - 1).clamp(0, 10) as u32;
+        Some(Duration::seconds((30 * 2i64.pow(exponent)).min(60 * 60)))
+    }
+}
+
+/// Sign a payload as `sha256=<hex HMAC-SHA256(secret, "{timestamp}.{body}")>`
+///
+/// Receivers recompute this over the `X-Webhook-Timestamp` header and the raw body.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether webhooks may be delivered to `ip`
+///
+/// Loopback, private, link-local and other non-routable addresses are refused
+/// so that a subscription can't be used to reach the server's own network.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network" and carrier-grade NAT
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link-local and the deprecated site-local range
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first & 0xffc0) == 0xfec0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_payload() {
+        let signature = sign_payload("secret", 1_700_000_000, "{}");
+        assert_eq!(
+            signature,
+            "sha256=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+        assert_ne!(signature, sign_payload("other", 1_700_000_000, "{}"));
+        assert_ne!(signature, sign_payload("secret", 1_700_000_001, "{}"));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(WebhookDelivery::retry_delay(1), Some(Duration::seconds(30)));
+        assert_eq!(WebhookDelivery::retry_delay(2), Some(Duration::seconds(60)));
+        assert_eq!(WebhookDelivery::retry_delay(7), Some(Duration::seconds(1920)));
+        assert_eq!(WebhookDelivery::retry_delay(MAX_DELIVERY_ATTEMPTS), None);
+    }
+
+    #[test]
+    fn test_subscription_matches() {
+        let sub = WebhookSubscription {
+            id: 1,
+            user_id: 1,
+            market_id: Some(7),
+            url: "http://localhost/hook".to_string(),
+            secret: "s".to_string(),
+            events: vec![WebhookEvent::MarketResolved],
+            active: true,
+            created_at: Utc::now(),
+        };
+        assert!(sub.matches(WebhookEvent::MarketResolved, 7));
+        assert!(!sub.matches(WebhookEvent::MarketResolved, 8));
+        assert!(!sub.matches(WebhookEvent::MarketTraded, 7));
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
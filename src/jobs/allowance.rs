@@ -0,0 +1,23 @@
+use crate::Database;
+use crate::domain::AllowancePolicy;
+use crate::repository::AllowanceRepository;
+use std::time::Duration;
+
+/// How often the job looks for users who are due their allowance
+const GRANT_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Spawn the background task that grants the allowance to every user who is due one
+pub fn spawn(db: Database, policy: AllowancePolicy) {
+    tokio::spawn(async move {
+        let allowance_repo = AllowanceRepository::new(db.pool().clone());
+        let mut interval = tokio::time::interval(GRANT_INTERVAL);
+        loop {
+            interval.tick().await;
+            match allowance_repo.claim_all(&policy).await {
+                Ok(0) => {}
+                Ok(granted) => tracing::info!("Granted the allowance to {} users", granted),
+                Err(e) => tracing::error!("Failed to grant allowances: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,5 @@
+//! Background work that runs alongside the web server
+pub mod allowance;
+pub mod seasons;
+pub mod sessions;
+pub mod webhooks;
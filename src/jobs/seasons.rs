@@ -0,0 +1,25 @@
+use crate::Database;
+use crate::repository::SeasonRepository;
+use std::time::Duration;
+
+/// How often the job looks for seasons that have ended
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Spawn the background task that archives the final standings of every season that has ended
+pub fn spawn(db: Database) {
+    tokio::spawn(async move {
+        let season_repo = SeasonRepository::new(db.pool().clone());
+        let mut interval = tokio::time::interval(ARCHIVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match season_repo.archive_ended().await {
+                Ok(ids) => {
+                    for id in ids {
+                        tracing::info!("Archived the final standings of season {}", id);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to archive ended seasons: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,18 @@
+use std::time::Duration;
+use tower_sessions::session_store::ExpiredDeletion;
+
+/// Spawn the background task that purges expired sessions every `period`
+pub fn spawn<S>(store: S, period: Duration)
+where
+    S: ExpiredDeletion + Clone,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = store.delete_expired().await {
+                tracing::error!("Failed to delete expired sessions: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,229 @@
+use crate::Database;
+use crate::domain::{is_public_address, sign_payload, LmsrPricing, Market, WebhookDelivery, WebhookEvent};
+use crate::repository::{MarketRepository, PendingDelivery, WebhookRepository};
+use chrono::Utc;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the queue is polled for due deliveries
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Deliveries attempted per poll
+const BATCH_SIZE: i64 = 50;
+
+/// Per-request timeout for webhook receivers
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct MarketSnapshot<'a> {
+    id: i64,
+    question: &'a str,
+    status: String,
+    yes_probability: f64,
+    q_yes: f64,
+    q_no: f64,
+    volume: f64,
+    end_date: String,
+    outcome: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    event: WebhookEvent,
+    occurred_at: String,
+    market: MarketSnapshot<'a>,
+    data: serde_json::Value,
+}
+
+/// Queue `event` for every subscription interested in `market`
+///
+/// Failures are logged rather than returned so that a broken queue never fails
+/// the user-facing action that triggered the event.
+pub async fn notify(db: &Database, event: WebhookEvent, market: &Market, data: serde_json::Value) {
+    let payload = WebhookPayload {
+        event,
+        occurred_at: Utc::now().to_rfc3339(),
+        market: MarketSnapshot {
+            id: market.id,
+            question: &market.question,
+            status: market.status().to_string(),
+            yes_probability: LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param),
+            q_yes: market.q_yes,
+            q_no: market.q_no,
+            volume: market.volume,
+            end_date: market.end_date.to_rfc3339(),
+            outcome: market.outcome,
+        },
+        data,
+    };
+
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to serialize {} webhook payload: {}", event, e);
+            return;
+        }
+    };
+
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+    if let Err(e) = webhook_repo.enqueue(event, market.id, &body).await {
+        tracing::error!("Failed to queue {} webhook for market {}: {}", event, market.id, e);
+    }
+}
+
+/// Reload a market and notify subscribers, for callers that only hold its id
+pub async fn notify_market(db: &Database, event: WebhookEvent, market_id: i64, data: serde_json::Value) {
+    let market_repo = MarketRepository::new(db.pool().clone());
+    match market_repo.find_by_id(market_id).await {
+        Ok(market) => notify(db, event, &market, data).await,
+        Err(e) => tracing::error!("Failed to load market {} for {} webhook: {}", market_id, event, e),
+    }
+}
+
+/// Check that a webhook URL only resolves to public addresses
+///
+/// Run when subscribing and again before every delivery, since the host's
+/// addresses can change in between.
+pub async fn check_public_url(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    let port = url.port_or_known_default().ok_or("URL has no port")?;
+    let host = url.host_str().ok_or("URL has no host")?;
+
+    // IP literals never reach the resolver, so they are checked here; IPv6 ones are bracketed
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) if is_public_address(ip) => Ok(()),
+        Ok(ip) => Err(format!("{} is not a public address", ip)),
+        Err(_) => lookup_public(host, port).await.map(|_| ()),
+    }
+}
+
+/// Resolve `host`, failing if it has no addresses or any of them is not public
+async fn lookup_public(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Could not resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} has no addresses", host));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_address(addr.ip())) {
+        return Err(format!("{} resolves to {}, which is not a public address", host, addr.ip()));
+    }
+    Ok(addrs)
+}
+
+/// The delivery client's resolver: the addresses it connects to are the ones checked
+///
+/// Checking the URL before sending is not enough on its own, as a host can
+/// resolve to a public address for the check and a private one for the request.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = lookup_public(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Spawn the background task that closes expired markets and delivers queued webhooks
+pub fn spawn(db: Database) {
+    tokio::spawn(async move {
+        // Redirects and proxies would send the request somewhere that wasn't checked
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy()
+            .dns_resolver(Arc::new(PublicResolver))
+            .build();
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Failed to build webhook HTTP client: {}", e);
+                return;
+            }
+        };
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            close_expired_markets(&db).await;
+            deliver_due(&db, &client).await;
+        }
+    });
+}
+
+/// Markets close implicitly when their end date passes; record it so `market.closed` fires
+async fn close_expired_markets(db: &Database) {
+    let market_repo = MarketRepository::new(db.pool().clone());
+    match market_repo.close_expired().await {
+        Ok(ids) => {
+            for id in ids {
+                notify_market(db, WebhookEvent::MarketClosed, id, serde_json::json!({ "reason": "end_date" })).await;
+            }
+        }
+        Err(e) => tracing::error!("Failed to close expired markets: {}", e),
+    }
+}
+
+async fn deliver_due(db: &Database, client: &reqwest::Client) {
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+    let due = match webhook_repo.find_due(BATCH_SIZE).await {
+        Ok(due) => due,
+        Err(e) => {
+            tracing::error!("Failed to load due webhook deliveries: {}", e);
+            return;
+        }
+    };
+
+    for pending in due {
+        let id = pending.delivery.id;
+        let attempts = pending.delivery.attempts + 1;
+        let result = match send(client, &pending).await {
+            Ok(status) => webhook_repo.mark_delivered(id, status).await,
+            Err((status, error)) => {
+                let next_attempt_at = WebhookDelivery::retry_delay(attempts).map(|delay| Utc::now() + delay);
+                tracing::warn!(
+                    "Webhook delivery {} to {} failed (attempt {}): {}",
+                    id, pending.url, attempts, error
+                );
+                webhook_repo.mark_attempt_failed(id, status, &error, next_attempt_at).await
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to record webhook delivery {}: {}", id, e);
+        }
+    }
+}
+
+/// POST a signed delivery; any 2xx response counts as delivered
+async fn send(client: &reqwest::Client, pending: &PendingDelivery) -> Result<i64, (Option<i64>, String)> {
+    check_public_url(&pending.url).await.map_err(|e| (None, e))?;
+
+    let timestamp = Utc::now().timestamp();
+    let signature = sign_payload(&pending.secret, timestamp, &pending.delivery.payload);
+
+    let response = client
+        .post(&pending.url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Event", &pending.delivery.event)
+        .header("X-Webhook-Delivery", pending.delivery.id.to_string())
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", signature)
+        .body(pending.delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = i64::from(response.status().as_u16());
+    if response.status().is_success() {
+        Ok(status)
+    } else {
+        Err((Some(status), format!("Receiver responded with HTTP {}", status)))
+    }
+}
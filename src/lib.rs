@@ -1,5 +1,10 @@
+pub mod cli;
+pub mod config;
 pub mod db;
 pub mod domain;
+pub mod jobs;
+pub mod mail;
+pub mod oidc;
 pub mod repository;
 pub mod web;
 
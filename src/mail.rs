@@ -0,0 +1,57 @@
+//! Outgoing mail through a sendmail-compatible program
+use crate::config::MailConfig;
+use std::io;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// A plain-text message; the addresses and subject must fit on one header line
+fn compose(from: &str, to: &str, subject: &str, body: &str) -> io::Result<String> {
+    if [from, to, subject].iter().any(|header| header.contains(['\r', '\n'])) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Mail headers cannot contain line breaks"));
+    }
+    Ok(format!(
+        "From: {}\nTo: {}\nSubject: {}\nMIME-Version: 1.0\nContent-Type: text/plain; charset=utf-8\n\n{}\n",
+        from, to, subject, body
+    ))
+}
+
+/// Send a message to `to`, waiting until the sendmail program has accepted it
+pub async fn send(config: &MailConfig, to: &str, subject: &str, body: &str) -> io::Result<()> {
+    let message = compose(&config.from, to, subject, body)?;
+
+    // The recipient is passed as an argument, so nothing in the message is read for addresses
+    let mut child = Command::new(&config.sendmail_path)
+        .args(["-i", "-f", &config.from, "--", to])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}: {}",
+            config.sendmail_path,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose() {
+        let message = compose("market@example.com", "ada@example.com", "Reset", "Hello").unwrap();
+        assert!(message.starts_with("From: market@example.com\nTo: ada@example.com\nSubject: Reset\n"));
+        assert!(message.ends_with("\n\nHello\n"));
+        assert!(compose("market@example.com", "ada@example.com", "Reset\nBcc: eve@example.com", "Hello").is_err());
+    }
+}
@@ -1,8 +1,15 @@
+use market::cli;
+use market::config::{Config, SessionStoreKind};
+use market::db::SqliteSessionStore;
 use market::Database;
-use market::web::create_router;
+use market::domain::LEDGER_EPSILON;
+use market::repository::LedgerRepository;
+use market::jobs;
+use market::web::{create_router, AppState};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use tower_sessions::{MemoryStore, SessionManagerLayer};
+use tower_sessions::{MemoryStore, SessionManagerLayer, SessionStore};
 use tower_sessions::Expiry;
+use std::net::SocketAddr;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -15,34 +22,85 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Initialize database
-    let database_url = std::env::var("DATABASE_URL")
-        .unwrap_or_else(|_| "sqlite:market.db".to_string());
+    let config = Config::from_env()?;
 
-    tracing::info!("Connecting to database: {}", database_url);
-    let db = Database::new(&database_url).await?;
+    // Initialize database
+    tracing::info!("Connecting to database: {}", config.database_url);
+    let db = Database::new(&config.database_url).await?;
 
     // Run migrations
     tracing::info!("Running database migrations");
     db.run_migrations().await?;
 
-    // Create session store and layer
-    let session_store = MemoryStore::default();
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(false) // Set to true in production with HTTPS
-        .with_expiry(Expiry::OnInactivity(time::Duration::days(7)));
+    // Cached balances are derived from the ledger and should never drift from it
+    check_ledger(&db).await;
+
+    // Administrative subcommands run instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&db, &args).await;
+    }
+
+    // Close expired markets and deliver queued webhooks in the background
+    jobs::webhooks::spawn(db.clone());
+
+    // Archive the standings of seasons as they end
+    jobs::seasons::spawn(db.clone());
+
+    if config.allowance.period.is_some() && config.allowance.automatic {
+        jobs::allowance::spawn(db.clone(), config.allowance);
+    }
 
-    // Create router with shared state and session layer
-    let app = create_router()
-        .layer(session_layer)
-        .with_state(db);
+    if let Some(oidc) = &config.oidc {
+        tracing::info!("Single sign-on enabled with issuer {}", oidc.issuer_url);
+    }
+
+    // Create router with the configured session store
+    tracing::info!("Using {} session store", config.session_store);
+    let router = create_router();
+    let app = match config.session_store {
+        SessionStoreKind::Memory => router.layer(session_layer(MemoryStore::default())),
+        SessionStoreKind::Sqlite => {
+            let store = SqliteSessionStore::new(db.pool().clone());
+            jobs::sessions::spawn(store.clone(), config.session_cleanup_interval);
+            router.layer(session_layer(store))
+        }
+    }
+    .with_state(AppState::new(db, config.clone())?);
 
     // Start server
-    let addr = "127.0.0.1:3000";
+    let addr = config.bind_addr();
     tracing::info!("Starting server on http://{}", addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
+
+/// Log any disagreement between cached balances and the ledger
+async fn check_ledger(db: &Database) {
+    let ledger_repo = LedgerRepository::new(db.pool().clone());
+    match ledger_repo.find_mismatches().await {
+        Ok(mismatches) => {
+            for mismatch in mismatches {
+                tracing::warn!(
+                    "Balance of user {} is {:.6} but the ledger says {:.6}",
+                    mismatch.user_id, mismatch.cached, mismatch.ledger
+                );
+            }
+        }
+        Err(e) => tracing::error!("Failed to check balances against the ledger: {}", e),
+    }
+    match ledger_repo.total().await {
+        Ok(total) if total.abs() > LEDGER_EPSILON => tracing::warn!("Ledger does not balance (off by {:.6})", total),
+        Ok(_) => {}
+        Err(e) => tracing::error!("Failed to total the ledger: {}", e),
+    }
+}
+
+fn session_layer<S: SessionStore + Clone>(store: S) -> SessionManagerLayer<S> {
+    SessionManagerLayer::new(store)
+        .with_secure(false) // Set to true in production with HTTPS
+        .with_expiry(Expiry::OnInactivity(time::Duration::days(7)))
+}
@@ -0,0 +1,213 @@
+//! OpenID Connect client for single sign-on
+//!
+//! Implements the authorization code flow with PKCE: provider discovery, the
+//! authorization redirect, the code exchange and verification of the returned
+//! RS256-signed ID token against the provider's published keys.
+use crate::config::OidcConfig;
+use crate::domain::{IdTokenClaims, OidcLoginRequest};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::signature::Verifier;
+use rsa::{BigUint, RsaPublicKey};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+/// Timeout for requests to the provider
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("Identity provider request failed: {0}")]
+    Provider(String),
+
+    #[error("Invalid ID token: {0}")]
+    InvalidToken(String),
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        OidcError::Provider(e.to_string())
+    }
+}
+
+/// The subset of the provider's discovery document we use
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Result<Self, OidcError> {
+        let http = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self {
+            config,
+            http,
+            metadata: OnceCell::new(),
+        })
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Where to send the browser to log in at the provider
+    pub async fn authorization_url(&self, request: &OidcLoginRequest) -> Result<String, OidcError> {
+        let metadata = self.metadata().await?;
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", self.config.scopes.as_str()),
+                ("state", request.state.as_str()),
+                ("nonce", request.nonce.as_str()),
+                ("code_challenge", request.code_challenge().as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| OidcError::Provider(format!("Invalid authorization endpoint: {}", e)))?;
+        Ok(url.into())
+    }
+
+    /// Redeem an authorization code and return the verified claims of the ID token
+    pub async fn exchange_code(&self, code: &str, request: &OidcLoginRequest) -> Result<IdTokenClaims, OidcError> {
+        let metadata = self.metadata().await?;
+
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", request.code_verifier.as_str()),
+        ];
+        let mut token_request = self.http.post(&metadata.token_endpoint).form(&params);
+        if let Some(secret) = &self.config.client_secret {
+            token_request = token_request.basic_auth(&self.config.client_id, Some(secret));
+        }
+
+        let response = token_request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(OidcError::Provider(format!("Token endpoint responded with HTTP {}: {}", status, body)));
+        }
+        let tokens: TokenResponse = response.json().await?;
+
+        let claims = self.verify_signature(&tokens.id_token, &metadata.jwks_uri).await?;
+        claims
+            .validate(&metadata.issuer, &self.config.client_id, &request.nonce, Utc::now())
+            .map_err(OidcError::InvalidToken)?;
+        Ok(claims)
+    }
+
+    /// Discover the provider's endpoints, once per process
+    async fn metadata(&self) -> Result<&ProviderMetadata, OidcError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.config.issuer_url);
+                let response = self.http.get(&url).send().await?.error_for_status()?;
+                let metadata: ProviderMetadata = response.json().await?;
+                if metadata.issuer.trim_end_matches('/') != self.config.issuer_url {
+                    return Err(OidcError::Provider(format!(
+                        "Discovery document names issuer {}, expected {}",
+                        metadata.issuer, self.config.issuer_url
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Check an RS256 signature against the provider's current keys and decode the claims
+    ///
+    /// Keys are fetched on every login so that rotations take effect without a restart.
+    async fn verify_signature(&self, id_token: &str, jwks_uri: &str) -> Result<IdTokenClaims, OidcError> {
+        let invalid = |msg: &str| OidcError::InvalidToken(msg.to_string());
+
+        let mut parts = id_token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("not a signed JWT"));
+        };
+
+        let header: JwtHeader = decode_segment(header).ok_or_else(|| invalid("malformed header"))?;
+        if header.alg != "RS256" {
+            return Err(OidcError::InvalidToken(format!("unsupported signing algorithm {}", header.alg)));
+        }
+
+        let keys: JwkSet = self.http.get(jwks_uri).send().await?.error_for_status()?.json().await?;
+        let key = keys
+            .keys
+            .iter()
+            .filter(|key| key.kty == "RSA")
+            .find(|key| header.kid.is_none() || key.kid == header.kid)
+            .ok_or_else(|| invalid("signed with an unknown key"))?;
+        let public_key = rsa_public_key(key).ok_or_else(|| invalid("provider published a malformed key"))?;
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or_else(|| invalid("malformed signature"))?;
+        let signing_input = &id_token[..header_and_payload_len(id_token)];
+        VerifyingKey::<Sha256>::new(public_key)
+            .verify(signing_input.as_bytes(), &signature)
+            .map_err(|_| invalid("signature does not match"))?;
+
+        decode_segment(payload).ok_or_else(|| invalid("malformed claims"))
+    }
+}
+
+fn decode_segment<T: serde::de::DeserializeOwned>(segment: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(segment).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn rsa_public_key(key: &Jwk) -> Option<RsaPublicKey> {
+    let n = URL_SAFE_NO_PAD.decode(key.n.as_deref()?).ok()?;
+    let e = URL_SAFE_NO_PAD.decode(key.e.as_deref()?).ok()?;
+    RsaPublicKey::new(BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e)).ok()
+}
+
+/// Length of the `header.payload` prefix that the signature covers
+fn header_and_payload_len(jwt: &str) -> usize {
+    jwt.rfind('.').unwrap_or(jwt.len())
+}
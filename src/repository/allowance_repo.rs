@@ -0,0 +1,154 @@
+use crate::domain::{AllowancePolicy, Journal, UserId};
+use crate::repository::ledger_repo::post_journal;
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+
+#[derive(Clone)]
+pub struct AllowanceRepository {
+    pool: SqlitePool,
+}
+
+impl AllowanceRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// When the user last received a grant, if ever
+    pub async fn last_claim(&self, user_id: UserId) -> Result<Option<DateTime<Utc>>> {
+        let created_at = sqlx::query_scalar::<_, String>(
+            "SELECT created_at FROM transactions WHERE user_id = ? AND transaction_type = 'grant' ORDER BY id DESC LIMIT 1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        created_at
+            .map(|s| {
+                DateTime::parse_from_rfc3339(&s)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))
+            })
+            .transpose()
+    }
+
+    /// Grant the allowance to a user, returning the amount granted
+    ///
+    /// Returns `None` when the user has already claimed within the current
+    /// period, or when their balance is high enough that nothing is due.
+    pub async fn claim(&self, user_id: UserId, policy: &AllowancePolicy) -> Result<Option<f64>> {
+        let Some(period) = policy.period else {
+            return Err(RepositoryError::ConstraintViolation("The allowance is disabled".to_string()));
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        let balance = sqlx::query_scalar::<_, f64>("SELECT balance FROM users WHERE id = ? AND deleted_at IS NULL")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        let amount = policy.grant_for(balance);
+        if amount <= 0.0 {
+            return Ok(None);
+        }
+
+        // The period check and the insert are one statement, so concurrent claims cannot both succeed
+        let now = Utc::now();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO transactions (user_id, market_id, transaction_type, side, shares, price, amount, created_at)
+            SELECT ?, NULL, 'grant', NULL, 0.0, 0.0, ?, ?
+            WHERE NOT EXISTS (
+                SELECT 1 FROM transactions
+                WHERE user_id = ? AND transaction_type = 'grant' AND julianday(created_at) > julianday(?)
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(amount)
+        .bind(now.to_rfc3339())
+        .bind(user_id)
+        .bind((now - period.duration()).to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        post_journal(&mut tx, &Journal::grant(user_id, amount)).await?;
+        tx.commit().await?;
+        Ok(Some(amount))
+    }
+
+    /// Grant the allowance to every active user who is due one, returning how many received it
+    ///
+    /// A failed grant is logged and skipped so that one user can't hold up everyone else's.
+    pub async fn claim_all(&self, policy: &AllowancePolicy) -> Result<usize> {
+        let user_ids = sqlx::query_scalar::<_, i64>("SELECT id FROM users WHERE deleted_at IS NULL ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut granted = 0;
+        for user_id in user_ids {
+            match self.claim(user_id, policy).await {
+                Ok(Some(_)) => granted += 1,
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to grant the allowance to user {}: {}", user_id, e),
+            }
+        }
+        Ok(granted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::AllowancePeriod;
+    use crate::repository::{LedgerRepository, UserRepository};
+    use crate::Database;
+
+    const POLICY: AllowancePolicy = AllowancePolicy {
+        period: Some(AllowancePeriod::Daily),
+        amount: 10.0,
+        minimum_balance: 50.0,
+        automatic: false,
+    };
+
+    #[tokio::test]
+    async fn test_claim_once_per_period() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let alice = users.create_with_balance("alice", "hash", 20.0).await.unwrap();
+        let allowances = AllowanceRepository::new(db.pool().clone());
+
+        // Topped up to the minimum balance, which is more than the allowance
+        assert_eq!(allowances.claim(alice.id, &POLICY).await.unwrap(), Some(30.0));
+        assert!(allowances.last_claim(alice.id).await.unwrap().is_some());
+        assert_eq!(allowances.claim(alice.id, &POLICY).await.unwrap(), None);
+
+        assert_eq!(users.find_by_id(alice.id).await.unwrap().balance, 50.0);
+        assert!(LedgerRepository::new(db.pool().clone()).find_mismatches().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_claim_all_grants_users_who_are_due() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let alice = users.create_with_balance("alice", "hash", 20.0).await.unwrap();
+        let bob = users.create_with_balance("bob", "hash", 500.0).await.unwrap();
+        let allowances = AllowanceRepository::new(db.pool().clone());
+        allowances.claim(alice.id, &POLICY).await.unwrap();
+
+        assert_eq!(allowances.claim_all(&POLICY).await.unwrap(), 1);
+        assert_eq!(users.find_by_id(alice.id).await.unwrap().balance, 50.0);
+        assert_eq!(users.find_by_id(bob.id).await.unwrap().balance, 510.0);
+
+        let disabled = AllowancePolicy { period: None, ..POLICY };
+        assert!(matches!(allowances.claim(bob.id, &disabled).await, Err(RepositoryError::ConstraintViolation(_))));
+        // Failed grants are skipped rather than ending the run
+        assert_eq!(allowances.claim_all(&disabled).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::domain::{idempotency_retention, IdempotencyRecord, TradeQuote, UserId};
+use crate::repository::{Result, RepositoryError};
+use chrono::Utc;
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(FromRow)]
+struct IdempotencyRow {
+    user_id: i64,
+    key: String,
+    fingerprint: String,
+    status: String,
+    response: Option<String>,
+    result: Option<String>,
+    created_at: String,
+}
+
+impl TryFrom<IdempotencyRow> for IdempotencyRecord {
+    type Error = RepositoryError;
+
+    fn try_from(row: IdempotencyRow) -> Result<Self> {
+        Ok(IdempotencyRecord {
+            user_id: row.user_id,
+            key: row.key,
+            fingerprint: row.fingerprint,
+            status: row.status.parse().map_err(|_| {
+                RepositoryError::Database(sqlx::Error::Decode("Invalid idempotency status".into()))
+            })?,
+            response: row.response,
+            result: row
+                .result
+                .map(|result| serde_json::from_str(&result))
+                .transpose()
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?,
+            created_at: row.created_at.parse().unwrap_or_else(|_| Utc::now()),
+        })
+    }
+}
+
+/// Result of trying to claim a key for a new request
+pub enum IdempotencyClaim {
+    /// The key was unused; the caller must `complete` or `release` it
+    Claimed,
+    /// The key was already used within the retention window
+    Existing(IdempotencyRecord),
+}
+
+#[derive(Clone)]
+pub struct IdempotencyRepository {
+    pool: SqlitePool,
+}
+
+impl IdempotencyRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Atomically claim `key` for `user_id`, or return the record that already holds it
+    ///
+    /// Keys older than the retention window are discarded first, so reusing one
+    /// after it expires starts a fresh request.
+    pub async fn claim(&self, user_id: UserId, key: &str, fingerprint: &str) -> Result<IdempotencyClaim> {
+        let now = Utc::now();
+        let cutoff = (now - idempotency_retention()).to_rfc3339();
+        sqlx::query("DELETE FROM idempotency_keys WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (user_id, key, fingerprint, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (user_id, key) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(fingerprint)
+        .bind(now.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        if inserted.rows_affected() == 1 {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+
+        Ok(IdempotencyClaim::Existing(self.find(user_id, key).await?))
+    }
+
+    pub async fn find(&self, user_id: UserId, key: &str) -> Result<IdempotencyRecord> {
+        let row = sqlx::query_as::<_, IdempotencyRow>(
+            r#"
+            SELECT user_id, key, fingerprint, status, response, result, created_at
+            FROM idempotency_keys
+            WHERE user_id = ? AND key = ?
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound)?;
+
+        row.try_into()
+    }
+
+    /// Store the outcome of the request that claimed the key: where it redirected and the trade it executed
+    pub async fn complete(&self, user_id: UserId, key: &str, response: &str, result: &TradeQuote) -> Result<()> {
+        let result = serde_json::to_string(result)
+            .map_err(|e| RepositoryError::Database(sqlx::Error::Protocol(format!("Invalid trade result: {}", e))))?;
+        sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET status = 'completed', response = ?, result = ?
+            WHERE user_id = ? AND key = ?
+            "#,
+        )
+        .bind(response)
+        .bind(result)
+        .bind(user_id)
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Give a claimed key back; only for a request that failed without side effects
+    pub async fn release(&self, user_id: UserId, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE user_id = ? AND key = ? AND status = 'pending'")
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{IdempotencyStatus, MarketSide, TradeAction, TradeSize};
+    use crate::repository::UserRepository;
+    use crate::Database;
+
+    #[tokio::test]
+    async fn test_completed_key_returns_trade() {
+        let db = Database::in_memory().await;
+        let alice = UserRepository::new(db.pool().clone()).create("alice", "hash").await.unwrap();
+        let keys = IdempotencyRepository::new(db.pool().clone());
+
+        assert!(matches!(keys.claim(alice.id, "k1", "buy:1:yes:10").await.unwrap(), IdempotencyClaim::Claimed));
+        let quote = TradeQuote::new(0.0, 0.0, 100.0, TradeAction::Buy, MarketSide::Yes, TradeSize::Shares(10.0)).unwrap();
+        keys.complete(alice.id, "k1", "/markets/1", &quote).await.unwrap();
+
+        let IdempotencyClaim::Existing(record) = keys.claim(alice.id, "k1", "buy:1:yes:10").await.unwrap() else {
+            panic!("completed key was claimed again");
+        };
+        assert_eq!(record.status, IdempotencyStatus::Completed);
+        assert_eq!(record.response.as_deref(), Some("/markets/1"));
+        let result = record.result.unwrap();
+        assert_eq!((result.shares, result.amount), (quote.shares, quote.amount));
+
+        // Completed keys are kept
+        keys.release(alice.id, "k1").await.unwrap();
+        assert!(keys.find(alice.id, "k1").await.is_ok());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::domain::{User, UserId, UserIdentity};
+use crate::repository::{Result, RepositoryError, UserRepository};
+use chrono::Utc;
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(FromRow)]
+struct IdentityRow {
+    issuer: String,
+    subject: String,
+    user_id: i64,
+    created_at: String,
+}
+
+impl From<IdentityRow> for UserIdentity {
+    fn from(row: IdentityRow) -> Self {
+        UserIdentity {
+            issuer: row.issuer,
+            subject: row.subject,
+            user_id: row.user_id,
+            created_at: row.created_at.parse().unwrap_or_else(|_| Utc::now()),
+        }
+    }
+}
+
+/// Attempts at a free username before giving up on provisioning
+const MAX_USERNAME_ATTEMPTS: u32 = 100;
+
+#[derive(Clone)]
+pub struct IdentityRepository {
+    pool: SqlitePool,
+}
+
+impl IdentityRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>> {
+        let row = sqlx::query_as::<_, IdentityRow>(
+            "SELECT issuer, subject, user_id, created_at FROM user_identities WHERE issuer = ? AND subject = ?",
+        )
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    pub async fn find_by_user(&self, user_id: UserId) -> Result<Vec<UserIdentity>> {
+        let rows = sqlx::query_as::<_, IdentityRow>(
+            "SELECT issuer, subject, user_id, created_at FROM user_identities WHERE user_id = ? ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Link an external identity to an existing user
+    pub async fn link(&self, issuer: &str, subject: &str, user_id: UserId) -> Result<()> {
+        sqlx::query("INSERT INTO user_identities (issuer, subject, user_id, created_at) VALUES (?, ?, ?, ?)")
+            .bind(issuer)
+            .bind(subject)
+            .bind(user_id)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                if let sqlx::Error::Database(db_err) = &e {
+                    if db_err.is_unique_violation() {
+                        return RepositoryError::ConstraintViolation(
+                            "This identity is already linked to an account".to_string(),
+                        );
+                    }
+                }
+                RepositoryError::Database(e)
+            })?;
+        Ok(())
+    }
+
+    /// Create a password-less user for a new identity and link the two
+    ///
+    /// `username` is used as is when free, otherwise with the first free numeric suffix.
+    pub async fn provision(
+        &self,
+        issuer: &str,
+        subject: &str,
+        username: &str,
+        password_hash: &str,
+        starting_balance: f64,
+    ) -> Result<User> {
+        let user_repo = UserRepository::new(self.pool.clone());
+        let mut user = None;
+        for attempt in 0..MAX_USERNAME_ATTEMPTS {
+            let candidate = match attempt {
+                0 => username.to_string(),
+                n => format!("{}{}", username, n + 1),
+            };
+            match user_repo.create_with_balance(&candidate, password_hash, starting_balance).await {
+                Ok(created) => {
+                    user = Some(created);
+                    break;
+                }
+                Err(RepositoryError::ConstraintViolation(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let user = user.ok_or_else(|| {
+            RepositoryError::ConstraintViolation(format!("No free username starting with '{}'", username))
+        })?;
+
+        self.link(issuer, subject, user.id).await?;
+        Ok(user)
+    }
+}
@@ -0,0 +1,129 @@
+use crate::domain::{InviteCode, InviteId, UserId};
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+
+const INVITE_COLUMNS: &str = "id, code, created_by, starting_balance, max_uses, uses, expires_at, revoked_at, created_at";
+
+#[derive(FromRow)]
+struct InviteRow {
+    id: i64,
+    code: String,
+    created_by: i64,
+    starting_balance: f64,
+    max_uses: i64,
+    uses: i64,
+    expires_at: Option<String>,
+    revoked_at: Option<String>,
+    created_at: String,
+}
+
+impl TryFrom<InviteRow> for InviteCode {
+    type Error = RepositoryError;
+
+    fn try_from(row: InviteRow) -> Result<Self> {
+        let parse = |s: &str| {
+            DateTime::parse_from_rfc3339(s)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))
+        };
+        Ok(InviteCode {
+            id: row.id,
+            code: row.code,
+            created_by: row.created_by,
+            starting_balance: row.starting_balance,
+            max_uses: row.max_uses,
+            uses: row.uses,
+            expires_at: row.expires_at.as_deref().map(parse).transpose()?,
+            revoked_at: row.revoked_at.as_deref().map(parse).transpose()?,
+            created_at: parse(&row.created_at)?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct InviteRepository {
+    pool: SqlitePool,
+}
+
+impl InviteRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        code: &str,
+        created_by: UserId,
+        starting_balance: f64,
+        max_uses: i64,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<InviteCode> {
+        let row = sqlx::query_as::<_, InviteRow>(&format!(
+            r#"
+            INSERT INTO invite_codes (code, created_by, starting_balance, max_uses, expires_at, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING {}
+            "#,
+            INVITE_COLUMNS
+        ))
+        .bind(code)
+        .bind(created_by)
+        .bind(starting_balance)
+        .bind(max_uses)
+        .bind(expires_at.map(|at| at.to_rfc3339()))
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    /// Every invite, newest first
+    pub async fn list_all(&self) -> Result<Vec<InviteCode>> {
+        let rows = sqlx::query_as::<_, InviteRow>(&format!(
+            "SELECT {} FROM invite_codes ORDER BY id DESC",
+            INVITE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Invites created by one user, newest first
+    pub async fn find_by_creator(&self, user_id: UserId) -> Result<Vec<InviteCode>> {
+        let rows = sqlx::query_as::<_, InviteRow>(&format!(
+            "SELECT {} FROM invite_codes WHERE created_by = ? ORDER BY id DESC",
+            INVITE_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Revoke an invite; with `created_by`, only if that user created it
+    pub async fn revoke(&self, id: InviteId, created_by: Option<UserId>) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE invite_codes
+            SET revoked_at = ?
+            WHERE id = ? AND revoked_at IS NULL AND (? IS NULL OR created_by = ?)
+            "#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .bind(created_by)
+        .bind(created_by)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use crate::domain::{
+    rank_entries, LeaderboardEntry, LeaderboardMetric, LeaderboardWindow, MarketId, MarketSide, UserId,
+    MIN_CALIBRATION_TRADES,
+};
+use crate::repository::{MarketRepository, Result};
+use chrono::Utc;
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashMap;
+
+/// Realized profit per user in the window starting at `?1`
+///
+/// Sales realize their proceeds less the average price of all the user's buys
+/// on that side. Shares still held when a market resolves settle at 1 on the
+/// winning side and 0 on the losing one, against the same average price.
+/// Minted complete sets count as buys and redeemed ones as sales, each side at
+/// the price it was recorded at, as they do for positions. Season markets are
+/// traded with season balances and left out.
+const PROFIT_QUERY: &str = r#"
+    WITH main_trades AS (
+        SELECT t.user_id, t.market_id, t.side, t.transaction_type, t.shares, t.amount, t.created_at
+        FROM transactions t
+        JOIN markets m ON m.id = t.market_id
+        WHERE m.season_id IS NULL
+    ),
+    bought AS (
+        SELECT user_id, market_id, side, COUNT(*) AS trades, SUM(shares) AS shares,
+            SUM(amount) / SUM(shares) AS avg_price
+        FROM main_trades
+        WHERE transaction_type IN ('buy', 'mint') AND shares > 0
+        GROUP BY user_id, market_id, side
+    ),
+    sold AS (
+        SELECT user_id, market_id, side, SUM(shares) AS shares,
+            SUM(CASE WHEN created_at >= ?1 THEN shares ELSE 0 END) AS window_shares,
+            SUM(CASE WHEN created_at >= ?1 THEN amount ELSE 0 END) AS window_proceeds,
+            COUNT(CASE WHEN created_at >= ?1 THEN 1 END) AS window_trades
+        FROM main_trades
+        WHERE transaction_type IN ('sell', 'redeem')
+        GROUP BY user_id, market_id, side
+    ),
+    realized AS (
+        SELECT s.user_id, s.window_proceeds - s.window_shares * COALESCE(b.avg_price, 0) AS profit,
+            s.window_trades AS trades
+        FROM sold s
+        LEFT JOIN bought b ON b.user_id = s.user_id AND b.market_id = s.market_id AND b.side = s.side
+        WHERE s.window_trades > 0
+        UNION ALL
+        SELECT b.user_id,
+            MAX(b.shares - COALESCE(s.shares, 0), 0)
+                * ((b.side = CASE WHEN m.outcome = 1 THEN 'yes' ELSE 'no' END) - b.avg_price) AS profit,
+            b.trades
+        FROM bought b
+        JOIN markets m ON m.id = b.market_id
+        LEFT JOIN sold s ON s.user_id = b.user_id AND s.market_id = b.market_id AND s.side = b.side
+        WHERE m.resolved = 1 AND m.resolved_at >= ?1
+    )
+    SELECT u.id AS user_id, u.username, SUM(r.profit) AS score, SUM(r.trades) AS trades
+    FROM realized r
+    JOIN users u ON u.id = r.user_id
+    WHERE u.deleted_at IS NULL
+    GROUP BY u.id
+    ORDER BY score DESC, u.id
+    LIMIT ?2
+"#;
+
+/// Amount bought, sold, minted and redeemed per user in the window starting at `?1`, outside season markets
+const VOLUME_QUERY: &str = r#"
+    SELECT u.id AS user_id, u.username, SUM(t.amount) AS score, COUNT(*) AS trades
+    FROM transactions t
+    JOIN markets m ON m.id = t.market_id
+    JOIN users u ON u.id = t.user_id
+    WHERE t.transaction_type IN ('buy', 'sell', 'mint', 'redeem') AND t.created_at >= ?1 AND m.season_id IS NULL
+        AND u.deleted_at IS NULL
+    GROUP BY u.id
+    ORDER BY score DESC, u.id
+    LIMIT ?2
+"#;
+
+/// Brier score per user of buys in markets resolved in the window starting at `?1`
+///
+/// Each buy forecasts that its side wins with the probability paid per share,
+/// scored against whether it did and weighted by shares.
+const CALIBRATION_QUERY: &str = r#"
+    SELECT u.id AS user_id, u.username,
+        SUM(t.shares * ((t.side = CASE WHEN m.outcome = 1 THEN 'yes' ELSE 'no' END) - t.amount / t.shares)
+            * ((t.side = CASE WHEN m.outcome = 1 THEN 'yes' ELSE 'no' END) - t.amount / t.shares))
+            / SUM(t.shares) AS score,
+        COUNT(*) AS trades
+    FROM transactions t
+    JOIN markets m ON m.id = t.market_id
+    JOIN users u ON u.id = t.user_id
+    WHERE t.transaction_type = 'buy' AND t.shares > 0 AND m.resolved = 1 AND m.resolved_at >= ?1
+        AND m.season_id IS NULL AND u.deleted_at IS NULL
+    GROUP BY u.id
+    HAVING COUNT(*) >= ?3
+    ORDER BY score, u.id
+    LIMIT ?2
+"#;
+
+#[derive(FromRow)]
+struct EntryRow {
+    user_id: i64,
+    username: String,
+    score: f64,
+    trades: i64,
+}
+
+#[derive(FromRow)]
+struct CashRow {
+    id: i64,
+    username: String,
+    balance: f64,
+}
+
+#[derive(FromRow)]
+struct OpenPositionRow {
+    user_id: i64,
+    market_id: i64,
+    side: String,
+    shares: f64,
+}
+
+#[derive(Clone)]
+pub struct LeaderboardRepository {
+    pool: SqlitePool,
+}
+
+impl LeaderboardRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// The top `limit` traders by `metric` over `window`
+    ///
+    /// Portfolio value is always as of now, so it ignores the window.
+    pub async fn rank(
+        &self,
+        metric: LeaderboardMetric,
+        window: LeaderboardWindow,
+        limit: i64,
+    ) -> Result<Vec<LeaderboardEntry>> {
+        let query = match metric {
+            LeaderboardMetric::Profit => PROFIT_QUERY,
+            LeaderboardMetric::Volume => VOLUME_QUERY,
+            LeaderboardMetric::Calibration => CALIBRATION_QUERY,
+            LeaderboardMetric::Portfolio => return self.rank_portfolios(limit).await,
+        };
+
+        // Timestamps are RFC 3339 and compare as text; every one sorts after ""
+        let since = window.since(Utc::now()).map(|since| since.to_rfc3339()).unwrap_or_default();
+        let mut query = sqlx::query_as::<_, EntryRow>(query).bind(since).bind(limit);
+        if metric == LeaderboardMetric::Calibration {
+            query = query.bind(MIN_CALIBRATION_TRADES);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+
+        Ok(rank_entries(
+            metric,
+            rows.into_iter()
+                .map(|r| LeaderboardEntry::new(r.user_id, r.username, r.score, r.trades))
+                .collect(),
+            limit.max(0) as usize,
+        ))
+    }
+
+    /// Rank users by cash plus open positions in main markets, priced at the current LMSR price
+    async fn rank_portfolios(&self, limit: i64) -> Result<Vec<LeaderboardEntry>> {
+        let users = sqlx::query_as::<_, CashRow>("SELECT id, username, balance FROM users WHERE deleted_at IS NULL")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let positions = sqlx::query_as::<_, OpenPositionRow>(
+            r#"
+            SELECT pos.user_id, pos.market_id, pos.side, pos.shares
+            FROM positions pos
+            JOIN markets m ON m.id = pos.market_id
+            WHERE m.resolved = 0 AND m.season_id IS NULL AND pos.shares > 0
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let markets: HashMap<MarketId, _> = MarketRepository::new(self.pool.clone())
+            .list_active()
+            .await?
+            .into_iter()
+            .map(|market| (market.id, market))
+            .collect();
+
+        // (value, open positions) per user
+        let mut holdings: HashMap<UserId, (f64, i64)> = HashMap::new();
+        for position in positions {
+            let (Some(market), Ok(side)) = (markets.get(&position.market_id), position.side.parse::<MarketSide>())
+            else {
+                continue;
+            };
+            let held = holdings.entry(position.user_id).or_default();
+            held.0 += position.shares * market.unsettled_value(side);
+            held.1 += 1;
+        }
+
+        Ok(rank_entries(
+            LeaderboardMetric::Portfolio,
+            users
+                .into_iter()
+                .map(|u| {
+                    let (value, open) = holdings.get(&u.id).copied().unwrap_or_default();
+                    LeaderboardEntry::new(u.id, u.username, u.balance + value, open)
+                })
+                .collect(),
+            limit.max(0) as usize,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TrackRecord;
+    use crate::repository::{PositionRepository, UserRepository};
+    use crate::Database;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_profit_counts_minted_sets_like_positions() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let creator = users.create_with_balance("creator", "hash", 1000.0).await.unwrap();
+        let trader = users.create_with_balance("trader", "hash", 100.0).await.unwrap();
+        let markets = MarketRepository::new(db.pool().clone());
+        let market = markets
+            .create("Will it rain?", None, creator.id, None, Utc::now() + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+
+        // Mint, sell the unwanted side and hold the other to resolution
+        let positions = PositionRepository::new(db.pool().clone());
+        positions.mint_complete_sets(trader.id, market.id, 10.0).await.unwrap();
+        positions.sell(trader.id, market.id, MarketSide::No, 10.0).await.unwrap();
+        markets.resolve_and_settle(market.id, true).await.unwrap();
+
+        let record = TrackRecord::from_positions(
+            &positions.find_history_by_user(trader.id).await.unwrap(),
+            &markets.outcomes_for_user(trader.id).await.unwrap(),
+        );
+        let entries = LeaderboardRepository::new(db.pool().clone())
+            .rank(LeaderboardMetric::Profit, LeaderboardWindow::All, 10)
+            .await
+            .unwrap();
+        let entry = entries.iter().find(|e| e.user_id == trader.id).unwrap();
+        assert!((entry.score - record.realized_profit).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::domain::{BalanceMismatch, Journal, JournalId, LedgerAccount, PostedEntry, LEDGER_EPSILON};
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+
+#[derive(FromRow)]
+struct PostedEntryRow {
+    journal_id: i64,
+    kind: String,
+    market_id: Option<i64>,
+    amount: f64,
+    created_at: String,
+}
+
+impl TryFrom<PostedEntryRow> for PostedEntry {
+    type Error = RepositoryError;
+
+    fn try_from(row: PostedEntryRow) -> Result<Self> {
+        Ok(PostedEntry {
+            journal_id: row.journal_id,
+            // The column has no CHECK, so this is where unknown kinds are caught
+            kind: row
+                .kind
+                .parse()
+                .map_err(|e: String| RepositoryError::Database(sqlx::Error::Decode(e.into())))?,
+            market_id: row.market_id,
+            amount: row.amount,
+            created_at: DateTime::parse_from_rfc3339(&row.created_at)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct BalanceMismatchRow {
+    id: i64,
+    balance: f64,
+    ledger: f64,
+}
+
+#[derive(Clone)]
+pub struct LedgerRepository {
+    pool: SqlitePool,
+}
+
+impl LedgerRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Post a journal on its own
+    pub async fn post(&self, journal: &Journal) -> Result<JournalId> {
+        let mut tx = self.pool.begin().await?;
+        let id = post_journal(&mut tx, journal).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Sum of the entries on one account
+    pub async fn balance(&self, account: LedgerAccount) -> Result<f64> {
+        let balance = sqlx::query_scalar::<_, f64>("SELECT COALESCE(SUM(amount), 0.0) FROM ledger_entries WHERE account = ?")
+            .bind(account.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(balance)
+    }
+
+    /// Entries on one account, oldest first
+    pub async fn entries_for(&self, account: LedgerAccount) -> Result<Vec<PostedEntry>> {
+        let rows = sqlx::query_as::<_, PostedEntryRow>(
+            r#"
+            SELECT e.journal_id, j.kind, j.market_id, e.amount, j.created_at
+            FROM ledger_entries e
+            JOIN ledger_journals j ON j.id = e.journal_id
+            WHERE e.account = ?
+            ORDER BY e.id
+            "#,
+        )
+        .bind(account.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Users whose cached balance differs from their ledger account
+    pub async fn find_mismatches(&self) -> Result<Vec<BalanceMismatch>> {
+        let rows = sqlx::query_as::<_, BalanceMismatchRow>(
+            r#"
+            SELECT u.id, u.balance, COALESCE(l.total, 0.0) AS ledger
+            FROM users u
+            LEFT JOIN (
+                SELECT account, SUM(amount) AS total FROM ledger_entries GROUP BY account
+            ) l ON l.account = 'user:' || u.id
+            WHERE ABS(u.balance - COALESCE(l.total, 0.0)) > ?
+            ORDER BY u.id
+            "#,
+        )
+        .bind(LEDGER_EPSILON)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| BalanceMismatch { user_id: row.id, cached: row.balance, ledger: row.ledger })
+            .collect())
+    }
+
+    /// Sum of every entry in the ledger, zero when all journals balance
+    pub async fn total(&self) -> Result<f64> {
+        let total = sqlx::query_scalar::<_, f64>("SELECT COALESCE(SUM(amount), 0.0) FROM ledger_entries")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(total)
+    }
+}
+
+/// Post a journal inside the caller's transaction
+///
+/// Entries on user accounts also update the cached `users.balance`; a debit
+/// that would take a user, or a user's season balance, below zero fails the
+/// whole journal with `ConstraintViolation`.
+pub(crate) async fn post_journal(conn: &mut SqliteConnection, journal: &Journal) -> Result<JournalId> {
+    journal.validate().map_err(RepositoryError::ConstraintViolation)?;
+
+    let journal_id = sqlx::query_scalar::<_, i64>(
+        "INSERT INTO ledger_journals (kind, market_id, created_at) VALUES (?, ?, ?) RETURNING id",
+    )
+    .bind(journal.kind.to_string())
+    .bind(journal.market_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    for entry in &journal.entries {
+        sqlx::query("INSERT INTO ledger_entries (journal_id, account, amount) VALUES (?, ?, ?)")
+            .bind(journal_id)
+            .bind(entry.account.to_string())
+            .bind(entry.amount)
+            .execute(&mut *conn)
+            .await?;
+
+        if let LedgerAccount::User(user_id) = entry.account {
+            let result = sqlx::query("UPDATE users SET balance = balance + ? WHERE id = ? AND balance + ? >= 0")
+                .bind(entry.amount)
+                .bind(user_id)
+                .bind(entry.amount)
+                .execute(&mut *conn)
+                .await?;
+
+            if result.rows_affected() == 0 {
+                return Err(if entry.amount < 0.0 {
+                    RepositoryError::ConstraintViolation("Insufficient balance".to_string())
+                } else {
+                    RepositoryError::NotFound
+                });
+            }
+        }
+
+        // Season balances are not cached, so they are summed from the entries
+        if matches!(entry.account, LedgerAccount::Season(..)) && entry.amount < 0.0 {
+            let balance = sqlx::query_scalar::<_, f64>("SELECT COALESCE(SUM(amount), 0.0) FROM ledger_entries WHERE account = ?")
+                .bind(entry.account.to_string())
+                .fetch_one(&mut *conn)
+                .await?;
+            if balance < 0.0 {
+                return Err(RepositoryError::ConstraintViolation("Insufficient season balance".to_string()));
+            }
+        }
+    }
+
+    Ok(journal_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{JournalKind, LedgerEntry};
+    use crate::repository::UserRepository;
+    use crate::Database;
+
+    #[tokio::test]
+    async fn test_insufficient_balance_rolls_back_journal() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let alice = users.create_with_balance("alice", "hash", 10.0).await.unwrap();
+        let bob = users.create_with_balance("bob", "hash", 10.0).await.unwrap();
+        let ledger = LedgerRepository::new(db.pool().clone());
+        let total_before = ledger.total().await.unwrap();
+
+        // Bob's credit is posted before Alice's debit fails
+        let journal = Journal {
+            kind: JournalKind::Transfer,
+            market_id: None,
+            entries: vec![
+                LedgerEntry { account: LedgerAccount::User(bob.id), amount: 25.0 },
+                LedgerEntry { account: LedgerAccount::User(alice.id), amount: -25.0 },
+            ],
+        };
+        assert!(matches!(ledger.post(&journal).await, Err(RepositoryError::ConstraintViolation(_))));
+
+        assert_eq!(users.find_by_id(alice.id).await.unwrap().balance, 10.0);
+        assert_eq!(users.find_by_id(bob.id).await.unwrap().balance, 10.0);
+        assert_eq!(ledger.balance(LedgerAccount::User(bob.id)).await.unwrap(), 10.0);
+        assert_eq!(ledger.entries_for(LedgerAccount::User(bob.id)).await.unwrap().len(), 1);
+        assert_eq!(ledger.total().await.unwrap(), total_before);
+        assert!(ledger.find_mismatches().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unbalanced_journal_is_rejected() {
+        let db = Database::in_memory().await;
+        let alice = UserRepository::new(db.pool().clone()).create_with_balance("alice", "hash", 10.0).await.unwrap();
+        let ledger = LedgerRepository::new(db.pool().clone());
+
+        let journal = Journal {
+            kind: JournalKind::Grant,
+            market_id: None,
+            entries: vec![
+                LedgerEntry { account: LedgerAccount::Faucet, amount: -5.0 },
+                LedgerEntry { account: LedgerAccount::User(alice.id), amount: 6.0 },
+            ],
+        };
+        assert!(matches!(ledger.post(&journal).await, Err(RepositoryError::ConstraintViolation(_))));
+        assert_eq!(ledger.balance(LedgerAccount::User(alice.id)).await.unwrap(), 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_journal_kind_is_rejected_on_read() {
+        let db = Database::in_memory().await;
+        let ledger = LedgerRepository::new(db.pool().clone());
+
+        let journal_id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO ledger_journals (kind, market_id, created_at) VALUES ('bonus', NULL, ?) RETURNING id",
+        )
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(db.pool())
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO ledger_entries (journal_id, account, amount) VALUES (?, 'faucet', 0.0)")
+            .bind(journal_id)
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        assert!(matches!(ledger.entries_for(LedgerAccount::Faucet).await, Err(RepositoryError::Database(_))));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::domain::{
+    distribute_pro_rata, lp_shares_for_deposit, Journal, JournalKind, LedgerAccount, LedgerEntry, LiquidityDeposit,
+    LiquidityProvider, LmsrPricing, Market, MarketId, SeasonId, UserId,
+};
+use crate::repository::ledger_repo::post_journal;
+use crate::repository::market_repo::{MarketRow, MARKET_COLUMNS};
+use crate::repository::price_snapshot_repo::insert_snapshot;
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+
+#[derive(FromRow)]
+struct DepositRow {
+    id: i64,
+    market_id: i64,
+    user_id: i64,
+    username: String,
+    amount: f64,
+    shares: f64,
+    liquidity_param: f64,
+    created_at: String,
+}
+
+impl TryFrom<DepositRow> for LiquidityDeposit {
+    type Error = RepositoryError;
+
+    fn try_from(row: DepositRow) -> Result<Self> {
+        Ok(LiquidityDeposit {
+            id: row.id,
+            market_id: row.market_id,
+            user_id: row.user_id,
+            username: row.username,
+            amount: row.amount,
+            shares: row.shares,
+            liquidity_param: row.liquidity_param,
+            created_at: DateTime::parse_from_rfc3339(&row.created_at)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct LiquidityRepository {
+    pool: SqlitePool,
+}
+
+impl LiquidityRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Add `amount` from a user's balance to an open market's subsidy, deepening it
+    ///
+    /// Season markets are funded from the user's season balance. The deposit
+    /// buys LP shares at what the market maker holds per share, see
+    /// `lp_shares_for_deposit`. Returns the market as rescaled by
+    /// `Market::add_liquidity`. Fails with
+    /// `ConstraintViolation` when the market is not open, the deposit would
+    /// take `b` past its maximum, or the user cannot afford it.
+    pub async fn deposit(&self, market_id: MarketId, user_id: UserId, amount: f64) -> Result<Market> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut market: Market = sqlx::query_as::<_, MarketRow>(&format!(
+            "SELECT {} FROM markets m WHERE m.id = ?",
+            MARKET_COLUMNS
+        ))
+        .bind(market_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(RepositoryError::NotFound)?
+        .try_into()?;
+
+        if !market.can_trade() {
+            return Err(RepositoryError::ConstraintViolation(
+                "Liquidity can only be added to open markets".to_string(),
+            ));
+        }
+        market.add_liquidity(amount).map_err(RepositoryError::ConstraintViolation)?;
+
+        sqlx::query(
+            r#"
+            UPDATE markets
+            SET q_yes = ?, q_no = ?, virtual_yes = ?, virtual_no = ?, liquidity_param = ?, cost_base = ?, subsidy = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(market.q_yes)
+        .bind(market.q_no)
+        .bind(market.virtual_yes)
+        .bind(market.virtual_no)
+        .bind(market.liquidity_param)
+        .bind(market.cost_base)
+        .bind(market.subsidy)
+        .bind(market_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // The price is unchanged, but the history should hold the rescaled q
+        let yes_probability = LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param);
+        insert_snapshot(&mut tx, market_id, yes_probability, 1.0 - yes_probability, market.q_yes, market.q_no).await?;
+
+        let outstanding =
+            sqlx::query_scalar::<_, f64>("SELECT COALESCE(SUM(shares), 0.0) FROM liquidity_deposits WHERE market_id = ?")
+                .bind(market_id)
+                .fetch_one(&mut *tx)
+                .await?;
+        let shares = lp_shares_for_deposit(amount, outstanding, market_maker_balance(&mut tx, market_id).await?);
+
+        let journal = Journal::transfer(
+            JournalKind::Subsidy,
+            LedgerAccount::trader(user_id, market.season_id),
+            LedgerAccount::MarketMaker(market_id),
+            amount,
+        );
+        let journal_id = post_journal(&mut tx, &journal).await?;
+
+        sqlx::query(
+            "INSERT INTO liquidity_deposits (market_id, user_id, amount, shares, liquidity_param, journal_id, \
+             created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(market_id)
+        .bind(user_id)
+        .bind(amount)
+        .bind(shares)
+        .bind(market.liquidity_param)
+        .bind(journal_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(market)
+    }
+
+    /// Deposits into a market, oldest first
+    pub async fn find_by_market(&self, market_id: MarketId) -> Result<Vec<LiquidityDeposit>> {
+        find_deposits(&mut *self.pool.acquire().await?, market_id).await
+    }
+}
+
+/// Pay what the market maker of a resolved market still holds to its providers, by LP share
+///
+/// Runs inside the caller's transaction, the one that settles the market.
+/// Returns what each provider received; nothing is paid when the market had
+/// no providers or its market maker lost the whole subsidy. Providers of a
+/// season market are paid into their season balance, which funded it, and
+/// the share of deposits forfeited by deleted accounts goes to the faucet.
+pub(crate) async fn distribute_to_providers(
+    conn: &mut SqliteConnection,
+    market_id: MarketId,
+    season_id: Option<SeasonId>,
+) -> Result<Vec<(UserId, f64)>> {
+    let market_maker = LedgerAccount::MarketMaker(market_id);
+    let balance = market_maker_balance(conn, market_id).await?;
+    let providers = LiquidityProvider::from_deposits(&find_deposits(conn, market_id).await?);
+    let amounts = distribute_pro_rata(balance, &providers);
+    if amounts.is_empty() {
+        return Ok(amounts);
+    }
+
+    let forfeited = sqlx::query_scalar::<_, i64>(
+        "SELECT DISTINCT user_id FROM liquidity_deposits WHERE market_id = ? AND forfeited = 1",
+    )
+    .bind(market_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut entries = vec![LedgerEntry { account: market_maker, amount: -balance }];
+    entries.extend(amounts.iter().map(|&(user_id, amount)| {
+        let account = if forfeited.contains(&user_id) {
+            LedgerAccount::Faucet
+        } else {
+            LedgerAccount::trader(user_id, season_id)
+        };
+        LedgerEntry { account, amount }
+    }));
+    let journal = Journal { kind: JournalKind::Refund, market_id: Some(market_id), entries };
+    post_journal(conn, &journal).await?;
+
+    Ok(amounts)
+}
+
+async fn market_maker_balance(conn: &mut SqliteConnection, market_id: MarketId) -> Result<f64> {
+    let balance =
+        sqlx::query_scalar::<_, f64>("SELECT COALESCE(SUM(amount), 0.0) FROM ledger_entries WHERE account = ?")
+            .bind(LedgerAccount::MarketMaker(market_id).to_string())
+            .fetch_one(&mut *conn)
+            .await?;
+    Ok(balance)
+}
+
+async fn find_deposits(conn: &mut SqliteConnection, market_id: MarketId) -> Result<Vec<LiquidityDeposit>> {
+    let rows = sqlx::query_as::<_, DepositRow>(
+        r#"
+        SELECT d.id, d.market_id, d.user_id, u.username, d.amount, d.shares, d.liquidity_param, d.created_at
+        FROM liquidity_deposits d
+        JOIN users u ON u.id = d.user_id
+        WHERE d.market_id = ?
+        ORDER BY d.id
+        "#,
+    )
+    .bind(market_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    rows.into_iter().map(TryInto::try_into).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{CandleInterval, MarketSide, TransactionType};
+    use crate::repository::{
+        LedgerRepository, MarketRepository, PositionRepository, PriceSnapshotRepository, TransactionRepository,
+        UserRepository,
+    };
+    use crate::Database;
+    use chrono::Duration;
+
+    const EPSILON: f64 = 1e-9;
+
+    /// A market created by `creator` with b = 100, and a provider holding $200
+    async fn setup() -> (Database, UserId, UserId, Market) {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let creator = users.create_with_balance("creator", "hash", 1000.0).await.unwrap();
+        let provider = users.create_with_balance("provider", "hash", 200.0).await.unwrap();
+        let market = MarketRepository::new(db.pool().clone())
+            .create("Will it rain?", None, creator.id, None, Utc::now() + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+        (db, creator.id, provider.id, market)
+    }
+
+    fn probability(market: &Market) -> f64 {
+        LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param)
+    }
+
+    #[tokio::test]
+    async fn test_deposit_keeps_price() {
+        let (db, creator, provider, market) = setup().await;
+        PositionRepository::new(db.pool().clone()).buy(creator, market.id, MarketSide::Yes, 60.0).await.unwrap();
+        let markets = MarketRepository::new(db.pool().clone());
+        let before = markets.find_by_id(market.id).await.unwrap();
+
+        let deepened = LiquidityRepository::new(db.pool().clone()).deposit(market.id, provider, 50.0).await.unwrap();
+        let after = markets.find_by_id(market.id).await.unwrap();
+        assert!((probability(&after) - probability(&before)).abs() < EPSILON);
+        assert!((probability(&deepened) - probability(&before)).abs() < EPSILON);
+        assert!(after.liquidity_param > before.liquidity_param);
+        assert!((after.collected() - before.collected()).abs() < 1e-6);
+        assert!((after.subsidy - before.subsidy - 50.0).abs() < EPSILON);
+
+        let users = UserRepository::new(db.pool().clone());
+        assert!((users.find_by_id(provider).await.unwrap().balance - 150.0).abs() < EPSILON);
+        assert!(LedgerRepository::new(db.pool().clone()).find_mismatches().await.unwrap().is_empty());
+
+        // Snapshotted with the rescaled q, but not counted as traded
+        let snapshots = PriceSnapshotRepository::new(db.pool().clone());
+        let latest = snapshots.get_latest(market.id).await.unwrap().unwrap();
+        assert_eq!((latest.q_yes, latest.q_no), (after.q_yes, after.q_no));
+        let now = Utc::now();
+        let candles = snapshots
+            .get_candles(market.id, now - Duration::days(1), now + Duration::days(1), CandleInterval::OneWeek)
+            .await
+            .unwrap();
+        assert!((candles.iter().map(|c| c.volume).sum::<f64>() - 60.0).abs() < EPSILON);
+        assert_eq!(candles.iter().map(|c| c.trades).sum::<i64>(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deposit_beyond_balance_changes_nothing() {
+        let (db, _, provider, market) = setup().await;
+
+        let result = LiquidityRepository::new(db.pool().clone()).deposit(market.id, provider, 250.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+
+        let after = MarketRepository::new(db.pool().clone()).find_by_id(market.id).await.unwrap();
+        assert_eq!(after.liquidity_param, market.liquidity_param);
+        assert_eq!(after.subsidy, market.subsidy);
+        assert_eq!(LiquidityRepository::new(db.pool().clone()).find_by_market(market.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_distribute_returns_subsidy_pro_rata() {
+        let (db, creator, provider, market) = setup().await;
+        let liquidity = LiquidityRepository::new(db.pool().clone());
+        liquidity.deposit(market.id, provider, 50.0).await.unwrap();
+        MarketRepository::new(db.pool().clone()).resolve_and_settle(market.id, true).await.unwrap();
+
+        // Nothing was traded, so each provider gets back exactly what they put in
+        let users = UserRepository::new(db.pool().clone());
+        assert!((users.find_by_id(creator).await.unwrap().balance - 1000.0).abs() < 1e-6);
+        assert!((users.find_by_id(provider).await.unwrap().balance - 200.0).abs() < 1e-6);
+        let ledger = LedgerRepository::new(db.pool().clone());
+        assert!(ledger.balance(LedgerAccount::MarketMaker(market.id)).await.unwrap().abs() < 1e-6);
+        assert!(ledger.find_mismatches().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_late_deposit_does_not_share_earlier_profit() {
+        let (db, creator, provider, market) = setup().await;
+        let trader = UserRepository::new(db.pool().clone()).create_with_balance("trader", "hash", 100.0).await.unwrap();
+        let bought = PositionRepository::new(db.pool().clone())
+            .buy(trader.id, market.id, MarketSide::Yes, 40.0)
+            .await
+            .unwrap();
+
+        // The market maker already holds the trader's payment when the provider buys in
+        let liquidity = LiquidityRepository::new(db.pool().clone());
+        liquidity.deposit(market.id, provider, 50.0).await.unwrap();
+        let deposits = liquidity.find_by_market(market.id).await.unwrap();
+        assert!((deposits[1].shares - 50.0 * market.subsidy / (market.subsidy + bought.amount)).abs() < 1e-6);
+
+        // NO wins, so the market maker keeps everything: the payment goes to the creator alone
+        MarketRepository::new(db.pool().clone()).resolve_and_settle(market.id, false).await.unwrap();
+        let users = UserRepository::new(db.pool().clone());
+        assert!((users.find_by_id(provider).await.unwrap().balance - 200.0).abs() < 1e-6);
+        assert!((users.find_by_id(creator).await.unwrap().balance - (1000.0 + bought.amount)).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_settle_pays_winners_once() {
+        let (db, creator, provider, market) = setup().await;
+        let shares = 40.0;
+        let quote = PositionRepository::new(db.pool().clone())
+            .buy(provider, market.id, MarketSide::Yes, shares)
+            .await
+            .unwrap();
+        let markets = MarketRepository::new(db.pool().clone());
+        markets.resolve_and_settle(market.id, true).await.unwrap();
+
+        let users = UserRepository::new(db.pool().clone());
+        let paid = users.find_by_id(provider).await.unwrap().balance;
+        assert!((paid - (200.0 - quote.amount + shares)).abs() < 1e-6);
+        let payouts: Vec<_> = TransactionRepository::new(db.pool().clone())
+            .find_by_user(provider)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.transaction_type == TransactionType::Payout)
+            .collect();
+        assert_eq!(payouts.len(), 1);
+        assert!((payouts[0].amount - shares).abs() < 1e-6);
+
+        // The creator's subsidy covered the trader's winnings; what is left goes back to them
+        let ledger = LedgerRepository::new(db.pool().clone());
+        assert!(ledger.balance(LedgerAccount::MarketMaker(market.id)).await.unwrap().abs() < 1e-6);
+        assert!(ledger.find_mismatches().await.unwrap().is_empty());
+        let settled = users.find_by_id(creator).await.unwrap().balance;
+
+        assert!(markets.resolve_and_settle(market.id, true).await.is_err());
+        assert!((users.find_by_id(provider).await.unwrap().balance - paid).abs() < 1e-6);
+        assert!((users.find_by_id(creator).await.unwrap().balance - settled).abs() < 1e-6);
+    }
+}
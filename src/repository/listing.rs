@@ -0,0 +1,115 @@
+use crate::domain::{Cursor, CursorKey, ListQuery, MarketSort, MarketStatus};
+use crate::repository::{RepositoryError, Result};
+use chrono::Utc;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+/// SQL expression and direction used to order a listing
+///
+/// `market` is the alias of the markets table and `newest` the timestamp column
+/// that "newest" refers to (market creation, or opening for positions). It must
+/// not change once a row exists, or cursors would skip or repeat rows.
+pub(crate) fn sort_column(sort: MarketSort, market: &str, newest: &str) -> (String, bool) {
+    match sort {
+        MarketSort::Newest => (newest.to_string(), true),
+        MarketSort::EndingSoon => (format!("{}.end_date", market), false),
+        MarketSort::MostTraded => (format!("{}.volume", market), true),
+        // |q_yes - q_no| / b is monotonic in the distance of the YES probability from 50%
+        MarketSort::Closest => (
+            format!("ABS({m}.q_yes - {m}.q_no) / {m}.liquidity_param", m = market),
+            false,
+        ),
+    }
+}
+
+/// Restrict a listing to markets with the given status (see `Market::status`)
+pub(crate) fn push_status_filter(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    status: Option<MarketStatus>,
+    market: &str,
+) {
+    let now = Utc::now().to_rfc3339();
+    match status {
+        None => {}
+        Some(MarketStatus::Active) => {
+            builder.push(format!(
+                " AND {m}.resolved = 0 AND {m}.closed_at IS NULL AND {m}.end_date > ",
+                m = market
+            ));
+            builder.push_bind(now);
+        }
+        Some(MarketStatus::Closed) => {
+            builder.push(format!(
+                " AND {m}.resolved = 0 AND ({m}.closed_at IS NOT NULL OR {m}.end_date <= ",
+                m = market
+            ));
+            builder.push_bind(now);
+            builder.push(")");
+        }
+        Some(MarketStatus::Resolved) => {
+            builder.push(format!(" AND {}.resolved = 1", market));
+        }
+    }
+}
+
+/// Append the keyset condition, ordering and limit for `query`
+///
+/// One extra row is fetched so the caller can tell whether another page follows.
+pub(crate) fn push_page(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    query: &ListQuery,
+    key: &str,
+    descending: bool,
+    id_column: &str,
+) {
+    let cmp = if descending { "<" } else { ">" };
+    if let Some(ref cursor) = query.cursor {
+        builder.push(format!(" AND ({} {} ", key, cmp));
+        push_key(builder, &cursor.key);
+        builder.push(format!(" OR ({} = ", key));
+        push_key(builder, &cursor.key);
+        builder.push(format!(" AND {} {} ", id_column, cmp));
+        builder.push_bind(cursor.id);
+        builder.push("))");
+    }
+
+    let dir = if descending { "DESC" } else { "ASC" };
+    builder.push(format!(" ORDER BY {} {}, {} {} LIMIT ", key, dir, id_column, dir));
+    builder.push_bind(query.limit + 1);
+}
+
+fn push_key(builder: &mut QueryBuilder<'_, Sqlite>, key: &CursorKey) {
+    match key {
+        CursorKey::Text(s) => builder.push_bind(s.clone()),
+        CursorKey::Number(n) => builder.push_bind(*n),
+    };
+}
+
+/// Read the `sort_key` and `id_field` columns of the last row into a cursor
+pub(crate) fn cursor_from_row(row: &SqliteRow, sort: MarketSort, id_field: &str) -> Result<Cursor> {
+    let key = match sort {
+        MarketSort::Newest | MarketSort::EndingSoon => CursorKey::Text(row.try_get("sort_key")?),
+        MarketSort::MostTraded | MarketSort::Closest => CursorKey::Number(row.try_get("sort_key")?),
+    };
+    let id = row.try_get(id_field)?;
+    Ok(Cursor { sort, key, id })
+}
+
+/// Trim the extra lookahead row and compute the next cursor
+///
+/// `id_field` is the name of the result column holding the row id the page is keyed on.
+pub(crate) fn split_page(
+    mut rows: Vec<SqliteRow>,
+    query: &ListQuery,
+    id_field: &str,
+) -> Result<(Vec<SqliteRow>, Option<Cursor>)> {
+    let has_more = rows.len() as i64 > query.limit;
+    rows.truncate(query.limit as usize);
+    let next_cursor = if has_more {
+        let last = rows.last().ok_or(RepositoryError::NotFound)?;
+        Some(cursor_from_row(last, query.sort, id_field)?)
+    } else {
+        None
+    };
+    Ok((rows, next_cursor))
+}
@@ -0,0 +1,175 @@
+use crate::domain::{LoginAttempts, LoginScope, LoginThrottlePolicy};
+use crate::repository::Result;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(FromRow)]
+struct LoginAttemptRow {
+    failures: i64,
+    last_failure_at: String,
+    locked_until: Option<String>,
+}
+
+impl From<LoginAttemptRow> for LoginAttempts {
+    fn from(row: LoginAttemptRow) -> Self {
+        LoginAttempts {
+            failures: u32::try_from(row.failures).unwrap_or(u32::MAX),
+            last_failure_at: row.last_failure_at.parse().unwrap_or_else(|_| Utc::now()),
+            locked_until: row.locked_until.and_then(|s| s.parse().ok()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoginAttemptRepository {
+    pool: SqlitePool,
+}
+
+impl LoginAttemptRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find(&self, scope: LoginScope, key: &str) -> Result<Option<LoginAttempts>> {
+        let row = sqlx::query_as::<_, LoginAttemptRow>(
+            r#"
+            SELECT failures, last_failure_at, locked_until
+            FROM login_attempts
+            WHERE scope = ? AND key = ?
+            "#,
+        )
+        .bind(scope.to_string())
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    /// Count one more failure against the key and return its history afterwards
+    ///
+    /// The increment happens in a single upsert so that concurrent failures can't
+    /// overwrite each other. Failures older than the policy's lockout are forgotten
+    /// unless the key is still locked, and reaching `max_failures` locks the key
+    /// until `lockout` from now. Failures during a lockout are counted but don't
+    /// extend it.
+    pub async fn record_failure(
+        &self,
+        scope: LoginScope,
+        key: &str,
+        policy: &LoginThrottlePolicy,
+        now: DateTime<Utc>,
+    ) -> Result<LoginAttempts> {
+        let row = sqlx::query_as::<_, LoginAttemptRow>(
+            r#"
+            INSERT INTO login_attempts (scope, key, failures, last_failure_at, locked_until)
+            VALUES (?1, ?2, 1, ?3, CASE WHEN 1 >= ?5 THEN ?6 END)
+            ON CONFLICT (scope, key) DO UPDATE SET
+                failures = CASE
+                    WHEN (locked_until IS NULL OR locked_until <= ?3) AND last_failure_at <= ?4 THEN 1
+                    ELSE failures + 1
+                END,
+                last_failure_at = excluded.last_failure_at,
+                locked_until = CASE
+                    WHEN (locked_until IS NULL OR locked_until <= ?3) AND last_failure_at <= ?4 THEN excluded.locked_until
+                    WHEN locked_until > ?3 THEN locked_until
+                    WHEN failures + 1 >= ?5 THEN ?6
+                END
+            RETURNING failures, last_failure_at, locked_until
+            "#,
+        )
+        .bind(scope.to_string())
+        .bind(key)
+        .bind(now.to_rfc3339())
+        .bind((now - policy.lockout).to_rfc3339())
+        .bind(i64::from(policy.max_failures))
+        .bind((now + policy.lockout).to_rfc3339())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    pub async fn clear(&self, scope: LoginScope, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_attempts WHERE scope = ? AND key = ?")
+            .bind(scope.to_string())
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Drop rows whose last failure is older than `before` and that are no longer locked
+    pub async fn purge_stale(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM login_attempts
+            WHERE last_failure_at < ? AND (locked_until IS NULL OR locked_until < ?)
+            "#,
+        )
+        .bind(before.to_rfc3339())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+    use chrono::Duration;
+
+    const POLICY: LoginThrottlePolicy = LoginThrottlePolicy {
+        max_failures: 3,
+        lockout: Duration::minutes(15),
+        base_delay: Duration::seconds(1),
+        max_delay: Duration::seconds(5),
+    };
+
+    #[tokio::test]
+    async fn test_record_failure_locks_out_after_max_failures() {
+        let db = Database::in_memory().await;
+        let attempts = LoginAttemptRepository::new(db.pool().clone());
+        let now = Utc::now();
+
+        let first = attempts.record_failure(LoginScope::Username, "alice", &POLICY, now).await.unwrap();
+        assert_eq!(first.failures, 1);
+        assert!(!first.is_locked(now));
+
+        attempts.record_failure(LoginScope::Username, "alice", &POLICY, now).await.unwrap();
+        let third = attempts.record_failure(LoginScope::Username, "alice", &POLICY, now).await.unwrap();
+        assert_eq!(third.failures, 3);
+        assert!(third.is_locked(now));
+        assert_eq!(attempts.find(LoginScope::Username, "alice").await.unwrap(), Some(third));
+
+        // Counted per scope and key
+        let other = attempts.record_failure(LoginScope::Ip, "alice", &POLICY, now).await.unwrap();
+        assert_eq!(other.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_record_failure_forgets_stale_failures() {
+        let db = Database::in_memory().await;
+        let attempts = LoginAttemptRepository::new(db.pool().clone());
+        let now = Utc::now();
+
+        attempts.record_failure(LoginScope::Ip, "10.0.0.1", &POLICY, now).await.unwrap();
+        let later = now + POLICY.lockout;
+        let next = attempts.record_failure(LoginScope::Ip, "10.0.0.1", &POLICY, later).await.unwrap();
+        assert_eq!(next.failures, 1);
+
+        // A lockout keeps counting until it expires, without being extended
+        for _ in 0..2 {
+            attempts.record_failure(LoginScope::Ip, "10.0.0.1", &POLICY, later).await.unwrap();
+        }
+        let locked = attempts
+            .record_failure(LoginScope::Ip, "10.0.0.1", &POLICY, later + POLICY.lockout / 2)
+            .await
+            .unwrap();
+        assert_eq!(locked.failures, 4);
+        assert!(locked.is_locked(later + POLICY.lockout / 2));
+        assert!(!locked.is_locked(later + POLICY.lockout));
+    }
+}
@@ -1,7 +1,80 @@
-use crate::domain::{Market, MarketId, UserId};
+use crate::domain::{
+    Journal, JournalKind, LedgerAccount, ListQuery, LmsrPricing, Market, MarketId, MarketSide, MarketStatus, Page,
+    SeasonId, TransactionType, UserId,
+};
+use crate::repository::ledger_repo::post_journal;
+use crate::repository::liquidity_repo::distribute_to_providers;
+use crate::repository::transaction_repo::record_transaction;
+use crate::repository::listing::{push_page, push_status_filter, sort_column, split_page};
 use crate::repository::{Result, RepositoryError};
-use sqlx::SqlitePool;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+pub(crate) const MARKET_COLUMNS: &str = "m.id, m.question, m.description, m.creator_id, m.oracle_id, m.end_date, \
+    m.closed_at, m.resolved, m.outcome, m.yes_pool, m.no_pool, m.q_yes, m.q_no, m.liquidity_param, m.subsidy, \
+    m.virtual_yes, m.virtual_no, m.cost_base, m.complete_sets, m.season_id, m.volume, m.created_at";
+
+#[derive(FromRow)]
+pub(crate) struct MarketRow {
+    id: i64,
+    question: String,
+    description: Option<String>,
+    creator_id: i64,
+    oracle_id: Option<i64>,
+    end_date: String,
+    closed_at: Option<String>,
+    resolved: bool,
+    outcome: Option<bool>,
+    yes_pool: f64,
+    no_pool: f64,
+    q_yes: f64,
+    q_no: f64,
+    liquidity_param: f64,
+    subsidy: f64,
+    virtual_yes: f64,
+    virtual_no: f64,
+    cost_base: f64,
+    complete_sets: f64,
+    season_id: Option<i64>,
+    volume: f64,
+    created_at: String,
+}
+
+impl TryFrom<MarketRow> for Market {
+    type Error = RepositoryError;
+
+    fn try_from(r: MarketRow) -> Result<Self> {
+        Ok(Market {
+            id: r.id,
+            question: r.question,
+            description: r.description,
+            creator_id: r.creator_id,
+            oracle_id: r.oracle_id,
+            end_date: DateTime::parse_from_rfc3339(&r.end_date)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+            closed_at: r.closed_at.as_ref().and_then(|s| DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.with_timezone(&Utc))),
+            resolved: r.resolved,
+            outcome: r.outcome,
+            yes_pool: r.yes_pool,
+            no_pool: r.no_pool,
+            q_yes: r.q_yes,
+            q_no: r.q_no,
+            liquidity_param: r.liquidity_param,
+            subsidy: r.subsidy,
+            virtual_yes: r.virtual_yes,
+            virtual_no: r.virtual_no,
+            cost_base: r.cost_base,
+            complete_sets: r.complete_sets,
+            season_id: r.season_id,
+            volume: r.volume,
+            created_at: DateTime::parse_from_rfc3339(&r.created_at)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+        })
+    }
+}
 
 #[derive(Clone)]
 pub struct MarketRepository {
@@ -13,6 +86,11 @@ impl MarketRepository {
         Self { pool }
     }
 
+    /// Create a market, charging the creator its subsidy of `b * ln 2`
+    ///
+    /// The subsidy moves from the creator to the market maker in the same
+    /// transaction, so a creator who cannot afford it gets `ConstraintViolation`
+    /// and no market. It is recorded as the market's first liquidity deposit.
     pub async fn create(
         &self,
         question: &str,
@@ -20,215 +98,251 @@ impl MarketRepository {
         creator_id: UserId,
         oracle_id: Option<UserId>,
         end_date: DateTime<Utc>,
-        initial_liquidity: f64,
+        liquidity_param: f64,
     ) -> Result<Market> {
-        let end_date_str = end_date.to_rfc3339();
-        let result = sqlx::query!(
+        let subsidy = LmsrPricing::max_loss(liquidity_param);
+        let mut tx = self.pool.begin().await?;
+
+        let id = sqlx::query_scalar::<_, i64>(
             r#"
-            INSERT INTO markets (question, description, creator_id, oracle_id, end_date, yes_pool, no_pool, q_yes, q_no, liquidity_param)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            RETURNING id, question, description, creator_id, oracle_id, end_date, closed_at, resolved, outcome, yes_pool, no_pool, q_yes, q_no, liquidity_param, created_at
+            INSERT INTO markets (question, description, creator_id, oracle_id, end_date, yes_pool, no_pool, q_yes, q_no, liquidity_param, subsidy, cost_base)
+            VALUES (?, ?, ?, ?, ?, 0.0, 0.0, 0.0, 0.0, ?, ?, ?)
+            RETURNING id
             "#,
-            question,
-            description,
-            creator_id,
-            oracle_id,
-            end_date_str,
-            0.0,  // Legacy yes_pool (not used)
-            0.0,  // Legacy no_pool (not used)
-            0.0,  // q_yes starts at 0
-            0.0,  // q_no starts at 0
-            initial_liquidity  // LMSR liquidity parameter
         )
-        .fetch_one(&self.pool)
+        .bind(question)
+        .bind(description)
+        .bind(creator_id)
+        .bind(oracle_id)
+        .bind(end_date.to_rfc3339())
+        .bind(liquidity_param)
+        .bind(subsidy)
+        .bind(LmsrPricing::cost_function(0.0, 0.0, liquidity_param))
+        .fetch_one(&mut *tx)
         .await?;
 
-        Ok(Market {
-            id: result.id,
-            question: result.question,
-            description: result.description,
-            creator_id: result.creator_id,
-            oracle_id: result.oracle_id,
-            end_date: DateTime::parse_from_rfc3339(&result.end_date)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-            closed_at: result.closed_at.as_ref().and_then(|s| DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.with_timezone(&Utc))),
-            resolved: result.resolved,
-            outcome: result.outcome,
-            yes_pool: result.yes_pool,
-            no_pool: result.no_pool,
-            q_yes: result.q_yes,
-            q_no: result.q_no,
-            liquidity_param: result.liquidity_param,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-        })
+        if subsidy > 0.0 {
+            let journal = Journal::transfer(
+                JournalKind::Subsidy,
+                LedgerAccount::User(creator_id),
+                LedgerAccount::MarketMaker(id),
+                subsidy,
+            );
+            let journal_id = post_journal(&mut tx, &journal).await?;
+
+            sqlx::query(
+                "INSERT INTO liquidity_deposits (market_id, user_id, amount, shares, liquidity_param, journal_id, \
+                 created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(id)
+            .bind(creator_id)
+            .bind(subsidy)
+            .bind(subsidy)
+            .bind(liquidity_param)
+            .bind(journal_id)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let row = sqlx::query_as::<_, MarketRow>(&format!("SELECT {} FROM markets m WHERE m.id = ?", MARKET_COLUMNS))
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        row.try_into()
     }
 
     pub async fn find_by_id(&self, id: MarketId) -> Result<Market> {
-        let result = sqlx::query!(
-            r#"
-            SELECT id, question, description, creator_id, oracle_id, end_date, closed_at, resolved, outcome, yes_pool, no_pool, q_yes, q_no, liquidity_param, created_at
-            FROM markets
-            WHERE id = ?
-            "#,
-            id
-        )
-        .fetch_optional(&self.pool)
-        .await?
-        .ok_or(RepositoryError::NotFound)?;
+        let row = sqlx::query_as::<_, MarketRow>(&format!("SELECT {} FROM markets m WHERE m.id = ?", MARKET_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
 
-        Ok(Market {
-            id: result.id,
-            question: result.question,
-            description: result.description,
-            creator_id: result.creator_id,
-            oracle_id: result.oracle_id,
-            end_date: DateTime::parse_from_rfc3339(&result.end_date)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-            closed_at: result.closed_at.as_ref().and_then(|s| DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.with_timezone(&Utc))),
-            resolved: result.resolved,
-            outcome: result.outcome,
-            yes_pool: result.yes_pool,
-            no_pool: result.no_pool,
-            q_yes: result.q_yes,
-            q_no: result.q_no,
-            liquidity_param: result.liquidity_param,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-        })
+        row.try_into()
     }
 
     pub async fn list_active(&self) -> Result<Vec<Market>> {
-        let results = sqlx::query!(
-            r#"
-            SELECT id, question, description, creator_id, oracle_id, end_date, closed_at, resolved, outcome, yes_pool, no_pool, q_yes, q_no, liquidity_param, created_at
-            FROM markets
-            WHERE resolved = 0
-            ORDER BY created_at DESC
-            "#
-        )
+        let rows = sqlx::query_as::<_, MarketRow>(&format!(
+            "SELECT {} FROM markets m WHERE m.resolved = 0 ORDER BY m.created_at DESC",
+            MARKET_COLUMNS
+        ))
         .fetch_all(&self.pool)
         .await?;
 
-        results
-            .into_iter()
-            .map(|r| {
-                Ok(Market {
-                    id: r.id.unwrap_or_default(),
-                    question: r.question,
-                    description: r.description,
-                    creator_id: r.creator_id,
-                    oracle_id: r.oracle_id,
-                    end_date: DateTime::parse_from_rfc3339(&r.end_date)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                    closed_at: r.closed_at.as_ref().and_then(|s| DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.with_timezone(&Utc))),
-                    resolved: r.resolved,
-                    outcome: r.outcome,
-                    yes_pool: r.yes_pool,
-                    no_pool: r.no_pool,
-                    q_yes: r.q_yes,
-                    q_no: r.q_no,
-                    liquidity_param: r.liquidity_param,
-                    created_at: DateTime::parse_from_rfc3339(&r.created_at)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                })
-            })
-            .collect()
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Markets attached to a season, newest first
+    pub async fn find_by_season(&self, season_id: SeasonId) -> Result<Vec<Market>> {
+        let rows = sqlx::query_as::<_, MarketRow>(&format!(
+            "SELECT {} FROM markets m WHERE m.season_id = ? ORDER BY m.created_at DESC",
+            MARKET_COLUMNS
+        ))
+        .bind(season_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
     }
 
     pub async fn list_all(&self) -> Result<Vec<Market>> {
-        let results = sqlx::query!(
+        let rows = sqlx::query_as::<_, MarketRow>(&format!(
+            "SELECT {} FROM markets m ORDER BY m.created_at DESC",
+            MARKET_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// List one page of markets matching the query's status filter and sort order
+    pub async fn list_page(&self, query: &ListQuery) -> Result<Page<Market>> {
+        let (key, descending) = sort_column(query.sort, "m", "m.created_at");
+
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+            "SELECT {}, {} AS sort_key FROM markets m WHERE 1 = 1",
+            MARKET_COLUMNS, key
+        ));
+        push_status_filter(&mut builder, query.status, "m");
+        push_page(&mut builder, query, &key, descending, "m.id");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        let (rows, next_cursor) = split_page(rows, query, "id")?;
+
+        let items = rows
+            .iter()
+            .map(|row| MarketRow::from_row(row)?.try_into())
+            .collect::<Result<Vec<Market>>>()?;
+
+        Ok(Page { items, next_cursor })
+    }
+
+    /// Count markets with the given status (all markets when `None`)
+    pub async fn count(&self, status: Option<MarketStatus>) -> Result<i64> {
+        let mut builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT COUNT(*) FROM markets m WHERE 1 = 1");
+        push_status_filter(&mut builder, status, "m");
+
+        let count: i64 = builder.build_query_scalar().fetch_one(&self.pool).await?;
+        Ok(count)
+    }
+
+    pub async fn count_created_by(&self, user_id: UserId) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM markets WHERE creator_id = ?")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    /// Count resolved markets the user resolved as oracle, including their own
+    /// markets without a designated oracle
+    pub async fn count_resolved_by_oracle(&self, user_id: UserId) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM markets WHERE resolved = 1 AND COALESCE(oracle_id, creator_id) = ?",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// Count unresolved markets waiting for the user to resolve them as oracle
+    pub async fn count_awaiting_oracle(&self, user_id: UserId) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM markets WHERE resolved = 0 AND COALESCE(oracle_id, creator_id) = ?",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// Markets created by a user, oldest first
+    pub async fn find_by_creator(&self, user_id: UserId) -> Result<Vec<Market>> {
+        let rows = sqlx::query_as::<_, MarketRow>(&format!(
+            "SELECT {} FROM markets m WHERE m.creator_id = ? ORDER BY m.id",
+            MARKET_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Outcomes of the resolved markets a user has held a position in
+    pub async fn outcomes_for_user(&self, user_id: UserId) -> Result<HashMap<MarketId, bool>> {
+        let rows = sqlx::query_as::<_, (i64, bool)>(
             r#"
-            SELECT id, question, description, creator_id, oracle_id, end_date, closed_at, resolved, outcome, yes_pool, no_pool, q_yes, q_no, liquidity_param, created_at
+            SELECT id, outcome
             FROM markets
-            ORDER BY created_at DESC
-            "#
+            WHERE resolved = 1 AND outcome IS NOT NULL
+              AND id IN (SELECT market_id FROM positions WHERE user_id = ?)
+            "#,
         )
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
-
-        results
-            .into_iter()
-            .map(|r| {
-                Ok(Market {
-                    id: r.id,
-                    question: r.question,
-                    description: r.description,
-                    creator_id: r.creator_id,
-                    oracle_id: r.oracle_id,
-                    end_date: DateTime::parse_from_rfc3339(&r.end_date)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                    closed_at: r.closed_at.as_ref().and_then(|s| DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.with_timezone(&Utc))),
-                    resolved: r.resolved,
-                    outcome: r.outcome,
-                    yes_pool: r.yes_pool,
-                    no_pool: r.no_pool,
-                    q_yes: r.q_yes,
-                    q_no: r.q_no,
-                    liquidity_param: r.liquidity_param,
-                    created_at: DateTime::parse_from_rfc3339(&r.created_at)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                })
-            })
-            .collect()
+        Ok(rows.into_iter().collect())
     }
 
     pub async fn close(&self, id: MarketId) -> Result<()> {
-        let closed_at = Utc::now().to_rfc3339();
-        sqlx::query!(
+        let result = sqlx::query(
             r#"
             UPDATE markets
             SET closed_at = ?
             WHERE id = ? AND closed_at IS NULL AND resolved = 0
             "#,
-            closed_at,
-            id
         )
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
         .execute(&self.pool)
         .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::ConstraintViolation(
+                "Market already closed, resolved or not found".to_string(),
+            ));
+        }
+
         Ok(())
     }
 
-    pub async fn update_pools(&self, id: MarketId, yes_pool: f64, no_pool: f64) -> Result<()> {
-        let result = sqlx::query!(
+    /// Stamp `closed_at` on unresolved markets whose end date has passed
+    ///
+    /// Returns the ids of the markets that were closed by this call.
+    pub async fn close_expired(&self) -> Result<Vec<MarketId>> {
+        let now = Utc::now().to_rfc3339();
+        let ids = sqlx::query_scalar::<_, i64>(
             r#"
             UPDATE markets
-            SET yes_pool = ?, no_pool = ?
-            WHERE id = ?
+            SET closed_at = end_date
+            WHERE closed_at IS NULL AND resolved = 0 AND end_date <= ?
+            RETURNING id
             "#,
-            yes_pool,
-            no_pool,
-            id
         )
-        .execute(&self.pool)
+        .bind(now)
+        .fetch_all(&self.pool)
         .await?;
-
-        if result.rows_affected() == 0 {
-            return Err(RepositoryError::NotFound);
-        }
-
-        Ok(())
+        Ok(ids)
     }
 
-    pub async fn update_outstanding_shares(&self, id: MarketId, q_yes: f64, q_no: f64) -> Result<()> {
-        let result = sqlx::query!(
+    pub async fn update_pools(&self, id: MarketId, yes_pool: f64, no_pool: f64) -> Result<()> {
+        let result = sqlx::query(
             r#"
             UPDATE markets
-            SET q_yes = ?, q_no = ?
+            SET yes_pool = ?, no_pool = ?
             WHERE id = ?
             "#,
-            q_yes,
-            q_no,
-            id
         )
+        .bind(yes_pool)
+        .bind(no_pool)
+        .bind(id)
         .execute(&self.pool)
         .await?;
 
@@ -239,26 +353,54 @@ impl MarketRepository {
         Ok(())
     }
 
-    pub async fn resolve(&self, id: MarketId, outcome: bool) -> Result<()> {
-        let outcome_int = if outcome { 1 } else { 0 };
-        let result = sqlx::query!(
+    /// Resolve a market and settle it in one transaction
+    ///
+    /// Each winning share pays $1 from the market maker, into the season balance
+    /// in season markets, and is recorded as a payout; whatever the market maker
+    /// holds afterwards goes back to the liquidity providers. Nothing is written
+    /// unless every step succeeds, so a failed resolution can be retried. Fails
+    /// with `ConstraintViolation` when the market is already resolved.
+    pub async fn resolve_and_settle(&self, id: MarketId, outcome: bool) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let season_id = sqlx::query_scalar::<_, Option<i64>>(
             r#"
             UPDATE markets
-            SET resolved = 1, outcome = ?
+            SET resolved = 1, outcome = ?, resolved_at = ?
             WHERE id = ? AND resolved = 0
+            RETURNING season_id
             "#,
-            outcome_int,
-            id
         )
-        .execute(&self.pool)
+        .bind(outcome)
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| RepositoryError::ConstraintViolation("Market already resolved or not found".to_string()))?;
+
+        let winning_side = MarketSide::from_outcome(outcome);
+        let winners = sqlx::query_as::<_, (i64, f64)>(
+            "SELECT user_id, shares FROM positions WHERE market_id = ? AND side = ? AND shares > 0 ORDER BY id",
+        )
+        .bind(id)
+        .bind(winning_side.to_string())
+        .fetch_all(&mut *tx)
         .await?;
 
-        if result.rows_affected() == 0 {
-            return Err(RepositoryError::ConstraintViolation(
-                "Market already resolved or not found".to_string(),
-            ));
+        for (user_id, shares) in winners {
+            let payout = Journal::transfer(
+                JournalKind::Payout,
+                LedgerAccount::MarketMaker(id),
+                LedgerAccount::trader(user_id, season_id),
+                shares,
+            );
+            post_journal(&mut tx, &payout).await?;
+            record_transaction(&mut tx, user_id, id, TransactionType::Payout, winning_side, shares, shares).await?;
         }
 
+        distribute_to_providers(&mut tx, id, season_id).await?;
+
+        tx.commit().await?;
         Ok(())
     }
 }
@@ -2,11 +2,40 @@ mod user_repo;
 mod market_repo;
 mod position_repo;
 mod price_snapshot_repo;
+mod listing;
+mod webhook_repo;
+mod idempotency_repo;
+mod password_reset_repo;
+mod login_attempt_repo;
+mod two_factor_repo;
+mod identity_repo;
+mod transaction_repo;
+mod invite_repo;
+mod ledger_repo;
+mod reconcile_repo;
+mod allowance_repo;
+mod liquidity_repo;
+mod season_repo;
+mod leaderboard_repo;
 
 pub use user_repo::UserRepository;
 pub use market_repo::MarketRepository;
 pub use position_repo::PositionRepository;
 pub use price_snapshot_repo::PriceSnapshotRepository;
+pub use webhook_repo::{PendingDelivery, WebhookRepository};
+pub use idempotency_repo::{IdempotencyClaim, IdempotencyRepository};
+pub use password_reset_repo::PasswordResetRepository;
+pub use login_attempt_repo::LoginAttemptRepository;
+pub use two_factor_repo::TwoFactorRepository;
+pub use identity_repo::IdentityRepository;
+pub use transaction_repo::TransactionRepository;
+pub use invite_repo::InviteRepository;
+pub use ledger_repo::LedgerRepository;
+pub use reconcile_repo::ReconciliationRepository;
+pub use allowance_repo::AllowanceRepository;
+pub use liquidity_repo::LiquidityRepository;
+pub use season_repo::SeasonRepository;
+pub use leaderboard_repo::LeaderboardRepository;
 
 use thiserror::Error;
 
@@ -0,0 +1,120 @@
+use crate::domain::{PasswordResetToken, UserId};
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(FromRow)]
+struct ResetRow {
+    user_id: i64,
+    created_by: Option<i64>,
+    expires_at: String,
+    used_at: Option<String>,
+    created_at: String,
+}
+
+impl From<ResetRow> for PasswordResetToken {
+    fn from(row: ResetRow) -> Self {
+        PasswordResetToken {
+            user_id: row.user_id,
+            created_by: row.created_by,
+            expires_at: row.expires_at.parse().unwrap_or_else(|_| Utc::now()),
+            used_at: row.used_at.and_then(|s| s.parse().ok()),
+            created_at: row.created_at.parse().unwrap_or_else(|_| Utc::now()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PasswordResetRepository {
+    pool: SqlitePool,
+}
+
+impl PasswordResetRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Store a new token for `user_id`, revoking any unused ones issued before it
+    pub async fn issue(
+        &self,
+        user_id: UserId,
+        created_by: Option<UserId>,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ? AND used_at IS NULL")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO password_reset_tokens (token_hash, user_id, created_by, expires_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(created_by)
+        .bind(expires_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Whether a token was issued to `user_id` without an admin, e.g. by email, after `since`
+    pub async fn self_issued_since(&self, user_id: UserId, since: DateTime<Utc>) -> Result<bool> {
+        let issued = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM password_reset_tokens
+                WHERE user_id = ? AND created_by IS NULL AND julianday(created_at) > julianday(?)
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(since.to_rfc3339())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(issued)
+    }
+
+    pub async fn find(&self, token_hash: &str) -> Result<PasswordResetToken> {
+        let row = sqlx::query_as::<_, ResetRow>(
+            r#"
+            SELECT user_id, created_by, expires_at, used_at, created_at
+            FROM password_reset_tokens
+            WHERE token_hash = ?
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound)?;
+
+        Ok(row.into())
+    }
+
+    /// Mark a token used, returning its user; fails if it was already used or has expired
+    pub async fn consume(&self, token_hash: &str) -> Result<UserId> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            UPDATE password_reset_tokens
+            SET used_at = ?
+            WHERE token_hash = ? AND used_at IS NULL AND expires_at > ?
+            RETURNING user_id
+            "#,
+        )
+        .bind(&now)
+        .bind(token_hash)
+        .bind(&now)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound)
+    }
+}
@@ -1,63 +1,97 @@
-use crate::domain::{Position, PositionId, UserId, MarketId, MarketSide};
+use crate::domain::{
+    Journal, JournalKind, LedgerAccount, ListQuery, LmsrPricing, Market, Position, Page, TradeAction, TradeQuote,
+    TradeSize, TransactionType, UserId, MarketId, MarketSide,
+};
+use crate::repository::ledger_repo::post_journal;
+use crate::repository::listing::{push_page, push_status_filter, sort_column, split_page};
+use crate::repository::market_repo::{MarketRow, MARKET_COLUMNS};
+use crate::repository::price_snapshot_repo::insert_snapshot;
+use crate::repository::transaction_repo::record_transaction;
 use crate::repository::{Result, RepositoryError};
-use sqlx::SqlitePool;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
 use chrono::{DateTime, Utc};
 
-#[derive(Clone)]
-pub struct PositionRepository {
-    pool: SqlitePool,
+#[derive(FromRow)]
+struct PositionRow {
+    id: i64,
+    user_id: i64,
+    market_id: i64,
+    side: String,
+    shares: f64,
+    avg_price: f64,
+    realized_pnl: f64,
+    created_at: String,
+    updated_at: String,
 }
 
-impl PositionRepository {
-    pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
-    }
-
-    pub async fn find_or_create(
-        &self,
-        user_id: UserId,
-        market_id: MarketId,
-        side: MarketSide,
-    ) -> Result<Position> {
-        // Try to find existing position
-        if let Ok(position) = self.find_by_user_market_side(user_id, market_id, side).await {
-            return Ok(position);
-        }
-
-        // Create new position
-        let side_str = side.to_string();
-        let result = sqlx::query!(
-            r#"
-            INSERT INTO positions (user_id, market_id, side, shares, avg_price)
-            VALUES (?, ?, ?, 0.0, 0.0)
-            RETURNING id, user_id, market_id, side, shares, avg_price, created_at, updated_at
-            "#,
-            user_id,
-            market_id,
-            side_str
-        )
-        .fetch_one(&self.pool)
-        .await?;
+impl TryFrom<PositionRow> for Position {
+    type Error = RepositoryError;
 
+    fn try_from(r: PositionRow) -> Result<Self> {
         Ok(Position {
-            id: result.id,
-            user_id: result.user_id,
-            market_id: result.market_id,
-            side: result.side.parse().map_err(|_| {
+            id: r.id,
+            user_id: r.user_id,
+            market_id: r.market_id,
+            side: r.side.parse().map_err(|_| {
                 RepositoryError::Database(sqlx::Error::Decode(
                     "Invalid market side".into(),
                 ))
             })?,
-            shares: result.shares,
-            avg_price: result.avg_price,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
+            shares: r.shares,
+            avg_price: r.avg_price,
+            realized_pnl: r.realized_pnl,
+            created_at: DateTime::parse_from_rfc3339(&r.created_at)
                 .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
                 .with_timezone(&Utc),
-            updated_at: DateTime::parse_from_rfc3339(&result.updated_at)
+            updated_at: DateTime::parse_from_rfc3339(&r.updated_at)
                 .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
                 .with_timezone(&Utc),
         })
     }
+}
+
+/// A position read alongside its market's columns
+#[derive(FromRow)]
+struct ListedPositionRow {
+    position_id: i64,
+    user_id: i64,
+    market_id: i64,
+    side: String,
+    shares: f64,
+    avg_price: f64,
+    realized_pnl: f64,
+    position_created_at: String,
+    position_updated_at: String,
+}
+
+impl TryFrom<ListedPositionRow> for Position {
+    type Error = RepositoryError;
+
+    fn try_from(r: ListedPositionRow) -> Result<Self> {
+        PositionRow {
+            id: r.position_id,
+            user_id: r.user_id,
+            market_id: r.market_id,
+            side: r.side,
+            shares: r.shares,
+            avg_price: r.avg_price,
+            realized_pnl: r.realized_pnl,
+            created_at: r.position_created_at,
+            updated_at: r.position_updated_at,
+        }
+        .try_into()
+    }
+}
+
+#[derive(Clone)]
+pub struct PositionRepository {
+    pool: SqlitePool,
+}
+
+impl PositionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
 
     pub async fn find_by_user_market_side(
         &self,
@@ -65,141 +99,572 @@ impl PositionRepository {
         market_id: MarketId,
         side: MarketSide,
     ) -> Result<Position> {
-        let side_str = side.to_string();
-        let result = sqlx::query!(
+        let row = sqlx::query_as::<_, PositionRow>(
             r#"
-            SELECT id, user_id, market_id, side, shares, avg_price, created_at, updated_at
+            SELECT id, user_id, market_id, side, shares, avg_price, realized_pnl, created_at, updated_at
             FROM positions
             WHERE user_id = ? AND market_id = ? AND side = ?
             "#,
-            user_id,
-            market_id,
-            side_str
         )
+        .bind(user_id)
+        .bind(market_id)
+        .bind(side.to_string())
         .fetch_optional(&self.pool)
         .await?
         .ok_or(RepositoryError::NotFound)?;
 
-        Ok(Position {
-            id: result.id.unwrap_or_default(),
-            user_id: result.user_id,
-            market_id: result.market_id,
-            side: result.side.parse().map_err(|_| {
-                RepositoryError::Database(sqlx::Error::Decode(
-                    "Invalid market side".into(),
-                ))
-            })?,
-            shares: result.shares,
-            avg_price: result.avg_price,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-            updated_at: DateTime::parse_from_rfc3339(&result.updated_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-        })
+        row.try_into()
     }
 
     pub async fn find_by_user(&self, user_id: UserId) -> Result<Vec<Position>> {
-        let results = sqlx::query!(
+        let rows = sqlx::query_as::<_, PositionRow>(
             r#"
-            SELECT id, user_id, market_id, side, shares, avg_price, created_at, updated_at
+            SELECT id, user_id, market_id, side, shares, avg_price, realized_pnl, created_at, updated_at
             FROM positions
             WHERE user_id = ? AND shares > 0
             ORDER BY updated_at DESC
             "#,
-            user_id
         )
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
 
-        results
-            .into_iter()
-            .map(|r| {
-                Ok(Position {
-                    id: r.id.unwrap_or_default(),
-                    user_id: r.user_id,
-                    market_id: r.market_id,
-                    side: r.side.parse().map_err(|_| {
-                        RepositoryError::Database(sqlx::Error::Decode(
-                            "Invalid market side".into(),
-                        ))
-                    })?,
-                    shares: r.shares,
-                    avg_price: r.avg_price,
-                    created_at: DateTime::parse_from_rfc3339(&r.created_at)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                    updated_at: DateTime::parse_from_rfc3339(&r.updated_at)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                })
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// List one page of a user's open positions, each with its market, filtered and sorted by the market
+    pub async fn find_by_user_page(&self, user_id: UserId, query: &ListQuery) -> Result<Page<(Position, Market)>> {
+        let (key, descending) = sort_column(query.sort, "m", "p.created_at");
+
+        // Position columns are aliased where they clash with market columns
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+            "SELECT {}, p.id AS position_id, p.user_id, p.market_id, p.side, p.shares, p.avg_price, p.realized_pnl, \
+             p.created_at AS position_created_at, p.updated_at AS position_updated_at, {} AS sort_key \
+             FROM positions p JOIN markets m ON m.id = p.market_id \
+             WHERE p.shares > 0 AND p.user_id = ",
+            MARKET_COLUMNS, key
+        ));
+        builder.push_bind(user_id);
+        push_status_filter(&mut builder, query.status, "m");
+        push_page(&mut builder, query, &key, descending, "p.id");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        let (rows, next_cursor) = split_page(rows, query, "position_id")?;
+
+        let items = rows
+            .iter()
+            .map(|row| {
+                let position: Position = ListedPositionRow::from_row(row)?.try_into()?;
+                let market: Market = MarketRow::from_row(row)?.try_into()?;
+                Ok((position, market))
             })
-            .collect()
+            .collect::<Result<Vec<(Position, Market)>>>()?;
+
+        Ok(Page { items, next_cursor })
     }
 
     pub async fn find_by_market(&self, market_id: MarketId) -> Result<Vec<Position>> {
-        let results = sqlx::query!(
+        let rows = sqlx::query_as::<_, PositionRow>(
             r#"
-            SELECT id, user_id, market_id, side, shares, avg_price, created_at, updated_at
+            SELECT id, user_id, market_id, side, shares, avg_price, realized_pnl, created_at, updated_at
             FROM positions
             WHERE market_id = ? AND shares > 0
             "#,
-            market_id
         )
+        .bind(market_id)
         .fetch_all(&self.pool)
         .await?;
 
-        results
-            .into_iter()
-            .map(|r| {
-                Ok(Position {
-                    id: r.id.unwrap_or_default(),
-                    user_id: r.user_id,
-                    market_id: r.market_id,
-                    side: r.side.parse().map_err(|_| {
-                        RepositoryError::Database(sqlx::Error::Decode(
-                            "Invalid market side".into(),
-                        ))
-                    })?,
-                    shares: r.shares,
-                    avg_price: r.avg_price,
-                    created_at: DateTime::parse_from_rfc3339(&r.created_at)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                    updated_at: DateTime::parse_from_rfc3339(&r.updated_at)
-                        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                        .with_timezone(&Utc),
-                })
-            })
-            .collect()
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Every position a user has held, including ones sold down to zero shares
+    pub async fn find_history_by_user(&self, user_id: UserId) -> Result<Vec<Position>> {
+        let rows = sqlx::query_as::<_, PositionRow>(
+            r#"
+            SELECT id, user_id, market_id, side, shares, avg_price, realized_pnl, created_at, updated_at
+            FROM positions
+            WHERE user_id = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Buy `shares` of `side` from the market maker
+    ///
+    /// The cost is quoted against the market as read inside the transaction,
+    /// and the payment, the change in outstanding shares, the position, the
+    /// price snapshot and the trade history are written together. Fails with
+    /// `ConstraintViolation` when the market is not open or the trader cannot
+    /// afford the shares, leaving everything untouched.
+    pub async fn buy(
+        &self,
+        user_id: UserId,
+        market_id: MarketId,
+        side: MarketSide,
+        shares: f64,
+    ) -> Result<TradeQuote> {
+        let mut tx = self.pool.begin().await?;
+
+        let market = find_open_market(&mut tx, market_id).await?;
+        let quote = TradeQuote::new(
+            market.q_yes,
+            market.q_no,
+            market.liquidity_param,
+            TradeAction::Buy,
+            side,
+            TradeSize::Shares(shares),
+        )
+        .map_err(|e| RepositoryError::ConstraintViolation(format!("Error calculating cost: {}", e)))?;
+
+        let journal = Journal::transfer(
+            JournalKind::Buy,
+            LedgerAccount::trader(user_id, market.season_id),
+            LedgerAccount::MarketMaker(market_id),
+            quote.amount,
+        );
+        post_journal(&mut tx, &journal).await?;
+
+        move_outstanding_shares(&mut tx, &market, side, shares).await?;
+
+        sqlx::query(
+            "INSERT INTO positions (user_id, market_id, side, shares, avg_price) VALUES (?, ?, ?, 0.0, 0.0) \
+             ON CONFLICT (user_id, market_id, side) DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(market_id)
+        .bind(side.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        // Average the cost of the new shares into the position
+        sqlx::query(
+            r#"
+            UPDATE positions
+            SET avg_price = (shares * avg_price + ?) / (shares + ?), shares = shares + ?, updated_at = ?
+            WHERE user_id = ? AND market_id = ? AND side = ?
+            "#,
+        )
+        .bind(quote.amount)
+        .bind(shares)
+        .bind(shares)
+        .bind(Utc::now().to_rfc3339())
+        .bind(user_id)
+        .bind(market_id)
+        .bind(side.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        record_transaction(&mut tx, user_id, market_id, TransactionType::Buy, side, shares, quote.amount).await?;
+
+        tx.commit().await?;
+        Ok(quote)
     }
 
-    pub async fn update(
+    /// Sell `shares` of `side` back to the market maker
+    ///
+    /// Proceeds are quoted inside the transaction against the market's pricing
+    /// state, and the shares are only taken if the position still holds them,
+    /// so concurrent sales of one position cannot both be paid. Fails with
+    /// `ConstraintViolation` when the market is not open or the position is
+    /// too small, leaving everything untouched.
+    pub async fn sell(
         &self,
-        id: PositionId,
+        user_id: UserId,
+        market_id: MarketId,
+        side: MarketSide,
         shares: f64,
-        avg_price: f64,
-    ) -> Result<()> {
-        let now = Utc::now().to_rfc3339();
-        let result = sqlx::query!(
+    ) -> Result<TradeQuote> {
+        let mut tx = self.pool.begin().await?;
+
+        let market = find_open_market(&mut tx, market_id).await?;
+        let (q_yes, q_no) = market.pricing_state();
+        let quote = TradeQuote::new(
+            q_yes,
+            q_no,
+            market.liquidity_param,
+            TradeAction::Sell,
+            side,
+            TradeSize::Shares(shares),
+        )
+        .map_err(|e| RepositoryError::ConstraintViolation(format!("Error calculating proceeds: {}", e)))?;
+
+        let result = sqlx::query(
             r#"
             UPDATE positions
-            SET shares = ?, avg_price = ?, updated_at = ?
-            WHERE id = ?
+            SET shares = shares - ?, realized_pnl = realized_pnl + ? - ? * avg_price, updated_at = ?
+            WHERE user_id = ? AND market_id = ? AND side = ? AND shares >= ?
             "#,
-            shares,
-            avg_price,
-            now,
-            id
         )
-        .execute(&self.pool)
+        .bind(shares)
+        .bind(quote.amount)
+        .bind(shares)
+        .bind(Utc::now().to_rfc3339())
+        .bind(user_id)
+        .bind(market_id)
+        .bind(side.to_string())
+        .bind(shares)
+        .execute(&mut *tx)
         .await?;
 
         if result.rows_affected() == 0 {
-            return Err(RepositoryError::NotFound);
+            return Err(RepositoryError::ConstraintViolation("Insufficient shares to sell".to_string()));
+        }
+
+        let journal = Journal::transfer(
+            JournalKind::Sell,
+            LedgerAccount::MarketMaker(market_id),
+            LedgerAccount::trader(user_id, market.season_id),
+            quote.amount,
+        );
+        post_journal(&mut tx, &journal).await?;
+
+        move_outstanding_shares(&mut tx, &market, side, -shares).await?;
+
+        record_transaction(&mut tx, user_id, market_id, TransactionType::Sell, side, shares, quote.amount).await?;
+
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    /// Buy `sets` complete sets from the market maker for $1 each
+    ///
+    /// The user gets one YES and one NO share per set, at a cost basis that
+    /// splits the $1 by the market's current price. `q_yes`/`q_no` do not move.
+    /// Fails with `ConstraintViolation` when the market is not open or the
+    /// user cannot afford the sets.
+    pub async fn mint_complete_sets(&self, user_id: UserId, market_id: MarketId, sets: f64) -> Result<()> {
+        validate_sets(sets)?;
+        let mut tx = self.pool.begin().await?;
+
+        let market = find_open_market(&mut tx, market_id).await?;
+
+        let journal = Journal::transfer(
+            JournalKind::Mint,
+            LedgerAccount::trader(user_id, market.season_id),
+            LedgerAccount::MarketMaker(market.id),
+            sets,
+        );
+        post_journal(&mut tx, &journal).await?;
+
+        let (yes_price, no_price) = market.complete_set_prices();
+        for (side, price) in [(MarketSide::Yes, yes_price), (MarketSide::No, no_price)] {
+            sqlx::query(
+                "INSERT INTO positions (user_id, market_id, side, shares, avg_price) VALUES (?, ?, ?, 0.0, 0.0) \
+                 ON CONFLICT (user_id, market_id, side) DO NOTHING",
+            )
+            .bind(user_id)
+            .bind(market.id)
+            .bind(side.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+            let mut position = find_position(&mut tx, user_id, market.id, side).await?.ok_or(RepositoryError::NotFound)?;
+            position.add_shares(sets, price);
+            sqlx::query("UPDATE positions SET shares = ?, avg_price = ?, updated_at = ? WHERE id = ?")
+                .bind(position.shares)
+                .bind(position.avg_price)
+                .bind(Utc::now().to_rfc3339())
+                .bind(position.id)
+                .execute(&mut *tx)
+                .await?;
+            record_transaction(&mut tx, user_id, market.id, TransactionType::Mint, side, sets, sets * price).await?;
         }
 
+        sqlx::query("UPDATE markets SET complete_sets = complete_sets + ? WHERE id = ?")
+            .bind(sets)
+            .bind(market.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Sell `sets` matched YES and NO pairs back to the market maker for $1 each
+    ///
+    /// The $1 is split between the two positions by the market's current price
+    /// to realize their profit or loss. `q_yes`/`q_no` do not move. Sets can
+    /// still be merged after trading closes, until the market resolves. Fails
+    /// with `ConstraintViolation` when the market is resolved or the user holds
+    /// fewer than `sets` shares on either side.
+    pub async fn redeem_complete_sets(&self, user_id: UserId, market_id: MarketId, sets: f64) -> Result<()> {
+        validate_sets(sets)?;
+        let mut tx = self.pool.begin().await?;
+
+        let market = find_unresolved_market(&mut tx, market_id).await?;
+
+        let (yes_price, no_price) = market.complete_set_prices();
+        for (side, price) in [(MarketSide::Yes, yes_price), (MarketSide::No, no_price)] {
+            let mut position = find_position(&mut tx, user_id, market.id, side)
+                .await?
+                .filter(|p| p.shares > 0.0)
+                .ok_or_else(|| RepositoryError::ConstraintViolation("Merging needs both YES and NO shares".to_string()))?;
+            position
+                .sell_shares(sets, sets * price)
+                .map_err(|_| RepositoryError::ConstraintViolation(format!("Not enough {} shares to merge", side)))?;
+            sqlx::query("UPDATE positions SET shares = ?, realized_pnl = ?, updated_at = ? WHERE id = ?")
+                .bind(position.shares)
+                .bind(position.realized_pnl)
+                .bind(Utc::now().to_rfc3339())
+                .bind(position.id)
+                .execute(&mut *tx)
+                .await?;
+            record_transaction(&mut tx, user_id, market.id, TransactionType::Redeem, side, sets, sets * price).await?;
+        }
+
+        let journal = Journal::transfer(
+            JournalKind::Redeem,
+            LedgerAccount::MarketMaker(market.id),
+            LedgerAccount::trader(user_id, market.season_id),
+            sets,
+        );
+        post_journal(&mut tx, &journal).await?;
+
+        sqlx::query("UPDATE markets SET complete_sets = complete_sets - ? WHERE id = ?")
+            .bind(sets)
+            .bind(market.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
         Ok(())
     }
 }
+
+/// Read a market inside the caller's transaction, failing unless it is open for trading
+async fn find_open_market(conn: &mut SqliteConnection, market_id: MarketId) -> Result<Market> {
+    let market = find_market(conn, market_id).await?;
+    if !market.can_trade() {
+        return Err(RepositoryError::ConstraintViolation("Market is not open for trading".to_string()));
+    }
+    Ok(market)
+}
+
+/// Read a market inside the caller's transaction, failing once it is resolved
+async fn find_unresolved_market(conn: &mut SqliteConnection, market_id: MarketId) -> Result<Market> {
+    let market = find_market(conn, market_id).await?;
+    if market.resolved {
+        return Err(RepositoryError::ConstraintViolation("Market is already resolved".to_string()));
+    }
+    Ok(market)
+}
+
+async fn find_market(conn: &mut SqliteConnection, market_id: MarketId) -> Result<Market> {
+    sqlx::query_as::<_, MarketRow>(&format!("SELECT {} FROM markets m WHERE m.id = ?", MARKET_COLUMNS))
+        .bind(market_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(RepositoryError::NotFound)?
+        .try_into()
+}
+
+/// Add `shares` (negative for a sale) to the market's outstanding shares on `side` and snapshot the new price
+///
+/// The update is relative, so trades committed since `market` was read are kept.
+async fn move_outstanding_shares(
+    conn: &mut SqliteConnection,
+    market: &Market,
+    side: MarketSide,
+    shares: f64,
+) -> Result<()> {
+    let (yes, no) = match side {
+        MarketSide::Yes => (shares, 0.0),
+        MarketSide::No => (0.0, shares),
+    };
+
+    let (q_yes, q_no) = sqlx::query_as::<_, (f64, f64)>(
+        r#"
+        UPDATE markets
+        SET q_yes = q_yes + ?, q_no = q_no + ?, volume = volume + ?
+        WHERE id = ? AND resolved = 0
+        RETURNING q_yes, q_no
+        "#,
+    )
+    .bind(yes)
+    .bind(no)
+    .bind(shares.abs())
+    .bind(market.id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| RepositoryError::ConstraintViolation("Market is not open for trading".to_string()))?;
+
+    let yes_probability = LmsrPricing::implied_probability(q_yes, q_no, market.liquidity_param);
+    insert_snapshot(conn, market.id, yes_probability, 1.0 - yes_probability, q_yes, q_no).await?;
+    Ok(())
+}
+
+fn validate_sets(sets: f64) -> Result<()> {
+    if !sets.is_finite() || sets <= 0.0 {
+        return Err(RepositoryError::ConstraintViolation("Number of sets must be positive".to_string()));
+    }
+    Ok(())
+}
+
+async fn find_position(
+    conn: &mut SqliteConnection,
+    user_id: UserId,
+    market_id: MarketId,
+    side: MarketSide,
+) -> Result<Option<Position>> {
+    sqlx::query_as::<_, PositionRow>(
+        r#"
+        SELECT id, user_id, market_id, side, shares, avg_price, realized_pnl, created_at, updated_at
+        FROM positions
+        WHERE user_id = ? AND market_id = ? AND side = ?
+        "#,
+    )
+    .bind(user_id)
+    .bind(market_id)
+    .bind(side.to_string())
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(TryInto::try_into)
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{LedgerRepository, MarketRepository, TransactionRepository, UserRepository};
+    use crate::Database;
+    use chrono::Duration;
+
+    const EPSILON: f64 = 1e-9;
+
+    /// A trader holding $100 and an open market created by someone else
+    async fn setup() -> (Database, UserId, Market) {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let creator = users.create_with_balance("creator", "hash", 1000.0).await.unwrap();
+        let trader = users.create_with_balance("trader", "hash", 100.0).await.unwrap();
+        let market = MarketRepository::new(db.pool().clone())
+            .create("Will it rain?", None, creator.id, None, Utc::now() + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+        (db, trader.id, market)
+    }
+
+    async fn balance(db: &Database, user_id: UserId) -> f64 {
+        UserRepository::new(db.pool().clone()).find_by_id(user_id).await.unwrap().balance
+    }
+
+    #[tokio::test]
+    async fn test_mint_then_merge_nets_to_zero() {
+        let (db, trader, market) = setup().await;
+        let positions = PositionRepository::new(db.pool().clone());
+
+        positions.mint_complete_sets(trader, market.id, 40.0).await.unwrap();
+        assert!((balance(&db, trader).await - 60.0).abs() < EPSILON);
+        for side in [MarketSide::Yes, MarketSide::No] {
+            assert_eq!(positions.find_by_user_market_side(trader, market.id, side).await.unwrap().shares, 40.0);
+        }
+
+        positions.redeem_complete_sets(trader, market.id, 40.0).await.unwrap();
+        assert!((balance(&db, trader).await - 100.0).abs() < EPSILON);
+        let mut realized = 0.0;
+        for side in [MarketSide::Yes, MarketSide::No] {
+            let position = positions.find_by_user_market_side(trader, market.id, side).await.unwrap();
+            assert!(position.shares.abs() < EPSILON);
+            realized += position.realized_pnl;
+        }
+        assert!(realized.abs() < EPSILON);
+
+        let market = MarketRepository::new(db.pool().clone()).find_by_id(market.id).await.unwrap();
+        assert!(market.complete_sets.abs() < EPSILON);
+        assert!(LedgerRepository::new(db.pool().clone()).find_mismatches().await.unwrap().is_empty());
+
+        // One transaction per side, at the price each set was split at
+        let history = TransactionRepository::new(db.pool().clone()).find_by_user(trader).await.unwrap();
+        let recorded: Vec<_> = history.iter().map(|t| (t.transaction_type, t.side, t.shares, t.amount)).collect();
+        assert_eq!(
+            recorded,
+            vec![
+                (TransactionType::Mint, Some(MarketSide::Yes), 40.0, 20.0),
+                (TransactionType::Mint, Some(MarketSide::No), 40.0, 20.0),
+                (TransactionType::Redeem, Some(MarketSide::Yes), 40.0, 20.0),
+                (TransactionType::Redeem, Some(MarketSide::No), 40.0, 20.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mint_and_merge_fail_without_funds_or_shares() {
+        let (db, trader, market) = setup().await;
+        let positions = PositionRepository::new(db.pool().clone());
+
+        let result = positions.mint_complete_sets(trader, market.id, 150.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+        assert!(positions.find_by_user_market_side(trader, market.id, MarketSide::Yes).await.is_err());
+
+        positions.mint_complete_sets(trader, market.id, 10.0).await.unwrap();
+        let result = positions.redeem_complete_sets(trader, market.id, 11.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+        assert!((balance(&db, trader).await - 90.0).abs() < EPSILON);
+        assert_eq!(positions.find_by_user_market_side(trader, market.id, MarketSide::Yes).await.unwrap().shares, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_mint_and_merge_check_the_market_they_write() {
+        let (db, trader, market) = setup().await;
+        let positions = PositionRepository::new(db.pool().clone());
+        let markets = MarketRepository::new(db.pool().clone());
+        positions.mint_complete_sets(trader, market.id, 10.0).await.unwrap();
+
+        // Trading has closed but the market is unresolved: pairs can be merged but not minted
+        markets.close(market.id).await.unwrap();
+        let result = positions.mint_complete_sets(trader, market.id, 10.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+        positions.redeem_complete_sets(trader, market.id, 5.0).await.unwrap();
+
+        // Resolution pays the remaining YES shares, which can no longer be merged
+        markets.resolve_and_settle(market.id, true).await.unwrap();
+        let result = positions.redeem_complete_sets(trader, market.id, 5.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+        assert!((balance(&db, trader).await - 100.0).abs() < EPSILON);
+        assert!(markets.find_by_id(market.id).await.unwrap().complete_sets > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_buy_then_sell_round_trip() {
+        let (db, trader, market) = setup().await;
+        let positions = PositionRepository::new(db.pool().clone());
+
+        let bought = positions.buy(trader, market.id, MarketSide::Yes, 50.0).await.unwrap();
+        assert!((balance(&db, trader).await - (100.0 - bought.amount)).abs() < EPSILON);
+        let position = positions.find_by_user_market_side(trader, market.id, MarketSide::Yes).await.unwrap();
+        assert_eq!(position.shares, 50.0);
+        assert!((position.avg_price - bought.amount / 50.0).abs() < EPSILON);
+
+        // Selling more than is held takes nothing
+        let result = positions.sell(trader, market.id, MarketSide::Yes, 60.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+
+        let sold = positions.sell(trader, market.id, MarketSide::Yes, 50.0).await.unwrap();
+        assert!((sold.amount - bought.amount).abs() < EPSILON);
+        assert!((balance(&db, trader).await - 100.0).abs() < EPSILON);
+
+        let market = MarketRepository::new(db.pool().clone()).find_by_id(market.id).await.unwrap();
+        assert!(market.q_yes.abs() < EPSILON && market.q_no.abs() < EPSILON);
+        assert!((market.volume - 100.0).abs() < EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_buy_beyond_balance_changes_nothing() {
+        let (db, trader, market) = setup().await;
+        let positions = PositionRepository::new(db.pool().clone());
+
+        let result = positions.buy(trader, market.id, MarketSide::No, 500.0).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+
+        assert_eq!(balance(&db, trader).await, 100.0);
+        assert!(positions.find_by_user_market_side(trader, market.id, MarketSide::No).await.is_err());
+        let market = MarketRepository::new(db.pool().clone()).find_by_id(market.id).await.unwrap();
+        assert_eq!((market.q_yes, market.q_no, market.volume), (0.0, 0.0, 0.0));
+    }
+}
@@ -1,6 +1,6 @@
-use crate::domain::PriceSnapshot;
-use chrono::Utc;
-use sqlx::{FromRow, SqlitePool};
+use crate::domain::{Candle, CandleInterval, PriceSnapshot};
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
 
 #[derive(FromRow)]
 struct PriceSnapshotRow {
@@ -27,6 +27,25 @@ impl From<PriceSnapshotRow> for PriceSnapshot {
     }
 }
 
+#[derive(FromRow)]
+struct CandleRow {
+    bucket: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    trades: i64,
+}
+
+/// Format a bound for comparison against a `created_at` column with `julianday()`
+///
+/// Stored timestamps differ in their offset (`Z` or `+00:00`) and number of
+/// fractional digits, so they only compare correctly as times, not as text.
+fn time_bound(t: DateTime<Utc>) -> String {
+    t.to_rfc3339()
+}
+
 pub struct PriceSnapshotRepository {
     pool: SqlitePool,
 }
@@ -45,25 +64,8 @@ impl PriceSnapshotRepository {
         q_yes: f64,
         q_no: f64,
     ) -> Result<PriceSnapshot, sqlx::Error> {
-        let created_at = Utc::now().to_rfc3339();
-
-        let row = sqlx::query_as::<_, PriceSnapshotRow>(
-            r#"
-            INSERT INTO price_snapshots (market_id, yes_probability, no_probability, q_yes, q_no, created_at)
-            VALUES (?, ?, ?, ?, ?, ?)
-            RETURNING id, market_id, yes_probability, no_probability, q_yes, q_no, created_at
-            "#,
-        )
-        .bind(market_id)
-        .bind(yes_probability)
-        .bind(no_probability)
-        .bind(q_yes)
-        .bind(q_no)
-        .bind(created_at)
-        .fetch_one(&self.pool)
-        .await?;
-
-        Ok(row.into())
+        let mut conn = self.pool.acquire().await?;
+        insert_snapshot(&mut conn, market_id, yes_probability, no_probability, q_yes, q_no).await
     }
 
     /// Get price history for a market, ordered by time ascending
@@ -126,4 +128,180 @@ impl PriceSnapshotRepository {
 
         Ok(row.map(Into::into))
     }
+
+    /// Get price history for a market within `[from, to)`, capped to the most recent `limit` snapshots
+    pub async fn get_history_range(
+        &self,
+        market_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PriceSnapshot>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, PriceSnapshotRow>(
+            r#"
+            SELECT id, market_id, yes_probability, no_probability, q_yes, q_no, created_at
+            FROM price_snapshots
+            WHERE market_id = ?
+                AND julianday(created_at) >= julianday(?) AND julianday(created_at) < julianday(?)
+            ORDER BY created_at DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(market_id)
+        .bind(time_bound(from))
+        .bind(time_bound(to))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut snapshots: Vec<PriceSnapshot> = rows.into_iter().map(Into::into).collect();
+        snapshots.reverse();
+        Ok(snapshots)
+    }
+
+    /// Resample price history into OHLC candles of YES probability
+    ///
+    /// Prices come from the snapshots and volume from the buys and sells in
+    /// each bucket, so snapshots that don't record a trade (liquidity deposits)
+    /// move neither. Buckets without any snapshot are omitted.
+    pub async fn get_candles(
+        &self,
+        market_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: CandleInterval,
+    ) -> Result<Vec<Candle>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, CandleRow>(
+            r#"
+            WITH bucketed AS (
+                SELECT
+                    (CAST(strftime('%s', created_at) AS INTEGER) / ?4) * ?4 AS bucket,
+                    id,
+                    created_at,
+                    yes_probability
+                FROM price_snapshots
+                WHERE market_id = ?1
+                    AND julianday(created_at) >= julianday(?2) AND julianday(created_at) < julianday(?3)
+            ),
+            framed AS (
+                SELECT
+                    bucket,
+                    yes_probability,
+                    FIRST_VALUE(yes_probability) OVER bucket_window AS open,
+                    LAST_VALUE(yes_probability) OVER bucket_window AS close
+                FROM bucketed
+                WINDOW bucket_window AS (
+                    PARTITION BY bucket
+                    ORDER BY created_at, id
+                    ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING
+                )
+            ),
+            traded AS (
+                SELECT
+                    (CAST(strftime('%s', created_at) AS INTEGER) / ?4) * ?4 AS bucket,
+                    SUM(shares) AS volume,
+                    COUNT(*) AS trades
+                FROM transactions
+                WHERE market_id = ?1 AND transaction_type IN ('buy', 'sell')
+                    AND julianday(created_at) >= julianday(?2) AND julianday(created_at) < julianday(?3)
+                GROUP BY bucket
+            )
+            SELECT
+                f.bucket,
+                MIN(f.open) AS open,
+                MAX(f.yes_probability) AS high,
+                MIN(f.yes_probability) AS low,
+                MIN(f.close) AS close,
+                CAST(COALESCE(t.volume, 0) AS REAL) AS volume,
+                COALESCE(t.trades, 0) AS trades
+            FROM framed f
+            LEFT JOIN traded t ON t.bucket = f.bucket
+            GROUP BY f.bucket
+            ORDER BY f.bucket ASC
+            "#,
+        )
+        .bind(market_id)
+        .bind(time_bound(from))
+        .bind(time_bound(to))
+        .bind(interval.seconds())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Candle {
+                market_id,
+                bucket_start: Utc.timestamp_opt(row.bucket, 0).single().unwrap_or_else(Utc::now),
+                open: row.open,
+                high: row.high,
+                low: row.low,
+                close: row.close,
+                volume: row.volume,
+                trades: row.trades,
+            })
+            .collect())
+    }
+}
+
+/// Record a snapshot inside the caller's transaction, e.g. the trade that moved the price
+pub(crate) async fn insert_snapshot(
+    conn: &mut SqliteConnection,
+    market_id: i64,
+    yes_probability: f64,
+    no_probability: f64,
+    q_yes: f64,
+    q_no: f64,
+) -> Result<PriceSnapshot, sqlx::Error> {
+    let row = sqlx::query_as::<_, PriceSnapshotRow>(
+        r#"
+        INSERT INTO price_snapshots (market_id, yes_probability, no_probability, q_yes, q_no, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        RETURNING id, market_id, yes_probability, no_probability, q_yes, q_no, created_at
+        "#,
+    )
+    .bind(market_id)
+    .bind(yes_probability)
+    .bind(no_probability)
+    .bind(q_yes)
+    .bind(q_no)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(row.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{MarketRepository, UserRepository};
+    use crate::Database;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_history_range_compares_times_not_text() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let creator = users.create_with_balance("creator", "hash", 1000.0).await.unwrap();
+        let market = MarketRepository::new(db.pool().clone())
+            .create("Will it rain?", None, creator.id, None, Utc::now() + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+        // `Z` sorts after the `.` of a fractional bound, so as text this row would fall outside the range
+        sqlx::query(
+            "INSERT INTO price_snapshots (market_id, yes_probability, no_probability, q_yes, q_no, created_at) \
+             VALUES (?, 0.5, 0.5, 0.0, 0.0, '2025-01-01T00:00:00Z')",
+        )
+        .bind(market.id)
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let history = PriceSnapshotRepository::new(db.pool().clone())
+            .get_history_range(market.id, at, at + Duration::milliseconds(500), 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+    }
 }
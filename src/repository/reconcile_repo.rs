@@ -0,0 +1,196 @@
+use crate::domain::{differs, expected_market_maker_balance, Discrepancy, MarketSide, ReconciliationReport};
+use crate::repository::{LedgerRepository, MarketRepository, Result};
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashMap;
+
+#[derive(FromRow)]
+struct MarketTotalsRow {
+    id: i64,
+    yes_shares: f64,
+    no_shares: f64,
+    ledger: f64,
+}
+
+#[derive(FromRow)]
+struct NegativePositionRow {
+    id: i64,
+    user_id: i64,
+    market_id: i64,
+    shares: f64,
+}
+
+#[derive(FromRow)]
+struct UserTotalsRow {
+    id: i64,
+    balance: f64,
+    /// Entries on the user's account that are not trades or payouts, such as grants
+    other: f64,
+    /// Trades and payouts from the trade history, signed as they move the balance
+    traded: f64,
+}
+
+/// Recomputes cached figures from the records they are derived from
+#[derive(Clone)]
+pub struct ReconciliationRepository {
+    pool: SqlitePool,
+}
+
+impl ReconciliationRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Run every check and collect what disagrees
+    pub async fn check(&self) -> Result<ReconciliationReport> {
+        let mut report = ReconciliationReport::default();
+
+        let totals: HashMap<i64, MarketTotalsRow> = sqlx::query_as::<_, MarketTotalsRow>(
+            r#"
+            SELECT m.id,
+                COALESCE((SELECT SUM(shares) FROM positions p WHERE p.market_id = m.id AND p.side = 'yes'), 0.0) AS yes_shares,
+                COALESCE((SELECT SUM(shares) FROM positions p WHERE p.market_id = m.id AND p.side = 'no'), 0.0) AS no_shares,
+                COALESCE((SELECT SUM(amount) FROM ledger_entries e WHERE e.account = 'market:' || m.id), 0.0) AS ledger
+            FROM markets m
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.id, row))
+        .collect();
+
+        let markets = MarketRepository::new(self.pool.clone()).list_all().await?;
+
+        report.markets_checked = markets.len();
+        for market in markets {
+            let Some(totals) = totals.get(&market.id) else { continue };
+            for (side, positions) in [(MarketSide::Yes, totals.yes_shares), (MarketSide::No, totals.no_shares)] {
+                let stored = market.outstanding_shares(side);
+                if differs(stored, positions) {
+                    report.discrepancies.push(Discrepancy::OutstandingShares {
+                        market_id: market.id,
+                        side,
+                        stored,
+                        positions,
+                    });
+                }
+            }
+
+            let expected = expected_market_maker_balance(&market);
+            if differs(totals.ledger, expected) {
+                report.discrepancies.push(Discrepancy::MarketMakerPnl {
+                    market_id: market.id,
+                    ledger: totals.ledger,
+                    expected,
+                });
+            }
+        }
+
+        report.positions_checked = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM positions")
+            .fetch_one(&self.pool)
+            .await? as usize;
+
+        let negative = sqlx::query_as::<_, NegativePositionRow>(
+            "SELECT id, user_id, market_id, shares FROM positions WHERE shares < 0 ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        report.discrepancies.extend(negative.into_iter().map(|row| Discrepancy::NegativeShares {
+            position_id: row.id,
+            user_id: row.user_id,
+            market_id: row.market_id,
+            shares: row.shares,
+        }));
+
+        let ledger_repo = LedgerRepository::new(self.pool.clone());
+        report.discrepancies.extend(ledger_repo.find_mismatches().await?.into_iter().map(|mismatch| {
+            Discrepancy::CachedBalance { user_id: mismatch.user_id, cached: mismatch.cached, ledger: mismatch.ledger }
+        }));
+
+        // Trades older than a user's first journal predate the ledger and are
+        // already part of the balance it was opened with. Trades in season
+        // markets move the season balance instead.
+        let users = sqlx::query_as::<_, UserTotalsRow>(
+            r#"
+            SELECT u.id, u.balance,
+                COALESCE((
+                    SELECT SUM(e.amount)
+                    FROM ledger_entries e
+                    JOIN ledger_journals j ON j.id = e.journal_id
+                    WHERE e.account = 'user:' || u.id AND j.kind NOT IN ('buy', 'sell', 'payout')
+                ), 0.0) AS other,
+                COALESCE((
+                    SELECT SUM(CASE t.transaction_type WHEN 'buy' THEN -t.amount ELSE t.amount END)
+                    FROM transactions t
+                    WHERE t.user_id = u.id AND t.transaction_type IN ('buy', 'sell', 'payout')
+                        AND t.market_id NOT IN (SELECT id FROM markets WHERE season_id IS NOT NULL)
+                        AND julianday(t.created_at) >= (
+                            SELECT MIN(julianday(j.created_at))
+                            FROM ledger_entries e
+                            JOIN ledger_journals j ON j.id = e.journal_id
+                            WHERE e.account = 'user:' || u.id
+                        )
+                ), 0.0) AS traded
+            FROM users u
+            ORDER BY u.id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        report.users_checked = users.len();
+        for user in users {
+            let expected = user.other + user.traded;
+            if differs(user.balance, expected) {
+                report.discrepancies.push(Discrepancy::TradeHistory { user_id: user.id, balance: user.balance, expected });
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Fix the repairable discrepancies of a report, returning how many were fixed
+    ///
+    /// Outstanding shares are reset to the shares held in positions (plus the
+    /// market maker's virtual shares, less complete sets) and cached
+    /// balances to the ledger, both recomputed at the time of the repair.
+    pub async fn repair(&self, report: &ReconciliationReport) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut repaired = 0;
+
+        for discrepancy in report.repairable() {
+            match discrepancy {
+                Discrepancy::OutstandingShares { market_id, side, .. } => {
+                    let (column, virtual_column) = match side {
+                        MarketSide::Yes => ("q_yes", "virtual_yes"),
+                        MarketSide::No => ("q_no", "virtual_no"),
+                    };
+                    sqlx::query(&format!(
+                        "UPDATE markets SET {} = {} - complete_sets + COALESCE((SELECT SUM(shares) FROM positions \
+                         WHERE market_id = markets.id AND side = ?), 0.0) WHERE id = ?",
+                        column, virtual_column
+                    ))
+                    .bind(side.to_string())
+                    .bind(market_id)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                Discrepancy::CachedBalance { user_id, .. } => {
+                    sqlx::query(
+                        "UPDATE users SET balance = COALESCE((SELECT SUM(amount) FROM ledger_entries \
+                         WHERE account = 'user:' || users.id), 0.0) WHERE id = ?",
+                    )
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                _ => continue,
+            }
+            repaired += 1;
+        }
+
+        tx.commit().await?;
+        Ok(repaired)
+    }
+}
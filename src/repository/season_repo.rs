@@ -0,0 +1,457 @@
+use crate::domain::{
+    rank_standings, Journal, JournalKind, LedgerAccount, MarketId, MarketSide, Season, SeasonId, SeasonStanding,
+    SeasonStatus, UserId,
+};
+use crate::repository::ledger_repo::post_journal;
+use crate::repository::{MarketRepository, Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashMap;
+
+const SEASON_COLUMNS: &str =
+    "id, name, description, starting_balance, starts_at, ends_at, archived_at, created_by, created_at";
+
+#[derive(FromRow)]
+struct SeasonRow {
+    id: i64,
+    name: String,
+    description: Option<String>,
+    starting_balance: f64,
+    starts_at: String,
+    ends_at: String,
+    archived_at: Option<String>,
+    created_by: i64,
+    created_at: String,
+}
+
+fn parse_date(s: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(s)
+        .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+        .with_timezone(&Utc))
+}
+
+impl TryFrom<SeasonRow> for Season {
+    type Error = RepositoryError;
+
+    fn try_from(r: SeasonRow) -> Result<Self> {
+        Ok(Season {
+            id: r.id,
+            name: r.name,
+            description: r.description,
+            starting_balance: r.starting_balance,
+            starts_at: parse_date(&r.starts_at)?,
+            ends_at: parse_date(&r.ends_at)?,
+            archived_at: r.archived_at.as_deref().map(parse_date).transpose()?,
+            created_by: r.created_by,
+            created_at: parse_date(&r.created_at)?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct ParticipantRow {
+    user_id: i64,
+    username: String,
+    balance: f64,
+}
+
+#[derive(FromRow)]
+struct SeasonPositionRow {
+    user_id: i64,
+    market_id: i64,
+    side: String,
+    shares: f64,
+}
+
+#[derive(FromRow)]
+struct StandingRow {
+    user_id: i64,
+    username: String,
+    rank: i64,
+    balance: f64,
+    positions_value: f64,
+    total_value: f64,
+}
+
+/// A liquidity deposit of a market being attached, and whether its provider joined the season
+#[derive(FromRow)]
+struct DepositRow {
+    id: i64,
+    user_id: i64,
+    amount: f64,
+    joined: bool,
+}
+
+#[derive(Clone)]
+pub struct SeasonRepository {
+    pool: SqlitePool,
+}
+
+impl SeasonRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Fails with `ConstraintViolation` if another season has the same name
+    pub async fn create(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        starting_balance: f64,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        created_by: UserId,
+    ) -> Result<Season> {
+        let row = sqlx::query_as::<_, SeasonRow>(&format!(
+            "INSERT INTO seasons (name, description, starting_balance, starts_at, ends_at, created_by, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+            SEASON_COLUMNS
+        ))
+        .bind(name)
+        .bind(description)
+        .bind(starting_balance)
+        .bind(starts_at.to_rfc3339())
+        .bind(ends_at.to_rfc3339())
+        .bind(created_by)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::Database(db_err) = &e {
+                if db_err.is_unique_violation() {
+                    return RepositoryError::ConstraintViolation("A season with this name already exists".to_string());
+                }
+            }
+            RepositoryError::Database(e)
+        })?;
+
+        row.try_into()
+    }
+
+    pub async fn find_by_id(&self, id: SeasonId) -> Result<Season> {
+        sqlx::query_as::<_, SeasonRow>(&format!("SELECT {} FROM seasons WHERE id = ?", SEASON_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound)?
+            .try_into()
+    }
+
+    /// Every season, latest first
+    pub async fn list_all(&self) -> Result<Vec<Season>> {
+        let rows = sqlx::query_as::<_, SeasonRow>(&format!(
+            "SELECT {} FROM seasons ORDER BY starts_at DESC, id DESC",
+            SEASON_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Enter a user into a season, granting them its starting balance
+    ///
+    /// Fails with `ConstraintViolation` if the season has ended or the user has already joined.
+    pub async fn join(&self, season: &Season, user_id: UserId) -> Result<()> {
+        if !season.can_join() {
+            return Err(RepositoryError::ConstraintViolation("This season has ended".to_string()));
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "INSERT INTO season_participants (season_id, user_id, joined_at) VALUES (?, ?, ?) \
+             ON CONFLICT (season_id, user_id) DO NOTHING",
+        )
+        .bind(season.id)
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::ConstraintViolation("You have already joined this season".to_string()));
+        }
+
+        let grant = Journal::transfer(
+            JournalKind::Grant,
+            LedgerAccount::Faucet,
+            LedgerAccount::Season(season.id, user_id),
+            season.starting_balance,
+        );
+        post_journal(&mut tx, &grant).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn is_participant(&self, season_id: SeasonId, user_id: UserId) -> Result<bool> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM season_participants WHERE season_id = ? AND user_id = ?",
+        )
+        .bind(season_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count > 0)
+    }
+
+    /// Make a market part of a season, so that it is traded with season balances
+    ///
+    /// Only markets nobody has traded yet and that belong to no other season can
+    /// be attached, since existing positions were paid for with main balances.
+    /// The subsidy moves with the market: each liquidity deposit is refunded to
+    /// its provider's main balance and charged to their season balance instead,
+    /// so providers must have joined the season and be able to afford it.
+    pub async fn attach_market(&self, season: &Season, market_id: MarketId) -> Result<()> {
+        if season.status() == SeasonStatus::Ended {
+            return Err(RepositoryError::ConstraintViolation("This season has ended".to_string()));
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE markets SET season_id = ?
+            WHERE id = ? AND season_id IS NULL AND resolved = 0 AND volume = 0 AND complete_sets = 0
+                AND NOT EXISTS (SELECT 1 FROM positions WHERE market_id = markets.id AND shares > 0)
+            "#,
+        )
+        .bind(season.id)
+        .bind(market_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::ConstraintViolation(
+                "Only open markets that have not been traded and belong to no season can be attached".to_string(),
+            ));
+        }
+
+        let deposits = sqlx::query_as::<_, DepositRow>(
+            r#"
+            SELECT d.id, d.user_id, d.amount,
+                EXISTS (SELECT 1 FROM season_participants p WHERE p.season_id = ? AND p.user_id = d.user_id) AS joined
+            FROM liquidity_deposits d
+            WHERE d.market_id = ?
+            ORDER BY d.id
+            "#,
+        )
+        .bind(season.id)
+        .bind(market_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for deposit in deposits {
+            if !deposit.joined {
+                return Err(RepositoryError::ConstraintViolation(format!(
+                    "Every liquidity provider of the market must join season {} before it is attached",
+                    season.name
+                )));
+            }
+
+            let market_maker = LedgerAccount::MarketMaker(market_id);
+            let refund = Journal::transfer(
+                JournalKind::Refund,
+                market_maker,
+                LedgerAccount::User(deposit.user_id),
+                deposit.amount,
+            );
+            post_journal(&mut tx, &refund).await?;
+            let subsidy = Journal::transfer(
+                JournalKind::Subsidy,
+                LedgerAccount::Season(season.id, deposit.user_id),
+                market_maker,
+                deposit.amount,
+            );
+            let journal_id = post_journal(&mut tx, &subsidy).await?;
+
+            sqlx::query("UPDATE liquidity_deposits SET journal_id = ? WHERE id = ?")
+                .bind(journal_id)
+                .bind(deposit.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The season's leaderboard: archived once it has ended, otherwise computed now
+    pub async fn standings(&self, season: &Season) -> Result<Vec<SeasonStanding>> {
+        if season.archived_at.is_some() {
+            self.archived_standings(season.id).await
+        } else {
+            self.live_standings(season.id).await
+        }
+    }
+
+    /// Rank participants by season balance plus positions marked to market
+    async fn live_standings(&self, season_id: SeasonId) -> Result<Vec<SeasonStanding>> {
+        let participants = sqlx::query_as::<_, ParticipantRow>(
+            r#"
+            SELECT p.user_id, u.username,
+                COALESCE((
+                    SELECT SUM(e.amount) FROM ledger_entries e
+                    WHERE e.account = 'season:' || p.season_id || ':user:' || p.user_id
+                ), 0.0) AS balance
+            FROM season_participants p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.season_id = ?
+            "#,
+        )
+        .bind(season_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let positions = sqlx::query_as::<_, SeasonPositionRow>(
+            r#"
+            SELECT pos.user_id, pos.market_id, pos.side, pos.shares
+            FROM positions pos
+            JOIN markets m ON m.id = pos.market_id
+            WHERE m.season_id = ? AND pos.shares > 0
+            "#,
+        )
+        .bind(season_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let markets: HashMap<MarketId, _> = MarketRepository::new(self.pool.clone())
+            .find_by_season(season_id)
+            .await?
+            .into_iter()
+            .map(|market| (market.id, market))
+            .collect();
+
+        let mut positions_value: HashMap<UserId, f64> = HashMap::new();
+        for position in positions {
+            let (Some(market), Ok(side)) = (markets.get(&position.market_id), position.side.parse::<MarketSide>())
+            else {
+                continue;
+            };
+            *positions_value.entry(position.user_id).or_default() += position.shares * market.unsettled_value(side);
+        }
+
+        Ok(rank_standings(
+            participants
+                .into_iter()
+                .map(|p| {
+                    let value = positions_value.get(&p.user_id).copied().unwrap_or_default();
+                    SeasonStanding::new(p.user_id, p.username, p.balance, value)
+                })
+                .collect(),
+        ))
+    }
+
+    async fn archived_standings(&self, season_id: SeasonId) -> Result<Vec<SeasonStanding>> {
+        let rows = sqlx::query_as::<_, StandingRow>(
+            r#"
+            SELECT s.user_id, u.username, s.rank, s.balance, s.positions_value, s.total_value
+            FROM season_standings s
+            JOIN users u ON u.id = s.user_id
+            WHERE s.season_id = ?
+            ORDER BY s.rank, s.user_id
+            "#,
+        )
+        .bind(season_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| SeasonStanding {
+                user_id: r.user_id,
+                username: r.username,
+                rank: r.rank,
+                balance: r.balance,
+                positions_value: r.positions_value,
+                total_value: r.total_value,
+            })
+            .collect())
+    }
+
+    /// Archive the final standings of every season that has ended, returning their ids
+    pub async fn archive_ended(&self) -> Result<Vec<SeasonId>> {
+        let mut archived = Vec::new();
+        for season in self.list_all().await?.into_iter().filter(Season::needs_archiving) {
+            let standings = self.live_standings(season.id).await?;
+
+            let mut tx = self.pool.begin().await?;
+            for standing in &standings {
+                sqlx::query(
+                    "INSERT INTO season_standings (season_id, user_id, rank, balance, positions_value, total_value) \
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(season.id)
+                .bind(standing.user_id)
+                .bind(standing.rank)
+                .bind(standing.balance)
+                .bind(standing.positions_value)
+                .bind(standing.total_value)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            sqlx::query("UPDATE seasons SET archived_at = ? WHERE id = ? AND archived_at IS NULL")
+                .bind(Utc::now().to_rfc3339())
+                .bind(season.id)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            archived.push(season.id);
+        }
+        Ok(archived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::LmsrPricing;
+    use crate::repository::{LedgerRepository, UserRepository};
+    use crate::Database;
+    use chrono::Duration;
+
+    const EPSILON: f64 = 1e-9;
+
+    #[tokio::test]
+    async fn test_attach_market_moves_subsidy_to_season_balance() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let creator = users.create_with_balance("creator", "hash", 1000.0).await.unwrap();
+        let outsider = users.create_with_balance("outsider", "hash", 1000.0).await.unwrap();
+        let seasons = SeasonRepository::new(db.pool().clone());
+        let now = Utc::now();
+        let season = seasons
+            .create("Spring", None, 500.0, now - Duration::days(1), now + Duration::days(7), creator.id)
+            .await
+            .unwrap();
+        seasons.join(&season, creator.id).await.unwrap();
+
+        let markets = MarketRepository::new(db.pool().clone());
+        let market = markets
+            .create("Will it rain?", None, creator.id, None, now + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+        seasons.attach_market(&season, market.id).await.unwrap();
+
+        let ledger = LedgerRepository::new(db.pool().clone());
+        let subsidy = LmsrPricing::max_loss(100.0);
+        assert!((users.find_by_id(creator.id).await.unwrap().balance - 1000.0).abs() < EPSILON);
+        let season_balance = ledger.balance(LedgerAccount::Season(season.id, creator.id)).await.unwrap();
+        assert!((season_balance - (500.0 - subsidy)).abs() < EPSILON);
+        assert!((ledger.balance(LedgerAccount::MarketMaker(market.id)).await.unwrap() - subsidy).abs() < EPSILON);
+        assert!(ledger.find_mismatches().await.unwrap().is_empty());
+
+        // A provider outside the season keeps the market out of it
+        let other = markets
+            .create("Will it snow?", None, outsider.id, None, now + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+        let result = seasons.attach_market(&season, other.id).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+        assert_eq!(markets.find_by_id(other.id).await.unwrap().season_id, None);
+        assert!((users.find_by_id(outsider.id).await.unwrap().balance - (1000.0 - subsidy)).abs() < EPSILON);
+    }
+}
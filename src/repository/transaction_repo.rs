@@ -0,0 +1,128 @@
+use crate::domain::{MarketId, MarketSide, Transaction, TransactionType, UserId};
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+
+const TRANSACTION_COLUMNS: &str = "id, user_id, market_id, transaction_type, side, shares, price, amount, created_at";
+
+#[derive(FromRow)]
+struct TransactionRow {
+    id: i64,
+    user_id: i64,
+    market_id: Option<i64>,
+    transaction_type: String,
+    side: Option<String>,
+    shares: f64,
+    price: f64,
+    amount: f64,
+    created_at: String,
+}
+
+impl TryFrom<TransactionRow> for Transaction {
+    type Error = RepositoryError;
+
+    fn try_from(row: TransactionRow) -> Result<Self> {
+        let decode = |msg: &'static str| RepositoryError::Database(sqlx::Error::Decode(msg.into()));
+        Ok(Transaction {
+            id: row.id,
+            user_id: row.user_id,
+            market_id: row.market_id,
+            // Like journal kinds, types are only validated here and in TransactionType
+            transaction_type: row
+                .transaction_type
+                .parse()
+                .map_err(|e: String| RepositoryError::Database(sqlx::Error::Decode(e.into())))?,
+            side: row
+                .side
+                .map(|side| side.parse::<MarketSide>())
+                .transpose()
+                .map_err(|_| decode("Invalid market side"))?,
+            shares: row.shares,
+            price: row.price,
+            amount: row.amount,
+            created_at: DateTime::parse_from_rfc3339(&row.created_at)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct TransactionRepository {
+    pool: SqlitePool,
+}
+
+impl TransactionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// A user's trade history, oldest first
+    pub async fn find_by_user(&self, user_id: UserId) -> Result<Vec<Transaction>> {
+        let rows = sqlx::query_as::<_, TransactionRow>(&format!(
+            "SELECT {} FROM transactions WHERE user_id = ? ORDER BY id",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+}
+
+/// Record a trade or payout inside the caller's transaction, so it is kept exactly when the trade is
+pub(crate) async fn record_transaction(
+    conn: &mut SqliteConnection,
+    user_id: UserId,
+    market_id: MarketId,
+    transaction_type: TransactionType,
+    side: MarketSide,
+    shares: f64,
+    amount: f64,
+) -> Result<()> {
+    let price = if shares > 0.0 { amount / shares } else { 0.0 };
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (user_id, market_id, transaction_type, side, shares, price, amount, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(user_id)
+    .bind(market_id)
+    .bind(transaction_type.to_string())
+    .bind(side.to_string())
+    .bind(shares)
+    .bind(price)
+    .bind(amount)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::UserRepository;
+    use crate::Database;
+
+    #[tokio::test]
+    async fn test_unknown_transaction_type_is_rejected_on_read() {
+        let db = Database::in_memory().await;
+        let user = UserRepository::new(db.pool().clone()).create_with_balance("alice", "hash", 0.0).await.unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (user_id, transaction_type, shares, price, amount, created_at) \
+             VALUES (?, 'bonus', 0.0, 0.0, 0.0, ?)",
+        )
+        .bind(user.id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let result = TransactionRepository::new(db.pool().clone()).find_by_user(user.id).await;
+        assert!(matches!(result, Err(RepositoryError::Database(_))));
+    }
+}
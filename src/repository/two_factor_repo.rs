@@ -0,0 +1,189 @@
+use crate::domain::{TotpCredential, TotpSecret, UserId};
+use crate::repository::{Result, RepositoryError};
+use chrono::Utc;
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(FromRow)]
+struct CredentialRow {
+    user_id: i64,
+    secret: String,
+    last_used_step: Option<i64>,
+    enabled_at: String,
+}
+
+impl TryFrom<CredentialRow> for TotpCredential {
+    type Error = RepositoryError;
+
+    fn try_from(row: CredentialRow) -> Result<Self> {
+        Ok(TotpCredential {
+            user_id: row.user_id,
+            secret: TotpSecret::from_base32(&row.secret).ok_or_else(|| {
+                RepositoryError::Database(sqlx::Error::Decode("Invalid TOTP secret".into()))
+            })?,
+            last_used_step: row.last_used_step,
+            enabled_at: row.enabled_at.parse().unwrap_or_else(|_| Utc::now()),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct TwoFactorRepository {
+    pool: SqlitePool,
+}
+
+impl TwoFactorRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// The user's TOTP enrollment, if two-factor authentication is enabled
+    pub async fn find(&self, user_id: UserId) -> Result<Option<TotpCredential>> {
+        let row = sqlx::query_as::<_, CredentialRow>(
+            "SELECT user_id, secret, last_used_step, enabled_at FROM totp_credentials WHERE user_id = ?",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(TryInto::try_into).transpose()
+    }
+
+    pub async fn is_enabled(&self, user_id: UserId) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM totp_credentials WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Enable two-factor authentication, replacing any earlier enrollment and recovery codes
+    ///
+    /// `confirmed_step` is the step of the code the user entered to confirm the secret.
+    pub async fn enable(
+        &self,
+        user_id: UserId,
+        secret: &TotpSecret,
+        confirmed_step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO totp_credentials (user_id, secret, last_used_step, enabled_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (user_id) DO UPDATE SET
+                secret = excluded.secret,
+                last_used_step = excluded.last_used_step,
+                enabled_at = excluded.enabled_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(secret.to_base32())
+        .bind(confirmed_step)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        Self::insert_recovery_codes(&mut tx, user_id, recovery_code_hashes).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Turn two-factor authentication off, discarding the secret and recovery codes
+    pub async fn disable(&self, user_id: UserId) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM totp_credentials WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record `step` as used, failing if it isn't newer than the last accepted step
+    ///
+    /// The compare-and-set makes a code usable once even under concurrent requests.
+    pub async fn use_step(&self, user_id: UserId, step: i64) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE totp_credentials
+            SET last_used_step = ?
+            WHERE user_id = ? AND (last_used_step IS NULL OR last_used_step < ?)
+            "#,
+        )
+        .bind(step)
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Mark an unused recovery code as used; returns whether one matched
+    pub async fn use_recovery_code(&self, user_id: UserId, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE recovery_codes
+            SET used_at = ?
+            WHERE user_id = ? AND code_hash = ? AND used_at IS NULL
+            "#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn remaining_recovery_codes(&self, user_id: UserId) -> Result<i64> {
+        let count = sqlx::query_scalar("SELECT COUNT(*) FROM recovery_codes WHERE user_id = ? AND used_at IS NULL")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    /// Replace all of a user's recovery codes with a fresh set
+    pub async fn replace_recovery_codes(&self, user_id: UserId, code_hashes: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::insert_recovery_codes(&mut tx, user_id, code_hashes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_recovery_codes(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        user_id: UserId,
+        code_hashes: &[String],
+    ) -> Result<()> {
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        for hash in code_hashes {
+            sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)")
+                .bind(user_id)
+                .bind(hash)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+}
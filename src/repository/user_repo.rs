@@ -1,8 +1,95 @@
-use crate::domain::{User, UserId};
+use crate::domain::{
+    InviteId, Journal, JournalKind, LedgerAccount, Role, Transfer, TransferLimits, User, UserId,
+    DEFAULT_STARTING_BALANCE, NO_PASSWORD_HASH,
+};
+use crate::repository::ledger_repo::post_journal;
 use crate::repository::{Result, RepositoryError};
-use sqlx::SqlitePool;
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
 use chrono::{DateTime, Utc};
 
+const USER_COLUMNS: &str =
+    "id, username, password_hash, balance, role, session_version, display_name, bio, hide_positions, email, created_at, \
+     deleted_at";
+
+#[derive(FromRow)]
+struct UserRow {
+    id: i64,
+    username: String,
+    password_hash: String,
+    balance: f64,
+    role: String,
+    session_version: i64,
+    display_name: Option<String>,
+    bio: Option<String>,
+    hide_positions: bool,
+    email: Option<String>,
+    created_at: String,
+    deleted_at: Option<String>,
+}
+
+impl TryFrom<UserRow> for User {
+    type Error = RepositoryError;
+
+    fn try_from(row: UserRow) -> Result<Self> {
+        Ok(User {
+            id: row.id,
+            username: row.username,
+            password_hash: row.password_hash,
+            balance: row.balance,
+            role: row.role.parse().map_err(|_| {
+                RepositoryError::Database(sqlx::Error::Decode("Invalid user role".into()))
+            })?,
+            session_version: row.session_version,
+            display_name: row.display_name,
+            bio: row.bio,
+            hide_positions: row.hide_positions,
+            email: row.email,
+            created_at: DateTime::parse_from_rfc3339(&row.created_at)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+            deleted_at: row
+                .deleted_at
+                .map(|s| DateTime::parse_from_rfc3339(&s).map(|dt| dt.with_timezone(&Utc)))
+                .transpose()
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct TransferRow {
+    id: i64,
+    sender_id: i64,
+    sender: String,
+    recipient_id: i64,
+    recipient: String,
+    amount: f64,
+    memo: Option<String>,
+    created_at: String,
+}
+
+impl TryFrom<TransferRow> for Transfer {
+    type Error = RepositoryError;
+
+    fn try_from(row: TransferRow) -> Result<Self> {
+        Ok(Transfer {
+            id: row.id,
+            sender_id: row.sender_id,
+            sender: row.sender,
+            recipient_id: row.recipient_id,
+            recipient: row.recipient,
+            amount: row.amount,
+            memo: row.memo,
+            created_at: DateTime::parse_from_rfc3339(&row.created_at)
+                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
+                .with_timezone(&Utc),
+        })
+    }
+}
+
+const TRANSFER_COLUMNS: &str = "t.id, t.sender_id, s.username AS sender, t.recipient_id, r.username AS recipient, \
+    t.amount, t.memo, t.created_at";
+
 #[derive(Clone)]
 pub struct UserRepository {
     pool: SqlitePool,
@@ -14,143 +101,485 @@ impl UserRepository {
     }
 
     pub async fn create(&self, username: &str, password_hash: &str) -> Result<User> {
-        let result = sqlx::query!(
+        self.create_with_balance(username, password_hash, DEFAULT_STARTING_BALANCE).await
+    }
+
+    /// Create a user, granting `balance` from the faucet
+    pub async fn create_with_balance(&self, username: &str, password_hash: &str, balance: f64) -> Result<User> {
+        let mut tx = self.pool.begin().await?;
+        let user = insert_user(&mut tx, username, password_hash, balance, None).await?;
+        tx.commit().await?;
+        Ok(user)
+    }
+
+    /// Create a user with an invite code, consuming one of its uses
+    ///
+    /// The account gets the invite's starting balance. Fails with `NotFound` when
+    /// the code doesn't exist or can no longer be used; a use is only consumed
+    /// if the account is created.
+    pub async fn create_with_invite(&self, username: &str, password_hash: &str, code: &str) -> Result<User> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now().to_rfc3339();
+
+        let (invite_id, starting_balance) = sqlx::query_as::<_, (i64, f64)>(
             r#"
-            INSERT INTO users (username, password_hash, balance)
-            VALUES (?, ?, 1000.0)
-            RETURNING id, username, password_hash, balance, created_at
+            UPDATE invite_codes
+            SET uses = uses + 1
+            WHERE code = ? AND revoked_at IS NULL AND uses < max_uses
+              AND (expires_at IS NULL OR expires_at > ?)
+            RETURNING id, starting_balance
             "#,
-            username,
-            password_hash
         )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| {
-            if let sqlx::Error::Database(db_err) = &e {
-                if db_err.is_unique_violation() {
-                    return RepositoryError::ConstraintViolation("Username already exists".to_string());
-                }
-            }
-            RepositoryError::Database(e)
-        })?;
+        .bind(code)
+        .bind(&now)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(RepositoryError::NotFound)?;
 
-        Ok(User {
-            id: result.id,
-            username: result.username,
-            password_hash: result.password_hash,
-            balance: result.balance,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-        })
+        let user = insert_user(&mut tx, username, password_hash, starting_balance, Some(invite_id)).await?;
+        tx.commit().await?;
+        Ok(user)
     }
 
     pub async fn find_by_id(&self, id: UserId) -> Result<User> {
-        let result = sqlx::query!(
-            r#"
-            SELECT id, username, password_hash, balance, created_at
-            FROM users
-            WHERE id = ?
-            "#,
-            id
-        )
+        let row = sqlx::query_as::<_, UserRow>(&format!(
+            "SELECT {} FROM users WHERE id = ?",
+            USER_COLUMNS
+        ))
+        .bind(id)
         .fetch_optional(&self.pool)
         .await?
         .ok_or(RepositoryError::NotFound)?;
 
-        Ok(User {
-            id: result.id,
-            username: result.username,
-            password_hash: result.password_hash,
-            balance: result.balance,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-        })
+        row.try_into()
     }
 
     pub async fn find_by_username(&self, username: &str) -> Result<User> {
-        let result = sqlx::query!(
-            r#"
-            SELECT id, username, password_hash, balance, created_at
-            FROM users
-            WHERE username = ?
-            "#,
-            username
-        )
+        let row = sqlx::query_as::<_, UserRow>(&format!(
+            "SELECT {} FROM users WHERE username = ?",
+            USER_COLUMNS
+        ))
+        .bind(username)
         .fetch_optional(&self.pool)
         .await?
         .ok_or(RepositoryError::NotFound)?;
 
-        Ok(User {
-            id: result.id.unwrap_or_default(),
-            username: result.username,
-            password_hash: result.password_hash,
-            balance: result.balance,
-            created_at: DateTime::parse_from_rfc3339(&result.created_at)
-                .map_err(|e| RepositoryError::Database(sqlx::Error::Decode(Box::new(e))))?
-                .with_timezone(&Utc),
-        })
+        row.try_into()
+    }
+
+    /// All users, oldest account first
+    pub async fn list_all(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query_as::<_, UserRow>(&format!(
+            "SELECT {} FROM users ORDER BY id",
+            USER_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    pub async fn count_by_role(&self, role: Role) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE role = ?")
+            .bind(role.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
     }
 
-    pub async fn update_balance(&self, id: UserId, new_balance: f64) -> Result<()> {
-        let result = sqlx::query!(
+    /// Change a user's role, refusing to demote the last remaining admin
+    pub async fn update_role(&self, id: UserId, role: Role) -> Result<()> {
+        let result = sqlx::query(
             r#"
             UPDATE users
-            SET balance = ?
+            SET role = ?
             WHERE id = ?
+              AND (? = 'admin' OR role != 'admin'
+                   OR (SELECT COUNT(*) FROM users WHERE role = 'admin') > 1)
             "#,
-            new_balance,
-            id
         )
+        .bind(role.to_string())
+        .bind(id)
+        .bind(role.to_string())
         .execute(&self.pool)
         .await?;
 
         if result.rows_affected() == 0 {
-            return Err(RepositoryError::NotFound);
+            return match self.find_by_id(id).await {
+                Ok(_) => Err(RepositoryError::ConstraintViolation(
+                    "Cannot demote the last admin".to_string(),
+                )),
+                Err(e) => Err(e),
+            };
         }
 
         Ok(())
     }
 
-    pub async fn deduct_balance(&self, id: UserId, amount: f64) -> Result<()> {
-        let result = sqlx::query!(
+    pub async fn session_version(&self, id: UserId) -> Result<i64> {
+        sqlx::query_scalar::<_, i64>("SELECT session_version FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    /// Replace the password hash and invalidate every existing session
+    ///
+    /// Returns the new session version, so the caller can keep its own session alive.
+    pub async fn update_password(&self, id: UserId, password_hash: &str) -> Result<i64> {
+        sqlx::query_scalar::<_, i64>(
             r#"
             UPDATE users
-            SET balance = balance - ?
-            WHERE id = ? AND balance >= ?
+            SET password_hash = ?, session_version = session_version + 1
+            WHERE id = ?
+            RETURNING session_version
             "#,
-            amount,
-            id,
-            amount
         )
-        .execute(&self.pool)
-        .await?;
+        .bind(password_hash)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound)
+    }
+
+    pub async fn update_profile(
+        &self,
+        id: UserId,
+        display_name: Option<&str>,
+        bio: Option<&str>,
+        hide_positions: bool,
+    ) -> Result<()> {
+        let result = sqlx::query("UPDATE users SET display_name = ?, bio = ?, hide_positions = ? WHERE id = ?")
+            .bind(display_name)
+            .bind(bio)
+            .bind(hide_positions)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
 
         if result.rows_affected() == 0 {
-            return Err(RepositoryError::ConstraintViolation("Insufficient balance".to_string()));
+            return Err(RepositoryError::NotFound);
         }
 
         Ok(())
     }
 
-    pub async fn add_balance(&self, id: UserId, amount: f64) -> Result<()> {
-        let result = sqlx::query!(
+    /// Set or clear the address password reset links are sent to
+    pub async fn update_email(&self, id: UserId, email: Option<&str>) -> Result<()> {
+        let result = sqlx::query("UPDATE users SET email = ? WHERE id = ?")
+            .bind(email)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Delete an account while keeping its row for the records that refer to it
+    ///
+    /// Personal details and credentials are erased, the remaining balance and
+    /// every season balance are forfeited to the faucet, as is what the user's
+    /// liquidity deposits would return at resolution. Every session is
+    /// invalidated and the username is freed.
+    pub async fn anonymize(&self, id: UserId) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
             r#"
             UPDATE users
-            SET balance = balance + ?
-            WHERE id = ?
+            SET username = ?, password_hash = ?, display_name = NULL, bio = NULL, hide_positions = 1, email = NULL,
+                role = 'user', session_version = session_version + 1, deleted_at = ?
+            WHERE id = ? AND deleted_at IS NULL
             "#,
-            amount,
-            id
         )
-        .execute(&self.pool)
+        .bind(User::anonymized_username(id))
+        .bind(NO_PASSWORD_HASH)
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(&mut *tx)
         .await?;
 
         if result.rows_affected() == 0 {
             return Err(RepositoryError::NotFound);
         }
 
+        let balance = sqlx::query_scalar::<_, f64>("SELECT balance FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if balance > 0.0 {
+            let forfeit = Journal::transfer(JournalKind::Forfeit, LedgerAccount::User(id), LedgerAccount::Faucet, balance);
+            post_journal(&mut tx, &forfeit).await?;
+        }
+
+        let season_balances = sqlx::query_as::<_, (i64, f64)>(
+            r#"
+            SELECT p.season_id, COALESCE(SUM(e.amount), 0.0)
+            FROM season_participants p
+            JOIN ledger_entries e ON e.account = 'season:' || p.season_id || ':user:' || p.user_id
+            WHERE p.user_id = ?
+            GROUP BY p.season_id
+            ORDER BY p.season_id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for (season_id, balance) in season_balances {
+            if balance > 0.0 {
+                let season_account = LedgerAccount::Season(season_id, id);
+                let forfeit = Journal::transfer(JournalKind::Forfeit, season_account, LedgerAccount::Faucet, balance);
+                post_journal(&mut tx, &forfeit).await?;
+            }
+        }
+
+        sqlx::query("UPDATE liquidity_deposits SET forfeited = 1 WHERE user_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        for table in [
+            "totp_credentials",
+            "recovery_codes",
+            "user_identities",
+            "password_reset_tokens",
+            "webhook_subscriptions",
+            "idempotency_keys",
+        ] {
+            sqlx::query(&format!("DELETE FROM {} WHERE user_id = ?", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
         Ok(())
     }
+
+    /// Send `amount` from one user to another, debiting and crediting both in one journal
+    ///
+    /// Fails with `NotFound` if the recipient does not exist or is deleted, and
+    /// with `ConstraintViolation` if the transfer is to oneself, breaks `limits`
+    /// or exceeds the sender's balance.
+    pub async fn transfer(
+        &self,
+        sender_id: UserId,
+        recipient: &str,
+        amount: f64,
+        memo: Option<&str>,
+        limits: &TransferLimits,
+    ) -> Result<Transfer> {
+        let mut tx = self.pool.begin().await?;
+
+        let recipient_id = sqlx::query_scalar::<_, i64>("SELECT id FROM users WHERE username = ? AND deleted_at IS NULL")
+            .bind(recipient)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        if recipient_id == sender_id {
+            return Err(RepositoryError::ConstraintViolation("You cannot send money to yourself".to_string()));
+        }
+
+        let now = Utc::now();
+        let sent_today = sqlx::query_scalar::<_, f64>(
+            "SELECT COALESCE(SUM(amount), 0.0) FROM transfers WHERE sender_id = ? AND julianday(created_at) > julianday(?)",
+        )
+        .bind(sender_id)
+        .bind((now - chrono::Duration::days(1)).to_rfc3339())
+        .fetch_one(&mut *tx)
+        .await?;
+        limits.check(amount, sent_today).map_err(RepositoryError::ConstraintViolation)?;
+
+        let journal = Journal::transfer(
+            JournalKind::Transfer,
+            LedgerAccount::User(sender_id),
+            LedgerAccount::User(recipient_id),
+            amount,
+        );
+        let journal_id = post_journal(&mut tx, &journal).await?;
+
+        let id = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO transfers (sender_id, recipient_id, amount, memo, journal_id, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(sender_id)
+        .bind(recipient_id)
+        .bind(amount)
+        .bind(memo)
+        .bind(journal_id)
+        .bind(now.to_rfc3339())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let row = sqlx::query_as::<_, TransferRow>(&format!(
+            "SELECT {} FROM transfers t JOIN users s ON s.id = t.sender_id JOIN users r ON r.id = t.recipient_id WHERE t.id = ?",
+            TRANSFER_COLUMNS
+        ))
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        row.try_into()
+    }
+
+    /// Transfers a user sent or received, newest first
+    pub async fn find_transfers(&self, user_id: UserId) -> Result<Vec<Transfer>> {
+        let rows = sqlx::query_as::<_, TransferRow>(&format!(
+            r#"
+            SELECT {}
+            FROM transfers t
+            JOIN users s ON s.id = t.sender_id
+            JOIN users r ON r.id = t.recipient_id
+            WHERE t.sender_id = ? OR t.recipient_id = ?
+            ORDER BY t.id DESC
+            "#,
+            TRANSFER_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+}
+
+/// Insert a user inside the caller's transaction, granting `balance` from the faucet
+async fn insert_user(
+    conn: &mut SqliteConnection,
+    username: &str,
+    password_hash: &str,
+    balance: f64,
+    invite_id: Option<InviteId>,
+) -> Result<User> {
+    let id = sqlx::query_scalar::<_, i64>(
+        "INSERT INTO users (username, password_hash, balance, invite_id) VALUES (?, ?, 0.0, ?) RETURNING id",
+    )
+    .bind(username)
+    .bind(password_hash)
+    .bind(invite_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(map_username_conflict)?;
+
+    if balance > 0.0 {
+        post_journal(conn, &Journal::grant(id, balance)).await?;
+    }
+
+    let row = sqlx::query_as::<_, UserRow>(&format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS))
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+    row.try_into()
+}
+
+fn map_username_conflict(e: sqlx::Error) -> RepositoryError {
+    if let sqlx::Error::Database(db_err) = &e {
+        if db_err.is_unique_violation() {
+            return RepositoryError::ConstraintViolation("Username already exists".to_string());
+        }
+    }
+    RepositoryError::Database(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{LedgerRepository, LiquidityRepository, MarketRepository, SeasonRepository};
+    use crate::Database;
+    use chrono::Duration;
+
+    const LIMITS: TransferLimits = TransferLimits { max_amount: 100.0, daily_limit: 150.0 };
+
+    #[tokio::test]
+    async fn test_transfer_moves_balance() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let alice = users.create_with_balance("alice", "hash", 200.0).await.unwrap();
+        let bob = users.create_with_balance("bob", "hash", 50.0).await.unwrap();
+
+        let transfer = users.transfer(alice.id, "bob", 80.0, Some("rent"), &LIMITS).await.unwrap();
+        assert_eq!((transfer.sender_id, transfer.recipient_id, transfer.amount), (alice.id, bob.id, 80.0));
+        assert_eq!(transfer.memo.as_deref(), Some("rent"));
+
+        assert_eq!(users.find_by_id(alice.id).await.unwrap().balance, 120.0);
+        assert_eq!(users.find_by_id(bob.id).await.unwrap().balance, 130.0);
+        assert_eq!(users.find_transfers(bob.id).await.unwrap().len(), 1);
+        assert!(LedgerRepository::new(db.pool().clone()).find_mismatches().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_rejections_leave_balances_alone() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let alice = users.create_with_balance("alice", "hash", 60.0).await.unwrap();
+        let bob = users.create_with_balance("bob", "hash", 500.0).await.unwrap();
+
+        // More than the sender holds
+        let result = users.transfer(alice.id, "bob", 70.0, None, &LIMITS).await;
+        assert!(matches!(result, Err(RepositoryError::ConstraintViolation(_))));
+        // To themselves, to nobody, and past the daily limit
+        assert!(users.transfer(alice.id, "alice", 10.0, None, &LIMITS).await.is_err());
+        assert!(matches!(users.transfer(alice.id, "carol", 10.0, None, &LIMITS).await, Err(RepositoryError::NotFound)));
+        users.transfer(bob.id, "alice", 100.0, None, &LIMITS).await.unwrap();
+        assert!(users.transfer(bob.id, "alice", 60.0, None, &LIMITS).await.is_err());
+
+        assert_eq!(users.find_by_id(alice.id).await.unwrap().balance, 160.0);
+        assert_eq!(users.find_by_id(bob.id).await.unwrap().balance, 400.0);
+        assert_eq!(users.find_transfers(alice.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_anonymize_forfeits_season_balances() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let alice = users.create_with_balance("alice", "hash", 100.0).await.unwrap();
+        let seasons = SeasonRepository::new(db.pool().clone());
+        let now = Utc::now();
+        let season = seasons
+            .create("Spring", None, 500.0, now - Duration::days(1), now + Duration::days(7), alice.id)
+            .await
+            .unwrap();
+        seasons.join(&season, alice.id).await.unwrap();
+
+        users.anonymize(alice.id).await.unwrap();
+        let ledger = LedgerRepository::new(db.pool().clone());
+        assert_eq!(ledger.balance(LedgerAccount::User(alice.id)).await.unwrap(), 0.0);
+        assert_eq!(ledger.balance(LedgerAccount::Season(season.id, alice.id)).await.unwrap(), 0.0);
+        assert!(ledger.find_mismatches().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_anonymize_forfeits_liquidity_deposits() {
+        let db = Database::in_memory().await;
+        let users = UserRepository::new(db.pool().clone());
+        let creator = users.create_with_balance("creator", "hash", 1000.0).await.unwrap();
+        let alice = users.create_with_balance("alice", "hash", 100.0).await.unwrap();
+        let markets = MarketRepository::new(db.pool().clone());
+        let market = markets
+            .create("Will it rain?", None, creator.id, None, Utc::now() + Duration::days(7), 100.0)
+            .await
+            .unwrap();
+        LiquidityRepository::new(db.pool().clone()).deposit(market.id, alice.id, 50.0).await.unwrap();
+
+        users.anonymize(alice.id).await.unwrap();
+        let ledger = LedgerRepository::new(db.pool().clone());
+        let faucet = ledger.balance(LedgerAccount::Faucet).await.unwrap();
+
+        // Nothing was traded: the creator gets their subsidy back and alice's deposit goes to the faucet
+        markets.resolve_and_settle(market.id, true).await.unwrap();
+        assert_eq!(users.find_by_id(alice.id).await.unwrap().balance, 0.0);
+        assert!((users.find_by_id(creator.id).await.unwrap().balance - 1000.0).abs() < 1e-6);
+        assert!((ledger.balance(LedgerAccount::Faucet).await.unwrap() - (faucet + 50.0)).abs() < 1e-6);
+        assert!(ledger.find_mismatches().await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,323 @@
+use crate::domain::{
+    DeliveryStatus, MarketId, UserId, WebhookDelivery, WebhookEvent, WebhookId, WebhookSubscription,
+};
+use crate::repository::{Result, RepositoryError};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(FromRow)]
+struct SubscriptionRow {
+    id: i64,
+    user_id: i64,
+    market_id: Option<i64>,
+    url: String,
+    secret: String,
+    events: String,
+    active: bool,
+    created_at: String,
+}
+
+impl From<SubscriptionRow> for WebhookSubscription {
+    fn from(row: SubscriptionRow) -> Self {
+        WebhookSubscription {
+            id: row.id,
+            user_id: row.user_id,
+            market_id: row.market_id,
+            url: row.url,
+            secret: row.secret,
+            events: row.events.split(',').filter_map(|e| e.parse().ok()).collect(),
+            active: row.active,
+            created_at: row.created_at.parse().unwrap_or_else(|_| Utc::now()),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct DeliveryRow {
+    id: i64,
+    subscription_id: i64,
+    event: String,
+    payload: String,
+    status: String,
+    attempts: i64,
+    next_attempt_at: String,
+    last_status_code: Option<i64>,
+    last_error: Option<String>,
+    created_at: String,
+    delivered_at: Option<String>,
+}
+
+impl TryFrom<DeliveryRow> for WebhookDelivery {
+    type Error = RepositoryError;
+
+    fn try_from(row: DeliveryRow) -> Result<Self> {
+        Ok(WebhookDelivery {
+            id: row.id,
+            subscription_id: row.subscription_id,
+            event: row.event,
+            payload: row.payload,
+            status: row.status.parse().map_err(|_| {
+                RepositoryError::Database(sqlx::Error::Decode("Invalid delivery status".into()))
+            })?,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at.parse().unwrap_or_else(|_| Utc::now()),
+            last_status_code: row.last_status_code,
+            last_error: row.last_error,
+            created_at: row.created_at.parse().unwrap_or_else(|_| Utc::now()),
+            delivered_at: row.delivered_at.and_then(|s| s.parse().ok()),
+        })
+    }
+}
+
+/// A due delivery together with where and how to send it
+pub struct PendingDelivery {
+    pub delivery: WebhookDelivery,
+    pub url: String,
+    pub secret: String,
+}
+
+const SUBSCRIPTION_COLUMNS: &str = "id, user_id, market_id, url, secret, events, active, created_at";
+
+const DELIVERY_COLUMNS: &str = "d.id, d.subscription_id, d.event, d.payload, d.status, d.attempts, \
+    d.next_attempt_at, d.last_status_code, d.last_error, d.created_at, d.delivered_at";
+
+#[derive(Clone)]
+pub struct WebhookRepository {
+    pool: SqlitePool,
+}
+
+impl WebhookRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create_subscription(
+        &self,
+        user_id: UserId,
+        market_id: Option<MarketId>,
+        url: &str,
+        secret: &str,
+        events: &[WebhookEvent],
+    ) -> Result<WebhookSubscription> {
+        let events = events.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(",");
+        let row = sqlx::query_as::<_, SubscriptionRow>(&format!(
+            r#"
+            INSERT INTO webhook_subscriptions (user_id, market_id, url, secret, events)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING {}
+            "#,
+            SUBSCRIPTION_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(market_id)
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    pub async fn find_subscriptions_by_user(&self, user_id: UserId) -> Result<Vec<WebhookSubscription>> {
+        let rows = sqlx::query_as::<_, SubscriptionRow>(&format!(
+            r#"
+            SELECT {}
+            FROM webhook_subscriptions
+            WHERE user_id = ?
+            ORDER BY created_at DESC
+            "#,
+            SUBSCRIPTION_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Delete a subscription owned by `user_id`, along with its delivery log
+    pub async fn delete_subscription(&self, id: WebhookId, user_id: UserId) -> Result<()> {
+        let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Queue one delivery per active subscription interested in `event` on `market_id`
+    pub async fn enqueue(&self, event: WebhookEvent, market_id: MarketId, payload: &str) -> Result<usize> {
+        let rows = sqlx::query_as::<_, SubscriptionRow>(&format!(
+            r#"
+            SELECT {}
+            FROM webhook_subscriptions
+            WHERE active = 1 AND (market_id IS NULL OR market_id = ?)
+            "#,
+            SUBSCRIPTION_COLUMNS
+        ))
+        .bind(market_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let now = Utc::now().to_rfc3339();
+        let event_name = event.to_string();
+        let mut queued = 0;
+        for subscription in rows.into_iter().map(WebhookSubscription::from) {
+            if !subscription.matches(event, market_id) {
+                continue;
+            }
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_deliveries (subscription_id, event, payload, next_attempt_at)
+                VALUES (?, ?, ?, ?)
+                "#,
+            )
+            .bind(subscription.id)
+            .bind(&event_name)
+            .bind(payload)
+            .bind(&now)
+            .execute(&self.pool)
+            .await?;
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    /// Pending deliveries whose next attempt is due, oldest first
+    pub async fn find_due(&self, limit: i64) -> Result<Vec<PendingDelivery>> {
+        #[derive(FromRow)]
+        struct DueRow {
+            #[sqlx(flatten)]
+            delivery: DeliveryRow,
+            url: String,
+            secret: String,
+        }
+
+        let rows = sqlx::query_as::<_, DueRow>(&format!(
+            r#"
+            SELECT {}, s.url, s.secret
+            FROM webhook_deliveries d
+            JOIN webhook_subscriptions s ON s.id = d.subscription_id
+            WHERE d.status = 'pending' AND d.next_attempt_at <= ?
+            ORDER BY d.next_attempt_at ASC, d.id ASC
+            LIMIT ?
+            "#,
+            DELIVERY_COLUMNS
+        ))
+        .bind(Utc::now().to_rfc3339())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(PendingDelivery {
+                    delivery: row.delivery.try_into()?,
+                    url: row.url,
+                    secret: row.secret,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn mark_delivered(&self, id: i64, status_code: i64) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'delivered', attempts = attempts + 1, last_status_code = ?,
+                last_error = NULL, delivered_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(status_code)
+        .bind(now)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt; `next_attempt_at` of `None` gives up on the delivery
+    pub async fn mark_attempt_failed(
+        &self,
+        id: i64,
+        status_code: Option<i64>,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let status = if next_attempt_at.is_some() {
+            DeliveryStatus::Pending
+        } else {
+            DeliveryStatus::Failed
+        };
+        let next_attempt_at = next_attempt_at.unwrap_or_else(Utc::now).to_rfc3339();
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = ?, attempts = attempts + 1, last_status_code = ?, last_error = ?,
+                next_attempt_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(status.to_string())
+        .bind(status_code)
+        .bind(error)
+        .bind(next_attempt_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Put a failed delivery owned by `user_id` back in the queue
+    pub async fn retry(&self, id: i64, user_id: UserId) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = ?
+            WHERE id = ? AND status = 'failed'
+              AND subscription_id IN (SELECT id FROM webhook_subscriptions WHERE user_id = ?)
+            "#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Most recent deliveries across all of a user's subscriptions
+    pub async fn find_recent_by_user(&self, user_id: UserId, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, DeliveryRow>(&format!(
+            r#"
+            SELECT {}
+            FROM webhook_deliveries d
+            JOIN webhook_subscriptions s ON s.id = d.subscription_id
+            WHERE s.user_id = ?
+            ORDER BY d.id DESC
+            LIMIT ?
+            "#,
+            DELIVERY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+}
@@ -0,0 +1,246 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{
+    LedgerAccount, Market, MarketId, Position, PostedEntry, Role, Transaction, Transfer, UserIdentity, WebhookEvent,
+    NO_PASSWORD_HASH,
+};
+use crate::repository::{
+    IdentityRepository, LedgerRepository, MarketRepository, PositionRepository, TransactionRepository, TwoFactorRepository,
+    UserRepository, WebhookRepository,
+};
+use crate::web::handlers::settings::render_settings;
+use crate::web::handlers::trading::sell_back;
+use crate::web::handlers::two_factor::verify_second_factor;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::{clear_user_session, RequireAuth};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Everything held about a user, as downloaded from settings
+#[derive(Serialize)]
+struct AccountExport {
+    exported_at: DateTime<Utc>,
+    profile: ProfileExport,
+    /// Every position held, including ones since sold
+    positions: Vec<Position>,
+    trades: Vec<Transaction>,
+    /// Every movement of the balance
+    ledger: Vec<PostedEntry>,
+    /// Transfers sent and received
+    transfers: Vec<Transfer>,
+    markets_created: Vec<Market>,
+    linked_identities: Vec<UserIdentity>,
+    webhooks: Vec<WebhookExport>,
+    two_factor_enabled: bool,
+}
+
+#[derive(Serialize)]
+struct ProfileExport {
+    id: i64,
+    username: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+    hide_positions: bool,
+    email: Option<String>,
+    role: Role,
+    balance: f64,
+    created_at: DateTime<Utc>,
+}
+
+/// A webhook subscription without its signing secret
+#[derive(Serialize)]
+struct WebhookExport {
+    url: String,
+    market_id: Option<MarketId>,
+    events: Vec<WebhookEvent>,
+    active: bool,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountForm {
+    confirm_username: String,
+    /// Not asked of accounts that only log in through single sign-on
+    password: Option<String>,
+    /// Asked when two-factor authentication is enabled
+    code: Option<String>,
+}
+
+/// Download the user's data as a JSON file
+pub async fn export_data(auth: RequireAuth, State(db): State<Database>) -> Response {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let user = match user_repo.find_by_id(auth.user_id).await {
+        Ok(user) => user,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Error loading user: {}", e)).into_response(),
+    };
+
+    let position_repo = PositionRepository::new(db.pool().clone());
+    let transaction_repo = TransactionRepository::new(db.pool().clone());
+    let ledger_repo = LedgerRepository::new(db.pool().clone());
+    let market_repo = MarketRepository::new(db.pool().clone());
+    let identity_repo = IdentityRepository::new(db.pool().clone());
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+
+    let export = async {
+        Ok::<_, crate::repository::RepositoryError>(AccountExport {
+            exported_at: Utc::now(),
+            positions: position_repo.find_history_by_user(user.id).await?,
+            trades: transaction_repo.find_by_user(user.id).await?,
+            ledger: ledger_repo.entries_for(LedgerAccount::User(user.id)).await?,
+            transfers: user_repo.find_transfers(user.id).await?,
+            markets_created: market_repo.find_by_creator(user.id).await?,
+            linked_identities: identity_repo.find_by_user(user.id).await?,
+            webhooks: webhook_repo
+                .find_subscriptions_by_user(user.id)
+                .await?
+                .into_iter()
+                .map(|subscription| WebhookExport {
+                    url: subscription.url,
+                    market_id: subscription.market_id,
+                    events: subscription.events,
+                    active: subscription.active,
+                    created_at: subscription.created_at,
+                })
+                .collect(),
+            two_factor_enabled: two_factor_repo.is_enabled(user.id).await?,
+            profile: ProfileExport {
+                id: user.id,
+                username: user.username.clone(),
+                display_name: user.display_name.clone(),
+                bio: user.bio.clone(),
+                hide_positions: user.hide_positions,
+                email: user.email.clone(),
+                role: user.role,
+                balance: user.balance,
+                created_at: user.created_at,
+            },
+        })
+    };
+
+    match export.await {
+        Ok(export) => {
+            let disposition = format!("attachment; filename=\"market-{}.json\"", user.username);
+            ([(header::CONTENT_DISPOSITION, disposition)], Json(export)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Error exporting data: {}", e)).into_response(),
+    }
+}
+
+/// Delete the account after re-authenticating
+///
+/// Positions in markets still open for trading are sold back at the current
+/// price. Deletion is refused while the user holds shares in closed markets
+/// that haven't resolved, since their payouts would go to the anonymized
+/// account. The account row is kept, anonymized, so markets and their history
+/// stay intact.
+pub async fn delete_account(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    session: Session,
+    Form(form): Form<DeleteAccountForm>,
+) -> Result<Redirect, Html<String>> {
+    let fail = |error: String| {
+        let (db, config, csrf) = (db.clone(), config.clone(), csrf.clone());
+        async move { render_settings(&db, &config, auth.user_id, csrf, Some(error), None).await }
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    let user = match user_repo.find_by_id(auth.user_id).await {
+        Ok(user) => user,
+        Err(e) => return Err(fail(format!("Error loading user: {}", e)).await),
+    };
+
+    if form.confirm_username.trim() != user.username {
+        return Err(fail("Type your username to confirm deleting your account".to_string()).await);
+    }
+
+    if user.password_hash != NO_PASSWORD_HASH {
+        let password = form.password.as_deref().unwrap_or_default();
+        if !bcrypt::verify(password, &user.password_hash).unwrap_or(false) {
+            return Err(fail("Password is incorrect".to_string()).await);
+        }
+    }
+
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    if let Ok(Some(credential)) = two_factor_repo.find(user.id).await {
+        let code = form.code.as_deref().unwrap_or_default();
+        if !verify_second_factor(&two_factor_repo, &credential, code).await.unwrap_or(false) {
+            return Err(fail("Authentication code is incorrect".to_string()).await);
+        }
+    }
+
+    if user.role == Role::Admin && user_repo.count_by_role(Role::Admin).await.unwrap_or(0) <= 1 {
+        return Err(fail("Make another user an admin before deleting the last admin account".to_string()).await);
+    }
+
+    let market_repo = MarketRepository::new(db.pool().clone());
+    let awaiting = market_repo.count_awaiting_oracle(user.id).await.unwrap_or(0);
+    if awaiting > 0 {
+        return Err(fail(format!(
+            "You are the oracle of {} unresolved market{}; resolve {} before deleting your account",
+            awaiting,
+            if awaiting == 1 { "" } else { "s" },
+            if awaiting == 1 { "it" } else { "them" }
+        ))
+        .await);
+    }
+
+    let position_repo = PositionRepository::new(db.pool().clone());
+    let positions = match position_repo.find_by_user(user.id).await {
+        Ok(positions) => positions,
+        Err(e) => return Err(fail(format!("Error loading positions: {}", e)).await),
+    };
+    let mut held = Vec::with_capacity(positions.len());
+    for position in positions {
+        match market_repo.find_by_id(position.market_id).await {
+            Ok(market) => held.push((position, market)),
+            Err(e) => return Err(fail(format!("Error loading market {}: {}", position.market_id, e)).await),
+        }
+    }
+
+    // A payout after deletion would credit a balance that was already forfeited
+    let settling = held
+        .iter()
+        .filter(|(position, market)| {
+            position.shares > 0.0 && !market.resolved && !market.can_trade() && market.season_id.is_none()
+        })
+        .count();
+    if settling > 0 {
+        return Err(fail(format!(
+            "You hold positions in {} closed market{} awaiting resolution; delete your account once {} resolved",
+            settling,
+            if settling == 1 { "" } else { "s" },
+            if settling == 1 { "it is" } else { "they are" }
+        ))
+        .await);
+    }
+
+    // Positions in season markets stay in the competition's standings
+    for (position, market) in held {
+        if !market.can_trade() || market.season_id.is_some() {
+            continue;
+        }
+        if let Err(e) = sell_back(&db, user.id, position.market_id, position.side, position.shares).await {
+            return Err(fail(format!("Error selling your position in market {}: {}", position.market_id, e)).await);
+        }
+    }
+
+    if let Err(e) = user_repo.anonymize(user.id).await {
+        return Err(fail(format!("Error deleting account: {}", e)).await);
+    }
+
+    let _ = clear_user_session(&session).await;
+    tracing::info!("User {} deleted their account", user.id);
+    Ok(Redirect::to("/"))
+}
@@ -0,0 +1,227 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{password_reset_ttl, PasswordResetToken, ReconciliationReport, Role};
+use crate::repository::{PasswordResetRepository, ReconciliationRepository, RepositoryError, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::RequireAdmin;
+use axum::{
+    extract::{State, Path},
+    response::{Html, Redirect},
+    Form,
+};
+use askama::Template;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Template)]
+#[template(path = "admin_users.html")]
+struct AdminUsersTemplate {
+    csrf_token: CsrfToken,
+    users: Vec<UserDisplay>,
+    roles: Vec<String>,
+    current_user_id: i64,
+    reset_link: Option<ResetLink>,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+struct UserDisplay {
+    id: i64,
+    username: String,
+    balance: f64,
+    role: String,
+    created_at: String,
+    deleted: bool,
+}
+
+/// A freshly issued reset link, shown once to the admin to hand over to the user
+struct ResetLink {
+    username: String,
+    url: String,
+    expires_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_check.html")]
+struct AdminCheckTemplate {
+    csrf_token: CsrfToken,
+    report: ReconciliationReport,
+    discrepancies: Vec<DiscrepancyDisplay>,
+    repairable: usize,
+    repaired: Option<usize>,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+struct DiscrepancyDisplay {
+    description: String,
+    repairable: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRoleForm {
+    role: String,
+}
+
+pub async fn users_page(
+    auth: RequireAdmin,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+) -> Html<String> {
+    render_users(&db, auth.user_id, &csrf, None, None).await
+}
+
+pub async fn update_role(
+    auth: RequireAdmin,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+    Form(form): Form<UpdateRoleForm>,
+) -> Result<Redirect, Html<String>> {
+    let role: Role = match form.role.parse() {
+        Ok(role) => role,
+        Err(e) => return Err(render_users(&db, auth.user_id, &csrf, None, Some(e)).await),
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    if user_repo.find_by_id(id).await.is_ok_and(|user| user.is_deleted()) {
+        return Err(render_users(&db, auth.user_id, &csrf, None, Some("Deleted accounts cannot be given a role".to_string())).await);
+    }
+    match user_repo.update_role(id, role).await {
+        Ok(()) => {
+            tracing::info!("User {} set role of user {} to {}", auth.user_id, id, role);
+            Ok(Redirect::to("/admin/users"))
+        }
+        Err(RepositoryError::ConstraintViolation(e)) => {
+            Err(render_users(&db, auth.user_id, &csrf, None, Some(e)).await)
+        }
+        Err(e) => Err(render_users(&db, auth.user_id, &csrf, None, Some(format!("Error updating role: {}", e))).await),
+    }
+}
+
+/// Issue a single-use password reset link for a user
+pub async fn issue_reset_token(
+    auth: RequireAdmin,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i64>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let user = match user_repo.find_by_id(id).await {
+        Ok(user) if !user.is_deleted() => user,
+        _ => return render_users(&db, auth.user_id, &csrf, None, Some("User not found".to_string())).await,
+    };
+
+    let (token, token_hash) = PasswordResetToken::generate();
+    let expires_at = chrono::Utc::now() + password_reset_ttl();
+    let reset_repo = PasswordResetRepository::new(db.pool().clone());
+    if let Err(e) = reset_repo.issue(user.id, Some(auth.user_id), &token_hash, expires_at).await {
+        return render_users(&db, auth.user_id, &csrf, None, Some(format!("Error issuing reset token: {}", e))).await;
+    }
+
+    tracing::info!("User {} issued a password reset token for user {}", auth.user_id, user.id);
+
+    let reset_link = ResetLink {
+        username: user.username,
+        url: PasswordResetToken::url(&config.public_url, &token),
+        expires_at: expires_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+    };
+    render_users(&db, auth.user_id, &csrf, Some(reset_link), None).await
+}
+
+async fn render_users(
+    db: &Database,
+    user_id: i64,
+    csrf: &CsrfToken,
+    reset_link: Option<ResetLink>,
+    error: Option<String>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+
+    let users = user_repo.list_all().await.unwrap_or_default();
+    let username = users.iter().find(|u| u.id == user_id).map(|u| u.username.clone());
+
+    let template = AdminUsersTemplate {
+        csrf_token: csrf.clone(),
+        users: users
+            .into_iter()
+            .map(|u| UserDisplay {
+                deleted: u.is_deleted(),
+                id: u.id,
+                username: u.username,
+                balance: u.balance,
+                role: u.role.to_string(),
+                created_at: u.created_at.format("%Y-%m-%d").to_string(),
+            })
+            .collect(),
+        roles: Role::ALL.iter().map(|r| r.to_string()).collect(),
+        current_user_id: user_id,
+        reset_link,
+        error,
+        username,
+    };
+    Html(template.render().unwrap())
+}
+
+/// Check that markets, positions and balances agree
+pub async fn check_page(
+    auth: RequireAdmin,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+) -> Html<String> {
+    render_check(&db, auth.user_id, &csrf, None, None).await
+}
+
+/// Fix the discrepancies that can be rebuilt from other records
+pub async fn repair(
+    auth: RequireAdmin,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+) -> Html<String> {
+    let reconcile_repo = ReconciliationRepository::new(db.pool().clone());
+    let repaired = match reconcile_repo.check().await {
+        Ok(report) => reconcile_repo.repair(&report).await,
+        Err(e) => Err(e),
+    };
+
+    match repaired {
+        Ok(repaired) => {
+            tracing::info!("User {} repaired {} discrepancies", auth.user_id, repaired);
+            render_check(&db, auth.user_id, &csrf, Some(repaired), None).await
+        }
+        Err(e) => render_check(&db, auth.user_id, &csrf, None, Some(format!("Error repairing: {}", e))).await,
+    }
+}
+
+async fn render_check(
+    db: &Database,
+    user_id: i64,
+    csrf: &CsrfToken,
+    repaired: Option<usize>,
+    mut error: Option<String>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let reconcile_repo = ReconciliationRepository::new(db.pool().clone());
+
+    let report = reconcile_repo.check().await.unwrap_or_else(|e| {
+        error.get_or_insert_with(|| format!("Error checking: {}", e));
+        ReconciliationReport::default()
+    });
+
+    let template = AdminCheckTemplate {
+        csrf_token: csrf.clone(),
+        discrepancies: report
+            .discrepancies
+            .iter()
+            .map(|d| DiscrepancyDisplay { description: d.to_string(), repairable: d.is_repairable() })
+            .collect(),
+        repairable: report.repairable().count(),
+        report,
+        repaired,
+        error,
+        username: user_repo.find_by_id(user_id).await.ok().map(|u| u.username),
+    };
+    Html(template.render().unwrap())
+}
@@ -1,13 +1,35 @@
 use crate::Database;
-use crate::repository::{PriceSnapshotRepository, MarketRepository};
-use crate::domain::{LmsrPricing, MarketSide};
+use crate::repository::{LedgerRepository, PriceSnapshotRepository, MarketRepository, PositionRepository, UserRepository};
+use crate::domain::{
+    CandleInterval, LedgerAccount, LmsrPricing, MarketSide, MarketStatus, TradeAction, TradeQuote, TradeSize,
+};
+use crate::web::handlers::ListParams;
+use crate::web::session::OptionalAuth;
 use axum::{
     extract::{State, Path, Query},
     http::StatusCode,
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of raw snapshots returned when no interval is requested
+const MAX_HISTORY_POINTS: i64 = 1000;
+
+/// Maximum number of candles a single request may produce
+const MAX_CANDLES: i64 = 2000;
+
+/// Target number of candles when `interval=auto`
+const AUTO_CANDLES: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct PriceHistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Candle width (`1m`, `5m`, `15m`, `1h`, `4h`, `1d`, `1w` or `auto`)
+    pub interval: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PriceHistoryPoint {
     pub timestamp: String,
@@ -15,34 +37,109 @@ pub struct PriceHistoryPoint {
     pub no_probability: f64,
 }
 
+#[derive(Debug, Serialize)]
+pub struct CandlePoint {
+    pub timestamp: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub trades: i64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PriceHistoryResponse {
     pub market_id: i64,
+    pub from: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
     pub data: Vec<PriceHistoryPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candles: Option<Vec<CandlePoint>>,
 }
 
 /// Get price history for a market
+///
+/// Without `interval` this returns raw snapshots in `[from, to)` (capped to the most
+/// recent ones); with `interval` it returns OHLC candles of YES probability instead.
+/// `from` defaults to the market creation time and `to` to now.
 pub async fn get_price_history(
     State(db): State<Database>,
     Path(market_id): Path<i64>,
+    Query(params): Query<PriceHistoryQuery>,
 ) -> Result<Json<PriceHistoryResponse>, StatusCode> {
+    let market_repo = MarketRepository::new(db.pool().clone());
     let snapshot_repo = PriceSnapshotRepository::new(db.pool().clone());
 
-    let snapshots = snapshot_repo
-        .get_history(market_id)
+    let market = market_repo
+        .find_by_id(market_id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let to = params.to.unwrap_or_else(Utc::now);
+    let from = params.from.unwrap_or(market.created_at).min(to);
+
+    let interval = match params.interval.as_deref() {
+        None => None,
+        Some("auto") => Some(CandleInterval::auto_for_range(from, to, AUTO_CANDLES)),
+        Some(s) => Some(s.parse::<CandleInterval>().map_err(|_| StatusCode::BAD_REQUEST)?),
+    };
+
+    let Some(interval) = interval else {
+        let snapshots = snapshot_repo
+            .get_history_range(market_id, from, to, MAX_HISTORY_POINTS)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let data: Vec<PriceHistoryPoint> = snapshots
+            .into_iter()
+            .map(|s| PriceHistoryPoint {
+                timestamp: s.created_at.to_rfc3339(),
+                yes_probability: s.yes_probability,
+                no_probability: s.no_probability,
+            })
+            .collect();
+
+        return Ok(Json(PriceHistoryResponse {
+            market_id,
+            from: from.to_rfc3339(),
+            to: to.to_rfc3339(),
+            interval: None,
+            data,
+            candles: None,
+        }));
+    };
 
-    let data: Vec<PriceHistoryPoint> = snapshots
+    if (to - from).num_seconds() / interval.seconds() > MAX_CANDLES {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let candles = snapshot_repo
+        .get_candles(market_id, from, to, interval)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .into_iter()
-        .map(|s| PriceHistoryPoint {
-            timestamp: s.created_at.to_rfc3339(),
-            yes_probability: s.yes_probability,
-            no_probability: s.no_probability,
+        .map(|c| CandlePoint {
+            timestamp: c.bucket_start.to_rfc3339(),
+            open: c.open,
+            high: c.high,
+            low: c.low,
+            close: c.close,
+            volume: c.volume,
+            trades: c.trades,
         })
         .collect();
 
-    Ok(Json(PriceHistoryResponse { market_id, data }))
+    Ok(Json(PriceHistoryResponse {
+        market_id,
+        from: from.to_rfc3339(),
+        to: to.to_rfc3339(),
+        interval: Some(interval.to_string()),
+        data: Vec::new(),
+        candles: Some(candles),
+    }))
 }
 
 #[derive(Debug, Deserialize)]
@@ -95,3 +192,290 @@ pub async fn calculate_buy_cost(
         avg_price,
     }))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteQuery {
+    pub action: String,
+    pub side: String,
+    pub shares: Option<f64>,
+    pub amount: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserQuote {
+    pub balance_before: f64,
+    pub balance_after: f64,
+    pub position_shares_before: f64,
+    pub position_shares_after: f64,
+    pub position_avg_price_after: f64,
+    pub can_execute: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteResponse {
+    pub market_id: i64,
+    pub action: TradeAction,
+    pub side: MarketSide,
+    pub shares: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proceeds: Option<f64>,
+    pub avg_price: f64,
+    pub price_before: f64,
+    pub price_after: f64,
+    pub probability_before: f64,
+    pub probability_after: f64,
+    pub price_impact_pct: f64,
+    pub potential_payout: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub potential_profit: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<UserQuote>,
+}
+
+/// Quote any trade (buy or sell, sized by shares or by amount)
+///
+/// When the request is authenticated the response also includes the user's
+/// resulting balance and position, and whether they can execute the trade.
+pub async fn quote_trade(
+    auth: OptionalAuth,
+    State(db): State<Database>,
+    Path(market_id): Path<i64>,
+    Query(params): Query<QuoteQuery>,
+) -> Result<Json<QuoteResponse>, (StatusCode, String)> {
+    let market_repo = MarketRepository::new(db.pool().clone());
+
+    let market = market_repo
+        .find_by_id(market_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Market not found".to_string()))?;
+
+    let action: TradeAction = params.action.parse()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let side: MarketSide = params.side.parse()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let size = match (params.shares, params.amount) {
+        (Some(shares), None) => TradeSize::Shares(shares),
+        (None, Some(amount)) => TradeSize::Amount(amount),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Specify exactly one of shares or amount".to_string(),
+            ))
+        }
+    };
+
+    let (q_yes, q_no) = market.pricing_state();
+    let quote = TradeQuote::new(q_yes, q_no, market.liquidity_param, action, side, size)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let user = match auth.user_id {
+        Some(user_id) => {
+            let user_repo = UserRepository::new(db.pool().clone());
+            let position_repo = PositionRepository::new(db.pool().clone());
+
+            let user = user_repo
+                .find_by_id(user_id)
+                .await
+                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "User not found".to_string()))?;
+            // Season markets are traded with the season balance
+            let balance = match market.season_id {
+                Some(season_id) => LedgerRepository::new(db.pool().clone())
+                    .balance(LedgerAccount::Season(season_id, user_id))
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+                None => user.balance,
+            };
+            let position = position_repo
+                .find_by_user_market_side(user_id, market_id, side)
+                .await
+                .ok();
+            let (shares_before, avg_before) = position
+                .map(|p| (p.shares, p.avg_price))
+                .unwrap_or((0.0, 0.0));
+
+            let reason = if !market.can_trade() {
+                Some("Market is not open for trading".to_string())
+            } else {
+                match action {
+                    TradeAction::Buy if balance < quote.amount => {
+                        Some("Insufficient balance".to_string())
+                    }
+                    TradeAction::Sell if shares_before < quote.shares => {
+                        Some("Insufficient shares to sell".to_string())
+                    }
+                    _ => None,
+                }
+            };
+
+            let (balance_after, shares_after, avg_after) = match action {
+                TradeAction::Buy => {
+                    let shares_after = shares_before + quote.shares;
+                    let avg_after = (shares_before * avg_before + quote.amount) / shares_after;
+                    (balance - quote.amount, shares_after, avg_after)
+                }
+                TradeAction::Sell => {
+                    (balance + quote.amount, shares_before - quote.shares, avg_before)
+                }
+            };
+
+            Some(UserQuote {
+                balance_before: balance,
+                balance_after,
+                position_shares_before: shares_before,
+                position_shares_after: shares_after,
+                position_avg_price_after: avg_after,
+                can_execute: reason.is_none(),
+                reason,
+            })
+        }
+        None => None,
+    };
+
+    let (cost, proceeds, potential_profit) = match action {
+        TradeAction::Buy => (Some(quote.amount), None, Some(quote.shares - quote.amount)),
+        TradeAction::Sell => (None, Some(quote.amount), None),
+    };
+
+    Ok(Json(QuoteResponse {
+        market_id,
+        action,
+        side,
+        shares: quote.shares,
+        cost,
+        proceeds,
+        avg_price: quote.avg_price,
+        price_before: quote.price_before,
+        price_after: quote.price_after,
+        probability_before: quote.probability_before,
+        probability_after: quote.probability_after,
+        price_impact_pct: quote.price_impact_pct,
+        potential_payout: quote.shares,
+        potential_profit,
+        user,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarketSummary {
+    pub id: i64,
+    pub question: String,
+    pub status: MarketStatus,
+    pub end_date: String,
+    pub yes_probability: f64,
+    pub volume: f64,
+    pub outcome: Option<bool>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarketListResponse {
+    pub markets: Vec<MarketSummary>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+/// List markets with `status`, `sort`, `cursor` and `limit` query parameters
+pub async fn list_markets(
+    State(db): State<Database>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<MarketListResponse>, (StatusCode, String)> {
+    let query = params.to_query().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let market_repo = MarketRepository::new(db.pool().clone());
+
+    let page = market_repo
+        .list_page(&query)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let total = market_repo
+        .count(query.status)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let markets = page
+        .items
+        .into_iter()
+        .map(|m| MarketSummary {
+            id: m.id,
+            status: m.status(),
+            end_date: m.end_date.to_rfc3339(),
+            yes_probability: LmsrPricing::implied_probability(m.q_yes, m.q_no, m.liquidity_param),
+            volume: m.volume,
+            outcome: m.outcome,
+            created_at: m.created_at.to_rfc3339(),
+            question: m.question,
+        })
+        .collect();
+
+    Ok(Json(MarketListResponse {
+        markets,
+        total,
+        next_cursor: page.next_cursor.map(|c| c.encode()),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PositionSummary {
+    pub market_id: i64,
+    pub market_question: String,
+    pub market_status: MarketStatus,
+    pub side: MarketSide,
+    pub shares: f64,
+    pub avg_price: f64,
+    pub current_price: f64,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PositionListResponse {
+    pub positions: Vec<PositionSummary>,
+    pub next_cursor: Option<String>,
+}
+
+/// List the current user's open positions with the same parameters as `list_markets`
+pub async fn list_positions(
+    auth: OptionalAuth,
+    State(db): State<Database>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<PositionListResponse>, (StatusCode, String)> {
+    let user_id = auth
+        .user_id
+        .ok_or((StatusCode::UNAUTHORIZED, "Not logged in".to_string()))?;
+    let query = params.to_query().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let position_repo = PositionRepository::new(db.pool().clone());
+
+    let page = position_repo
+        .find_by_user_page(user_id, &query)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let positions = page
+        .items
+        .into_iter()
+        .map(|(position, market)| PositionSummary {
+            market_id: market.id,
+            market_status: market.status(),
+            current_price: LmsrPricing::instantaneous_price(
+                market.q_yes,
+                market.q_no,
+                position.side,
+                market.liquidity_param,
+            ),
+            market_question: market.question,
+            side: position.side,
+            shares: position.shares,
+            avg_price: position.avg_price,
+            updated_at: position.updated_at.to_rfc3339(),
+        })
+        .collect();
+
+    Ok(Json(PositionListResponse {
+        positions,
+        next_cursor: page.next_cursor.map(|c| c.encode()),
+    }))
+}
@@ -1,33 +1,94 @@
 use crate::Database;
-use crate::repository::UserRepository;
-use crate::web::session::{set_user_session, clear_user_session};
+use crate::config::Config;
+use crate::domain::{
+    normalize_invite_code, password_reset_email_cooldown, password_reset_ttl, validate_password, LoginScope,
+    LoginThrottlePolicy, PasswordResetToken, RegistrationMode, User, UserId,
+};
+use crate::mail;
+use crate::repository::{
+    LoginAttemptRepository, PasswordResetRepository, RepositoryError, TwoFactorRepository, UserRepository,
+};
+use crate::web::handlers::two_factor::verify_second_factor;
+use crate::web::middleware::{ClientIp, CsrfToken};
+use crate::web::session::{
+    clear_pending_two_factor, clear_user_session, get_pending_two_factor, set_pending_two_factor, set_user_session,
+};
 use axum::{
-    extract::State,
+    extract::{Query, State},
+    http::StatusCode,
     response::{Html, Redirect},
     Form,
 };
 use askama::Template;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use std::sync::Arc;
 use tower_sessions::Session;
 
 #[derive(Template)]
 #[template(path = "signup.html")]
 struct SignupTemplate {
+    csrf_token: CsrfToken,
     error: Option<String>,
+    /// Registration mode: `open`, `invite-only` or `closed`
+    registration: String,
+    invite_code: String,
     username: Option<String>,
 }
 
 #[derive(Template)]
 #[template(path = "login.html")]
 struct LoginTemplate {
+    csrf_token: CsrfToken,
+    error: Option<String>,
+    notice: Option<String>,
+    password_login: bool,
+    sso_login: bool,
+    /// Offer to email a reset link for a forgotten password
+    password_reset_by_email: bool,
+    username: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "login_two_factor.html")]
+struct TwoFactorTemplate {
+    csrf_token: CsrfToken,
     error: Option<String>,
     username: Option<String>,
 }
 
+#[derive(Template)]
+#[template(path = "reset_password.html")]
+struct ResetPasswordTemplate {
+    csrf_token: CsrfToken,
+    token: String,
+    valid: bool,
+    reset_username: String,
+    password_reset_by_email: bool,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "forgot_password.html")]
+struct ForgotPasswordTemplate {
+    csrf_token: CsrfToken,
+    /// Shown once a request is accepted, whether or not a link was sent
+    notice: Option<String>,
+    username: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct SignupForm {
     username: String,
     password: String,
+    invite_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SignupQuery {
+    /// Prefills the invite code, as in links shared from the invites page
+    invite: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -36,120 +97,555 @@ pub struct LoginForm {
     password: String,
 }
 
-pub async fn signup_page() -> Html<String> {
-    let template = SignupTemplate {
-        error: None,
-        username: None,
-    };
-    Html(template.render().unwrap())
+#[derive(Deserialize)]
+pub struct TwoFactorForm {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    reset: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordQuery {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordForm {
+    username: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordForm {
+    token: String,
+    new_password: String,
+    confirm_password: String,
+}
+
+pub async fn signup_page(
+    csrf: CsrfToken,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<SignupQuery>,
+) -> Result<Html<String>, Redirect> {
+    // Accounts come from single sign-on only
+    if !config.password_login {
+        return Err(Redirect::to("/login"));
+    }
+
+    Ok(render_signup(csrf, &config, query.invite.unwrap_or_default(), None))
 }
 
 pub async fn signup(
+    csrf: CsrfToken,
     State(db): State<Database>,
+    State(config): State<Arc<Config>>,
     Form(form): Form<SignupForm>,
 ) -> Result<Redirect, Html<String>> {
+    if !config.password_login {
+        return Ok(Redirect::to("/login"));
+    }
+
+    let invite_code = normalize_invite_code(form.invite_code.as_deref().unwrap_or_default());
+    let fail = |error: &str| render_signup(csrf.clone(), &config, invite_code.clone(), Some(error.to_string()));
+
+    match config.registration {
+        RegistrationMode::Closed => return Err(fail("Registration is closed")),
+        RegistrationMode::InviteOnly if invite_code.is_empty() => {
+            return Err(fail("An invite code is required to sign up"));
+        }
+        _ => {}
+    }
+
     // Validate input
     if form.username.is_empty() || form.password.is_empty() {
-        let template = SignupTemplate {
-            error: Some("Username and password are required".to_string()),
-            username: None,
-        };
-        return Err(Html(template.render().unwrap()));
+        return Err(fail("Username and password are required"));
     }
 
     if form.username.len() < 3 {
-        let template = SignupTemplate {
-            error: Some("Username must be at least 3 characters".to_string()),
-            username: None,
-        };
-        return Err(Html(template.render().unwrap()));
+        return Err(fail("Username must be at least 3 characters"));
     }
 
-    if form.password.len() < 6 {
-        let template = SignupTemplate {
-            error: Some("Password must be at least 6 characters".to_string()),
-            username: None,
-        };
-        return Err(Html(template.render().unwrap()));
+    if let Err(e) = validate_password(&form.password) {
+        return Err(fail(&e));
     }
 
     // Hash password
     let password_hash = bcrypt::hash(&form.password, bcrypt::DEFAULT_COST)
-        .map_err(|_| {
-            let template = SignupTemplate {
-                error: Some("Error processing password".to_string()),
-                username: None,
-            };
-            Html(template.render().unwrap())
-        })?;
-
-    // Create user
+        .map_err(|_| fail("Error processing password"))?;
+
+    // Create user, with the invite's starting balance if one was given
     let user_repo = UserRepository::new(db.pool().clone());
-    match user_repo.create(&form.username, &password_hash).await {
-        Ok(_) => Ok(Redirect::to("/login")),
-        Err(e) => {
-            let template = SignupTemplate {
-                error: Some(format!("Error creating account: {}", e)),
-                username: None,
-            };
-            Err(Html(template.render().unwrap()))
+    let created = if invite_code.is_empty() {
+        user_repo.create_with_balance(&form.username, &password_hash, config.starting_balance).await
+    } else {
+        user_repo.create_with_invite(&form.username, &password_hash, &invite_code).await
+    };
+
+    match created {
+        Ok(user) => {
+            if !invite_code.is_empty() {
+                tracing::info!("User {} signed up with invite code {}", user.id, invite_code);
+            }
+            Ok(Redirect::to("/login"))
         }
+        Err(RepositoryError::NotFound) => Err(fail("Invite code is invalid, used up or expired")),
+        Err(e) => Err(fail(&format!("Error creating account: {}", e))),
     }
 }
 
-pub async fn login_page() -> Html<String> {
-    let template = LoginTemplate {
-        error: None,
+fn render_signup(csrf: CsrfToken, config: &Config, invite_code: String, error: Option<String>) -> Html<String> {
+    let template = SignupTemplate {
+        csrf_token: csrf,
+        error,
+        registration: config.registration.to_string(),
+        invite_code,
         username: None,
     };
     Html(template.render().unwrap())
 }
 
+pub async fn login_page(
+    csrf: CsrfToken,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<LoginQuery>,
+) -> Html<String> {
+    let notice = query.reset.map(|_| "Password updated. Log in with your new password.".to_string());
+    render_login_page(csrf, &config, None, notice)
+}
+
+/// Shown for every failed login, whether the password was wrong or the attempt was throttled
+const LOGIN_FAILED: &str = "Invalid username or password";
+
+/// The second step's equivalent of `LOGIN_FAILED`
+const TWO_FACTOR_FAILED: &str = "Invalid authentication code";
+
+/// Checked in place of a password hash when the username doesn't exist, so the
+/// response takes as long as a wrong password; its cost matches `bcrypt::DEFAULT_COST`
+const DUMMY_PASSWORD_HASH: &str = "$2b$12$BF.Aq5OFAKJuFneVL9nHBOVvZKaTPU29u58NZNf6ArtXRbvmNi8Iq";
+
 pub async fn login(
+    csrf: CsrfToken,
     State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    client_ip: ClientIp,
     session: Session,
     Form(form): Form<LoginForm>,
 ) -> Result<Redirect, Html<String>> {
+    if !config.password_login {
+        return Err(render_login(csrf, &config, Some("Password login is disabled; use single sign-on".to_string())));
+    }
+
     let user_repo = UserRepository::new(db.pool().clone());
+    let attempt_repo = LoginAttemptRepository::new(db.pool().clone());
+    let ip_key = client_ip.to_string();
+    let now = Utc::now();
 
-    // Find user
-    let user = match user_repo.find_by_username(&form.username).await {
-        Ok(user) => user,
-        Err(_) => {
-            let template = LoginTemplate {
-                error: Some("Invalid username or password".to_string()),
-                username: None,
-            };
-            return Err(Html(template.render().unwrap()));
+    // Refuse throttled attempts without saying so, so that an attacker can't tell
+    // a locked account from a wrong password. The password is still checked below
+    // to keep response times alike, and the attempt counts against the client IP.
+    let blocked = is_throttled(&attempt_repo, &config, &form.username, &ip_key, now).await;
+
+    let user = user_repo.find_by_username(&form.username).await.ok();
+    let password_hash = user.as_ref().map_or(DUMMY_PASSWORD_HASH, |user| user.password_hash.as_str());
+    let password_valid = bcrypt::verify(&form.password, password_hash).unwrap_or(false) && user.is_some();
+
+    if blocked {
+        tracing::warn!("Throttled login attempt for '{}' from {}", form.username, client_ip);
+        record_throttled_attempt(&attempt_repo, &config, &ip_key, now).await;
+        return Err(render_login(csrf, &config, Some(LOGIN_FAILED.to_string())));
+    }
+
+    let user = match user {
+        Some(user) if password_valid => user,
+        _ => {
+            record_login_failure(&attempt_repo, &config, &form.username, &ip_key, now).await;
+            return Err(render_login(csrf, &config, Some(LOGIN_FAILED.to_string())));
         }
     };
 
-    // Verify password
-    let password_valid = bcrypt::verify(&form.password, &user.password_hash)
-        .unwrap_or(false);
+    // Failures keep counting until a second factor, if any, has been accepted too
+    start_session(&db, &config, &session, &user)
+        .await
+        .map_err(|_| render_login(csrf.clone(), &config, Some("Error creating session".to_string())))
+}
 
-    if !password_valid {
-        let template = LoginTemplate {
-            error: Some("Invalid username or password".to_string()),
-            username: None,
-        };
-        return Err(Html(template.render().unwrap()));
+/// Continue a login whose first factor (password or single sign-on) was accepted
+///
+/// With two-factor authentication on, the session only logs in after the second
+/// step. Users whose role requires it but who haven't enrolled are sent to
+/// enrollment, the only way to reach the pages the role is for.
+pub(crate) async fn start_session(
+    db: &Database,
+    config: &Config,
+    session: &Session,
+    user: &User,
+) -> Result<Redirect, tower_sessions::session::Error> {
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    if two_factor_repo.is_enabled(user.id).await.unwrap_or(false) {
+        set_pending_two_factor(session, user.id, &user.username, user.session_version).await?;
+        return Ok(Redirect::to("/login/2fa"));
     }
 
-    // Create session
-    set_user_session(&session, user.id).await.map_err(|_| {
-        let template = LoginTemplate {
-            error: Some("Error creating session".to_string()),
-            username: None,
-        };
-        Html(template.render().unwrap())
-    })?;
+    let attempt_repo = LoginAttemptRepository::new(db.pool().clone());
+    finish_login(session, &attempt_repo, user.id, &user.username, user.session_version).await?;
+
+    if config.two_factor.requires_for_role(user.role) {
+        Ok(Redirect::to("/settings/2fa"))
+    } else {
+        Ok(Redirect::to("/markets"))
+    }
+}
+
+pub async fn two_factor_page(csrf: CsrfToken, session: Session) -> Result<Html<String>, Redirect> {
+    if get_pending_two_factor(&session).await.is_none() {
+        return Err(Redirect::to("/login"));
+    }
+    Ok(render_two_factor(csrf, None))
+}
+
+/// Second login step: check a TOTP or recovery code for the user whose password was accepted
+pub async fn verify_two_factor(
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    client_ip: ClientIp,
+    session: Session,
+    Form(form): Form<TwoFactorForm>,
+) -> Result<Redirect, Html<String>> {
+    let Some(pending) = get_pending_two_factor(&session).await else {
+        return Ok(Redirect::to("/login"));
+    };
+
+    let attempt_repo = LoginAttemptRepository::new(db.pool().clone());
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    let ip_key = client_ip.to_string();
+    let now = Utc::now();
+
+    if is_throttled(&attempt_repo, &config, &pending.username, &ip_key, now).await {
+        tracing::warn!("Throttled two-factor attempt for '{}' from {}", pending.username, client_ip);
+        record_throttled_attempt(&attempt_repo, &config, &ip_key, now).await;
+        return Err(render_two_factor(csrf, Some(TWO_FACTOR_FAILED.to_string())));
+    }
+
+    let verified = match two_factor_repo.find(pending.user_id).await {
+        Ok(Some(credential)) => verify_second_factor(&two_factor_repo, &credential, &form.code)
+            .await
+            .unwrap_or(false),
+        // Disabled since the password step
+        Ok(None) => true,
+        Err(e) => {
+            tracing::error!("Failed to load two-factor credential of user {}: {}", pending.user_id, e);
+            false
+        }
+    };
+
+    if !verified {
+        record_login_failure(&attempt_repo, &config, &pending.username, &ip_key, now).await;
+        return Err(render_two_factor(csrf, Some(TWO_FACTOR_FAILED.to_string())));
+    }
+
+    let _ = clear_pending_two_factor(&session).await;
+    finish_login(&session, &attempt_repo, pending.user_id, &pending.username, pending.session_version)
+        .await
+        .map_err(|_| render_two_factor(csrf.clone(), Some("Error creating session".to_string())))?;
 
     Ok(Redirect::to("/markets"))
 }
 
+/// Log the session in once every factor has been checked
+///
+/// Only the username's failure counter is reset: succeeding on one account says
+/// nothing about what the same client is doing to others.
+async fn finish_login(
+    session: &Session,
+    attempt_repo: &LoginAttemptRepository,
+    user_id: UserId,
+    username: &str,
+    session_version: i64,
+) -> Result<(), tower_sessions::session::Error> {
+    if let Err(e) = attempt_repo.clear(LoginScope::Username, username).await {
+        tracing::error!("Failed to clear login failures for '{}': {}", username, e);
+    }
+
+    set_user_session(session, user_id, session_version).await?;
+    session.cycle_id().await
+}
+
+/// Whether either the username or the client IP must wait before trying again
+async fn is_throttled(
+    repo: &LoginAttemptRepository,
+    config: &Config,
+    username: &str,
+    ip: &str,
+    now: DateTime<Utc>,
+) -> bool {
+    let checks = [
+        (LoginScope::Username, username, &config.login_throttle_per_user),
+        (LoginScope::Ip, ip, &config.login_throttle_per_ip),
+    ];
+    for (scope, key, policy) in checks {
+        match repo.find(scope, key).await {
+            Ok(Some(attempts)) if attempts.blocked_until(policy, now).is_some() => return true,
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed to load login failures for {} '{}': {}", scope, key, e),
+        }
+    }
+    false
+}
+
+/// Count a failed login against both the username and the client IP
+///
+/// Unknown usernames are tracked like real ones so that lockouts don't reveal
+/// which accounts exist.
+async fn record_login_failure(
+    repo: &LoginAttemptRepository,
+    config: &Config,
+    username: &str,
+    ip: &str,
+    now: DateTime<Utc>,
+) {
+    purge_stale_failures(repo, config, now).await;
+    record_scope_failure(repo, LoginScope::Username, username, &config.login_throttle_per_user, now).await;
+    record_scope_failure(repo, LoginScope::Ip, ip, &config.login_throttle_per_ip, now).await;
+}
+
+/// Count a throttled login against the client IP only
+///
+/// Counting it against the username too would let anyone keep an account
+/// locked out by guessing at it.
+async fn record_throttled_attempt(repo: &LoginAttemptRepository, config: &Config, ip: &str, now: DateTime<Utc>) {
+    purge_stale_failures(repo, config, now).await;
+    record_scope_failure(repo, LoginScope::Ip, ip, &config.login_throttle_per_ip, now).await;
+}
+
+async fn purge_stale_failures(repo: &LoginAttemptRepository, config: &Config, now: DateTime<Utc>) {
+    let window = config.login_throttle_per_user.lockout.max(config.login_throttle_per_ip.lockout);
+    if let Err(e) = repo.purge_stale(now - window).await {
+        tracing::error!("Failed to purge stale login failures: {}", e);
+    }
+}
+
+async fn record_scope_failure(
+    repo: &LoginAttemptRepository,
+    scope: LoginScope,
+    key: &str,
+    policy: &LoginThrottlePolicy,
+    now: DateTime<Utc>,
+) {
+    let attempts = match repo.record_failure(scope, key, policy, now).await {
+        Ok(attempts) => attempts,
+        Err(e) => {
+            tracing::error!("Failed to record login failure for {} '{}': {}", scope, key, e);
+            return;
+        }
+    };
+    if attempts.is_locked(now) {
+        tracing::warn!(
+            "Locking out {} '{}' until {} after {} failed logins",
+            scope, key, attempts.locked_until.unwrap_or(now), attempts.failures
+        );
+    } else {
+        tracing::warn!("Failed login for {} '{}' ({} in a row)", scope, key, attempts.failures);
+    }
+}
+
+fn render_two_factor(csrf: CsrfToken, error: Option<String>) -> Html<String> {
+    let template = TwoFactorTemplate {
+        csrf_token: csrf,
+        error,
+        username: None,
+    };
+    Html(template.render().unwrap())
+}
+
+pub(crate) fn render_login(csrf: CsrfToken, config: &Config, error: Option<String>) -> Html<String> {
+    render_login_page(csrf, config, error, None)
+}
+
+fn render_login_page(csrf: CsrfToken, config: &Config, error: Option<String>, notice: Option<String>) -> Html<String> {
+    let template = LoginTemplate {
+        csrf_token: csrf,
+        error,
+        notice,
+        password_login: config.password_login,
+        sso_login: config.oidc.is_some(),
+        password_reset_by_email: password_reset_by_email(config),
+        username: None,
+    };
+    Html(template.render().unwrap())
+}
+
 pub async fn logout(session: Session) -> Redirect {
     // Clear session
     let _ = clear_user_session(&session).await;
     Redirect::to("/")
 }
+
+/// Whether users can have a reset link emailed to them
+fn password_reset_by_email(config: &Config) -> bool {
+    config.mail.is_some() && config.password_login
+}
+
+/// Ask for a reset link to be emailed to the account's address
+pub async fn forgot_password_page(
+    csrf: CsrfToken,
+    State(config): State<Arc<Config>>,
+) -> Result<Html<String>, StatusCode> {
+    if !password_reset_by_email(&config) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(render_forgot_password(csrf, None))
+}
+
+/// Email a single-use reset link to the account's address, if it has one
+///
+/// The response is the same whether or not the account exists or has an
+/// address, and the mail is sent in the background, so the form cannot be
+/// used to find out either.
+pub async fn forgot_password(
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<ForgotPasswordForm>,
+) -> Result<Html<String>, StatusCode> {
+    if !password_reset_by_email(&config) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    if let Ok(user) = user_repo.find_by_username(form.username.trim()).await {
+        tokio::spawn(async move {
+            if let Err(e) = email_reset_link(&db, &config, &user).await {
+                tracing::error!("Failed to email a password reset link to user {}: {}", user.id, e);
+            }
+        });
+    }
+
+    let notice = "If that account has an email address, a reset link is on its way. \
+                  It can be used once within 24 hours.";
+    Ok(render_forgot_password(csrf, Some(notice.to_string())))
+}
+
+/// Issue a reset token for `user` and mail its link, at most once per cooldown
+async fn email_reset_link(db: &Database, config: &Config, user: &User) -> Result<(), String> {
+    let (Some(mail_config), Some(email)) = (config.mail.as_ref(), user.email.as_deref()) else {
+        return Ok(());
+    };
+    if user.is_deleted() {
+        return Ok(());
+    }
+
+    let reset_repo = PasswordResetRepository::new(db.pool().clone());
+    let now = Utc::now();
+    if reset_repo
+        .self_issued_since(user.id, now - password_reset_email_cooldown())
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        tracing::warn!("Not emailing user {} another reset link so soon after the last", user.id);
+        return Ok(());
+    }
+
+    let (token, token_hash) = PasswordResetToken::generate();
+    let expires_at = now + password_reset_ttl();
+    reset_repo.issue(user.id, None, &token_hash, expires_at).await.map_err(|e| e.to_string())?;
+
+    let body = format!(
+        "A password reset was requested for your account {}.\n\n\
+         Set a new password here:\n{}\n\n\
+         The link can be used once and expires at {}. Resetting logs out all your sessions.\n\
+         If you did not ask for this, ignore this email and your password stays the same.",
+        user.username,
+        PasswordResetToken::url(&config.public_url, &token),
+        expires_at.format("%Y-%m-%d %H:%M UTC"),
+    );
+    mail::send(mail_config, email, "Reset your password", &body).await.map_err(|e| e.to_string())?;
+
+    tracing::info!("Emailed a password reset link to user {}", user.id);
+    Ok(())
+}
+
+fn render_forgot_password(csrf: CsrfToken, notice: Option<String>) -> Html<String> {
+    let template = ForgotPasswordTemplate {
+        csrf_token: csrf,
+        notice,
+        username: None,
+    };
+    Html(template.render().unwrap())
+}
+
+pub async fn reset_password_page(
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<ResetPasswordQuery>,
+) -> Html<String> {
+    render_reset(&db, &config, csrf, query.token, None).await
+}
+
+/// Set a new password with a single-use reset token, logging out every session
+pub async fn reset_password(
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<ResetPasswordForm>,
+) -> Result<Redirect, Html<String>> {
+    if form.new_password != form.confirm_password {
+        return Err(render_reset(&db, &config, csrf, form.token, Some("Passwords do not match".to_string())).await);
+    }
+
+    if let Err(e) = validate_password(&form.new_password) {
+        return Err(render_reset(&db, &config, csrf, form.token, Some(e)).await);
+    }
+
+    let password_hash = match bcrypt::hash(&form.new_password, bcrypt::DEFAULT_COST) {
+        Ok(hash) => hash,
+        Err(_) => {
+            let error = Some("Error processing password".to_string());
+            return Err(render_reset(&db, &config, csrf, form.token, error).await);
+        }
+    };
+
+    let reset_repo = PasswordResetRepository::new(db.pool().clone());
+    let user_id = match reset_repo.consume(&PasswordResetToken::hash(&form.token)).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(render_reset(&db, &config, csrf, form.token, None).await),
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    if let Err(e) = user_repo.update_password(user_id, &password_hash).await {
+        return Err(render_reset(&db, &config, csrf, form.token, Some(format!("Error updating password: {}", e))).await);
+    }
+
+    tracing::info!("User {} reset their password with a reset token", user_id);
+    Ok(Redirect::to("/login?reset=1"))
+}
+
+async fn render_reset(
+    db: &Database,
+    config: &Config,
+    csrf: CsrfToken,
+    token: String,
+    error: Option<String>,
+) -> Html<String> {
+    let reset_repo = PasswordResetRepository::new(db.pool().clone());
+    let user_repo = UserRepository::new(db.pool().clone());
+
+    let reset_user = match reset_repo.find(&PasswordResetToken::hash(&token)).await {
+        Ok(reset) if reset.is_usable(chrono::Utc::now()) => user_repo.find_by_id(reset.user_id).await.ok(),
+        _ => None,
+    };
+
+    let template = ResetPasswordTemplate {
+        csrf_token: csrf,
+        token,
+        valid: reset_user.is_some(),
+        reset_username: reset_user.map(|u| u.username).unwrap_or_default(),
+        password_reset_by_email: password_reset_by_email(config),
+        error,
+        username: None,
+    };
+    Html(template.render().unwrap())
+}
@@ -0,0 +1,213 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{
+    generate_invite_code, InviteTerms, RegistrationMode, Role, MAX_USER_INVITE_DAYS, MAX_USER_INVITE_USES,
+};
+use crate::repository::{InviteRepository, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::RequireAuth;
+use axum::{
+    extract::{State, Path},
+    response::{Html, Redirect},
+    Form,
+};
+use askama::Template;
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Template)]
+#[template(path = "invites.html")]
+struct InvitesTemplate {
+    csrf_token: CsrfToken,
+    invites: Vec<InviteDisplay>,
+    /// Whether this user may create invites at all
+    can_create: bool,
+    is_admin: bool,
+    registration: String,
+    default_starting_balance: f64,
+    max_user_uses: i64,
+    max_user_days: i64,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+struct InviteDisplay {
+    id: i64,
+    code: String,
+    signup_url: String,
+    created_by: String,
+    starting_balance: f64,
+    uses: i64,
+    max_uses: i64,
+    expires_at: Option<String>,
+    status: String,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateInviteForm {
+    max_uses: String,
+    /// Empty for no expiry (admins only)
+    valid_days: Option<String>,
+    /// Empty for the configured default (admins only)
+    starting_balance: Option<String>,
+}
+
+impl CreateInviteForm {
+    fn terms(&self) -> Result<InviteTerms, String> {
+        let optional = |value: &Option<String>| {
+            value.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+        };
+        Ok(InviteTerms {
+            max_uses: self.max_uses.trim().parse().map_err(|_| "Invalid number of uses".to_string())?,
+            valid_days: optional(&self.valid_days)
+                .map(|s| s.parse().map_err(|_| "Invalid number of days".to_string()))
+                .transpose()?,
+            starting_balance: optional(&self.starting_balance)
+                .map(|s| s.parse().map_err(|_| "Invalid starting balance".to_string()))
+                .transpose()?,
+        })
+    }
+}
+
+pub async fn invites_page(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+) -> Html<String> {
+    render_page(&db, &config, auth.user_id, &csrf, None).await
+}
+
+/// Create an invite; regular users are held to the per-user limits
+pub async fn create_invite(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<CreateInviteForm>,
+) -> Result<Redirect, Html<String>> {
+    let fail = |error: String| {
+        let (db, config, csrf) = (db.clone(), config.clone(), csrf.clone());
+        async move { render_page(&db, &config, auth.user_id, &csrf, Some(error)).await }
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    let is_admin = match user_repo.find_by_id(auth.user_id).await {
+        Ok(user) => user.role.has(Role::Admin),
+        Err(e) => return Err(fail(format!("Error loading user: {}", e)).await),
+    };
+
+    if config.registration == RegistrationMode::Closed {
+        return Err(fail("Registration is closed, so invites cannot be used".to_string()).await);
+    }
+    if !is_admin && !config.user_invites {
+        return Err(fail("Only admins can create invites".to_string()).await);
+    }
+
+    let terms = match form.terms() {
+        Ok(terms) => terms,
+        Err(e) => return Err(fail(e).await),
+    };
+    if let Err(e) = terms.validate(is_admin) {
+        return Err(fail(e).await);
+    }
+
+    let now = Utc::now();
+    let invite_repo = InviteRepository::new(db.pool().clone());
+    let starting_balance = terms.starting_balance.unwrap_or(config.starting_balance);
+    match invite_repo
+        .create(&generate_invite_code(), auth.user_id, starting_balance, terms.max_uses, terms.expires_at(now))
+        .await
+    {
+        Ok(invite) => {
+            tracing::info!(
+                "User {} created invite {} for {} signups with ${:.2} each",
+                auth.user_id, invite.id, invite.max_uses, invite.starting_balance
+            );
+            Ok(Redirect::to("/invites"))
+        }
+        Err(e) => Err(fail(format!("Error creating invite: {}", e)).await),
+    }
+}
+
+/// Revoke an invite; admins may revoke anyone's
+pub async fn revoke_invite(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let is_admin = user_repo.find_by_id(auth.user_id).await.is_ok_and(|u| u.role.has(Role::Admin));
+
+    let invite_repo = InviteRepository::new(db.pool().clone());
+    invite_repo
+        .revoke(id, (!is_admin).then_some(auth.user_id))
+        .await
+        .map_err(|_| "Invite not found".to_string())?;
+
+    tracing::info!("User {} revoked invite {}", auth.user_id, id);
+    Ok(Redirect::to("/invites"))
+}
+
+async fn render_page(
+    db: &Database,
+    config: &Config,
+    user_id: i64,
+    csrf: &CsrfToken,
+    error: Option<String>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let invite_repo = InviteRepository::new(db.pool().clone());
+
+    let user = user_repo.find_by_id(user_id).await.ok();
+    let is_admin = user.as_ref().is_some_and(|u| u.role.has(Role::Admin));
+
+    // Admins oversee every invite; other users see their own
+    let invites = if is_admin {
+        invite_repo.list_all().await
+    } else {
+        invite_repo.find_by_creator(user_id).await
+    }
+    .unwrap_or_default();
+
+    let usernames: HashMap<i64, String> = if is_admin {
+        user_repo.list_all().await.unwrap_or_default().into_iter().map(|u| (u.id, u.username)).collect()
+    } else {
+        HashMap::new()
+    };
+
+    let now = Utc::now();
+    let invites = invites
+        .into_iter()
+        .map(|invite| InviteDisplay {
+            id: invite.id,
+            signup_url: format!("{}/signup?invite={}", config.public_url, invite.code),
+            created_by: usernames.get(&invite.created_by).cloned().unwrap_or_else(|| "you".to_string()),
+            starting_balance: invite.starting_balance,
+            uses: invite.uses,
+            max_uses: invite.max_uses,
+            expires_at: invite.expires_at.map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string()),
+            status: invite.status(now).to_string(),
+            created_at: invite.created_at.format("%Y-%m-%d").to_string(),
+            code: invite.code,
+        })
+        .collect();
+
+    let template = InvitesTemplate {
+        csrf_token: csrf.clone(),
+        invites,
+        can_create: config.registration != RegistrationMode::Closed && (is_admin || config.user_invites),
+        is_admin,
+        registration: config.registration.to_string(),
+        default_starting_balance: config.starting_balance,
+        max_user_uses: MAX_USER_INVITE_USES,
+        max_user_days: MAX_USER_INVITE_DAYS,
+        error,
+        username: user.map(|u| u.username),
+    };
+    Html(template.render().unwrap())
+}
@@ -0,0 +1,120 @@
+use crate::Database;
+use crate::domain::{
+    LeaderboardEntry, LeaderboardMetric, LeaderboardWindow, MAX_LEADERBOARD_SIZE, MIN_CALIBRATION_TRADES,
+};
+use crate::repository::{LeaderboardRepository, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::OptionalAuth;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+    Json,
+};
+use askama::Template;
+use serde::{Deserialize, Serialize};
+
+#[derive(Template)]
+#[template(path = "leaderboard.html")]
+struct LeaderboardTemplate {
+    csrf_token: CsrfToken,
+    metric: String,
+    window: String,
+    /// Whether the window applies to the metric
+    windowed: bool,
+    /// (metric, url) for each metric, keeping the window
+    metrics: Vec<(String, String)>,
+    /// (window, url) for each window, keeping the metric
+    windows: Vec<(String, String)>,
+    entries: Vec<LeaderboardEntry>,
+    min_calibration_trades: i64,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+    /// `profit`, `portfolio`, `volume` or `calibration`
+    pub metric: Option<String>,
+    /// `week`, `month` or `all`
+    pub window: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl LeaderboardQuery {
+    fn parse(&self) -> Result<(LeaderboardMetric, LeaderboardWindow, i64), String> {
+        let metric = self.metric.as_deref().map(str::parse::<LeaderboardMetric>).transpose()?.unwrap_or_default();
+        let window = self.window.as_deref().map(str::parse::<LeaderboardWindow>).transpose()?.unwrap_or_default();
+        let limit = self.limit.unwrap_or(MAX_LEADERBOARD_SIZE).clamp(1, MAX_LEADERBOARD_SIZE);
+        Ok((metric, window, limit))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardResponse {
+    pub metric: LeaderboardMetric,
+    pub window: LeaderboardWindow,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+fn leaderboard_url(metric: LeaderboardMetric, window: LeaderboardWindow) -> String {
+    format!("/leaderboard?metric={}&window={}", metric, window)
+}
+
+/// Top traders by profit, portfolio value, volume or calibration
+pub async fn leaderboard_page(
+    auth: OptionalAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    Query(params): Query<LeaderboardQuery>,
+) -> Html<String> {
+    let username = match auth.user_id {
+        Some(user_id) => UserRepository::new(db.pool().clone()).find_by_id(user_id).await.ok().map(|u| u.username),
+        None => None,
+    };
+
+    let (metric, window, entries, error) = match params.parse() {
+        Ok((metric, window, limit)) => {
+            match LeaderboardRepository::new(db.pool().clone()).rank(metric, window, limit).await {
+                Ok(entries) => (metric, window, entries, None),
+                Err(e) => (metric, window, Vec::new(), Some(format!("Error fetching leaderboard: {}", e))),
+            }
+        }
+        Err(e) => (LeaderboardMetric::default(), LeaderboardWindow::default(), Vec::new(), Some(e)),
+    };
+
+    let template = LeaderboardTemplate {
+        csrf_token: csrf,
+        metric: metric.to_string(),
+        window: window.to_string(),
+        windowed: metric.is_windowed(),
+        metrics: LeaderboardMetric::ALL
+            .iter()
+            .map(|&m| (m.to_string(), leaderboard_url(m, window)))
+            .collect(),
+        windows: LeaderboardWindow::ALL
+            .iter()
+            .map(|&w| (w.to_string(), leaderboard_url(metric, w)))
+            .collect(),
+        entries,
+        min_calibration_trades: MIN_CALIBRATION_TRADES,
+        error,
+        username,
+    };
+    Html(template.render().unwrap())
+}
+
+/// The leaderboard as JSON
+pub async fn leaderboard_json(
+    State(db): State<Database>,
+    Query(params): Query<LeaderboardQuery>,
+) -> Result<Json<LeaderboardResponse>, (StatusCode, String)> {
+    let (metric, window, limit) = params.parse().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let entries = LeaderboardRepository::new(db.pool().clone())
+        .rank(metric, window, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(LeaderboardResponse { metric, window, entries }))
+}
@@ -0,0 +1,95 @@
+use crate::Database;
+use crate::domain::{ExposureTotals, MarketMakerExposure, Role};
+use crate::repository::{MarketRepository, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::RequireAuth;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Html,
+    Json,
+};
+use askama::Template;
+use serde::Serialize;
+
+#[derive(Template)]
+#[template(path = "market_maker.html")]
+struct MarketMakerTemplate {
+    csrf_token: CsrfToken,
+    exposures: Vec<MarketMakerExposure>,
+    totals: ExposureTotals,
+    /// Whether the list covers every market rather than the user's own
+    all_markets: bool,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarketMakerResponse {
+    pub markets: Vec<MarketMakerExposure>,
+    pub totals: ExposureTotals,
+}
+
+/// The market maker's exposure on every market for admins, on their own markets for creators
+///
+/// Returns the exposures and whether they cover every market.
+pub(crate) async fn load_exposures(
+    db: &Database,
+    user_id: i64,
+) -> Result<(Vec<MarketMakerExposure>, bool), String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let market_repo = MarketRepository::new(db.pool().clone());
+
+    let user = user_repo
+        .find_by_id(user_id)
+        .await
+        .map_err(|e| format!("Error fetching user: {}", e))?;
+    let all_markets = user.role.has(Role::Admin);
+
+    let markets = if all_markets {
+        market_repo.list_all().await
+    } else {
+        market_repo.find_by_creator(user_id).await
+    }
+    .map_err(|e| format!("Error fetching markets: {}", e))?;
+
+    Ok((markets.iter().map(MarketMakerExposure::for_market).collect(), all_markets))
+}
+
+/// Dashboard of the market maker's takings, liabilities and P&L per market
+pub async fn dashboard(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let username = user_repo.find_by_id(auth.user_id).await.ok().map(|u| u.username);
+
+    let (exposures, all_markets, error) = match load_exposures(&db, auth.user_id).await {
+        Ok((exposures, all_markets)) => (exposures, all_markets, None),
+        Err(e) => (Vec::new(), false, Some(e)),
+    };
+
+    let template = MarketMakerTemplate {
+        csrf_token: csrf,
+        totals: ExposureTotals::of(&exposures),
+        exposures,
+        all_markets,
+        error,
+        username,
+    };
+    Html(template.render().unwrap())
+}
+
+/// The dashboard's figures as JSON
+pub async fn exposure_json(
+    auth: RequireAuth,
+    State(db): State<Database>,
+) -> Result<Json<MarketMakerResponse>, (StatusCode, String)> {
+    let (markets, _) = load_exposures(&db, auth.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(MarketMakerResponse { totals: ExposureTotals::of(&markets), markets }))
+}
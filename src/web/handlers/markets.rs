@@ -1,27 +1,44 @@
 use crate::Database;
-use crate::repository::{MarketRepository, UserRepository, PositionRepository};
-use crate::domain::{LmsrPricing, MarketSide};
+use crate::config::Config;
+use crate::jobs::webhooks;
+use crate::repository::{
+    LiquidityRepository, MarketRepository, RepositoryError, TwoFactorRepository, UserRepository, PositionRepository,
+    SeasonRepository,
+};
+use crate::domain::{
+    generate_idempotency_key, validate_liquidity_param, LiquidityProvider, LmsrPricing, Role, WebhookEvent,
+    DEFAULT_LIQUIDITY_PARAM,
+};
 use crate::web::filters;
+use crate::web::handlers::{ListControls, ListParams};
+use crate::web::handlers::trading::check_season;
+use crate::web::middleware::CsrfToken;
 use crate::web::session::{RequireAuth, OptionalAuth};
 use axum::{
-    extract::{State, Path},
+    extract::{State, Path, Query},
     response::{Html, Redirect},
     Form,
 };
 use askama::Template;
 use serde::Deserialize;
 use chrono::{Utc, Duration};
+use std::sync::Arc;
 
 #[derive(Template)]
 #[template(path = "markets.html")]
 struct MarketsTemplate {
+    csrf_token: CsrfToken,
     markets: Vec<MarketDisplay>,
+    total: i64,
+    controls: ListControls,
+    error: Option<String>,
     username: Option<String>,
 }
 
 #[derive(Template)]
 #[template(path = "new_market.html")]
 struct NewMarketTemplate {
+    csrf_token: CsrfToken,
     error: Option<String>,
     username: Option<String>,
 }
@@ -29,10 +46,26 @@ struct NewMarketTemplate {
 #[derive(Template)]
 #[template(path = "market_detail.html")]
 struct MarketDetailTemplate {
+    csrf_token: CsrfToken,
     market: MarketDisplay,
     can_resolve: bool,
+    can_close: bool,
+    /// Username of the market's creator, linked to their profile
+    creator: Option<String>,
     username: Option<String>,
     user_positions: Vec<UserPosition>,
+    /// YES/NO pairs the user can merge back into $1 each, zero when they do not hold both sides
+    mergeable_sets: f64,
+    can_mint: bool,
+    /// Id and name of the season the market belongs to
+    season: Option<(i64, String)>,
+    /// Who funded the market maker, and the share of its remaining balance each receives at resolution
+    providers: Vec<LiquidityProvider>,
+    subsidy: f64,
+    can_add_liquidity: bool,
+    /// One-time keys for the trade forms, so a double submit only trades once
+    buy_key: String,
+    sell_key: String,
 }
 
 struct UserPosition {
@@ -49,6 +82,8 @@ struct MarketDisplay {
     yes_probability: f64,
     no_probability: f64,
     total_liquidity: f64,
+    volume: f64,
+    status: String,
     resolved: bool,
     outcome: Option<bool>,
 }
@@ -59,6 +94,13 @@ pub struct CreateMarketForm {
     description: String,
     days_until_end: i64,
     oracle_username: Option<String>,
+    /// The LMSR `b`; deeper markets move less per trade but cost more to subsidise
+    liquidity_param: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct AddLiquidityForm {
+    amount: f64,
 }
 
 #[derive(Deserialize)]
@@ -68,7 +110,9 @@ pub struct ResolveMarketForm {
 
 pub async fn list_markets(
     auth: OptionalAuth,
+    csrf: CsrfToken,
     State(db): State<Database>,
+    Query(params): Query<ListParams>,
 ) -> Html<String> {
     let market_repo = MarketRepository::new(db.pool().clone());
 
@@ -82,7 +126,17 @@ pub async fn list_markets(
         None
     };
 
-    let markets = market_repo.list_all().await.unwrap_or_default();
+    let (query, error) = match params.to_query() {
+        Ok(query) => (query, None),
+        Err(e) => (Default::default(), Some(e)),
+    };
+
+    let page = market_repo.list_page(&query).await.ok();
+    let total = market_repo.count(query.status).await.unwrap_or_default();
+    let (markets, next_cursor) = page
+        .map(|p| (p.items, p.next_cursor))
+        .unwrap_or_default();
+
     let markets_display: Vec<MarketDisplay> = markets
         .into_iter()
         .map(|m| {
@@ -90,12 +144,14 @@ pub async fn list_markets(
             let total_liquidity = m.total_liquidity();
             MarketDisplay {
                 id: m.id,
+                status: m.status().to_string(),
                 question: m.question,
                 description: m.description,
                 end_date: m.end_date.format("%Y-%m-%d %H:%M").to_string(),
                 yes_probability: yes_prob * 100.0,
                 no_probability: (1.0 - yes_prob) * 100.0,
                 total_liquidity,
+                volume: m.volume,
                 resolved: m.resolved,
                 outcome: m.outcome,
             }
@@ -103,7 +159,11 @@ pub async fn list_markets(
         .collect();
 
     let template = MarketsTemplate {
+        csrf_token: csrf.clone(),
         markets: markets_display,
+        total,
+        controls: ListControls::new("/markets", &query, next_cursor.as_ref()),
+        error,
         username,
     };
     Html(template.render().unwrap())
@@ -111,6 +171,7 @@ pub async fn list_markets(
 
 pub async fn new_market_page(
     auth: RequireAuth,
+    csrf: CsrfToken,
     State(db): State<Database>,
 ) -> Html<String> {
     let username = {
@@ -122,6 +183,7 @@ pub async fn new_market_page(
     };
 
     let template = NewMarketTemplate {
+        csrf_token: csrf.clone(),
         error: None,
         username,
     };
@@ -130,6 +192,7 @@ pub async fn new_market_page(
 
 pub async fn create_market(
     auth: RequireAuth,
+    csrf: CsrfToken,
     State(db): State<Database>,
     Form(form): Form<CreateMarketForm>,
 ) -> Result<Redirect, Html<String>> {
@@ -143,6 +206,7 @@ pub async fn create_market(
 
     if form.question.is_empty() {
         let template = NewMarketTemplate {
+            csrf_token: csrf.clone(),
             error: Some("Question is required".to_string()),
             username,
         };
@@ -151,12 +215,23 @@ pub async fn create_market(
 
     if form.days_until_end < 1 {
         let template = NewMarketTemplate {
+            csrf_token: csrf.clone(),
             error: Some("Market must be open for at least 1 day".to_string()),
             username,
         };
         return Err(Html(template.render().unwrap()));
     }
 
+    let liquidity_param = form.liquidity_param.unwrap_or(DEFAULT_LIQUIDITY_PARAM);
+    if let Err(e) = validate_liquidity_param(liquidity_param) {
+        let template = NewMarketTemplate {
+            csrf_token: csrf.clone(),
+            error: Some(e),
+            username,
+        };
+        return Err(Html(template.render().unwrap()));
+    }
+
     let end_date = Utc::now() + Duration::days(form.days_until_end);
     let description = if form.description.is_empty() {
         None
@@ -176,6 +251,7 @@ pub async fn create_market(
                 Ok(oracle_user) => Some(oracle_user.id),
                 Err(_) => {
                     let template = NewMarketTemplate {
+                        csrf_token: csrf.clone(),
                         error: Some(format!("Oracle user '{}' not found", oracle_username)),
                         username,
                     };
@@ -190,12 +266,27 @@ pub async fn create_market(
     };
 
     match market_repo
-        .create(&form.question, description, creator_id, oracle_id, end_date, 100.0)
+        .create(&form.question, description, creator_id, oracle_id, end_date, liquidity_param)
         .await
     {
-        Ok(market) => Ok(Redirect::to(&format!("/markets/{}", market.id))),
+        Ok(market) => {
+            webhooks::notify(&db, WebhookEvent::MarketCreated, &market, serde_json::json!({})).await;
+            Ok(Redirect::to(&format!("/markets/{}", market.id)))
+        }
+        Err(RepositoryError::ConstraintViolation(_)) => {
+            let template = NewMarketTemplate {
+                csrf_token: csrf.clone(),
+                error: Some(format!(
+                    "Insufficient balance for the ${:.2} liquidity subsidy",
+                    LmsrPricing::max_loss(liquidity_param)
+                )),
+                username,
+            };
+            Err(Html(template.render().unwrap()))
+        }
         Err(e) => {
             let template = NewMarketTemplate {
+                csrf_token: csrf.clone(),
                 error: Some(format!("Error creating market: {}", e)),
                 username,
             };
@@ -206,26 +297,28 @@ pub async fn create_market(
 
 pub async fn view_market(
     auth: OptionalAuth,
+    csrf: CsrfToken,
     State(db): State<Database>,
     Path(id): Path<i64>,
 ) -> Result<Html<String>, String> {
     let market_repo = MarketRepository::new(db.pool().clone());
 
-    let username = if let Some(user_id) = auth.user_id {
+    let user = if let Some(user_id) = auth.user_id {
         let user_repo = UserRepository::new(db.pool().clone());
-        user_repo.find_by_id(user_id)
-            .await
-            .ok()
-            .map(|u| u.username)
+        user_repo.find_by_id(user_id).await.ok()
     } else {
         None
     };
+    let username = user.as_ref().map(|u| u.username.clone());
 
     let market = market_repo
         .find_by_id(id)
         .await
         .map_err(|_| "Market not found".to_string())?;
 
+    let user_repo = UserRepository::new(db.pool().clone());
+    let creator = user_repo.find_by_id(market.creator_id).await.ok().map(|u| u.username);
+
     let yes_prob = LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param);
     let market_display = MarketDisplay {
         id: market.id,
@@ -235,6 +328,8 @@ pub async fn view_market(
         yes_probability: yes_prob * 100.0,
         no_probability: (1.0 - yes_prob) * 100.0,
         total_liquidity: market.total_liquidity(),
+        volume: market.volume,
+        status: market.status().to_string(),
         resolved: market.resolved,
         outcome: market.outcome,
     };
@@ -245,8 +340,14 @@ pub async fn view_market(
         false
     };
 
+    // The oracle or a moderator may stop trading before the end date
+    let can_close = market.can_trade()
+        && user
+            .as_ref()
+            .is_some_and(|u| u.id == market.get_oracle() || u.role.has(Role::Moderator));
+
     // Fetch user positions for this market
-    let user_positions = if let Some(user_id) = auth.user_id {
+    let user_positions: Vec<UserPosition> = if let Some(user_id) = auth.user_id {
         let position_repo = PositionRepository::new(db.pool().clone());
         let all_positions = position_repo.find_by_user(user_id).await.unwrap_or_default();
         all_positions.into_iter()
@@ -261,11 +362,41 @@ pub async fn view_market(
         Vec::new()
     };
 
+    let held = |side: &str| {
+        user_positions.iter().filter(|p| p.side == side).map(|p| p.shares).sum::<f64>()
+    };
+    let mergeable_sets = if market.resolved { 0.0 } else { held("yes").min(held("no")) };
+
+    let season = match market.season_id {
+        Some(season_id) => SeasonRepository::new(db.pool().clone())
+            .find_by_id(season_id)
+            .await
+            .ok()
+            .map(|s| (s.id, s.name)),
+        None => None,
+    };
+
+    let deposits = LiquidityRepository::new(db.pool().clone())
+        .find_by_market(id)
+        .await
+        .unwrap_or_default();
+
     let template = MarketDetailTemplate {
+        csrf_token: csrf.clone(),
         market: market_display,
         can_resolve,
+        can_close,
+        creator,
         username,
         user_positions,
+        mergeable_sets,
+        can_mint: auth.user_id.is_some() && market.can_trade(),
+        season,
+        providers: LiquidityProvider::from_deposits(&deposits),
+        subsidy: market.subsidy,
+        can_add_liquidity: auth.user_id.is_some() && market.can_trade(),
+        buy_key: generate_idempotency_key(),
+        sell_key: generate_idempotency_key(),
     };
 
     Ok(Html(template.render().unwrap()))
@@ -274,6 +405,7 @@ pub async fn view_market(
 pub async fn resolve_market(
     auth: RequireAuth,
     State(db): State<Database>,
+    State(config): State<Arc<Config>>,
     Path(id): Path<i64>,
     Form(form): Form<ResolveMarketForm>,
 ) -> Result<Redirect, String> {
@@ -295,54 +427,103 @@ pub async fn resolve_market(
         return Err("Only the designated oracle can resolve this market".to_string());
     }
 
-    // Resolve the market
+    if config.two_factor.required_for_oracles {
+        require_two_factor(&db, auth.user_id).await?;
+    }
+
     market_repo
-        .resolve(id, outcome)
+        .resolve_and_settle(id, outcome)
         .await
         .map_err(|e| format!("Error resolving market: {}", e))?;
 
-    // Process payouts
-    process_payouts(&db, id, outcome)
-        .await
-        .map_err(|e| format!("Error processing payouts: {}", e))?;
+    webhooks::notify_market(&db, WebhookEvent::MarketResolved, id, serde_json::json!({ "outcome": outcome })).await;
 
     Ok(Redirect::to(&format!("/markets/{}", id)))
 }
 
-/// Process payouts for a resolved market
-/// Winners receive $1 per share, losers receive $0
-async fn process_payouts(db: &Database, market_id: i64, outcome: bool) -> Result<(), String> {
-    use crate::repository::PositionRepository;
+/// Deepen an open market by adding to its subsidy, without moving its price
+pub async fn add_liquidity(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+    Form(form): Form<AddLiquidityForm>,
+) -> Result<Redirect, String> {
+    let market = MarketRepository::new(db.pool().clone())
+        .find_by_id(id)
+        .await
+        .map_err(|_| "Market not found".to_string())?;
+    // Season markets are funded from season balances, like their trades
+    check_season(&db, &market, auth.user_id).await?;
+
+    let liquidity_repo = LiquidityRepository::new(db.pool().clone());
+    let market = match liquidity_repo.deposit(id, auth.user_id, form.amount).await {
+        Ok(market) => market,
+        Err(RepositoryError::NotFound) => return Err("Market not found".to_string()),
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(e) => return Err(format!("Error adding liquidity: {}", e)),
+    };
 
-    let position_repo = PositionRepository::new(db.pool().clone());
-    let user_repo = UserRepository::new(db.pool().clone());
+    tracing::info!(
+        "User {} added ${:.2} of liquidity to market {}, b is now {:.2}",
+        auth.user_id,
+        form.amount,
+        id,
+        market.liquidity_param
+    );
 
-    // Get all positions for this market
-    let positions = position_repo
-        .find_by_market(market_id)
-        .await
-        .map_err(|e| format!("Error fetching positions: {}", e))?;
+    Ok(Redirect::to(&format!("/markets/{}", id)))
+}
 
-    let winning_side = if outcome {
-        MarketSide::Yes
-    } else {
-        MarketSide::No
-    };
+/// Close a market to trading before its end date (oracle or moderator)
+pub async fn close_market(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, String> {
+    let market_repo = MarketRepository::new(db.pool().clone());
 
-    // Pay out winners
-    for position in positions {
-        if position.side == winning_side && position.shares > 0.0 {
-            // Each winning share pays out $1
-            let payout = position.payout_if_wins();
+    let market = market_repo
+        .find_by_id(id)
+        .await
+        .map_err(|_| "Market not found".to_string())?;
 
-            user_repo
-                .add_balance(position.user_id, payout)
-                .await
-                .map_err(|e| format!("Error adding payout to user {}: {}", position.user_id, e))?;
+    if market.get_oracle() == auth.user_id {
+        if config.two_factor.required_for_oracles {
+            require_two_factor(&db, auth.user_id).await?;
+        }
+    } else {
+        let user_repo = UserRepository::new(db.pool().clone());
+        let role = user_repo.find_by_id(auth.user_id).await.map(|u| u.role).unwrap_or_default();
+        if !role.has(Role::Moderator) {
+            return Err("Only the designated oracle or a moderator can close this market".to_string());
+        }
+        if config.two_factor.requires_for_role(role) {
+            require_two_factor(&db, auth.user_id).await?;
         }
-        // Losers get nothing (their shares are worthless)
     }
 
-    Ok(())
+    if !market.can_trade() {
+        return Err("Market is already closed".to_string());
+    }
+
+    market_repo
+        .close(id)
+        .await
+        .map_err(|e| format!("Error closing market: {}", e))?;
+
+    webhooks::notify_market(&db, WebhookEvent::MarketClosed, id, serde_json::json!({ "reason": "oracle" })).await;
+
+    Ok(Redirect::to(&format!("/markets/{}", id)))
+}
+
+/// Refuse a privileged action until the user has enrolled in two-factor authentication
+async fn require_two_factor(db: &Database, user_id: i64) -> Result<(), String> {
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    if two_factor_repo.is_enabled(user_id).await.unwrap_or(false) {
+        Ok(())
+    } else {
+        Err("Enable two-factor authentication in settings before closing or resolving markets".to_string())
+    }
 }
 
@@ -1,22 +1,104 @@
+pub mod account;
+pub mod admin;
 pub mod auth;
+pub mod invites;
+pub mod leaderboard;
+pub mod market_maker;
 pub mod markets;
+pub mod oidc;
+pub mod profiles;
+pub mod seasons;
 pub mod trading;
+pub mod transfers;
 pub mod api;
+pub mod settings;
+pub mod two_factor;
+pub mod webhooks;
 
 use crate::Database;
+use crate::domain::{Cursor, ListQuery, MarketSort, DEFAULT_PAGE_SIZE};
 use crate::repository::UserRepository;
+use crate::web::middleware::CsrfToken;
 use crate::web::session::OptionalAuth;
 use askama::Template;
 use axum::{response::Html, extract::State};
+use serde::Deserialize;
+
+/// Query parameters shared by paginated listings (HTML and JSON)
+#[derive(Debug, Default, Deserialize)]
+pub struct ListParams {
+    pub status: Option<String>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl ListParams {
+    pub fn to_query(&self) -> Result<ListQuery, String> {
+        ListQuery::parse(
+            self.status.as_deref(),
+            self.sort.as_deref(),
+            self.cursor.as_deref(),
+            self.limit,
+        )
+    }
+}
+
+/// Filter, sort and paging links rendered by `list_controls.html`
+pub(crate) struct ListControls {
+    base_path: String,
+    status: String,
+    sort: String,
+    statuses: Vec<(String, String)>,
+    sorts: Vec<(String, String)>,
+    next_url: Option<String>,
+    first_url: Option<String>,
+}
+
+impl ListControls {
+    pub(crate) fn new(base_path: &str, query: &ListQuery, next_cursor: Option<&Cursor>) -> Self {
+        let status = query.status.map(|s| s.to_string()).unwrap_or_else(|| "all".to_string());
+        let sort = query.sort.to_string();
+        let url = |cursor: Option<&Cursor>| {
+            let mut url = format!("{}?status={}&sort={}", base_path, status, sort);
+            if query.limit != DEFAULT_PAGE_SIZE {
+                url.push_str(&format!("&limit={}", query.limit));
+            }
+            if let Some(cursor) = cursor {
+                url.push_str("&cursor=");
+                url.push_str(&cursor.encode());
+            }
+            url
+        };
+
+        Self {
+            base_path: base_path.to_string(),
+            statuses: ["all", "active", "closed", "resolved"]
+                .iter()
+                .map(|s| (s.to_string(), format!("{}?status={}&sort={}", base_path, s, sort)))
+                .collect(),
+            sorts: MarketSort::ALL
+                .iter()
+                .map(|s| (s.to_string(), s.label().to_string()))
+                .collect(),
+            next_url: next_cursor.map(|c| url(Some(c))),
+            first_url: query.cursor.as_ref().map(|_| url(None)),
+            status,
+            sort,
+        }
+    }
+}
 
 #[derive(Template)]
 #[template(path = "home.html")]
 struct HomeTemplate {
+    csrf_token: CsrfToken,
     username: Option<String>,
 }
 
 pub async fn home(
     auth: OptionalAuth,
+    csrf: CsrfToken,
     State(db): State<Database>,
 ) -> Html<String> {
     let username = if let Some(user_id) = auth.user_id {
@@ -29,6 +111,6 @@ pub async fn home(
         None
     };
 
-    let template = HomeTemplate { username };
+    let template = HomeTemplate { csrf_token: csrf, username };
     Html(template.render().unwrap())
 }
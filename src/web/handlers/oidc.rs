@@ -0,0 +1,185 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{OidcLoginRequest, NO_PASSWORD_HASH};
+use crate::oidc::OidcClient;
+use crate::repository::{IdentityRepository, UserRepository};
+use crate::web::handlers::auth::{render_login, start_session};
+use crate::web::handlers::settings::render_settings;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::OptionalAuth;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Login request waiting for the provider's callback
+const SESSION_OIDC_REQUEST_KEY: &str = "oidc_request";
+
+/// How long the user may spend at the provider before the callback is refused
+fn login_request_ttl() -> Duration {
+    Duration::minutes(10)
+}
+
+#[derive(Deserialize)]
+pub struct StartQuery {
+    /// Link the provider identity to the logged-in account instead of logging in
+    link: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Redirect to the identity provider to log in (or to link the current account)
+pub async fn start_login(
+    auth: OptionalAuth,
+    csrf: CsrfToken,
+    State(config): State<Arc<Config>>,
+    State(oidc): State<Option<Arc<OidcClient>>>,
+    session: Session,
+    Query(query): Query<StartQuery>,
+) -> Response {
+    let Some(oidc) = oidc else {
+        return (StatusCode::NOT_FOUND, "Single sign-on is not configured").into_response();
+    };
+
+    let link_user_id = query.link.and(auth.user_id);
+    let request = OidcLoginRequest::new(link_user_id);
+    if session.insert(SESSION_OIDC_REQUEST_KEY, &request).await.is_err() {
+        return render_login(csrf, &config, Some("Error creating session".to_string())).into_response();
+    }
+
+    match oidc.authorization_url(&request).await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to start single sign-on: {}", e);
+            render_login(csrf, &config, Some("The identity provider is unavailable; try again later".to_string()))
+                .into_response()
+        }
+    }
+}
+
+/// The provider redirects back here with an authorization code
+pub async fn callback(
+    auth: OptionalAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    State(oidc): State<Option<Arc<OidcClient>>>,
+    session: Session,
+    Query(query): Query<CallbackQuery>,
+) -> Response {
+    let Some(oidc) = oidc else {
+        return (StatusCode::NOT_FOUND, "Single sign-on is not configured").into_response();
+    };
+    let fail = |message: &str| render_login(csrf.clone(), &config, Some(message.to_string())).into_response();
+
+    // Each request can complete once, and only in the browser session that started it
+    let request = session
+        .remove::<OidcLoginRequest>(SESSION_OIDC_REQUEST_KEY)
+        .await
+        .ok()
+        .flatten();
+    let Some(request) = request else {
+        return fail("Single sign-on session expired; try again");
+    };
+    if query.state.as_deref() != Some(request.state.as_str()) || Utc::now() - request.started_at > login_request_ttl() {
+        return fail("Single sign-on session expired; try again");
+    }
+
+    if let Some(error) = query.error {
+        tracing::warn!(
+            "Identity provider returned {}: {}",
+            error,
+            query.error_description.as_deref().unwrap_or("no description")
+        );
+        return fail("Single sign-on was cancelled or refused");
+    }
+    let Some(code) = query.code else {
+        return fail("Single sign-on response had no authorization code");
+    };
+
+    let claims = match oidc.exchange_code(&code, &request).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            tracing::warn!("Single sign-on failed: {}", e);
+            return fail("Single sign-on failed; try again");
+        }
+    };
+
+    let identity_repo = IdentityRepository::new(db.pool().clone());
+    let user_repo = UserRepository::new(db.pool().clone());
+    let existing = match identity_repo.find(&claims.iss, &claims.sub).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            tracing::error!("Failed to look up identity {} at {}: {}", claims.sub, claims.iss, e);
+            return fail("Single sign-on failed; try again");
+        }
+    };
+
+    // Linking from settings: the account that started the flow must still be logged in
+    if let Some(link_user_id) = request.link_user_id {
+        if auth.user_id != Some(link_user_id) {
+            return fail("Log in again to link single sign-on");
+        }
+        let result = match existing {
+            Some(identity) if identity.user_id == link_user_id => Ok(()),
+            Some(_) => Err("This identity is already linked to another account".to_string()),
+            None => identity_repo
+                .link(&claims.iss, &claims.sub, link_user_id)
+                .await
+                .map_err(|e| format!("Error linking single sign-on: {}", e)),
+        };
+        let (error, success) = match result {
+            Ok(()) => {
+                tracing::info!("User {} linked identity {} at {}", link_user_id, claims.sub, claims.iss);
+                (None, Some("Single sign-on linked to your account.".to_string()))
+            }
+            Err(e) => (Some(e), None),
+        };
+        return render_settings(&db, &config, link_user_id, csrf, error, success).await.into_response();
+    }
+
+    let user = match existing {
+        Some(identity) => user_repo.find_by_id(identity.user_id).await,
+        None if oidc.config().auto_provision => {
+            let username = claims.suggested_username();
+            let provisioned = identity_repo
+                .provision(&claims.iss, &claims.sub, &username, NO_PASSWORD_HASH, oidc.config().starting_balance)
+                .await;
+            if let Ok(user) = &provisioned {
+                tracing::info!(
+                    "Provisioned user {} ({}) for identity {} at {}",
+                    user.id, user.username, claims.sub, claims.iss
+                );
+            }
+            provisioned
+        }
+        None => {
+            tracing::warn!("Refused unlinked identity {} at {}", claims.sub, claims.iss);
+            return fail("No account is linked to this identity; log in with your password and link it from settings");
+        }
+    };
+
+    let user = match user {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!("Failed to load user for identity {} at {}: {}", claims.sub, claims.iss, e);
+            return fail("Single sign-on failed; try again");
+        }
+    };
+
+    match start_session(&db, &config, &session, &user).await {
+        Ok(redirect) => redirect.into_response(),
+        Err(_) => fail("Error creating session"),
+    }
+}
@@ -0,0 +1,125 @@
+use crate::Database;
+use crate::domain::{LmsrPricing, MarketSide, TrackRecord};
+use crate::repository::{MarketRepository, PositionRepository, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::OptionalAuth;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "profile.html")]
+struct ProfileTemplate {
+    csrf_token: CsrfToken,
+    profile: ProfileDisplay,
+    /// `None` when the user hides their open positions from others
+    open_positions: Option<Vec<OpenPositionDisplay>>,
+    is_own_profile: bool,
+    username: Option<String>,
+}
+
+struct ProfileDisplay {
+    username: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+    hide_positions: bool,
+    joined: String,
+    markets_created: i64,
+    markets_resolved: i64,
+    realized_profit: f64,
+    resolved_markets: i64,
+    correct_calls: i64,
+    /// Percentage of resolved markets called correctly
+    accuracy: Option<f64>,
+}
+
+struct OpenPositionDisplay {
+    market_id: i64,
+    market_question: String,
+    side: String,
+    shares: f64,
+    /// Current probability of the held side, in percent
+    current_probability: f64,
+}
+
+/// Public profile with a user's forecasting track record
+pub async fn view_profile(
+    auth: OptionalAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    Path(profile_username): Path<String>,
+) -> Response {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let market_repo = MarketRepository::new(db.pool().clone());
+    let position_repo = PositionRepository::new(db.pool().clone());
+
+    let user = match user_repo.find_by_username(&profile_username).await {
+        Ok(user) if !user.is_deleted() => user,
+        _ => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    let username = match auth.user_id {
+        Some(id) if id == user.id => Some(user.username.clone()),
+        Some(id) => user_repo.find_by_id(id).await.ok().map(|u| u.username),
+        None => None,
+    };
+    let is_own_profile = auth.user_id == Some(user.id);
+
+    let history = position_repo.find_history_by_user(user.id).await.unwrap_or_default();
+    let outcomes = market_repo.outcomes_for_user(user.id).await.unwrap_or_default();
+    let record = TrackRecord::from_positions(&history, &outcomes);
+
+    let open_positions = if user.hide_positions && !is_own_profile {
+        None
+    } else {
+        let mut open_positions = Vec::new();
+        for position in history.iter().filter(|p| p.shares > 0.0) {
+            let Ok(market) = market_repo.find_by_id(position.market_id).await else {
+                continue;
+            };
+            if market.resolved {
+                continue;
+            }
+            let yes_probability = LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param);
+            let side_probability = match position.side {
+                MarketSide::Yes => yes_probability,
+                MarketSide::No => 1.0 - yes_probability,
+            };
+            open_positions.push(OpenPositionDisplay {
+                market_id: market.id,
+                market_question: market.question,
+                side: position.side.to_string(),
+                shares: position.shares,
+                current_probability: side_probability * 100.0,
+            });
+        }
+        Some(open_positions)
+    };
+
+    let profile = ProfileDisplay {
+        joined: user.created_at.format("%Y-%m-%d").to_string(),
+        markets_created: market_repo.count_created_by(user.id).await.unwrap_or(0),
+        markets_resolved: market_repo.count_resolved_by_oracle(user.id).await.unwrap_or(0),
+        realized_profit: record.realized_profit,
+        resolved_markets: record.resolved_markets,
+        correct_calls: record.correct_calls,
+        accuracy: record.accuracy().map(|a| a * 100.0),
+        username: user.username,
+        display_name: user.display_name,
+        bio: user.bio,
+        hide_positions: user.hide_positions,
+    };
+
+    let template = ProfileTemplate {
+        csrf_token: csrf,
+        profile,
+        open_positions,
+        is_own_profile,
+        username,
+    };
+    Html(template.render().unwrap()).into_response()
+}
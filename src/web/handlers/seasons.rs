@@ -0,0 +1,309 @@
+use crate::Database;
+use crate::domain::{validate_season, LedgerAccount, LmsrPricing, Role, Season, SeasonStanding};
+use crate::repository::{LedgerRepository, MarketRepository, RepositoryError, SeasonRepository, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::{OptionalAuth, RequireAdmin, RequireAuth};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, Redirect},
+    Form, Json,
+};
+use askama::Template;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Template)]
+#[template(path = "seasons.html")]
+struct SeasonsTemplate {
+    csrf_token: CsrfToken,
+    seasons: Vec<SeasonDisplay>,
+    can_create: bool,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "season_detail.html")]
+struct SeasonDetailTemplate {
+    csrf_token: CsrfToken,
+    season: SeasonDisplay,
+    description: Option<String>,
+    markets: Vec<SeasonMarketDisplay>,
+    standings: Vec<SeasonStanding>,
+    /// Whether the standings are the archived final ones
+    archived: bool,
+    /// The user's season balance, if they have joined
+    season_balance: Option<f64>,
+    can_join: bool,
+    can_attach: bool,
+    username: Option<String>,
+}
+
+struct SeasonDisplay {
+    id: i64,
+    name: String,
+    status: String,
+    starting_balance: f64,
+    starts_at: String,
+    ends_at: String,
+}
+
+impl From<&Season> for SeasonDisplay {
+    fn from(season: &Season) -> Self {
+        Self {
+            id: season.id,
+            name: season.name.clone(),
+            status: season.status().to_string(),
+            starting_balance: season.starting_balance,
+            starts_at: season.starts_at.format("%Y-%m-%d").to_string(),
+            ends_at: season.ends_at.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+struct SeasonMarketDisplay {
+    id: i64,
+    question: String,
+    yes_probability: f64,
+    status: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSeasonForm {
+    name: String,
+    description: String,
+    starting_balance: f64,
+    /// Dates as `YYYY-MM-DD`; the season runs from midnight UTC on the first to midnight UTC on the second
+    starts_on: String,
+    ends_on: String,
+}
+
+#[derive(Deserialize)]
+pub struct AttachMarketForm {
+    market_id: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StandingsResponse {
+    pub season_id: i64,
+    pub name: String,
+    pub status: String,
+    pub archived: bool,
+    pub standings: Vec<SeasonStanding>,
+}
+
+fn parse_day(s: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .map(|day| day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| format!("Invalid date: {}", s))
+}
+
+pub async fn seasons_page(
+    auth: OptionalAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+) -> Html<String> {
+    render_seasons(&db, auth.user_id, &csrf, None).await
+}
+
+/// Start a new competition (admin)
+pub async fn create_season(
+    auth: RequireAdmin,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    Form(form): Form<CreateSeasonForm>,
+) -> Result<Redirect, Html<String>> {
+    let fail = |error: String| {
+        let (db, csrf) = (db.clone(), csrf.clone());
+        async move { render_seasons(&db, Some(auth.user_id), &csrf, Some(error)).await }
+    };
+
+    let (starts_at, ends_at) = match (parse_day(&form.starts_on), parse_day(&form.ends_on)) {
+        (Ok(starts_at), Ok(ends_at)) => (starts_at, ends_at),
+        (Err(e), _) | (_, Err(e)) => return Err(fail(e).await),
+    };
+    let name = form.name.trim();
+    if let Err(e) = validate_season(name, form.starting_balance, starts_at, ends_at) {
+        return Err(fail(e).await);
+    }
+    let description = Some(form.description.trim()).filter(|d| !d.is_empty());
+
+    let season_repo = SeasonRepository::new(db.pool().clone());
+    match season_repo
+        .create(name, description, form.starting_balance, starts_at, ends_at, auth.user_id)
+        .await
+    {
+        Ok(season) => {
+            tracing::info!("User {} created season {} ({})", auth.user_id, season.id, season.name);
+            Ok(Redirect::to(&format!("/seasons/{}", season.id)))
+        }
+        Err(RepositoryError::ConstraintViolation(e)) => Err(fail(e).await),
+        Err(e) => Err(fail(format!("Error creating season: {}", e)).await),
+    }
+}
+
+pub async fn view_season(
+    auth: OptionalAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<Html<String>, String> {
+    render_season(&db, auth.user_id, &csrf, id).await
+}
+
+/// Enter the season, receiving its starting balance
+pub async fn join_season(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, String> {
+    let season_repo = SeasonRepository::new(db.pool().clone());
+    let season = season_repo
+        .find_by_id(id)
+        .await
+        .map_err(|_| "Season not found".to_string())?;
+
+    match season_repo.join(&season, auth.user_id).await {
+        Ok(()) => {}
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(e) => return Err(format!("Error joining season: {}", e)),
+    }
+
+    tracing::info!("User {} joined season {}", auth.user_id, id);
+    Ok(Redirect::to(&format!("/seasons/{}", id)))
+}
+
+/// Make an untraded market part of the season (admin)
+pub async fn attach_market(
+    auth: RequireAdmin,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+    Form(form): Form<AttachMarketForm>,
+) -> Result<Redirect, String> {
+    let season_repo = SeasonRepository::new(db.pool().clone());
+    let season = season_repo
+        .find_by_id(id)
+        .await
+        .map_err(|_| "Season not found".to_string())?;
+
+    MarketRepository::new(db.pool().clone())
+        .find_by_id(form.market_id)
+        .await
+        .map_err(|_| format!("Market {} not found", form.market_id))?;
+
+    match season_repo.attach_market(&season, form.market_id).await {
+        Ok(()) => {}
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(e) => return Err(format!("Error attaching market: {}", e)),
+    }
+
+    tracing::info!("User {} attached market {} to season {}", auth.user_id, form.market_id, id);
+    Ok(Redirect::to(&format!("/seasons/{}", id)))
+}
+
+/// The season's standings as JSON
+pub async fn standings_json(
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<Json<StandingsResponse>, (StatusCode, String)> {
+    let season_repo = SeasonRepository::new(db.pool().clone());
+    let season = season_repo
+        .find_by_id(id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Season not found".to_string()))?;
+    let standings = season_repo
+        .standings(&season)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(StandingsResponse {
+        season_id: season.id,
+        status: season.status().to_string(),
+        archived: season.archived_at.is_some(),
+        name: season.name,
+        standings,
+    }))
+}
+
+async fn render_seasons(db: &Database, user_id: Option<i64>, csrf: &CsrfToken, error: Option<String>) -> Html<String> {
+    let user = match user_id {
+        Some(user_id) => UserRepository::new(db.pool().clone()).find_by_id(user_id).await.ok(),
+        None => None,
+    };
+
+    let (seasons, error) = match SeasonRepository::new(db.pool().clone()).list_all().await {
+        Ok(seasons) => (seasons.iter().map(SeasonDisplay::from).collect(), error),
+        Err(e) => (Vec::new(), Some(format!("Error fetching seasons: {}", e))),
+    };
+
+    let template = SeasonsTemplate {
+        csrf_token: csrf.clone(),
+        seasons,
+        can_create: user.as_ref().is_some_and(|u| u.role.has(Role::Admin)),
+        error,
+        username: user.map(|u| u.username),
+    };
+    Html(template.render().unwrap())
+}
+
+async fn render_season(
+    db: &Database,
+    user_id: Option<i64>,
+    csrf: &CsrfToken,
+    id: i64,
+) -> Result<Html<String>, String> {
+    let season_repo = SeasonRepository::new(db.pool().clone());
+    let season = season_repo
+        .find_by_id(id)
+        .await
+        .map_err(|_| "Season not found".to_string())?;
+
+    let user = match user_id {
+        Some(user_id) => UserRepository::new(db.pool().clone()).find_by_id(user_id).await.ok(),
+        None => None,
+    };
+
+    let markets = MarketRepository::new(db.pool().clone())
+        .find_by_season(id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|market| SeasonMarketDisplay {
+            id: market.id,
+            question: market.question.clone(),
+            yes_probability: LmsrPricing::implied_probability(market.q_yes, market.q_no, market.liquidity_param)
+                * 100.0,
+            status: market.status().to_string(),
+        })
+        .collect();
+
+    let standings = season_repo.standings(&season).await.unwrap_or_default();
+
+    let joined = match &user {
+        Some(user) => season_repo.is_participant(id, user.id).await.unwrap_or(false),
+        None => false,
+    };
+    let season_balance = match (&user, joined) {
+        (Some(user), true) => LedgerRepository::new(db.pool().clone())
+            .balance(LedgerAccount::Season(id, user.id))
+            .await
+            .ok(),
+        _ => None,
+    };
+
+    let template = SeasonDetailTemplate {
+        csrf_token: csrf.clone(),
+        season: SeasonDisplay::from(&season),
+        description: season.description.clone(),
+        markets,
+        standings,
+        archived: season.archived_at.is_some(),
+        season_balance,
+        can_join: user.is_some() && !joined && season.can_join(),
+        can_attach: user.as_ref().is_some_and(|u| u.role.has(Role::Admin)) && season.can_join(),
+        username: user.map(|u| u.username),
+    };
+    Ok(Html(template.render().unwrap()))
+}
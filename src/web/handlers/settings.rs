@@ -0,0 +1,231 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{
+    normalize_email, normalize_profile_text, validate_password, MAX_BIO_LEN, MAX_DISPLAY_NAME_LEN, MAX_EMAIL_LEN,
+    NO_PASSWORD_HASH,
+};
+use crate::repository::{IdentityRepository, TwoFactorRepository, UserRepository};
+use crate::web::middleware::CsrfToken;
+use crate::web::session::{set_user_session, RequireAuth};
+use axum::{
+    extract::State,
+    response::Html,
+    Form,
+};
+use askama::Template;
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+#[derive(Template)]
+#[template(path = "settings.html")]
+struct SettingsTemplate {
+    csrf_token: CsrfToken,
+    error: Option<String>,
+    success: Option<String>,
+    two_factor_enabled: bool,
+    recovery_codes_left: i64,
+    /// Accounts created through single sign-on have no password to confirm with
+    has_password: bool,
+    sso_login: bool,
+    linked_identities: Vec<IdentityDisplay>,
+    display_name: String,
+    bio: String,
+    hide_positions: bool,
+    max_display_name_len: usize,
+    max_bio_len: usize,
+    /// Mail delivery is configured, so an address enables password resets by email
+    email_enabled: bool,
+    email: String,
+    max_email_len: usize,
+    username: Option<String>,
+}
+
+struct IdentityDisplay {
+    issuer: String,
+    subject: String,
+    linked_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordForm {
+    current_password: String,
+    new_password: String,
+    confirm_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmailForm {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileForm {
+    display_name: String,
+    bio: String,
+    /// Present only when the checkbox is ticked
+    hide_positions: Option<String>,
+}
+
+pub async fn settings_page(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+) -> Html<String> {
+    render_settings(&db, &config, auth.user_id, csrf, None, None).await
+}
+
+/// Update the public profile's display name, bio and position visibility
+pub async fn update_profile(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<ProfileForm>,
+) -> Html<String> {
+    let display_name = match normalize_profile_text(&form.display_name, MAX_DISPLAY_NAME_LEN, "Display name") {
+        Ok(name) => name,
+        Err(e) => return render_settings(&db, &config, auth.user_id, csrf, Some(e), None).await,
+    };
+    let bio = match normalize_profile_text(&form.bio, MAX_BIO_LEN, "Bio") {
+        Ok(bio) => bio,
+        Err(e) => return render_settings(&db, &config, auth.user_id, csrf, Some(e), None).await,
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    if let Err(e) = user_repo
+        .update_profile(auth.user_id, display_name.as_deref(), bio.as_deref(), form.hide_positions.is_some())
+        .await
+    {
+        return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error updating profile: {}", e)), None).await;
+    }
+
+    render_settings(&db, &config, auth.user_id, csrf, None, Some("Profile updated.".to_string())).await
+}
+
+/// Set or clear the address password reset links are emailed to
+pub async fn update_email(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<EmailForm>,
+) -> Html<String> {
+    let email = match normalize_email(&form.email) {
+        Ok(email) => email,
+        Err(e) => return render_settings(&db, &config, auth.user_id, csrf, Some(e), None).await,
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    if let Err(e) = user_repo.update_email(auth.user_id, email.as_deref()).await {
+        return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error updating email: {}", e)), None).await;
+    }
+
+    let success = if email.is_some() { "Email saved." } else { "Email removed." };
+    render_settings(&db, &config, auth.user_id, csrf, None, Some(success.to_string())).await
+}
+
+/// Change the password after re-checking the current one
+///
+/// Every other session of the user is logged out; this one stays logged in.
+pub async fn change_password(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    session: Session,
+    Form(form): Form<ChangePasswordForm>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let user = match user_repo.find_by_id(auth.user_id).await {
+        Ok(user) => user,
+        Err(e) => return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error loading user: {}", e)), None).await,
+    };
+
+    if !bcrypt::verify(&form.current_password, &user.password_hash).unwrap_or(false) {
+        return render_settings(&db, &config, auth.user_id, csrf, Some("Current password is incorrect".to_string()), None).await;
+    }
+
+    if form.new_password != form.confirm_password {
+        return render_settings(&db, &config, auth.user_id, csrf, Some("New passwords do not match".to_string()), None).await;
+    }
+
+    if let Err(e) = validate_password(&form.new_password) {
+        return render_settings(&db, &config, auth.user_id, csrf, Some(e), None).await;
+    }
+
+    let password_hash = match bcrypt::hash(&form.new_password, bcrypt::DEFAULT_COST) {
+        Ok(hash) => hash,
+        Err(_) => return render_settings(&db, &config, auth.user_id, csrf, Some("Error processing password".to_string()), None).await,
+    };
+
+    let session_version = match user_repo.update_password(user.id, &password_hash).await {
+        Ok(version) => version,
+        Err(e) => return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error updating password: {}", e)), None).await,
+    };
+
+    // Keep this session valid under the new version and rotate its id
+    if set_user_session(&session, user.id, session_version).await.is_err() || session.cycle_id().await.is_err() {
+        return render_settings(&db, &config, auth.user_id, csrf, Some("Password changed, but your session could not be refreshed; log in again".to_string()), None).await;
+    }
+
+    tracing::info!("User {} changed their password", user.id);
+    render_settings(
+        &db,
+        &config,
+        auth.user_id,
+        csrf,
+        None,
+        Some("Password changed. Other sessions have been logged out.".to_string()),
+    )
+    .await
+}
+
+pub(crate) async fn render_settings(
+    db: &Database,
+    config: &Config,
+    user_id: i64,
+    csrf: CsrfToken,
+    error: Option<String>,
+    success: Option<String>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    let user = user_repo.find_by_id(user_id).await.ok();
+    let two_factor_enabled = two_factor_repo.is_enabled(user_id).await.unwrap_or(false);
+    let recovery_codes_left = two_factor_repo.remaining_recovery_codes(user_id).await.unwrap_or(0);
+    let identity_repo = IdentityRepository::new(db.pool().clone());
+    let linked_identities = identity_repo
+        .find_by_user(user_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|identity| IdentityDisplay {
+            issuer: identity.issuer,
+            subject: identity.subject,
+            linked_at: identity.created_at.format("%Y-%m-%d").to_string(),
+        })
+        .collect();
+
+    let template = SettingsTemplate {
+        csrf_token: csrf,
+        error,
+        success,
+        two_factor_enabled,
+        recovery_codes_left,
+        has_password: user.as_ref().is_some_and(|u| u.password_hash != NO_PASSWORD_HASH),
+        sso_login: config.oidc.is_some(),
+        linked_identities,
+        display_name: user.as_ref().and_then(|u| u.display_name.clone()).unwrap_or_default(),
+        bio: user.as_ref().and_then(|u| u.bio.clone()).unwrap_or_default(),
+        hide_positions: user.as_ref().is_some_and(|u| u.hide_positions),
+        max_display_name_len: MAX_DISPLAY_NAME_LEN,
+        max_bio_len: MAX_BIO_LEN,
+        email_enabled: config.mail.is_some(),
+        email: user.as_ref().and_then(|u| u.email.clone()).unwrap_or_default(),
+        max_email_len: MAX_EMAIL_LEN,
+        username: user.map(|u| u.username),
+    };
+    Html(template.render().unwrap())
+}
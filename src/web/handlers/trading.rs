@@ -1,24 +1,54 @@
 use crate::Database;
-use crate::repository::{MarketRepository, PositionRepository, UserRepository, PriceSnapshotRepository};
-use crate::domain::{LmsrPricing, MarketSide};
+use crate::config::Config;
+use crate::jobs::webhooks;
+use crate::repository::{
+    AllowanceRepository, IdempotencyClaim, IdempotencyRepository, MarketRepository, PositionRepository, UserRepository,
+    RepositoryError, SeasonRepository,
+};
+use crate::domain::{
+    validate_idempotency_key, IdempotencyStatus, Market, MarketSide, TradeAction, TradeQuote, UserId,
+    WebhookEvent, IDEMPOTENCY_KEY_HEADER, TRADE_RESULT_HEADER,
+};
 use crate::web::filters;
+use crate::web::handlers::{ListControls, ListParams};
+use crate::web::middleware::CsrfToken;
 use crate::web::session::RequireAuth;
 use axum::{
-    extract::{State, Path},
-    response::{Html, Redirect},
+    extract::{State, Path, Query},
+    http::{HeaderMap, HeaderValue},
+    response::{Html, IntoResponse, Redirect, Response},
     Form,
 };
 use askama::Template;
 use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a replay waits for the original request with the same key to finish
+const REPLAY_WAIT: Duration = Duration::from_secs(10);
+const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Template)]
 #[template(path = "positions.html")]
 struct PositionsTemplate {
+    csrf_token: CsrfToken,
     positions: Vec<PositionDisplay>,
     balance: f64,
+    allowance: Option<AllowanceDisplay>,
+    controls: ListControls,
+    error: Option<String>,
     username: Option<String>,
 }
 
+struct AllowanceDisplay {
+    period: String,
+    /// What a claim would grant now
+    amount: f64,
+    /// When the next claim is possible, if not now
+    next_claim_at: Option<String>,
+}
+
 struct PositionDisplay {
     market_id: i64,
     market_question: String,
@@ -37,26 +67,56 @@ struct PositionDisplay {
 pub struct TradeForm {
     shares: f64,
     side: String,
+    /// Hidden form field; the `Idempotency-Key` header takes precedence
+    idempotency_key: Option<String>,
+}
+
+impl TradeForm {
+    /// Identifies the trade a key was used for, so a key cannot be replayed for another trade
+    fn fingerprint(&self, action: TradeAction, market_id: i64) -> String {
+        format!("{}:{}:{}:{}", action, market_id, self.side, self.shares)
+    }
 }
 
 pub async fn buy_shares(
     auth: RequireAuth,
     State(db): State<Database>,
     Path(market_id): Path<i64>,
+    headers: HeaderMap,
     Form(form): Form<TradeForm>,
-) -> Result<Redirect, String> {
-    if form.shares <= 0.0 {
-        return Err("Shares must be positive".to_string());
-    }
+) -> Result<Response, String> {
+    let key = idempotency_key(&headers, &form)?;
+    let fingerprint = form.fingerprint(TradeAction::Buy, market_id);
+    let trade = execute_buy(&db, auth.user_id, market_id, &form);
+    idempotent(&db, auth.user_id, key, &fingerprint, &format!("/markets/{}", market_id), trade).await
+}
 
-    let side: MarketSide = form.side.parse()
-        .map_err(|e| format!("Invalid side: {}", e))?;
+pub async fn sell_shares(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(market_id): Path<i64>,
+    headers: HeaderMap,
+    Form(form): Form<TradeForm>,
+) -> Result<Response, String> {
+    let key = idempotency_key(&headers, &form)?;
+    let fingerprint = form.fingerprint(TradeAction::Sell, market_id);
+    let trade = execute_sell(&db, auth.user_id, market_id, &form);
+    idempotent(&db, auth.user_id, key, &fingerprint, &format!("/markets/{}", market_id), trade).await
+}
 
-    let market_repo = MarketRepository::new(db.pool().clone());
-    let user_repo = UserRepository::new(db.pool().clone());
-    let position_repo = PositionRepository::new(db.pool().clone());
+#[derive(Deserialize)]
+pub struct CompleteSetForm {
+    sets: f64,
+}
 
-    // Get market
+/// Buy complete sets, one YES and one NO share each, from the market maker for $1 a set
+pub async fn mint_sets(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(market_id): Path<i64>,
+    Form(form): Form<CompleteSetForm>,
+) -> Result<Redirect, String> {
+    let market_repo = MarketRepository::new(db.pool().clone());
     let market = market_repo
         .find_by_id(market_id)
         .await
@@ -65,75 +125,176 @@ pub async fn buy_shares(
     if !market.can_trade() {
         return Err("Market is not open for trading".to_string());
     }
+    check_season(&db, &market, auth.user_id).await?;
 
-    // Calculate cost using LMSR
-    let cost = LmsrPricing::calculate_buy_cost(
-        market.q_yes,
-        market.q_no,
-        form.shares,
-        side,
-        market.liquidity_param
-    ).map_err(|e| format!("Error calculating cost: {}", e))?;
+    let position_repo = PositionRepository::new(db.pool().clone());
+    match position_repo.mint_complete_sets(auth.user_id, market_id, form.sets).await {
+        Ok(()) => {}
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(e) => return Err(format!("Error minting sets: {}", e)),
+    }
 
-    let user_id = auth.user_id;
+    tracing::info!("User {} minted {:.2} complete sets in market {}", auth.user_id, form.sets, market_id);
+    Ok(Redirect::to(&format!("/markets/{}", market_id)))
+}
 
-    // Check user balance
-    let user = user_repo
-        .find_by_id(user_id)
+/// Redeem matched YES and NO shares for $1 a pair, without selling either side through the market maker
+pub async fn merge_sets(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(market_id): Path<i64>,
+    Form(form): Form<CompleteSetForm>,
+) -> Result<Redirect, String> {
+    let market_repo = MarketRepository::new(db.pool().clone());
+    let market = market_repo
+        .find_by_id(market_id)
         .await
-        .map_err(|_| "User not found".to_string())?;
+        .map_err(|_| "Market not found".to_string())?;
 
-    if !user.can_afford(cost) {
-        return Err("Insufficient balance".to_string());
+    // Pairs can still be merged once trading closes, until the market resolves
+    if market.resolved {
+        return Err("Market is already resolved".to_string());
     }
+    check_season(&db, &market, auth.user_id).await?;
 
-    // Deduct from user balance
-    user_repo
-        .deduct_balance(user_id, cost)
-        .await
-        .map_err(|e| format!("Error deducting balance: {}", e))?;
+    let position_repo = PositionRepository::new(db.pool().clone());
+    match position_repo.redeem_complete_sets(auth.user_id, market_id, form.sets).await {
+        Ok(()) => {}
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(e) => return Err(format!("Error merging shares: {}", e)),
+    }
+
+    tracing::info!("User {} merged {:.2} YES/NO pairs in market {}", auth.user_id, form.sets, market_id);
+    Ok(Redirect::to(&format!("/markets/{}", market_id)))
+}
 
-    // Update market outstanding shares (LMSR)
-    let (new_q_yes, new_q_no) = match side {
-        MarketSide::Yes => (market.q_yes + form.shares, market.q_no),
-        MarketSide::No => (market.q_yes, market.q_no + form.shares),
+/// Season markets only trade while their season is active, and only with its participants
+pub(crate) async fn check_season(db: &Database, market: &Market, user_id: UserId) -> Result<(), String> {
+    let Some(season_id) = market.season_id else {
+        return Ok(());
     };
 
-    market_repo
-        .update_outstanding_shares(market_id, new_q_yes, new_q_no)
+    let season_repo = SeasonRepository::new(db.pool().clone());
+    let season = season_repo
+        .find_by_id(season_id)
         .await
-        .map_err(|e| format!("Error updating outstanding shares: {}", e))?;
-
-    // Record price snapshot
-    let new_probability = LmsrPricing::implied_probability(new_q_yes, new_q_no, market.liquidity_param);
-    let snapshot_repo = PriceSnapshotRepository::new(db.pool().clone());
-    let _ = snapshot_repo
-        .create(market_id, new_probability, 1.0 - new_probability, new_q_yes, new_q_no)
-        .await;
-
-    // Update user position
-    let mut position = position_repo
-        .find_or_create(user_id, market_id, side)
+        .map_err(|_| "Season not found".to_string())?;
+
+    if !season.is_active() {
+        return Err(format!("Season {} is not active", season.name));
+    }
+    let joined = season_repo
+        .is_participant(season_id, user_id)
         .await
-        .map_err(|e| format!("Error getting position: {}", e))?;
+        .map_err(|e| format!("Error checking season: {}", e))?;
+    if !joined {
+        return Err(format!("Join season {} to trade this market", season.name));
+    }
+    Ok(())
+}
 
-    let price_per_share = cost / form.shares;
-    position.add_shares(form.shares, price_per_share);
+/// Read the key from the `Idempotency-Key` header, falling back to the form field
+fn idempotency_key(headers: &HeaderMap, form: &TradeForm) -> Result<Option<String>, String> {
+    let key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .map_err(|_| "Invalid Idempotency-Key header".to_string())?
+                .to_string(),
+        ),
+        None => form.idempotency_key.clone().filter(|k| !k.is_empty()),
+    };
 
-    position_repo
-        .update(position.id, position.shares, position.avg_price)
-        .await
-        .map_err(|e| format!("Error updating position: {}", e))?;
+    if let Some(ref key) = key {
+        validate_idempotency_key(key)?;
+    }
+    Ok(key)
+}
 
-    Ok(Redirect::to(&format!("/markets/{}", market_id)))
+/// Run `trade` at most once per idempotency key
+///
+/// Replays of a completed trade redirect to the original result and return the
+/// stored trade without trading again, and a replay that races the original
+/// waits for it to finish. `trade` runs in a single transaction, so when it
+/// fails nothing was written and the key is released for the form to be
+/// corrected and resubmitted. A key whose trade succeeded is never released.
+async fn idempotent<F>(
+    db: &Database,
+    user_id: UserId,
+    key: Option<String>,
+    fingerprint: &str,
+    location: &str,
+    trade: F,
+) -> Result<Response, String>
+where
+    F: Future<Output = Result<TradeQuote, String>>,
+{
+    let Some(key) = key else {
+        let quote = trade.await?;
+        return Ok(traded(location, Some(&quote)));
+    };
+
+    let idempotency_repo = IdempotencyRepository::new(db.pool().clone());
+    let deadline = Instant::now() + REPLAY_WAIT;
+    loop {
+        let claim = idempotency_repo
+            .claim(user_id, &key, fingerprint)
+            .await
+            .map_err(|e| format!("Error checking idempotency key: {}", e))?;
+
+        let record = match claim {
+            IdempotencyClaim::Claimed => break,
+            IdempotencyClaim::Existing(record) => record,
+        };
+
+        if !record.matches(fingerprint) {
+            return Err(
+                "This idempotency key was already used for a different trade; reload the page to trade again"
+                    .to_string(),
+            );
+        }
+
+        if record.status == IdempotencyStatus::Completed {
+            let location = record.response.as_deref().unwrap_or(location);
+            return Ok(([("Idempotent-Replayed", "true")], traded(location, record.result.as_ref())).into_response());
+        }
+
+        if Instant::now() >= deadline {
+            return Err("A trade with this idempotency key is still being processed".to_string());
+        }
+        tokio::time::sleep(REPLAY_POLL_INTERVAL).await;
+    }
+
+    match trade.await {
+        Ok(quote) => {
+            // The trade is committed; if this fails the key stays pending and replays are refused, never re-run
+            if let Err(e) = idempotency_repo.complete(user_id, &key, location, &quote).await {
+                tracing::error!("Failed to record idempotency key for user {}: {}", user_id, e);
+            }
+            Ok(traded(location, Some(&quote)))
+        }
+        Err(e) => {
+            if let Err(e) = idempotency_repo.release(user_id, &key).await {
+                tracing::error!("Failed to release idempotency key for user {}: {}", user_id, e);
+            }
+            Err(e)
+        }
+    }
 }
 
-pub async fn sell_shares(
-    auth: RequireAuth,
-    State(db): State<Database>,
-    Path(market_id): Path<i64>,
-    Form(form): Form<TradeForm>,
-) -> Result<Redirect, String> {
+/// Redirect to `location`, describing the executed trade in the `Trade-Result` header
+fn traded(location: &str, quote: Option<&TradeQuote>) -> Response {
+    let mut response = Redirect::to(location).into_response();
+    let header = quote
+        .and_then(|quote| serde_json::to_string(quote).ok())
+        .and_then(|json| HeaderValue::from_str(&json).ok());
+    if let Some(header) = header {
+        response.headers_mut().insert(TRADE_RESULT_HEADER, header);
+    }
+    response
+}
+
+async fn execute_buy(db: &Database, user_id: UserId, market_id: i64, form: &TradeForm) -> Result<TradeQuote, String> {
     if form.shares <= 0.0 {
         return Err("Shares must be positive".to_string());
     }
@@ -141,12 +302,7 @@ pub async fn sell_shares(
     let side: MarketSide = form.side.parse()
         .map_err(|e| format!("Invalid side: {}", e))?;
 
-    let market_repo = MarketRepository::new(db.pool().clone());
-    let user_repo = UserRepository::new(db.pool().clone());
-    let position_repo = PositionRepository::new(db.pool().clone());
-
-    // Get market
-    let market = market_repo
+    let market = MarketRepository::new(db.pool().clone())
         .find_by_id(market_id)
         .await
         .map_err(|_| "Market not found".to_string())?;
@@ -154,114 +310,174 @@ pub async fn sell_shares(
     if !market.can_trade() {
         return Err("Market is not open for trading".to_string());
     }
+    check_season(db, &market, user_id).await?;
 
-    let user_id = auth.user_id;
+    // Payment, outstanding shares, position and history are written in one transaction
+    let position_repo = PositionRepository::new(db.pool().clone());
+    let quote = match position_repo.buy(user_id, market_id, side, form.shares).await {
+        Ok(quote) => quote,
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(e) => return Err(format!("Error buying shares: {}", e)),
+    };
 
-    // Check user position
-    let mut position = position_repo
-        .find_by_user_market_side(user_id, market_id, side)
-        .await
-        .map_err(|_| "Position not found".to_string())?;
+    notify_trade(db, market_id, &quote).await;
+    Ok(quote)
+}
 
-    if position.shares < form.shares {
-        return Err("Insufficient shares to sell".to_string());
-    }
+async fn execute_sell(db: &Database, user_id: UserId, market_id: i64, form: &TradeForm) -> Result<TradeQuote, String> {
+    let side: MarketSide = form.side.parse()
+        .map_err(|e| format!("Invalid side: {}", e))?;
 
-    // Calculate proceeds using LMSR
-    let proceeds = LmsrPricing::calculate_sell_proceeds(
-        market.q_yes,
-        market.q_no,
-        form.shares,
-        side,
-        market.liquidity_param
-    ).map_err(|e| format!("Error calculating proceeds: {}", e))?;
-
-    // Add to user balance
-    user_repo
-        .add_balance(user_id, proceeds)
-        .await
-        .map_err(|e| format!("Error adding balance: {}", e))?;
+    sell_back(db, user_id, market_id, side, form.shares).await
+}
 
-    // Update market outstanding shares (LMSR)
-    let (new_q_yes, new_q_no) = match side {
-        MarketSide::Yes => (market.q_yes - form.shares, market.q_no),
-        MarketSide::No => (market.q_yes, market.q_no - form.shares),
-    };
+/// Sell shares back to the market maker
+pub(crate) async fn sell_back(
+    db: &Database,
+    user_id: UserId,
+    market_id: i64,
+    side: MarketSide,
+    shares: f64,
+) -> Result<TradeQuote, String> {
+    if shares <= 0.0 {
+        return Err("Shares must be positive".to_string());
+    }
 
-    market_repo
-        .update_outstanding_shares(market_id, new_q_yes, new_q_no)
+    let market = MarketRepository::new(db.pool().clone())
+        .find_by_id(market_id)
         .await
-        .map_err(|e| format!("Error updating outstanding shares: {}", e))?;
+        .map_err(|_| "Market not found".to_string())?;
 
-    // Record price snapshot
-    let new_probability = LmsrPricing::implied_probability(new_q_yes, new_q_no, market.liquidity_param);
-    let snapshot_repo = PriceSnapshotRepository::new(db.pool().clone());
-    let _ = snapshot_repo
-        .create(market_id, new_probability, 1.0 - new_probability, new_q_yes, new_q_no)
-        .await;
+    if !market.can_trade() {
+        return Err("Market is not open for trading".to_string());
+    }
+    check_season(db, &market, user_id).await?;
 
-    // Update user position
-    position.remove_shares(form.shares)
-        .map_err(|e| format!("Error removing shares: {}", e))?;
+    // The shares are only taken, and paid for, if the position still holds them
+    let position_repo = PositionRepository::new(db.pool().clone());
+    let quote = match position_repo.sell(user_id, market_id, side, shares).await {
+        Ok(quote) => quote,
+        Err(RepositoryError::ConstraintViolation(e)) => return Err(e),
+        Err(RepositoryError::NotFound) => return Err("Market not found".to_string()),
+        Err(e) => return Err(format!("Error selling shares: {}", e)),
+    };
 
-    position_repo
-        .update(position.id, position.shares, position.avg_price)
-        .await
-        .map_err(|e| format!("Error updating position: {}", e))?;
+    notify_trade(db, market_id, &quote).await;
+    Ok(quote)
+}
 
-    Ok(Redirect::to(&format!("/markets/{}", market_id)))
+/// Tell webhook subscribers about a trade once it has been committed
+async fn notify_trade(db: &Database, market_id: i64, quote: &TradeQuote) {
+    let trade = serde_json::json!({
+        "action": quote.action,
+        "side": quote.side,
+        "shares": quote.shares,
+        "amount": quote.amount,
+        "probability_after": quote.probability_after,
+    });
+    webhooks::notify_market(db, WebhookEvent::MarketTraded, market_id, trade).await;
 }
 
-pub async fn view_positions(auth: RequireAuth, State(db): State<Database>) -> Html<String> {
+pub async fn view_positions(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<ListParams>,
+) -> Html<String> {
     let user_id = auth.user_id;
 
     let position_repo = PositionRepository::new(db.pool().clone());
-    let market_repo = MarketRepository::new(db.pool().clone());
     let user_repo = UserRepository::new(db.pool().clone());
 
-    let positions = position_repo.find_by_user(user_id).await.unwrap_or_default();
+    let (query, error) = match params.to_query() {
+        Ok(query) => (query, None),
+        Err(e) => (Default::default(), Some(e)),
+    };
+
+    let (positions, next_cursor) = position_repo
+        .find_by_user_page(user_id, &query)
+        .await
+        .map(|p| (p.items, p.next_cursor))
+        .unwrap_or_default();
     let user = user_repo.find_by_id(user_id).await.unwrap();
 
     let mut positions_display = Vec::new();
-    for position in positions {
-        if let Ok(market) = market_repo.find_by_id(position.market_id).await {
-            let total_cost = position.shares * position.avg_price;
-            let payout_if_win = position.shares; // $1 per share
-            let profit_if_win = payout_if_win - total_cost;
-            let loss_if_lose = total_cost; // You lose what you paid
-
-            // Determine if position won (if market is resolved)
-            let won = if market.resolved {
-                if let Some(outcome) = market.outcome {
-                    (outcome && position.side == MarketSide::Yes) ||
-                    (!outcome && position.side == MarketSide::No)
-                } else {
-                    false
-                }
+    for (position, market) in positions {
+        let total_cost = position.shares * position.avg_price;
+        let payout_if_win = position.shares; // $1 per share
+        let profit_if_win = payout_if_win - total_cost;
+        let loss_if_lose = total_cost; // You lose what you paid
+
+        // Determine if position won (if market is resolved)
+        let won = if market.resolved {
+            if let Some(outcome) = market.outcome {
+                (outcome && position.side == MarketSide::Yes) ||
+                (!outcome && position.side == MarketSide::No)
             } else {
                 false
-            };
-
-            positions_display.push(PositionDisplay {
-                market_id: market.id,
-                market_question: market.question,
-                side: position.side.to_string(),
-                shares: position.shares,
-                avg_price: position.avg_price,
-                total_cost,
-                payout_if_win,
-                profit_if_win,
-                loss_if_lose,
-                market_resolved: market.resolved,
-                won,
-            });
-        }
+            }
+        } else {
+            false
+        };
+
+        positions_display.push(PositionDisplay {
+            market_id: market.id,
+            market_question: market.question,
+            side: position.side.to_string(),
+            shares: position.shares,
+            avg_price: position.avg_price,
+            total_cost,
+            payout_if_win,
+            profit_if_win,
+            loss_if_lose,
+            market_resolved: market.resolved,
+            won,
+        });
     }
 
+    let allowance = match config.allowance.period {
+        Some(period) => {
+            let allowance_repo = AllowanceRepository::new(db.pool().clone());
+            let last_claim = allowance_repo.last_claim(user_id).await.unwrap_or_default();
+            let next_claim_at = last_claim
+                .filter(|&last| !config.allowance.can_claim(Some(last), chrono::Utc::now()))
+                .and_then(|last| config.allowance.next_claim_at(last));
+            Some(AllowanceDisplay {
+                period: period.to_string(),
+                amount: config.allowance.grant_for(user.balance),
+                next_claim_at: next_claim_at.map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string()),
+            })
+        }
+        None => None,
+    };
+
     let template = PositionsTemplate {
+        csrf_token: csrf.clone(),
         positions: positions_display,
         balance: user.balance,
+        allowance,
+        controls: ListControls::new("/positions", &query, next_cursor.as_ref()),
+        error,
         username: Some(user.username),
     };
     Html(template.render().unwrap())
 }
+
+/// Claim the recurring allowance, topped up to the configured minimum balance
+pub async fn claim_allowance(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+) -> Result<Redirect, String> {
+    let allowance_repo = AllowanceRepository::new(db.pool().clone());
+    match allowance_repo.claim(auth.user_id, &config.allowance).await {
+        Ok(Some(amount)) => {
+            tracing::info!("User {} claimed an allowance of {:.2}", auth.user_id, amount);
+            Ok(Redirect::to("/positions"))
+        }
+        Ok(None) => Err("No allowance is due yet".to_string()),
+        Err(RepositoryError::ConstraintViolation(e)) => Err(e),
+        Err(e) => Err(format!("Error claiming allowance: {}", e)),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{normalize_memo, MAX_TRANSFER_MEMO_LEN};
+use crate::repository::{RepositoryError, UserRepository};
+use crate::web::filters;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::RequireAuth;
+use axum::{
+    extract::{Query, State},
+    response::{Html, Redirect},
+    Form,
+};
+use askama::Template;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Template)]
+#[template(path = "transfers.html")]
+struct TransfersTemplate {
+    csrf_token: CsrfToken,
+    transfers: Vec<TransferDisplay>,
+    balance: f64,
+    /// Recipient to fill in, e.g. when coming from a profile page
+    recipient: String,
+    max_amount: f64,
+    daily_limit: f64,
+    max_memo_len: usize,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+struct TransferDisplay {
+    /// The other party
+    counterparty: String,
+    sent: bool,
+    amount: f64,
+    memo: Option<String>,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct TransfersQuery {
+    to: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TransferForm {
+    recipient: String,
+    amount: String,
+    memo: Option<String>,
+}
+
+pub async fn transfers_page(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<TransfersQuery>,
+) -> Html<String> {
+    render_page(&db, &config, auth.user_id, &csrf, query.to.unwrap_or_default(), None).await
+}
+
+/// Send part of the balance to another user
+pub async fn send_transfer(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<TransferForm>,
+) -> Result<Redirect, Html<String>> {
+    let recipient = form.recipient.trim().to_string();
+    let fail = |error: String| {
+        let (db, config, csrf, recipient) = (db.clone(), config.clone(), csrf.clone(), recipient.clone());
+        async move { render_page(&db, &config, auth.user_id, &csrf, recipient, Some(error)).await }
+    };
+
+    let amount: f64 = match form.amount.trim().parse() {
+        Ok(amount) => amount,
+        Err(_) => return Err(fail("Invalid amount".to_string()).await),
+    };
+    let memo = match normalize_memo(form.memo.as_deref()) {
+        Ok(memo) => memo,
+        Err(e) => return Err(fail(e).await),
+    };
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    match user_repo
+        .transfer(auth.user_id, &recipient, amount, memo.as_deref(), &config.transfer_limits)
+        .await
+    {
+        Ok(transfer) => {
+            tracing::info!(
+                "User {} sent ${:.2} to user {} (transfer {})",
+                transfer.sender_id, transfer.amount, transfer.recipient_id, transfer.id
+            );
+            Ok(Redirect::to("/transfers"))
+        }
+        Err(RepositoryError::NotFound) => Err(fail(format!("User '{}' not found", recipient)).await),
+        Err(RepositoryError::ConstraintViolation(e)) => Err(fail(e).await),
+        Err(e) => Err(fail(format!("Error sending transfer: {}", e)).await),
+    }
+}
+
+async fn render_page(
+    db: &Database,
+    config: &Config,
+    user_id: i64,
+    csrf: &CsrfToken,
+    recipient: String,
+    error: Option<String>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let user = user_repo.find_by_id(user_id).await.ok();
+
+    let transfers = user_repo
+        .find_transfers(user_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| {
+            let sent = t.sender_id == user_id;
+            TransferDisplay {
+                counterparty: if sent { t.recipient } else { t.sender },
+                sent,
+                amount: t.amount,
+                memo: t.memo,
+                created_at: t.created_at.format("%Y-%m-%d %H:%M").to_string(),
+            }
+        })
+        .collect();
+
+    let template = TransfersTemplate {
+        csrf_token: csrf.clone(),
+        transfers,
+        balance: user.as_ref().map(|u| u.balance).unwrap_or_default(),
+        recipient,
+        max_amount: config.transfer_limits.max_amount,
+        daily_limit: config.transfer_limits.daily_limit,
+        max_memo_len: MAX_TRANSFER_MEMO_LEN,
+        error,
+        username: user.map(|u| u.username),
+    };
+    Html(template.render().unwrap())
+}
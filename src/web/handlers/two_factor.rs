@@ -0,0 +1,275 @@
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{
+    generate_recovery_codes, hash_recovery_code, is_recovery_code, TotpCredential, TotpSecret, RECOVERY_CODE_COUNT,
+};
+use crate::repository::{RepositoryError, TwoFactorRepository, UserRepository};
+use crate::web::handlers::settings::render_settings;
+use crate::web::middleware::CsrfToken;
+use crate::web::session::RequireAuth;
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use askama::Template;
+use chrono::Utc;
+use qrcode::{render::svg, QrCode};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Issuer shown by authenticator apps next to the account name
+const TOTP_ISSUER: &str = "Prediction Market";
+
+/// Secret being enrolled, kept in the session until the user confirms a code
+const SESSION_SETUP_SECRET_KEY: &str = "totp_setup_secret";
+
+#[derive(Template)]
+#[template(path = "two_factor_setup.html")]
+struct SetupTemplate {
+    csrf_token: CsrfToken,
+    secret: String,
+    qr_svg: String,
+    required: bool,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "recovery_codes.html")]
+struct RecoveryCodesTemplate {
+    csrf_token: CsrfToken,
+    codes: Vec<String>,
+    username: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EnableForm {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableForm {
+    password: String,
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegenerateForm {
+    code: String,
+}
+
+/// Start enrollment: show a new secret as a QR code, to be confirmed with a code
+pub async fn setup_page(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    session: Session,
+) -> Response {
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    if two_factor_repo.is_enabled(auth.user_id).await.unwrap_or(false) {
+        return Redirect::to("/settings").into_response();
+    }
+
+    render_setup(&db, &config, &session, auth.user_id, csrf, None).await.into_response()
+}
+
+/// Confirm enrollment with a code from the authenticator and issue recovery codes
+pub async fn enable(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    session: Session,
+    Form(form): Form<EnableForm>,
+) -> Html<String> {
+    let secret = session
+        .get::<String>(SESSION_SETUP_SECRET_KEY)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|s| TotpSecret::from_base32(&s));
+    let Some(secret) = secret else {
+        return render_setup(&db, &config, &session, auth.user_id, csrf, Some("Setup expired; scan the new code".to_string())).await;
+    };
+
+    let Some(step) = secret.verify(&form.code, Utc::now()) else {
+        return render_setup(
+            &db,
+            &config,
+            &session,
+            auth.user_id,
+            csrf,
+            Some("That code is not valid; check your device's clock and try again".to_string()),
+        )
+        .await;
+    };
+
+    let (codes, hashes): (Vec<_>, Vec<_>) = generate_recovery_codes().into_iter().unzip();
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+    if let Err(e) = two_factor_repo.enable(auth.user_id, &secret, step, &hashes).await {
+        return render_setup(&db, &config, &session, auth.user_id, csrf, Some(format!("Error enabling two-factor authentication: {}", e))).await;
+    }
+    let _ = session.remove::<String>(SESSION_SETUP_SECRET_KEY).await;
+
+    tracing::info!("User {} enabled two-factor authentication", auth.user_id);
+    render_recovery_codes(&db, auth.user_id, csrf, codes).await
+}
+
+/// Turn two-factor authentication off after re-checking the password and a code
+pub async fn disable(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<DisableForm>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+
+    let user = match user_repo.find_by_id(auth.user_id).await {
+        Ok(user) => user,
+        Err(e) => return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error loading user: {}", e)), None).await,
+    };
+
+    if config.two_factor.requires_for_role(user.role) {
+        return render_settings(
+            &db,
+            &config,
+            auth.user_id,
+            csrf,
+            Some(format!("Two-factor authentication is required for the {} role", user.role)),
+            None,
+        )
+        .await;
+    }
+
+    if !bcrypt::verify(&form.password, &user.password_hash).unwrap_or(false) {
+        return render_settings(&db, &config, auth.user_id, csrf, Some("Password is incorrect".to_string()), None).await;
+    }
+
+    let credential = match two_factor_repo.find(auth.user_id).await {
+        Ok(Some(credential)) => credential,
+        _ => return render_settings(&db, &config, auth.user_id, csrf, Some("Two-factor authentication is not enabled".to_string()), None).await,
+    };
+
+    if !verify_second_factor(&two_factor_repo, &credential, &form.code).await.unwrap_or(false) {
+        return render_settings(&db, &config, auth.user_id, csrf, Some("Authentication code is incorrect".to_string()), None).await;
+    }
+
+    if let Err(e) = two_factor_repo.disable(auth.user_id).await {
+        return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error disabling two-factor authentication: {}", e)), None).await;
+    }
+
+    tracing::info!("User {} disabled two-factor authentication", auth.user_id);
+    render_settings(&db, &config, auth.user_id, csrf, None, Some("Two-factor authentication disabled.".to_string())).await
+}
+
+/// Replace the recovery codes, invalidating the old ones
+pub async fn regenerate_recovery_codes(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    State(config): State<Arc<Config>>,
+    Form(form): Form<RegenerateForm>,
+) -> Html<String> {
+    let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+
+    let credential = match two_factor_repo.find(auth.user_id).await {
+        Ok(Some(credential)) => credential,
+        _ => return render_settings(&db, &config, auth.user_id, csrf, Some("Two-factor authentication is not enabled".to_string()), None).await,
+    };
+
+    if !verify_second_factor(&two_factor_repo, &credential, &form.code).await.unwrap_or(false) {
+        return render_settings(&db, &config, auth.user_id, csrf, Some("Authentication code is incorrect".to_string()), None).await;
+    }
+
+    let (codes, hashes): (Vec<_>, Vec<_>) = generate_recovery_codes().into_iter().unzip();
+    if let Err(e) = two_factor_repo.replace_recovery_codes(auth.user_id, &hashes).await {
+        return render_settings(&db, &config, auth.user_id, csrf, Some(format!("Error replacing recovery codes: {}", e)), None).await;
+    }
+
+    tracing::info!("User {} regenerated their recovery codes", auth.user_id);
+    render_recovery_codes(&db, auth.user_id, csrf, codes).await
+}
+
+/// Accept either a current TOTP code or an unused recovery code, consuming it
+///
+/// A TOTP code is accepted once: replaying it, even within its 30 seconds, fails.
+pub(crate) async fn verify_second_factor(
+    repo: &TwoFactorRepository,
+    credential: &TotpCredential,
+    code: &str,
+) -> Result<bool, RepositoryError> {
+    if let Some(step) = credential.secret.verify(code, Utc::now()) {
+        return repo.use_step(credential.user_id, step).await;
+    }
+
+    if is_recovery_code(code) && repo.use_recovery_code(credential.user_id, &hash_recovery_code(code)).await? {
+        let remaining = repo.remaining_recovery_codes(credential.user_id).await?;
+        tracing::warn!(
+            "User {} used a recovery code; {} of {} left",
+            credential.user_id, remaining, RECOVERY_CODE_COUNT
+        );
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+async fn render_setup(
+    db: &Database,
+    config: &Config,
+    session: &Session,
+    user_id: i64,
+    csrf: CsrfToken,
+    error: Option<String>,
+) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let user = user_repo.find_by_id(user_id).await.ok();
+
+    // Keep showing the same secret until enrollment completes, so a rescan isn't needed on a typo
+    let secret = session
+        .get::<String>(SESSION_SETUP_SECRET_KEY)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|s| TotpSecret::from_base32(&s));
+    let secret = match secret {
+        Some(secret) => secret,
+        None => {
+            let secret = TotpSecret::generate();
+            let _ = session.insert(SESSION_SETUP_SECRET_KEY, secret.to_base32()).await;
+            secret
+        }
+    };
+
+    let account = user.as_ref().map(|u| u.username.as_str()).unwrap_or_default();
+    let qr_svg = QrCode::new(secret.provisioning_uri(TOTP_ISSUER, account))
+        .map(|code| code.render::<svg::Color>().min_dimensions(200, 200).build())
+        .unwrap_or_default();
+
+    let template = SetupTemplate {
+        csrf_token: csrf,
+        secret: secret.to_base32(),
+        qr_svg,
+        required: user.as_ref().is_some_and(|u| config.two_factor.requires_for_role(u.role)),
+        error,
+        username: user.map(|u| u.username),
+    };
+    Html(template.render().unwrap())
+}
+
+async fn render_recovery_codes(db: &Database, user_id: i64, csrf: CsrfToken, codes: Vec<String>) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let username = user_repo.find_by_id(user_id).await.ok().map(|u| u.username);
+
+    let template = RecoveryCodesTemplate {
+        csrf_token: csrf,
+        codes,
+        username,
+    };
+    Html(template.render().unwrap())
+}
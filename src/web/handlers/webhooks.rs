@@ -0,0 +1,214 @@
+use crate::Database;
+use crate::jobs::webhooks;
+use crate::domain::{WebhookEvent, MAX_DELIVERY_ATTEMPTS};
+use crate::repository::{MarketRepository, UserRepository, WebhookRepository};
+use crate::web::middleware::CsrfToken;
+use crate::web::session::RequireAuth;
+use axum::{
+    extract::{State, Path},
+    response::{Html, Redirect},
+    Form,
+};
+use askama::Template;
+use rand::RngCore;
+use serde::Deserialize;
+
+/// Number of deliveries shown in the log
+const DELIVERY_LOG_SIZE: i64 = 50;
+
+#[derive(Template)]
+#[template(path = "webhooks.html")]
+struct WebhooksTemplate {
+    csrf_token: CsrfToken,
+    subscriptions: Vec<SubscriptionDisplay>,
+    deliveries: Vec<DeliveryDisplay>,
+    events: Vec<String>,
+    max_attempts: i64,
+    error: Option<String>,
+    username: Option<String>,
+}
+
+struct SubscriptionDisplay {
+    id: i64,
+    url: String,
+    secret: String,
+    market_id: Option<i64>,
+    events: String,
+    created_at: String,
+}
+
+struct DeliveryDisplay {
+    id: i64,
+    subscription_id: i64,
+    event: String,
+    status: String,
+    attempts: i64,
+    last_status_code: Option<i64>,
+    last_error: Option<String>,
+    created_at: String,
+    next_attempt_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookForm {
+    url: String,
+    market_id: Option<String>,
+    #[serde(rename = "market.created")]
+    market_created: Option<String>,
+    #[serde(rename = "market.traded")]
+    market_traded: Option<String>,
+    #[serde(rename = "market.closed")]
+    market_closed: Option<String>,
+    #[serde(rename = "market.resolved")]
+    market_resolved: Option<String>,
+}
+
+impl CreateWebhookForm {
+    fn events(&self) -> Vec<WebhookEvent> {
+        [
+            (WebhookEvent::MarketCreated, &self.market_created),
+            (WebhookEvent::MarketTraded, &self.market_traded),
+            (WebhookEvent::MarketClosed, &self.market_closed),
+            (WebhookEvent::MarketResolved, &self.market_resolved),
+        ]
+        .into_iter()
+        .filter(|(_, checked)| checked.is_some())
+        .map(|(event, _)| event)
+        .collect()
+    }
+}
+
+pub async fn webhooks_page(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+) -> Html<String> {
+    render_page(&db, auth.user_id, &csrf, None).await
+}
+
+pub async fn create_webhook(
+    auth: RequireAuth,
+    csrf: CsrfToken,
+    State(db): State<Database>,
+    Form(form): Form<CreateWebhookForm>,
+) -> Result<Redirect, Html<String>> {
+    let url = form.url.trim();
+    if !(url.starts_with("http://") || url.starts_with("https://")) || reqwest::Url::parse(url).is_err() {
+        return Err(render_page(&db, auth.user_id, &csrf, Some("URL must be a valid http(s) address".to_string())).await);
+    }
+    if let Err(e) = webhooks::check_public_url(url).await {
+        return Err(render_page(&db, auth.user_id, &csrf, Some(format!("URL must be publicly reachable: {}", e))).await);
+    }
+
+    let events = form.events();
+    if events.is_empty() {
+        return Err(render_page(&db, auth.user_id, &csrf, Some("Select at least one event".to_string())).await);
+    }
+
+    // An empty market id subscribes to every market
+    let market_id = match form.market_id.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(s) => {
+            let id = match s.parse::<i64>() {
+                Ok(id) => id,
+                Err(_) => return Err(render_page(&db, auth.user_id, &csrf, Some("Invalid market id".to_string())).await),
+            };
+            let market_repo = MarketRepository::new(db.pool().clone());
+            if market_repo.find_by_id(id).await.is_err() {
+                return Err(render_page(&db, auth.user_id, &csrf, Some(format!("Market {} not found", id))).await);
+            }
+            Some(id)
+        }
+    };
+
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+    match webhook_repo
+        .create_subscription(auth.user_id, market_id, url, &hex::encode(secret), &events)
+        .await
+    {
+        Ok(_) => Ok(Redirect::to("/webhooks")),
+        Err(e) => Err(render_page(&db, auth.user_id, &csrf, Some(format!("Error creating webhook: {}", e))).await),
+    }
+}
+
+pub async fn delete_webhook(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, String> {
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+    webhook_repo
+        .delete_subscription(id, auth.user_id)
+        .await
+        .map_err(|_| "Webhook not found".to_string())?;
+
+    Ok(Redirect::to("/webhooks"))
+}
+
+pub async fn retry_delivery(
+    auth: RequireAuth,
+    State(db): State<Database>,
+    Path(id): Path<i64>,
+) -> Result<Redirect, String> {
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+    webhook_repo
+        .retry(id, auth.user_id)
+        .await
+        .map_err(|_| "Failed delivery not found".to_string())?;
+
+    Ok(Redirect::to("/webhooks"))
+}
+
+async fn render_page(db: &Database, user_id: i64, csrf: &CsrfToken, error: Option<String>) -> Html<String> {
+    let user_repo = UserRepository::new(db.pool().clone());
+    let webhook_repo = WebhookRepository::new(db.pool().clone());
+
+    let username = user_repo.find_by_id(user_id).await.ok().map(|u| u.username);
+
+    let subscriptions = webhook_repo
+        .find_subscriptions_by_user(user_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| SubscriptionDisplay {
+            id: s.id,
+            url: s.url,
+            secret: s.secret,
+            market_id: s.market_id,
+            events: s.events.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", "),
+            created_at: s.created_at.format("%Y-%m-%d %H:%M").to_string(),
+        })
+        .collect();
+
+    let deliveries = webhook_repo
+        .find_recent_by_user(user_id, DELIVERY_LOG_SIZE)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|d| DeliveryDisplay {
+            id: d.id,
+            subscription_id: d.subscription_id,
+            event: d.event,
+            status: d.status.to_string(),
+            attempts: d.attempts,
+            last_status_code: d.last_status_code,
+            last_error: d.last_error,
+            created_at: d.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            next_attempt_at: d.next_attempt_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        })
+        .collect();
+
+    let template = WebhooksTemplate {
+        csrf_token: csrf.clone(),
+        subscriptions,
+        deliveries,
+        events: WebhookEvent::ALL.iter().map(|e| e.to_string()).collect(),
+        max_attempts: MAX_DELIVERY_ATTEMPTS,
+        error,
+        username,
+    };
+    Html(template.render().unwrap())
+}
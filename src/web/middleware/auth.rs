@@ -1,4 +0,0 @@
-// TODO: Implement session-based authentication middleware
-// For now, this is a placeholder
-
-pub struct AuthMiddleware;
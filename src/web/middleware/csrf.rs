@@ -0,0 +1,137 @@
+//! Cross-site request forgery protection for cookie-authenticated forms
+//!
+//! Every session carries a random token that pages embed in their forms as a
+//! hidden `csrf_token` field (see `templates/csrf_field.html`). State-changing
+//! requests must echo it back, either in that field or in an `X-CSRF-Token`
+//! header, which a third-party page cannot read or forge.
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rand::RngCore;
+use serde::Deserialize;
+use tower_sessions::Session;
+
+const SESSION_CSRF_TOKEN_KEY: &str = "csrf_token";
+
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Largest form body buffered while looking for the token
+const MAX_FORM_BYTES: usize = 2 * 1024 * 1024;
+
+/// The current session's CSRF token, created on first use
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+impl CsrfToken {
+    async fn from_session(session: &Session) -> Result<Self, tower_sessions::session::Error> {
+        if let Some(token) = session.get::<String>(SESSION_CSRF_TOKEN_KEY).await? {
+            return Ok(CsrfToken(token));
+        }
+
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+        session.insert(SESSION_CSRF_TOKEN_KEY, &token).await?;
+        Ok(CsrfToken(token))
+    }
+}
+
+impl std::fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CsrfToken
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Session error").into_response())?;
+
+        CsrfToken::from_session(&session)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Session error").into_response())
+    }
+}
+
+#[derive(Deserialize)]
+struct CsrfField {
+    csrf_token: Option<String>,
+}
+
+/// Reject state-changing requests that don't carry the session's CSRF token
+pub async fn verify_csrf(session: Session, request: Request, next: Next) -> Response {
+    if is_safe_method(request.method()) {
+        return next.run(request).await;
+    }
+
+    let expected = match session.get::<String>(SESSION_CSRF_TOKEN_KEY).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return forbidden(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Session error").into_response(),
+    };
+
+    if let Some(token) = request.headers().get(CSRF_HEADER) {
+        if token.to_str().is_ok_and(|t| tokens_match(t, &expected)) {
+            return next.run(request).await;
+        }
+        return forbidden();
+    }
+
+    if !is_form(request.headers()) {
+        return forbidden();
+    }
+
+    // Buffer the form to read the token, then hand the same bytes to the handler
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_FORM_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response(),
+    };
+
+    let submitted = serde_urlencoded::from_bytes::<CsrfField>(&bytes)
+        .ok()
+        .and_then(|field| field.csrf_token);
+    match submitted {
+        Some(token) if tokens_match(&token, &expected) => {
+            next.run(Request::from_parts(parts, Body::from(bytes))).await
+        }
+        _ => forbidden(),
+    }
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+fn is_form(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+/// Constant-time comparison so the token can't be guessed byte by byte
+fn tokens_match(submitted: &str, expected: &str) -> bool {
+    submitted.len() == expected.len()
+        && submitted
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn forbidden() -> Response {
+    (StatusCode::FORBIDDEN, "Invalid or missing CSRF token; reload the page and try again").into_response()
+}
@@ -1,3 +1,5 @@
-pub mod auth;
+pub mod client_ip;
+pub mod csrf;
 
-pub use auth::AuthMiddleware;
+pub use client_ip::ClientIp;
+pub use csrf::{verify_csrf, CsrfToken};
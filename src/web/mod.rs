@@ -4,27 +4,110 @@ pub mod filters;
 pub mod session;
 
 use crate::Database;
+use crate::config::Config;
+use crate::oidc::{OidcClient, OidcError};
 use axum::{
     Router,
+    extract::FromRef,
     routing::{get, post},
 };
+use std::sync::Arc;
 use tower_http::{trace::TraceLayer, services::ServeDir};
 
-pub fn create_router() -> Router<Database> {
+/// State shared by all handlers; extract `State<Database>`, `State<Arc<Config>>`
+/// or `State<Option<Arc<OidcClient>>>` from it
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Database,
+    pub config: Arc<Config>,
+    /// Single sign-on client, when a provider is configured
+    pub oidc: Option<Arc<OidcClient>>,
+}
+
+impl AppState {
+    pub fn new(db: Database, config: Config) -> Result<Self, OidcError> {
+        let oidc = config.oidc.clone().map(OidcClient::new).transpose()?.map(Arc::new);
+        Ok(Self { db, config: Arc::new(config), oidc })
+    }
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<OidcClient>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.oidc.clone()
+    }
+}
+
+pub fn create_router() -> Router<AppState> {
     Router::new()
         .route("/", get(handlers::home))
         .route("/signup", get(handlers::auth::signup_page).post(handlers::auth::signup))
         .route("/login", get(handlers::auth::login_page).post(handlers::auth::login))
+        .route("/login/2fa", get(handlers::auth::two_factor_page).post(handlers::auth::verify_two_factor))
         .route("/logout", post(handlers::auth::logout))
+        .route("/auth/oidc/login", get(handlers::oidc::start_login))
+        .route("/auth/oidc/callback", get(handlers::oidc::callback))
+        .route("/forgot-password", get(handlers::auth::forgot_password_page).post(handlers::auth::forgot_password))
+        .route("/reset-password", get(handlers::auth::reset_password_page).post(handlers::auth::reset_password))
+        .route("/settings", get(handlers::settings::settings_page))
+        .route("/settings/profile", post(handlers::settings::update_profile))
+        .route("/settings/email", post(handlers::settings::update_email))
+        .route("/settings/export", get(handlers::account::export_data))
+        .route("/settings/delete", post(handlers::account::delete_account))
+        .route("/settings/password", post(handlers::settings::change_password))
+        .route("/settings/2fa", get(handlers::two_factor::setup_page).post(handlers::two_factor::enable))
+        .route("/settings/2fa/disable", post(handlers::two_factor::disable))
+        .route("/settings/2fa/recovery-codes", post(handlers::two_factor::regenerate_recovery_codes))
         .route("/markets", get(handlers::markets::list_markets))
         .route("/markets/new", get(handlers::markets::new_market_page).post(handlers::markets::create_market))
         .route("/markets/:id", get(handlers::markets::view_market))
         .route("/markets/:id/resolve", post(handlers::markets::resolve_market))
+        .route("/markets/:id/close", post(handlers::markets::close_market))
+        .route("/markets/:id/liquidity", post(handlers::markets::add_liquidity))
+        .route("/market-maker", get(handlers::market_maker::dashboard))
+        .route("/seasons", get(handlers::seasons::seasons_page).post(handlers::seasons::create_season))
+        .route("/seasons/:id", get(handlers::seasons::view_season))
+        .route("/seasons/:id/join", post(handlers::seasons::join_season))
+        .route("/seasons/:id/markets", post(handlers::seasons::attach_market))
+        .route("/leaderboard", get(handlers::leaderboard::leaderboard_page))
         .route("/trade/:market_id/buy", post(handlers::trading::buy_shares))
         .route("/trade/:market_id/sell", post(handlers::trading::sell_shares))
+        .route("/trade/:market_id/mint", post(handlers::trading::mint_sets))
+        .route("/trade/:market_id/merge", post(handlers::trading::merge_sets))
         .route("/positions", get(handlers::trading::view_positions))
+        .route("/positions/allowance", post(handlers::trading::claim_allowance))
+        .route("/users/:username", get(handlers::profiles::view_profile))
+        .route("/transfers", get(handlers::transfers::transfers_page).post(handlers::transfers::send_transfer))
+        .route("/invites", get(handlers::invites::invites_page).post(handlers::invites::create_invite))
+        .route("/invites/:id/revoke", post(handlers::invites::revoke_invite))
+        .route("/webhooks", get(handlers::webhooks::webhooks_page).post(handlers::webhooks::create_webhook))
+        .route("/webhooks/:id/delete", post(handlers::webhooks::delete_webhook))
+        .route("/webhooks/deliveries/:id/retry", post(handlers::webhooks::retry_delivery))
+        .route("/admin/users", get(handlers::admin::users_page))
+        .route("/admin/users/:id/role", post(handlers::admin::update_role))
+        .route("/admin/users/:id/reset-token", post(handlers::admin::issue_reset_token))
+        .route("/admin/check", get(handlers::admin::check_page))
+        .route("/admin/check/repair", post(handlers::admin::repair))
+        .route("/api/markets", get(handlers::api::list_markets))
+        .route("/api/positions", get(handlers::api::list_positions))
         .route("/api/markets/:market_id/price-history", get(handlers::api::get_price_history))
         .route("/api/markets/:market_id/calculate-cost", get(handlers::api::calculate_buy_cost))
+        .route("/api/markets/:market_id/quote", get(handlers::api::quote_trade))
+        .route("/api/market-maker", get(handlers::market_maker::exposure_json))
+        .route("/api/seasons/:id/standings", get(handlers::seasons::standings_json))
+        .route("/api/leaderboard", get(handlers::leaderboard::leaderboard_json))
         .nest_service("/static", ServeDir::new("static"))
+        .layer(axum::middleware::from_fn(middleware::verify_csrf))
         .layer(TraceLayer::new_for_http())
 }
@@ -1,14 +1,24 @@
-use crate::domain::UserId;
+use crate::Database;
+use crate::config::Config;
+use crate::domain::{Role, UserId};
+use crate::repository::{RepositoryError, TwoFactorRepository, UserRepository};
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{FromRef, FromRequestParts},
     http::{request::Parts, StatusCode},
     response::{IntoResponse, Response, Redirect},
 };
 use tower_sessions::Session;
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 const SESSION_USER_ID_KEY: &str = "user_id";
+const SESSION_VERSION_KEY: &str = "session_version";
+const SESSION_PENDING_2FA_KEY: &str = "pending_2fa";
+
+/// How long the second login step may take after the password was accepted
+const PENDING_2FA_TTL_SECS: i64 = 300;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthSession {
@@ -22,8 +32,56 @@ impl AuthSession {
 }
 
 /// Helper to set the current user in the session
-pub async fn set_user_session(session: &Session, user_id: UserId) -> Result<(), tower_sessions::session::Error> {
-    session.insert(SESSION_USER_ID_KEY, user_id).await
+///
+/// `session_version` is the user's current `User::session_version`; bumping it
+/// in the database logs out every session created before the bump.
+pub async fn set_user_session(
+    session: &Session,
+    user_id: UserId,
+    session_version: i64,
+) -> Result<(), tower_sessions::session::Error> {
+    session.insert(SESSION_USER_ID_KEY, user_id).await?;
+    session.insert(SESSION_VERSION_KEY, session_version).await
+}
+
+/// A login whose password was accepted but which still needs a second factor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTwoFactor {
+    pub user_id: UserId,
+    pub username: String,
+    pub session_version: i64,
+    /// Unix timestamp of the password step
+    pub started_at: i64,
+}
+
+/// Remember that `user_id` passed the password step; the session stays logged out
+pub async fn set_pending_two_factor(
+    session: &Session,
+    user_id: UserId,
+    username: &str,
+    session_version: i64,
+) -> Result<(), tower_sessions::session::Error> {
+    let pending = PendingTwoFactor {
+        user_id,
+        username: username.to_string(),
+        session_version,
+        started_at: chrono::Utc::now().timestamp(),
+    };
+    session.insert(SESSION_PENDING_2FA_KEY, pending).await
+}
+
+/// The pending second login step, unless it has expired
+pub async fn get_pending_two_factor(session: &Session) -> Option<PendingTwoFactor> {
+    let pending = session
+        .get::<PendingTwoFactor>(SESSION_PENDING_2FA_KEY)
+        .await
+        .ok()
+        .flatten()?;
+    (chrono::Utc::now().timestamp() - pending.started_at < PENDING_2FA_TTL_SECS).then_some(pending)
+}
+
+pub async fn clear_pending_two_factor(session: &Session) -> Result<(), tower_sessions::session::Error> {
+    session.remove::<PendingTwoFactor>(SESSION_PENDING_2FA_KEY).await.map(|_| ())
 }
 
 /// Helper to get the current user from the session
@@ -37,6 +95,32 @@ pub async fn clear_user_session(session: &Session) -> Result<(), tower_sessions:
     session.flush().await
 }
 
+/// The session's user, provided the session hasn't been invalidated since login
+///
+/// Invalidated sessions (password changed or reset elsewhere) are cleared.
+async fn authenticated_user(session: &Session, db: &Database) -> Option<UserId> {
+    let user_id = get_user_session(session).await?;
+    let version = session
+        .get::<i64>(SESSION_VERSION_KEY)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+
+    let user_repo = UserRepository::new(db.pool().clone());
+    match user_repo.session_version(user_id).await {
+        Ok(current) if current == version => Some(user_id),
+        Ok(_) | Err(RepositoryError::NotFound) => {
+            let _ = clear_user_session(session).await;
+            None
+        }
+        Err(e) => {
+            tracing::error!("Failed to check session of user {}: {}", user_id, e);
+            None
+        }
+    }
+}
+
 /// Extractor that requires authentication
 pub struct RequireAuth {
     pub user_id: UserId,
@@ -46,6 +130,7 @@ pub struct RequireAuth {
 impl<S> FromRequestParts<S> for RequireAuth
 where
     S: Send + Sync,
+    Database: FromRef<S>,
 {
     type Rejection = Response;
 
@@ -56,7 +141,7 @@ where
                 (StatusCode::INTERNAL_SERVER_ERROR, "Session error").into_response()
             })?;
 
-        match get_user_session(&session).await {
+        match authenticated_user(&session, &Database::from_ref(state)).await {
             Some(user_id) => Ok(RequireAuth { user_id }),
             None => Err(Redirect::to("/login").into_response()),
         }
@@ -72,6 +157,7 @@ pub struct OptionalAuth {
 impl<S> FromRequestParts<S> for OptionalAuth
 where
     S: Send + Sync,
+    Database: FromRef<S>,
 {
     type Rejection = Response;
 
@@ -83,7 +169,83 @@ where
             })?;
 
         Ok(OptionalAuth {
-            user_id: get_user_session(&session).await,
+            user_id: authenticated_user(&session, &Database::from_ref(state)).await,
+        })
+    }
+}
+
+/// Minimum role required by a `RequireRole` extractor
+pub trait MinimumRole {
+    const ROLE: Role;
+}
+
+/// Marker for routes restricted to moderators and admins
+pub struct Moderator;
+
+impl MinimumRole for Moderator {
+    const ROLE: Role = Role::Moderator;
+}
+
+/// Marker for routes restricted to admins
+pub struct Admin;
+
+impl MinimumRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Extractor that requires a logged-in user holding at least the role `R`
+///
+/// The role is read from the database on every request, so a demotion takes
+/// effect immediately rather than at the next login. Users whose role the
+/// two-factor policy covers are refused until they have enrolled.
+pub struct RequireRole<R: MinimumRole> {
+    pub user_id: UserId,
+    pub role: Role,
+    _marker: PhantomData<R>,
+}
+
+pub type RequireModerator = RequireRole<Moderator>;
+pub type RequireAdmin = RequireRole<Admin>;
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    Database: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    R: MinimumRole,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let RequireAuth { user_id } = RequireAuth::from_request_parts(parts, state).await?;
+
+        let db = Database::from_ref(state);
+        let user_repo = UserRepository::new(db.pool().clone());
+        let user = user_repo
+            .find_by_id(user_id)
+            .await
+            .map_err(|_| Redirect::to("/login").into_response())?;
+
+        if !user.role.has(R::ROLE) {
+            return Err((StatusCode::FORBIDDEN, "You don't have permission to access this page").into_response());
+        }
+
+        if Arc::<Config>::from_ref(state).two_factor.requires_for_role(user.role) {
+            let two_factor_repo = TwoFactorRepository::new(db.pool().clone());
+            if !two_factor_repo.is_enabled(user_id).await.unwrap_or(false) {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    "Your role requires two-factor authentication; enable it in settings first",
+                )
+                    .into_response());
+            }
+        }
+
+        Ok(RequireRole {
+            user_id,
+            role: user.role,
+            _marker: PhantomData,
         })
     }
 }